@@ -0,0 +1,71 @@
+//! Benchmarks for extracting values from variant arrays.
+//!
+//! Run with `cargo bench -p arrow-open-variant --bench extract`, optionally
+//! followed by `--` and a regular expression matching the benchmarks to run.
+//! Batches with mostly null rows, as with optional payloads, show the cost of
+//! skipping null rows.
+
+use std::hint::black_box;
+
+use arrow_array::StringArray;
+use arrow_open_variant::extract::{flatten_variant, variant_like};
+use arrow_open_variant::json::variant_from_json;
+use arrow_open_variant::transform::variant_lower;
+use arrow_schema::DataType;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use open_variant::path::VariantPath;
+
+const ROWS: usize = 8192;
+
+fn bench_extract(c: &mut Criterion) {
+    let json = |i: usize| format!(r#"{{"id": {}, "name": "User {}", "score": {}.5}}"#, i, i, i);
+    let dense = (0..ROWS).map(|i| Some(json(i))).collect::<Vec<_>>();
+    // One row in 16 is valid, in runs of 4.
+    let sparse = (0..ROWS)
+        .map(|i| (i % 64 < 4).then(|| json(i)))
+        .collect::<Vec<_>>();
+    let all_null = vec![None::<String>; ROWS];
+
+    let columns = [
+        (VariantPath::parse("id").unwrap(), DataType::Int64, "id"),
+        (VariantPath::parse("name").unwrap(), DataType::Utf8, "name"),
+        (
+            VariantPath::parse("score").unwrap(),
+            DataType::Float64,
+            "score",
+        ),
+    ];
+    let name = VariantPath::parse("name").unwrap();
+    let batches = [("dense", dense), ("sparse", sparse), ("null", all_null)]
+        .map(|(batch, jsons)| (batch, variant_from_json(&StringArray::from(jsons)).unwrap()));
+
+    let mut group = c.benchmark_group("flatten_variant");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for (batch, array) in &batches {
+        group.bench_function(*batch, |b| {
+            b.iter(|| flatten_variant(black_box(array), &columns).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("variant_like");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for (batch, array) in &batches {
+        group.bench_function(*batch, |b| {
+            b.iter(|| variant_like(black_box(array), &name, "User 1%").unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("variant_lower");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for (batch, array) in &batches {
+        group.bench_function(*batch, |b| {
+            b.iter(|| variant_lower(black_box(array), &name).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_extract);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+//! Benchmarks for converting JSON to variant arrays.
+//!
+//! Run with `cargo bench -p arrow-open-variant --bench json`, optionally
+//! followed by `--` and a regular expression matching the benchmarks to run.
+//! Throughput is reported in rows per second.
+
+use std::hint::black_box;
+
+use arrow_array::StringArray;
+use arrow_open_variant::json::{variant_from_json, variant_from_json_parallel, JsonParseOptions};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const ROWS: usize = 8192;
+
+fn bench_variant_from_json(c: &mut Criterion) {
+    let flat = (0..ROWS)
+        .map(|i| {
+            format!(
+                r#"{{"id": {}, "name": "user {}", "score": {}.5, "active": true}}"#,
+                i, i, i
+            )
+        })
+        .collect::<Vec<_>>();
+    let nested = (0..ROWS)
+        .map(|i| {
+            format!(
+                r#"{{"id": {}, "tags": ["a", "b", "c"], "address": {{"city": "c{}", "zip": "{:05}"}}, "events": [{{"type": "click", "at": {}}}, {{"type": "view", "at": {}}}]}}"#,
+                i,
+                i % 100,
+                i,
+                i * 10,
+                i * 10 + 1
+            )
+        })
+        .collect::<Vec<_>>();
+    // Many small containers per row.
+    let deep = (0..ROWS)
+        .map(|i| {
+            let mut json = i.to_string();
+            for _ in 0..16 {
+                json = format!(r#"{{"a": [{}, "x"]}}"#, json);
+            }
+            json
+        })
+        .collect::<Vec<_>>();
+
+    let batches = [("flat", flat), ("nested", nested), ("deep", deep)]
+        .map(|(name, jsons)| (name, StringArray::from(jsons)));
+
+    let mut group = c.benchmark_group("variant_from_json");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for (name, jsons) in &batches {
+        group.bench_function(*name, |b| {
+            b.iter(|| variant_from_json(black_box(jsons)).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("variant_from_json_parallel");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    let options = JsonParseOptions::default();
+    for (name, jsons) in &batches {
+        group.bench_function(*name, |b| {
+            b.iter(|| variant_from_json_parallel(black_box(jsons), &options, 0).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_variant_from_json);
+criterion_main!(benches);
@@ -0,0 +1,1210 @@
+//! Aggregates over the values at a path of variant arrays.
+//!
+//! Each aggregate reads the values at its path directly from the variant
+//! bytes, without extracting them into a column first. Like
+//! [`TypeHistogram`](crate::histogram::TypeHistogram), an aggregate is
+//! updated with one array at a time and can be merged with aggregates built
+//! over other partitions, which is the shape of a query engine's
+//! accumulator.
+//!
+//! ```rust
+//! # #[cfg(feature = "json")]
+//! # {
+//! use arrow_array::StringArray;
+//! use arrow_open_variant::aggregate::PathSum;
+//! use arrow_open_variant::json::variant_from_json;
+//! use open_variant::path::VariantPath;
+//!
+//! let jsons = StringArray::from(vec![r#"{"foo": 1}"#, r#"{"foo": 2}"#, r#"{"bar": 3}"#]);
+//! let array = variant_from_json(&jsons).unwrap();
+//!
+//! let mut sum = PathSum::new(VariantPath::parse("foo").unwrap());
+//! sum.update(&array).unwrap();
+//! assert_eq!(sum.sum(), Some(3));
+//! assert_eq!(sum.count(), 2);
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, BinaryArray, StructArray};
+use arrow_schema::ArrowError;
+use open_variant::coerce::CoerceOptions;
+use open_variant::json::{write_json, JsonWriteOptions};
+use open_variant::metadata::{build_metadata, MetadataRef};
+use open_variant::path::{PathElement, VariantPath};
+use open_variant::shape::fnv1a;
+use open_variant::values::write::{
+    remap_field_ids, write_binary, write_string, ArrayBuilder, ObjectBuilder,
+};
+use open_variant::values::{BasicType, PrimitiveTypeId, VariantRef};
+
+use crate::array::{values_array_from_parts, VariantArray, VariantArrayReader};
+use crate::layout::{MetadataEncoding, VariantLayout};
+
+/// Call `f` with every value at `path` in the non-null rows of a variant
+/// array, and the metadata buffer of its row.
+///
+/// Paths with wildcards or descendants can match several values per row.
+fn for_each_value<'a>(
+    variant_array: &'a VariantArray,
+    path: &VariantPath,
+    mut f: impl FnMut(VariantRef<'a>, &'a [u8]) -> Result<(), ArrowError>,
+) -> Result<(), ArrowError> {
+    let expands = path
+        .elements()
+        .iter()
+        .any(|element| matches!(element, PathElement::Wildcard | PathElement::Descendants));
+    let mut resolver = variant_array.path_resolver(path);
+    for (start, end) in variant_array.valid_slices() {
+        for i in start..end {
+            let Some(variant) = variant_array.variant(i) else {
+                continue;
+            };
+            let metadata_bytes = variant_array.metadata(i);
+            let metadata = MetadataRef::new(metadata_bytes);
+            if expands {
+                for value in variant.get_path_all(path, &metadata) {
+                    f(value, metadata_bytes)?;
+                }
+                continue;
+            }
+            if let Some(value) = resolver.resolve(i).get(&variant) {
+                f(value, metadata_bytes)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counts the values at a path, like `count(variant_get(col, path))`.
+///
+/// Rows where the path doesn't exist, null rows, and variant nulls are not
+/// counted.
+#[derive(Debug, Clone)]
+pub struct PathCount {
+    path: VariantPath,
+    count: u64,
+}
+
+impl PathCount {
+    pub fn new(path: VariantPath) -> Self {
+        Self { path, count: 0 }
+    }
+
+    /// Count the values of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        for_each_value(&variant_array, &self.path, |value, _| {
+            self.count += u64::from(!value.is_null());
+            Ok(())
+        })
+    }
+
+    /// Add the count of another aggregate over the same path.
+    pub fn merge(&mut self, other: &PathCount) {
+        self.count += other.count;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Sums the integers at a path, like `sum(variant_get_int(col, path))`.
+///
+/// Values are read as `i64` with the rules of
+/// [`coerce`](open_variant::coerce), so integers of any width, and integral
+/// decimals and floats, are summed. Other values are skipped.
+#[derive(Debug, Clone)]
+pub struct PathSum {
+    path: VariantPath,
+    options: CoerceOptions,
+    sum: i64,
+    count: u64,
+}
+
+impl PathSum {
+    pub fn new(path: VariantPath) -> Self {
+        Self::with_options(path, CoerceOptions::default())
+    }
+
+    pub fn with_options(path: VariantPath, options: CoerceOptions) -> Self {
+        Self {
+            path,
+            options,
+            sum: 0,
+            count: 0,
+        }
+    }
+
+    /// Add the values of a variant array to the sum.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if the sum overflows an `i64`.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        for_each_value(&variant_array, &self.path, |value, _| {
+            if let Some(value) = value.coerce_i64(&self.options) {
+                self.sum = checked_add(self.sum, value, &self.path)?;
+                self.count += 1;
+            }
+            Ok(())
+        })
+    }
+
+    /// Add the sum of another aggregate over the same path.
+    ///
+    /// # Errors
+    ///
+    /// If the sum overflows an `i64`.
+    pub fn merge(&mut self, other: &PathSum) -> Result<(), ArrowError> {
+        self.sum = checked_add(self.sum, other.sum, &self.path)?;
+        self.count += other.count;
+        Ok(())
+    }
+
+    /// The sum, or `None` if no values were summed, as in SQL.
+    pub fn sum(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.sum)
+    }
+
+    /// The number of values summed.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+fn checked_add(sum: i64, value: i64, path: &VariantPath) -> Result<i64, ArrowError> {
+    sum.checked_add(value).ok_or_else(|| {
+        ArrowError::ComputeError(format!("Overflow summing the values at '{}'", path))
+    })
+}
+
+/// The number of bits of the hash used to pick a HyperLogLog register.
+const HLL_PRECISION: u32 = 12;
+
+/// Estimates the number of distinct values at a path with HyperLogLog, like
+/// `approx_distinct(variant_get(col, path))`.
+///
+/// Values are compared by their JSON text with sorted keys (see
+/// [`open_variant::json`]), so equal objects written with different metadata
+/// are the same value, and so are short and long strings, but `1` and `1.0`
+/// are different values. Null rows and variant nulls are not counted.
+///
+/// The estimate has a standard error of about 1.6%, using 4KB of memory
+/// regardless of the number of values. Hashes are stable, so aggregates
+/// built in different processes can be merged.
+#[derive(Debug, Clone)]
+pub struct PathApproxDistinct {
+    path: VariantPath,
+    registers: Vec<u8>,
+    /// Reused buffer for the JSON text of values.
+    json: String,
+}
+
+impl PathApproxDistinct {
+    pub fn new(path: VariantPath) -> Self {
+        Self {
+            path,
+            registers: vec![0; 1 << HLL_PRECISION],
+            json: String::new(),
+        }
+    }
+
+    /// Add the values of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        let options = JsonWriteOptions::default();
+        for_each_value(&variant_array, &self.path, |value, metadata_bytes| {
+            if value.is_null() {
+                return Ok(());
+            }
+            let metadata = MetadataRef::new(metadata_bytes);
+            self.json.clear();
+            write_json(&mut self.json, &value, &metadata, &options)
+                .map_err(ArrowError::InvalidArgumentError)?;
+            let hash = mix(fnv1a(self.json.as_bytes()));
+            let register = (hash >> (64 - HLL_PRECISION)) as usize;
+            // The set bit bounds the rank when the remaining bits are zero.
+            let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+            self.registers[register] = self.registers[register].max(rank as u8);
+            Ok(())
+        })
+    }
+
+    /// Add the values of another aggregate over the same path.
+    pub fn merge(&mut self, other: &PathApproxDistinct) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    /// The estimated number of distinct values.
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-(*register as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|register| **register == 0)
+            .count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities.
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// Samples up to `k` distinct values at a path, like
+/// `variant_sample_values(col, path, k)`, for example to preview a field in
+/// a data catalog.
+///
+/// The sample is the `k` distinct values with the smallest hashes of their
+/// JSON text, compared as in [`PathApproxDistinct`]. This is a uniform sample
+/// of the distinct values that needs no random number generator: the same
+/// data always gives the same sample, and merging the samples of two
+/// partitions gives the sample of both. Null rows and variant nulls are not
+/// sampled.
+#[derive(Debug, Clone)]
+pub struct PathSample {
+    path: VariantPath,
+    k: usize,
+    /// The sampled values by hash, with the metadata they were written with.
+    sample: BTreeMap<u64, (Vec<u8>, Vec<u8>)>,
+    /// Reused buffer for the JSON text of values.
+    json: String,
+}
+
+impl PathSample {
+    pub fn new(path: VariantPath, k: usize) -> Self {
+        Self {
+            path,
+            k,
+            sample: BTreeMap::new(),
+            json: String::new(),
+        }
+    }
+
+    /// Add the values of a variant array to the sample.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        let options = JsonWriteOptions::default();
+        for_each_value(&variant_array, &self.path, |value, metadata_bytes| {
+            if value.is_null() || self.k == 0 {
+                return Ok(());
+            }
+            let metadata = MetadataRef::new(metadata_bytes);
+            self.json.clear();
+            write_json(&mut self.json, &value, &metadata, &options)
+                .map_err(ArrowError::InvalidArgumentError)?;
+            let hash = mix(fnv1a(self.json.as_bytes()));
+            let full = self.sample.len() == self.k;
+            if full
+                && self
+                    .sample
+                    .last_key_value()
+                    .is_some_and(|(max, _)| hash >= *max)
+            {
+                return Ok(());
+            }
+            self.sample
+                .entry(hash)
+                .or_insert_with(|| (metadata_bytes.to_vec(), value.as_bytes().to_vec()));
+            if self.sample.len() > self.k {
+                self.sample.pop_last();
+            }
+            Ok(())
+        })
+    }
+
+    /// Add the values sampled by another aggregate over the same path.
+    pub fn merge(&mut self, other: &PathSample) {
+        for (hash, value) in &other.sample {
+            self.sample.entry(*hash).or_insert_with(|| value.clone());
+        }
+        while self.sample.len() > self.k {
+            self.sample.pop_last();
+        }
+    }
+
+    /// The sampled values as a variant array, in hash order.
+    ///
+    /// # Errors
+    ///
+    /// If the values are too large for a single array.
+    pub fn values(&self) -> Result<ArrayRef, ArrowError> {
+        let metadata =
+            BinaryArray::from_iter_values(self.sample.values().map(|(metadata, _)| metadata));
+        let values = BinaryArray::from_iter_values(self.sample.values().map(|(_, value)| value));
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()?;
+        let array = StructArray::try_new(
+            layout.fields(),
+            vec![Arc::new(metadata) as ArrayRef, Arc::new(values)],
+            None,
+        )?;
+        VariantArray::try_new(&array)?.to_layout(&VariantLayout::default())
+    }
+}
+
+/// Histograms of the values at a path, like
+/// `variant_histogram(col, path, buckets)`, for profiling a field without
+/// extracting it first.
+///
+/// Numbers of any type go into a streaming histogram of up to `buckets`
+/// bins, as in Hive's `histogram_numeric`: each bin has the mean and count of
+/// its values, and when a new value makes one bin too many, the two closest
+/// bins are merged. The bins are exact while there are at most `buckets`
+/// distinct numbers. Strings are counted separately, keeping the `buckets`
+/// most frequent with the space-saving algorithm, so once more distinct
+/// strings are seen, a count can be too high by up to the smallest count.
+/// Other values, null rows and variant nulls are skipped.
+#[derive(Debug, Clone)]
+pub struct PathHistogram {
+    path: VariantPath,
+    bins: NumericBins,
+    strings: SpaceSaving,
+}
+
+impl PathHistogram {
+    pub fn new(path: VariantPath, buckets: usize) -> Self {
+        Self {
+            path,
+            bins: NumericBins::new(buckets),
+            strings: SpaceSaving::new(buckets),
+        }
+    }
+
+    /// Add the values of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        let options = CoerceOptions::default();
+        for_each_value(&variant_array, &self.path, |value, metadata_bytes| {
+            if let Some(number) = value.coerce_f64(&options) {
+                self.bins.insert(number, 1);
+            } else if let Some(string) =
+                value.get_str_with_metadata(&MetadataRef::new(metadata_bytes))
+            {
+                self.strings.insert(string, 1, || ());
+            }
+            Ok(())
+        })
+    }
+
+    /// Add the values of another aggregate over the same path.
+    pub fn merge(&mut self, other: &PathHistogram) {
+        for (mean, count) in &other.bins.bins {
+            self.bins.insert(*mean, *count);
+        }
+        self.strings.merge(&other.strings);
+    }
+
+    /// The numeric bins, as `(mean, count)` pairs in increasing order.
+    pub fn numeric_bins(&self) -> &[(f64, u64)] {
+        &self.bins.bins
+    }
+
+    /// The most frequent strings with their counts, most frequent first.
+    pub fn top_strings(&self) -> Vec<(&str, u64)> {
+        self.strings
+            .top()
+            .into_iter()
+            .map(|(string, count, _)| (string, count))
+            .collect()
+    }
+}
+
+/// The most frequent leaf values at a path, like
+/// `variant_top_k(col, path, k)`.
+///
+/// Leaf values are values other than objects and arrays, including the
+/// elements and fields matched with `[*]` and `..`. They are compared by
+/// their JSON text, as in [`PathApproxDistinct`], and counted in `k`
+/// counters with the space-saving algorithm. The counts are exact while
+/// there are at most `k` distinct values. Past that, a new value replaces the
+/// least frequent one and takes over its count, so counts can be too high by
+/// up to the smallest count, but any value more frequent than that is kept.
+/// Null rows and variant nulls are not counted.
+#[derive(Debug, Clone)]
+pub struct PathTopK {
+    path: VariantPath,
+    /// The values by JSON text, with an encoding that doesn't refer to the
+    /// metadata.
+    values: SpaceSaving<Vec<u8>>,
+    /// Reused buffer for the JSON text of values.
+    json: String,
+}
+
+impl PathTopK {
+    pub fn new(path: VariantPath, k: usize) -> Self {
+        Self {
+            path,
+            values: SpaceSaving::new(k),
+            json: String::new(),
+        }
+    }
+
+    /// Count the values of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        let options = JsonWriteOptions::default();
+        for_each_value(&variant_array, &self.path, |value, metadata_bytes| {
+            if value.is_null() || matches!(value.basic_type(), BasicType::Object | BasicType::Array)
+            {
+                return Ok(());
+            }
+            let metadata = MetadataRef::new(metadata_bytes);
+            self.json.clear();
+            write_json(&mut self.json, &value, &metadata, &options)
+                .map_err(ArrowError::InvalidArgumentError)?;
+            self.values
+                .insert(&self.json, 1, || inline_leaf(&value, &metadata));
+            Ok(())
+        })
+    }
+
+    /// Add the counts of another aggregate over the same path.
+    pub fn merge(&mut self, other: &PathTopK) {
+        self.values.merge(&other.values);
+    }
+
+    /// The most frequent values, most frequent first, as a variant array of
+    /// objects with the fields `value` and `count`.
+    ///
+    /// # Errors
+    ///
+    /// If the values are too large for a single array.
+    pub fn values(&self) -> Result<ArrayRef, ArrowError> {
+        let top = self.values.top();
+        let metadata = build_metadata(["count", "value"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        let mut offsets = Vec::with_capacity(top.len() + 1);
+        offsets.push(0);
+        for (_, count, value) in &top {
+            let mut object = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 2);
+            let count = i64::try_from(*count).unwrap_or(i64::MAX);
+            object
+                .append_i64("count", count)
+                .expect("the key is in the metadata");
+            object
+                .append_value("value", value)
+                .expect("the key is in the metadata");
+            object.finish();
+            offsets.push(buffer.len());
+        }
+
+        let metadata = BinaryArray::from_iter_values(top.iter().map(|_| &metadata));
+        let values = values_array_from_parts(buffer, &offsets, None);
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()?;
+        let array = StructArray::try_new(
+            layout.fields(),
+            vec![Arc::new(metadata) as ArrayRef, values],
+            None,
+        )?;
+        VariantArray::try_new(&array)?.to_layout(&VariantLayout::default())
+    }
+}
+
+/// The encoding of a leaf value, with strings and binaries stored in the
+/// metadata dictionary written inline, so it can be used with other metadata.
+fn inline_leaf(value: &VariantRef, metadata: &MetadataRef) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    if value.basic_type() != BasicType::Primitive {
+        return bytes.to_vec();
+    }
+    let mut buffer = Vec::new();
+    match value.primitive_type_id() {
+        type_id @ (PrimitiveTypeId::StringFromDictionary
+        | PrimitiveTypeId::BinaryFromDictionary) => {
+            let id = u32::from_le_bytes(bytes[1..5].try_into().unwrap()) as usize;
+            // The id was already resolved to write the JSON text.
+            let string = metadata.get_string(id).unwrap_or_default();
+            if type_id == PrimitiveTypeId::StringFromDictionary {
+                write_string(&mut buffer, string);
+            } else {
+                write_binary(&mut buffer, string.as_bytes());
+            }
+        }
+        _ => buffer.extend_from_slice(bytes),
+    }
+    buffer
+}
+
+/// Collects the distinct elements of the arrays at a path into one array,
+/// like `variant_array_agg_distinct(col, path)`, for tag or set columns
+/// stored as arrays.
+///
+/// Elements are compared by their canonical encoding (see
+/// [`VariantRef::canonicalize`]), so objects with the same fields are the
+/// same element whatever metadata or field order they were written with.
+/// Values that aren't arrays, null rows and variant nulls are skipped, but
+/// null elements are kept.
+#[derive(Debug, Clone)]
+pub struct PathArrayAggDistinct {
+    path: VariantPath,
+    /// The canonical value and metadata of each distinct element.
+    elements: BTreeSet<(Vec<u8>, Vec<u8>)>,
+}
+
+impl PathArrayAggDistinct {
+    pub fn new(path: VariantPath) -> Self {
+        Self {
+            path,
+            elements: BTreeSet::new(),
+        }
+    }
+
+    /// Add the elements of the arrays of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        for_each_value(&variant_array, &self.path, |value, metadata_bytes| {
+            if value.basic_type() != BasicType::Array {
+                return Ok(());
+            }
+            let metadata = MetadataRef::new(metadata_bytes);
+            let elements = value
+                .get_array()
+                .map_err(ArrowError::InvalidArgumentError)?;
+            for element in elements.elements() {
+                let (element_metadata, element) = element
+                    .canonicalize(&metadata)
+                    .map_err(ArrowError::InvalidArgumentError)?;
+                self.elements.insert((element, element_metadata));
+            }
+            Ok(())
+        })
+    }
+
+    /// Add the elements of another aggregate over the same path.
+    pub fn merge(&mut self, other: &PathArrayAggDistinct) {
+        self.elements.extend(other.elements.iter().cloned());
+    }
+
+    /// The number of distinct elements.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// The distinct elements as a variant array with a single row, holding
+    /// an array of the elements in the order of their canonical encoding.
+    ///
+    /// # Errors
+    ///
+    /// If the array exceeds the limits of the format, or the keys of an
+    /// element can't be mapped to the output metadata.
+    pub fn value(&self) -> Result<ArrayRef, ArrowError> {
+        let element_metadata = self
+            .elements
+            .iter()
+            .map(|(_, metadata)| MetadataRef::new(metadata))
+            .collect::<Vec<_>>();
+        let keys = element_metadata
+            .iter()
+            .flat_map(|metadata| {
+                (0..metadata.dictionary_len()).filter_map(|id| metadata.get_string(id))
+            })
+            .collect::<BTreeSet<_>>();
+        let metadata = build_metadata(keys.into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        let mut buffer = Vec::new();
+        let mut builder = ArrayBuilder::new(&mut buffer, self.elements.len());
+        let mut element_buffer = Vec::new();
+        for ((value, _), element_metadata) in self.elements.iter().zip(&element_metadata) {
+            // Both dictionaries are sorted, so the mapping keeps the order of
+            // the fields.
+            let mapping = (0..element_metadata.dictionary_len())
+                .map(|id| {
+                    let key = element_metadata.get_string(id).ok_or_else(|| {
+                        ArrowError::InvalidArgumentError(format!(
+                            "Key id {} is missing from the metadata of an element",
+                            id
+                        ))
+                    })?;
+                    metadata_ref.find_string(key).ok_or_else(|| {
+                        ArrowError::InvalidArgumentError(format!(
+                            "Key {:?} of an element is missing from the output metadata",
+                            key
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let value = VariantRef::try_new(value).map_err(ArrowError::InvalidArgumentError)?;
+            element_buffer.clear();
+            remap_field_ids(&mut element_buffer, &value, &mapping, &metadata_ref)
+                .map_err(ArrowError::InvalidArgumentError)?;
+            builder.append_value(&element_buffer);
+        }
+        builder.try_finish().map_err(ArrowError::ComputeError)?;
+
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()?;
+        let array = StructArray::try_new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_iter_values([metadata])) as ArrayRef,
+                Arc::new(BinaryArray::from_iter_values([buffer])),
+            ],
+            None,
+        )?;
+        VariantArray::try_new(&array)?.to_layout(&VariantLayout::default())
+    }
+}
+
+/// A streaming histogram of up to `capacity` bins (Ben-Haim and Tom-Tov).
+#[derive(Debug, Clone)]
+struct NumericBins {
+    capacity: usize,
+    /// `(mean, count)` pairs, sorted by mean.
+    bins: Vec<(f64, u64)>,
+}
+
+impl NumericBins {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            bins: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, value: f64, count: u64) {
+        if !value.is_finite() || self.capacity == 0 {
+            return;
+        }
+        // Count -0.0 with 0.0.
+        let value = if value == 0.0 { 0.0 } else { value };
+        match self
+            .bins
+            .binary_search_by(|(mean, _)| mean.total_cmp(&value))
+        {
+            Ok(i) => self.bins[i].1 += count,
+            Err(i) => {
+                self.bins.insert(i, (value, count));
+                if self.bins.len() > self.capacity {
+                    self.merge_closest();
+                }
+            }
+        }
+    }
+
+    /// Merge the two adjacent bins with the closest means. The merged mean
+    /// is between them, so the bins stay sorted.
+    fn merge_closest(&mut self) {
+        let gap = |i: usize| self.bins[i + 1].0 - self.bins[i].0;
+        let i = (0..self.bins.len() - 1)
+            .min_by(|a, b| gap(*a).total_cmp(&gap(*b)))
+            .expect("there are at least 2 bins");
+        let (left, left_count) = self.bins[i];
+        let (right, right_count) = self.bins.remove(i + 1);
+        let count = left_count + right_count;
+        let mean = (left * left_count as f64 + right * right_count as f64) / count as f64;
+        self.bins[i] = (mean, count);
+    }
+}
+
+/// The `capacity` most frequent strings of a stream, counted with the
+/// space-saving algorithm (Metwally et al.), each with a payload kept from
+/// its first occurrence.
+///
+/// Once the summary is full, a new string replaces the least frequent one
+/// and takes over its count plus one, so counts are upper bounds that are
+/// too high by at most the smallest count. The counters are kept in a binary
+/// min-heap indexed by item, so counting an item and replacing the least
+/// frequent one take `O(log capacity)`.
+#[derive(Debug, Clone)]
+struct SpaceSaving<T = ()> {
+    capacity: usize,
+    /// The counters, as a min-heap on [`Counter::before`].
+    heap: Vec<Counter<T>>,
+    /// The position of each item in `heap`.
+    positions: HashMap<Arc<str>, usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Counter<T> {
+    item: Arc<str>,
+    count: u64,
+    payload: T,
+}
+
+impl<T> Counter<T> {
+    /// Whether the counter is evicted before `other`: it has a lower count,
+    /// or the same count and a greater item, so the summary doesn't depend
+    /// on the order of the items.
+    fn before(&self, other: &Counter<T>) -> bool {
+        (self.count, &other.item) < (other.count, &self.item)
+    }
+}
+
+impl<T: Clone> SpaceSaving<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: Vec::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Count `item`, calling `payload` if it is added to the summary.
+    fn insert(&mut self, item: &str, count: u64, payload: impl FnOnce() -> T) {
+        if let Some(&position) = self.positions.get(item) {
+            self.heap[position].count += count;
+            self.sift_down(position);
+            return;
+        }
+        if self.heap.len() < self.capacity {
+            self.push(Counter {
+                item: item.into(),
+                count,
+                payload: payload(),
+            });
+            return;
+        }
+        let Some(least) = self.heap.first() else {
+            return;
+        };
+        let counter = Counter {
+            item: item.into(),
+            count: least.count + count,
+            payload: payload(),
+        };
+        self.positions.remove(&least.item);
+        self.positions.insert(counter.item.clone(), 0);
+        self.heap[0] = counter;
+        self.sift_down(0);
+    }
+
+    /// The count a full summary may have missed for an item it doesn't hold.
+    fn missed_count(&self) -> u64 {
+        if self.heap.len() < self.capacity {
+            return 0;
+        }
+        self.heap.first().map_or(0, |least| least.count)
+    }
+
+    /// Combine with the summary of another stream, as in the mergeable
+    /// summaries of Agarwal et al.: items missing from one side are counted
+    /// with the most that side could have missed.
+    fn merge(&mut self, other: &SpaceSaving<T>) {
+        let (missed, other_missed) = (self.missed_count(), other.missed_count());
+        for counter in &mut self.heap {
+            counter.count += other
+                .positions
+                .get(&counter.item)
+                .map_or(other_missed, |&position| other.heap[position].count);
+        }
+        for counter in &other.heap {
+            if !self.positions.contains_key(&counter.item) {
+                self.heap.push(Counter {
+                    count: counter.count + missed,
+                    ..counter.clone()
+                });
+            }
+        }
+        // The counts changed everywhere, so rebuild the heap.
+        if self.heap.len() > self.capacity {
+            self.heap
+                .sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.item.cmp(&b.item)));
+            self.heap.truncate(self.capacity);
+        }
+        for position in (0..self.heap.len() / 2).rev() {
+            self.sift_down(position);
+        }
+        self.positions = self
+            .heap
+            .iter()
+            .enumerate()
+            .map(|(position, counter)| (counter.item.clone(), position))
+            .collect();
+    }
+
+    /// The items with their counts and payloads, most frequent first, then
+    /// in order.
+    fn top(&self) -> Vec<(&str, u64, &T)> {
+        let mut top = self
+            .heap
+            .iter()
+            .map(|counter| (&*counter.item, counter.count, &counter.payload))
+            .collect::<Vec<_>>();
+        top.sort_by(|(a, a_count, _), (b, b_count, _)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        top
+    }
+
+    fn push(&mut self, counter: Counter<T>) {
+        self.positions.insert(counter.item.clone(), self.heap.len());
+        self.heap.push(counter);
+        self.sift_up(self.heap.len() - 1);
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if !self.heap[position].before(&self.heap[parent]) {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let mut least = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.heap.len() && self.heap[child].before(&self.heap[least]) {
+                    least = child;
+                }
+            }
+            if least == position {
+                break;
+            }
+            self.swap(position, least);
+            position = least;
+        }
+    }
+
+    /// Swap two counters, keeping `positions` up to date.
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        for position in [a, b] {
+            if let Some(slot) = self.positions.get_mut(&self.heap[position].item) {
+                *slot = position;
+            }
+        }
+    }
+}
+
+/// A finalizer for FNV-1a hashes, whose high bits are poorly mixed for short
+/// inputs (from SplitMix64).
+fn mix(hash: u64) -> u64 {
+    let hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    fn array(jsons: Vec<Option<&str>>) -> arrow_array::ArrayRef {
+        variant_from_json(&StringArray::from(jsons)).unwrap()
+    }
+
+    #[test]
+    fn test_path_count() {
+        let array = array(vec![
+            Some(r#"{"a": 1, "b": [1, 2, null]}"#),
+            Some(r#"{"a": null}"#),
+            None,
+            Some(r#"{"a": "x"}"#),
+        ]);
+        let count = |path: &str| {
+            let mut count = PathCount::new(VariantPath::parse(path).unwrap());
+            count.update(&array.slice(0, 2)).unwrap();
+            let mut other = PathCount::new(VariantPath::parse(path).unwrap());
+            other.update(&array.slice(2, 2)).unwrap();
+            count.merge(&other);
+            count.count()
+        };
+        assert_eq!(count("a"), 2);
+        assert_eq!(count("b[*]"), 2);
+        assert_eq!(count("c"), 0);
+        assert_eq!(count(""), 3);
+    }
+
+    #[test]
+    fn test_path_sum() {
+        let array = array(vec![
+            Some(r#"{"foo": 1}"#),
+            Some(r#"{"foo": 2.0}"#),
+            Some(r#"{"foo": 2.5}"#),
+            Some(r#"{"foo": "4"}"#),
+            None,
+        ]);
+        let path = VariantPath::parse("foo").unwrap();
+        let mut sum = PathSum::new(path.clone());
+        sum.update(&array).unwrap();
+        assert_eq!((sum.sum(), sum.count()), (Some(3), 2));
+
+        let options = CoerceOptions {
+            parse_strings: true,
+        };
+        let mut parsed = PathSum::with_options(path.clone(), options);
+        parsed.update(&array).unwrap();
+        assert_eq!(parsed.sum(), Some(7));
+        sum.merge(&parsed).unwrap();
+        assert_eq!((sum.sum(), sum.count()), (Some(10), 5));
+
+        let mut empty = PathSum::new(VariantPath::parse("bar").unwrap());
+        empty.update(&array).unwrap();
+        assert_eq!(empty.sum(), None);
+
+        let max = i64::MAX.to_string();
+        let big = self::array(vec![Some(max.as_str()), Some("1")]);
+        let mut overflow = PathSum::new(VariantPath::default());
+        assert!(overflow.update(&big).is_err());
+    }
+
+    #[test]
+    fn test_path_approx_distinct() {
+        let jsons = (0..2000)
+            .map(|i| {
+                format!(
+                    r#"{{"id": {}, "group": "{}", "tags": {{"b": 1, "a": {}}}}}"#,
+                    i,
+                    i % 10,
+                    i % 3
+                )
+            })
+            .collect::<Vec<_>>();
+        let array = variant_from_json(&StringArray::from_iter_values(&jsons)).unwrap();
+        let estimate = |path: &str| {
+            let mut distinct = PathApproxDistinct::new(VariantPath::parse(path).unwrap());
+            distinct.update(&array.slice(0, 1000)).unwrap();
+            let mut other = PathApproxDistinct::new(VariantPath::parse(path).unwrap());
+            other.update(&array.slice(500, 1500)).unwrap();
+            distinct.merge(&other);
+            distinct.estimate()
+        };
+        let ids = estimate("id");
+        assert!((1900..=2100).contains(&ids), "{}", ids);
+        assert_eq!(estimate("group"), 10);
+        assert_eq!(estimate("tags"), 3);
+        assert_eq!(estimate("missing"), 0);
+    }
+
+    #[test]
+    fn test_path_sample() {
+        let jsons = (0..100)
+            .map(|i| format!(r#"{{"v": {}}}"#, i % 20))
+            .chain([
+                r#"{"v": null}"#.to_string(),
+                r#"{"v": {"x": [1]}}"#.to_string(),
+            ])
+            .collect::<Vec<_>>();
+        let array = variant_from_json(&StringArray::from_iter_values(&jsons)).unwrap();
+        let path = VariantPath::parse("v").unwrap();
+
+        let mut all = PathSample::new(path.clone(), 100);
+        all.update(&array).unwrap();
+        // 20 integers and the object, without duplicates or the null.
+        assert_eq!(all.values().unwrap().len(), 21);
+
+        let mut sample = PathSample::new(path.clone(), 5);
+        sample.update(&array.slice(0, 50)).unwrap();
+        let mut other = PathSample::new(path.clone(), 5);
+        other.update(&array.slice(50, 52)).unwrap();
+        sample.merge(&other);
+        let mut single = PathSample::new(path.clone(), 5);
+        single.update(&array).unwrap();
+        let values = sample.values().unwrap();
+        assert_eq!(values.len(), 5);
+        assert_eq!(&values, &single.values().unwrap());
+        assert_eq!(values.data_type(), &crate::variant_type());
+
+        let variant_array = VariantArray::try_new(&values).unwrap();
+        for i in 0..5 {
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            assert!(!variant_array.variant(i).unwrap().is_null());
+            assert!(open_variant::json::to_json(
+                &variant_array.variant(i).unwrap(),
+                &metadata,
+                &JsonWriteOptions::default()
+            )
+            .is_ok());
+        }
+
+        let empty = PathSample::new(path, 0);
+        assert_eq!(empty.values().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_path_histogram() {
+        let array = array(vec![
+            Some(r#"{"v": 1}"#),
+            Some(r#"{"v": 1.0}"#),
+            Some(r#"{"v": 2}"#),
+            Some(r#"{"v": 10}"#),
+            Some(r#"{"v": "a"}"#),
+            Some(r#"{"v": "b"}"#),
+            Some(r#"{"v": "a"}"#),
+            Some(r#"{"v": [1]}"#),
+            Some(r#"{"v": null}"#),
+            None,
+        ]);
+        let path = VariantPath::parse("v").unwrap();
+        let mut histogram = PathHistogram::new(path.clone(), 3);
+        histogram.update(&array).unwrap();
+        assert_eq!(histogram.numeric_bins(), &[(1.0, 2), (2.0, 1), (10.0, 1)]);
+        assert_eq!(histogram.top_strings(), vec![("a", 2), ("b", 1)]);
+
+        // A fourth distinct number merges the two closest bins.
+        let mut split = PathHistogram::new(path.clone(), 3);
+        split.update(&array.slice(0, 5)).unwrap();
+        let mut other = PathHistogram::new(path.clone(), 3);
+        other.update(&array.slice(5, 5)).unwrap();
+        other
+            .update(&self::array(vec![Some(r#"{"v": 10.5}"#)]))
+            .unwrap();
+        split.merge(&other);
+        assert_eq!(split.numeric_bins(), &[(1.0, 2), (2.0, 1), (10.25, 2)]);
+        assert_eq!(split.top_strings(), histogram.top_strings());
+    }
+
+    #[test]
+    fn test_path_top_k() {
+        let array = array(vec![
+            Some(r#"{"v": "a", "tags": ["x", "y"]}"#),
+            Some(r#"{"v": 1, "tags": ["x"]}"#),
+            Some(r#"{"v": "a"}"#),
+            Some(r#"{"v": {"w": 1}}"#),
+            Some(r#"{"v": null}"#),
+            None,
+            Some(r#"{"v": true}"#),
+            Some(r#"{"v": 1}"#),
+            Some(r#"{"v": "a"}"#),
+        ]);
+        let top_k = |path: &str, k: usize| {
+            let path = VariantPath::parse(path).unwrap();
+            let mut top_k = PathTopK::new(path.clone(), k);
+            top_k.update(&array.slice(0, 4)).unwrap();
+            let mut other = PathTopK::new(path, k);
+            other.update(&array.slice(4, 5)).unwrap();
+            top_k.merge(&other);
+            let values = top_k.values().unwrap();
+            assert_eq!(values.data_type(), &crate::variant_type());
+            let variant_array = VariantArray::try_new(&values).unwrap();
+            (0..variant_array.len())
+                .map(|i| {
+                    let metadata = MetadataRef::new(variant_array.metadata(i));
+                    open_variant::json::to_json(
+                        &variant_array.variant(i).unwrap(),
+                        &metadata,
+                        &JsonWriteOptions::default(),
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            top_k("v", 3),
+            vec![
+                r#"{"count":3,"value":"a"}"#,
+                r#"{"count":2,"value":1}"#,
+                r#"{"count":1,"value":true}"#,
+            ]
+        );
+        // With a single counter every value is counted for the one kept,
+        // and each partition adds its own overestimate.
+        assert_eq!(top_k("v", 1), vec![r#"{"count":6,"value":"a"}"#]);
+        assert_eq!(
+            top_k("tags[*]", 5),
+            vec![r#"{"count":2,"value":"x"}"#, r#"{"count":1,"value":"y"}"#]
+        );
+        assert!(top_k("missing", 5).is_empty());
+    }
+
+    #[test]
+    fn test_path_array_agg_distinct() {
+        let path = VariantPath::parse("tags").unwrap();
+        let mut distinct = PathArrayAggDistinct::new(path.clone());
+        distinct
+            .update(&array(vec![
+                Some(r#"{"tags": ["a", "b"]}"#),
+                Some(r#"{"tags": ["b", {"x": 1, "y": [2]}]}"#),
+                Some(r#"{"tags": "a"}"#),
+                None,
+            ]))
+            .unwrap();
+        // Written with another dictionary and field order.
+        let mut other = PathArrayAggDistinct::new(path.clone());
+        other
+            .update(&array(vec![
+                Some(r#"{"z": 0, "tags": [{"y": [2], "x": 1}, "a", null]}"#),
+                Some(r#"{"tags": null}"#),
+            ]))
+            .unwrap();
+        distinct.merge(&other);
+        assert_eq!(distinct.len(), 4);
+
+        let value = distinct.value().unwrap();
+        assert_eq!(value.data_type(), &crate::variant_type());
+        let json = crate::json::variant_to_json(&value).unwrap();
+        assert_eq!(json.value(0), r#"[null,{"x":1,"y":[2]},"a","b"]"#);
+
+        let empty = PathArrayAggDistinct::new(path);
+        assert!(empty.is_empty());
+        let json = crate::json::variant_to_json(&empty.value().unwrap()).unwrap();
+        assert_eq!(json.value(0), "[]");
+    }
+
+    #[test]
+    fn test_space_saving() {
+        let mut summary = SpaceSaving::new(2);
+        for (i, item) in ["a", "a", "a", "b", "c", "c", "a"].iter().enumerate() {
+            summary.insert(item, 1, || i);
+        }
+        // "c" replaced "b" and took over its count.
+        assert_eq!(summary.top(), vec![("a", 4, &0), ("c", 3, &4)]);
+
+        let mut other = SpaceSaving::new(2);
+        for (i, item) in ["b", "b", "d"].iter().enumerate() {
+            other.insert(item, 1, || 10 + i);
+        }
+        summary.merge(&other);
+        // "a" and "c" may have been missed once by the other summary, and
+        // "b" three times by this one.
+        assert_eq!(summary.top(), vec![("a", 5, &0), ("b", 5, &10)]);
+
+        let mut empty = SpaceSaving::<()>::new(0);
+        empty.insert("a", 1, || ());
+        assert!(empty.top().is_empty());
+    }
+}
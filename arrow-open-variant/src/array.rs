@@ -0,0 +1,1100 @@
+//! Typed access to Arrow arrays holding variant data.
+//!
+//! Variant data can be stored in several physical layouts. The values may be
+//! `Binary`, `LargeBinary`, `BinaryView` or dictionary encoded `Binary`, and
+//! the metadata may be plain `Binary`, dictionary encoded, or run-end encoded. [`VariantArrayReader`]
+//! provides a single interface over all of them so kernels only need to be
+//! written once.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+use arrow_array::cast::{as_run_array, AsArray};
+use arrow_array::types::{
+    ArrowDictionaryKeyType, Int16Type, Int32Type, Int64Type, Int8Type, RunEndIndexType, UInt16Type,
+    UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, BinaryViewArray, DictionaryArray, GenericBinaryArray, Int32Array,
+    Int8Array, LargeBinaryArray, OffsetSizeTrait, PrimitiveArray, RunArray, StructArray,
+};
+use arrow_buffer::{ArrowNativeType, Buffer, NullBuffer, OffsetBuffer};
+use arrow_schema::{ArrowError, DataType, Field, Fields};
+use open_variant::metadata::{build_metadata, MetadataRef};
+use open_variant::path::{ResolvedPath, VariantPath};
+use open_variant::values::{Variant, VariantRef};
+
+use crate::layout::{MetadataEncoding, VariantLayout};
+
+/// The Arrow data type of variant arrays, with `Binary` values.
+///
+/// This is the type of the default [`VariantLayout`].
+pub fn variant_type() -> DataType {
+    VariantLayout::default().data_type()
+}
+
+/// The Arrow data type of variant arrays, with `LargeBinary` values.
+///
+/// This is needed once the values of a single array exceed the 2GB that
+/// can be addressed with 32-bit offsets.
+pub fn large_variant_type() -> DataType {
+    let layout = VariantLayout::builder().large_offsets(true).build();
+    layout.expect("default layout is valid").data_type()
+}
+
+/// Build a values array from a buffer of concatenated values.
+///
+/// `offsets` has one more entry than there are rows. The array uses `Binary`
+/// unless the buffer is too large for 32-bit offsets, in which case it uses
+/// `LargeBinary`.
+pub(crate) fn values_array_from_parts(
+    buffer: Vec<u8>,
+    offsets: &[usize],
+    nulls: Option<NullBuffer>,
+) -> ArrayRef {
+    if buffer.len() <= i32::MAX as usize {
+        Arc::new(binary_array_from_parts::<i32>(buffer, offsets, nulls))
+    } else {
+        Arc::new(binary_array_from_parts::<i64>(buffer, offsets, nulls))
+    }
+}
+
+pub(crate) fn binary_array_from_parts<O: OffsetSizeTrait>(
+    buffer: Vec<u8>,
+    offsets: &[usize],
+    nulls: Option<NullBuffer>,
+) -> GenericBinaryArray<O> {
+    let offsets: Vec<O> = offsets.iter().map(|offset| O::usize_as(*offset)).collect();
+    GenericBinaryArray::new(OffsetBuffer::new(offsets.into()), buffer.into(), nulls)
+}
+
+/// Build a `Dictionary(Int32, Binary)` values array, storing identical values
+/// once. `None` is a null row.
+///
+/// # Errors
+///
+/// If there are too many distinct values for `Int32` keys, or too much
+/// distinct value data for 32-bit offsets.
+pub(crate) fn dictionary_values_array<'a>(
+    values: impl Iterator<Item = Option<&'a [u8]>>,
+) -> Result<DictionaryArray<Int32Type>, ArrowError> {
+    let mut distinct = HashMap::new();
+    let mut buffers = Vec::new();
+    let mut keys = Vec::with_capacity(values.size_hint().0);
+    let mut validity = Vec::with_capacity(values.size_hint().0);
+    for value in values {
+        let key = match value {
+            Some(value) => match distinct.get(value) {
+                Some(key) => *key,
+                None => {
+                    let key = i32::try_from(buffers.len())
+                        .map_err(|_| ArrowError::DictionaryKeyOverflowError)?;
+                    distinct.insert(value, key);
+                    buffers.push(value);
+                    key
+                }
+            },
+            None => 0,
+        };
+        keys.push(key);
+        validity.push(value.is_some());
+    }
+    let total_len: usize = buffers.iter().map(|buffer| buffer.len()).sum();
+    if total_len > i32::MAX as usize {
+        return Err(ArrowError::InvalidArgumentError(
+            "Variant values are too large for 32-bit offsets".into(),
+        ));
+    }
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    let keys = Int32Array::new(keys.into(), nulls);
+    DictionaryArray::try_new(keys, Arc::new(BinaryArray::from_iter_values(buffers)))
+}
+
+/// Row-wise access to variant data, independent of the physical layout.
+pub trait VariantArrayReader {
+    /// The number of rows.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_null(&self, i: usize) -> bool;
+
+    /// The metadata buffer for row `i`.
+    fn metadata(&self, i: usize) -> &[u8];
+
+    /// The value buffer for row `i`.
+    ///
+    /// Null rows return an empty slice.
+    fn value(&self, i: usize) -> &[u8];
+
+    /// The variant at row `i`, or `None` if the row is null.
+    fn variant(&self, i: usize) -> Option<VariantRef<'_>> {
+        if self.is_null(i) {
+            return None;
+        }
+        VariantRef::try_new(self.value(i)).ok()
+    }
+
+    /// Whether row `i` is null, either as an Arrow null or as a variant null
+    /// value.
+    ///
+    /// Writers differ in how they represent a top-level null, so predicates
+    /// should use this rather than [`VariantArrayReader::is_null`].
+    /// See [`crate::nulls`].
+    fn is_null_or_variant_null(&self, i: usize) -> bool {
+        self.variant(i).map_or(true, |variant| variant.is_null())
+    }
+}
+
+/// Metadata indices resolved when wrapping arrays, to share between wraps
+/// of the same array with [`VariantArray::try_new_with_cache`].
+///
+/// Wrapping an array whose metadata is dictionary or run-end encoded
+/// resolves the metadata buffer of every row. An engine calling several
+/// kernels on the same column can keep a cache for the batch and wrap the
+/// column with it, so the rows are resolved once. The cache keeps the keys
+/// and run ends buffers it has seen alive, so drop it with the batch.
+#[derive(Debug, Default)]
+pub struct IndexCache {
+    entries: Mutex<Vec<IndexCacheEntry>>,
+}
+
+#[derive(Debug)]
+struct IndexCacheEntry {
+    /// Keeps the buffer alive, so its address can't be reused by another
+    /// array while the entry is cached.
+    buffer: Buffer,
+    data_type: DataType,
+    offset: usize,
+    len: usize,
+    /// The number of dictionary values or runs the indices point into.
+    /// Normalized dictionary keys are clamped to it.
+    num_values: usize,
+    indices: Arc<Vec<usize>>,
+}
+
+impl IndexCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The indices resolved from `buffer`, calling `resolve` if they are not
+    /// cached. Without a cache, this only calls `resolve`.
+    fn get_or_resolve(
+        cache: Option<&Self>,
+        buffer: &Buffer,
+        data_type: &DataType,
+        (offset, len, num_values): (usize, usize, usize),
+        resolve: impl FnOnce() -> Vec<usize>,
+    ) -> Arc<Vec<usize>> {
+        let Some(cache) = cache else {
+            return Arc::new(resolve());
+        };
+        let matches = |entry: &&IndexCacheEntry| {
+            entry.buffer.as_ptr() == buffer.as_ptr()
+                && entry.buffer.len() == buffer.len()
+                && &entry.data_type == data_type
+                && entry.offset == offset
+                && entry.len == len
+                && entry.num_values == num_values
+        };
+        if let Ok(entries) = cache.entries.lock() {
+            if let Some(entry) = entries.iter().find(matches) {
+                return entry.indices.clone();
+            }
+        }
+
+        // Resolve without holding the lock, so other threads aren't blocked.
+        let indices = Arc::new(resolve());
+        if let Ok(mut entries) = cache.entries.lock() {
+            entries.push(IndexCacheEntry {
+                buffer: buffer.clone(),
+                data_type: data_type.clone(),
+                offset,
+                len,
+                num_values,
+                indices: indices.clone(),
+            });
+        }
+        indices
+    }
+}
+
+/// The metadata child, resolved to a binary array of buffers plus the index
+/// of the buffer for each row.
+#[derive(Debug, Clone)]
+struct MetadataColumn {
+    // None if the buffers are stored one per row. Shared with other arrays
+    // wrapping the same metadata, see `IndexCache`.
+    indices: Option<Arc<Vec<usize>>>,
+    buffers: BinaryArray,
+}
+
+impl MetadataColumn {
+    fn try_new(array: &dyn Array, cache: Option<&IndexCache>) -> Result<Self, ArrowError> {
+        match array.data_type() {
+            DataType::Binary => Ok(Self {
+                indices: None,
+                buffers: array.as_binary::<i32>().clone(),
+            }),
+            DataType::Dictionary(key_type, value_type)
+                if key_type.is_dictionary_key_type()
+                    && value_type.as_ref() == &DataType::Binary =>
+            {
+                let dictionary = array.as_any_dictionary();
+                let indices = if dictionary.values().is_empty() {
+                    // normalized_keys() panics on empty values. This can
+                    // only be valid if every row is null.
+                    Arc::new(vec![0; dictionary.len()])
+                } else {
+                    let keys = dictionary.keys();
+                    let keys_data = keys.to_data();
+                    IndexCache::get_or_resolve(
+                        cache,
+                        &keys_data.buffers()[0],
+                        keys.data_type(),
+                        (keys.offset(), keys.len(), dictionary.values().len()),
+                        || dictionary.normalized_keys(),
+                    )
+                };
+                Ok(Self {
+                    indices: Some(indices),
+                    buffers: dictionary.values().as_binary::<i32>().clone(),
+                })
+            }
+            DataType::RunEndEncoded(run_ends, values)
+                if values.data_type() == &DataType::Binary =>
+            {
+                match run_ends.data_type() {
+                    DataType::Int16 => Ok(Self::from_run_array::<Int16Type>(array, cache)),
+                    DataType::Int32 => Ok(Self::from_run_array::<Int32Type>(array, cache)),
+                    DataType::Int64 => Ok(Self::from_run_array::<Int64Type>(array, cache)),
+                    other => Err(ArrowError::InvalidArgumentError(format!(
+                        "Unsupported run end type for variant metadata: {}",
+                        other
+                    ))),
+                }
+            }
+            other => Err(ArrowError::InvalidArgumentError(format!(
+                "Unsupported variant metadata type: {}",
+                other
+            ))),
+        }
+    }
+
+    fn from_run_array<R: RunEndIndexType>(array: &dyn Array, cache: Option<&IndexCache>) -> Self {
+        let array = as_run_array::<R>(array);
+        let run_ends = array.run_ends();
+        let indices = IndexCache::get_or_resolve(
+            cache,
+            run_ends.inner().inner(),
+            array.data_type(),
+            (run_ends.offset(), run_ends.len(), array.values().len()),
+            || {
+                (0..array.len())
+                    .map(|i| array.get_physical_index(i))
+                    .collect()
+            },
+        );
+        Self {
+            indices: Some(indices),
+            buffers: array.values().as_binary::<i32>().clone(),
+        }
+    }
+
+    /// The distinct buffers used by the given rows. Buffers stored one per
+    /// row are not deduplicated.
+    fn distinct_buffers(&self, rows: impl Iterator<Item = usize>) -> Vec<&[u8]> {
+        match &self.indices {
+            Some(indices) => {
+                let mut seen = vec![false; self.buffers.len()];
+                rows.map(|i| indices[i])
+                    .filter(|index| !std::mem::replace(&mut seen[*index], true))
+                    .map(|index| self.buffers.value(index))
+                    .collect()
+            }
+            None => rows.map(|i| self.buffers.value(i)).collect(),
+        }
+    }
+
+    /// A dictionary array of the metadata of the given rows, holding only the
+    /// buffers they use. Buffers stored one per row are deduplicated.
+    fn take_dictionary(&self, rows: &[usize]) -> DictionaryArray<Int32Type> {
+        let mut buffers = Vec::new();
+        let keys = match &self.indices {
+            Some(indices) => {
+                let mut keys = vec![None; self.buffers.len()];
+                rows.iter()
+                    .map(|i| {
+                        *keys[indices[*i]].get_or_insert_with(|| {
+                            buffers.push(self.buffers.value(indices[*i]));
+                            buffers.len() as i32 - 1
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => {
+                let mut distinct = HashMap::new();
+                rows.iter()
+                    .map(|i| {
+                        let buffer = self.buffers.value(*i);
+                        *distinct.entry(buffer).or_insert_with(|| {
+                            buffers.push(buffer);
+                            buffers.len() as i32 - 1
+                        })
+                    })
+                    .collect()
+            }
+        };
+        DictionaryArray::new(
+            Int32Array::from(keys),
+            Arc::new(BinaryArray::from_iter_values(buffers)),
+        )
+    }
+
+    fn value(&self, i: usize) -> &[u8] {
+        match &self.indices {
+            Some(indices) => self.buffers.value(indices[i]),
+            None => self.buffers.value(i),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.indices {
+            Some(indices) => indices.len(),
+            None => self.buffers.len(),
+        }
+    }
+
+    /// The buffer index of each row and the buffers. Buffers stored one per
+    /// row are deduplicated.
+    fn dictionary_parts(&self) -> (Cow<'_, [usize]>, BinaryArray) {
+        match &self.indices {
+            // Only valid if every row is null, see `MetadataColumn::try_new`.
+            Some(indices) if self.buffers.is_empty() && !indices.is_empty() => (
+                Cow::Borrowed(indices.as_slice()),
+                BinaryArray::from_iter_values([b"".as_slice()]),
+            ),
+            Some(indices) => (Cow::Borrowed(indices.as_slice()), self.buffers.clone()),
+            None => {
+                let mut distinct = HashMap::new();
+                let indices = self
+                    .buffers
+                    .iter()
+                    .map(|buffer| {
+                        let buffer = buffer.unwrap_or_default();
+                        let next = distinct.len();
+                        *distinct.entry(buffer).or_insert(next)
+                    })
+                    .collect();
+                let mut buffers = vec![b"".as_slice(); distinct.len()];
+                for (buffer, index) in distinct {
+                    buffers[index] = buffer;
+                }
+                (Cow::Owned(indices), BinaryArray::from_iter_values(buffers))
+            }
+        }
+    }
+
+    fn to_layout(&self, layout: &VariantLayout) -> Result<ArrayRef, ArrowError> {
+        let key_type = layout.metadata_key_type();
+        match layout.metadata_encoding() {
+            MetadataEncoding::Plain => Ok(Arc::new(BinaryArray::from_iter_values(
+                (0..self.len()).map(|i| self.value(i)),
+            ))),
+            MetadataEncoding::Dictionary => match key_type {
+                DataType::Int8 => self.to_dictionary::<Int8Type>(),
+                DataType::Int16 => self.to_dictionary::<Int16Type>(),
+                DataType::Int32 => self.to_dictionary::<Int32Type>(),
+                DataType::Int64 => self.to_dictionary::<Int64Type>(),
+                DataType::UInt8 => self.to_dictionary::<UInt8Type>(),
+                DataType::UInt16 => self.to_dictionary::<UInt16Type>(),
+                DataType::UInt32 => self.to_dictionary::<UInt32Type>(),
+                DataType::UInt64 => self.to_dictionary::<UInt64Type>(),
+                _ => unreachable!("VariantLayout validates the key type"),
+            },
+            MetadataEncoding::RunEnd => match key_type {
+                DataType::Int16 => self.to_run_array::<Int16Type>(),
+                DataType::Int32 => self.to_run_array::<Int32Type>(),
+                DataType::Int64 => self.to_run_array::<Int64Type>(),
+                _ => unreachable!("VariantLayout validates the run end type"),
+            },
+        }
+    }
+
+    fn to_dictionary<K: ArrowDictionaryKeyType>(&self) -> Result<ArrayRef, ArrowError> {
+        let (indices, buffers) = self.dictionary_parts();
+        let keys = indices
+            .iter()
+            .map(|index| K::Native::from_usize(*index))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ArrowError::DictionaryKeyOverflowError)?;
+        let keys = PrimitiveArray::<K>::from_iter_values(keys);
+        Ok(Arc::new(DictionaryArray::try_new(keys, Arc::new(buffers))?))
+    }
+
+    fn to_run_array<R: RunEndIndexType>(&self) -> Result<ArrayRef, ArrowError> {
+        let (indices, buffers) = self.dictionary_parts();
+        let mut run_ends = Vec::new();
+        let mut run_buffers = Vec::new();
+        for (i, index) in indices.iter().enumerate() {
+            if indices.get(i + 1) != Some(index) {
+                let run_end =
+                    R::Native::from_usize(i + 1).ok_or(ArrowError::RunEndIndexOverflowError)?;
+                run_ends.push(run_end);
+                run_buffers.push(buffers.value(*index));
+            }
+        }
+        let run_ends = PrimitiveArray::<R>::from_iter_values(run_ends);
+        let values = BinaryArray::from_iter_values(run_buffers);
+        Ok(Arc::new(RunArray::try_new(&run_ends, &values)?))
+    }
+}
+
+/// The values child.
+#[derive(Debug, Clone)]
+enum ValuesColumn {
+    Binary(BinaryArray),
+    LargeBinary(LargeBinaryArray),
+    BinaryView(BinaryViewArray),
+    /// The dictionary, and its values.
+    Dictionary(DictionaryArray<Int32Type>, BinaryArray),
+}
+
+impl ValuesColumn {
+    fn try_new(array: &dyn Array) -> Result<Self, ArrowError> {
+        match array.data_type() {
+            DataType::Binary => Ok(Self::Binary(array.as_binary::<i32>().clone())),
+            DataType::LargeBinary => Ok(Self::LargeBinary(array.as_binary::<i64>().clone())),
+            DataType::BinaryView => Ok(Self::BinaryView(array.as_binary_view().clone())),
+            DataType::Dictionary(key_type, value_type)
+                if key_type.as_ref() == &DataType::Int32
+                    && value_type.as_ref() == &DataType::Binary =>
+            {
+                let dictionary = array.as_dictionary::<Int32Type>();
+                let values = dictionary.values().as_binary::<i32>().clone();
+                Ok(Self::Dictionary(dictionary.clone(), values))
+            }
+            other => Err(ArrowError::InvalidArgumentError(format!(
+                "Unsupported variant values type: {}",
+                other
+            ))),
+        }
+    }
+
+    fn value(&self, i: usize) -> &[u8] {
+        match self {
+            Self::Binary(array) => array.value(i),
+            Self::LargeBinary(array) => array.value(i),
+            Self::BinaryView(array) => array.value(i),
+            // The keys of null rows may be out of bounds.
+            Self::Dictionary(array, _) if array.is_null(i) => &[],
+            Self::Dictionary(array, values) => values.value(array.keys().value(i) as usize),
+        }
+    }
+
+    fn array(&self) -> &dyn Array {
+        match self {
+            Self::Binary(array) => array,
+            Self::LargeBinary(array) => array,
+            Self::BinaryView(array) => array,
+            Self::Dictionary(array, _) => array,
+        }
+    }
+
+    fn to_type(&self, data_type: &DataType) -> Result<ArrayRef, ArrowError> {
+        let array = self.array();
+        if array.data_type() == data_type {
+            return Ok(array.slice(0, array.len()));
+        }
+        let values = (0..array.len()).map(|i| (!array.is_null(i)).then(|| self.value(i)));
+        match data_type {
+            DataType::Binary => {
+                let total_len: usize = (0..array.len()).map(|i| self.value(i).len()).sum();
+                if total_len > i32::MAX as usize {
+                    return Err(ArrowError::InvalidArgumentError(
+                        "Variant values are too large for 32-bit offsets".into(),
+                    ));
+                }
+                Ok(Arc::new(BinaryArray::from_iter(values)))
+            }
+            DataType::LargeBinary => Ok(Arc::new(LargeBinaryArray::from_iter(values))),
+            DataType::BinaryView => Ok(Arc::new(BinaryViewArray::from_iter(values))),
+            DataType::Dictionary(key_type, value_type)
+                if key_type.as_ref() == &DataType::Int32
+                    && value_type.as_ref() == &DataType::Binary =>
+            {
+                Ok(Arc::new(dictionary_values_array(values)?))
+            }
+            other => Err(ArrowError::InvalidArgumentError(format!(
+                "Unsupported variant values type: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A view over an Arrow array holding variant data.
+///
+/// Variant data is stored as a struct with two children:
+///
+/// * `metadata`: the metadata buffers, typically dictionary or run-end encoded
+///   since they are shared by many rows.
+/// * `values`: the value buffers.
+///
+/// A row is null if it is null in the struct or in the `values` child; see
+/// [`crate::nulls`]. Use the [`VariantArrayReader`] trait to access the rows.
+#[derive(Debug, Clone)]
+pub struct VariantArray {
+    inner: StructArray,
+    metadata: MetadataColumn,
+    values: ValuesColumn,
+    /// The union of the struct and `values` null buffers.
+    nulls: Option<NullBuffer>,
+}
+
+impl VariantArray {
+    /// Wrap an array, validating it has the variant layout.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a struct with `metadata` and `values` children of
+    /// supported types.
+    pub fn try_new(array: &dyn Array) -> Result<Self, ArrowError> {
+        Self::try_new_impl(array, None)
+    }
+
+    /// Wrap an array like [`VariantArray::try_new`], reusing the metadata
+    /// indices already resolved for the same array in `cache`.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a struct with `metadata` and `values` children of
+    /// supported types.
+    pub fn try_new_with_cache(array: &dyn Array, cache: &IndexCache) -> Result<Self, ArrowError> {
+        Self::try_new_impl(array, Some(cache))
+    }
+
+    fn try_new_impl(array: &dyn Array, cache: Option<&IndexCache>) -> Result<Self, ArrowError> {
+        let inner = array.as_struct_opt().ok_or_else(|| {
+            ArrowError::InvalidArgumentError(format!(
+                "Expected a struct array for variant data, got {}",
+                array.data_type()
+            ))
+        })?;
+        let metadata = inner.column_by_name("metadata").ok_or_else(|| {
+            ArrowError::InvalidArgumentError("Variant array is missing 'metadata' field".into())
+        })?;
+        let values = inner.column_by_name("values").ok_or_else(|| {
+            ArrowError::InvalidArgumentError("Variant array is missing 'values' field".into())
+        })?;
+
+        Ok(Self {
+            inner: inner.clone(),
+            metadata: MetadataColumn::try_new(metadata, cache)?,
+            values: ValuesColumn::try_new(values)?,
+            nulls: NullBuffer::union(inner.nulls(), values.nulls()),
+        })
+    }
+
+    /// The null rows, which are null in either the struct or the `values`
+    /// child.
+    pub fn nulls(&self) -> Option<&NullBuffer> {
+        self.nulls.as_ref()
+    }
+
+    /// Resolve `path` against the metadata of row `i`, looking up keys
+    /// through the [`key_cache`](crate::key_cache) with the `key-cache`
+    /// feature, unless the metadata is plain.
+    pub fn resolve_path(&self, i: usize, path: &VariantPath) -> ResolvedPath {
+        #[cfg(feature = "key-cache")]
+        {
+            // Plain metadata has a buffer per row, which no other row hits.
+            if self.metadata.indices.is_some() {
+                return crate::key_cache::resolve_path(
+                    self.metadata.buffers.values(),
+                    self.metadata(i),
+                    path,
+                );
+            }
+        }
+        ResolvedPath::new(path, &MetadataRef::new(self.metadata(i)))
+    }
+
+    /// A [`PathResolver`] for `path` against the rows of this array.
+    pub fn path_resolver<'a>(&'a self, path: &'a VariantPath) -> PathResolver<'a> {
+        PathResolver {
+            array: self,
+            path,
+            cache: MetadataCache::new(),
+        }
+    }
+
+    /// The `(start, end)` ranges of consecutive non-null rows, in order.
+    ///
+    /// The null buffer is scanned a word at a time, so kernels can skip runs
+    /// of null rows in bulk instead of checking each row. This matters for
+    /// optional payloads, where most rows can be null.
+    pub fn valid_slices(&self) -> Box<dyn Iterator<Item = (usize, usize)> + '_> {
+        match &self.nulls {
+            Some(nulls) => Box::new(nulls.valid_slices()),
+            None => {
+                Box::new(std::iter::once((0, self.inner.len())).filter(|(start, end)| start < end))
+            }
+        }
+    }
+
+    /// Whether the struct and `values` null buffers agree, as in arrays
+    /// written by this crate. See [`normalize_struct_nulls`](crate::nulls::normalize_struct_nulls).
+    pub(crate) fn has_aligned_nulls(&self) -> bool {
+        self.inner.nulls() == self.values.array().nulls()
+    }
+
+    /// The total number of bytes of memory occupied by the buffers of the array.
+    ///
+    /// See [`Array::get_buffer_memory_size`].
+    pub fn get_buffer_memory_size(&self) -> usize {
+        self.inner.get_buffer_memory_size()
+    }
+
+    /// The number of bytes the values occupy in their encoded form.
+    ///
+    /// Unlike [`VariantArray::get_buffer_memory_size`], this excludes the
+    /// metadata, offsets, validity, and any unused capacity, so it can be
+    /// compared against the size of the JSON text the data was parsed from.
+    pub fn values_encoded_len(&self) -> usize {
+        (0..self.len())
+            .filter_map(|i| self.variant(i))
+            .map(|variant| variant.encoded_len())
+            .sum()
+    }
+
+    /// Build a variant array with the same metadata and new values.
+    ///
+    /// `values` must have one row per row of this array, each using the
+    /// metadata of the corresponding row.
+    pub(crate) fn with_values(&self, values: ArrayRef, nulls: Option<NullBuffer>) -> ArrayRef {
+        let mut fields = Vec::with_capacity(self.inner.num_columns());
+        let mut columns = Vec::with_capacity(self.inner.num_columns());
+        for (field, column) in self.inner.fields().iter().zip(self.inner.columns()) {
+            if field.name() == "values" {
+                fields.push(Arc::new(Field::new(
+                    "values",
+                    values.data_type().clone(),
+                    true,
+                )));
+                columns.push(values.clone());
+            } else {
+                fields.push(field.clone());
+                columns.push(column.clone());
+            }
+        }
+        Arc::new(StructArray::new(Fields::from(fields), columns, nulls))
+    }
+
+    /// Build a variant array of values taken from the rows of this array.
+    ///
+    /// `values` holds one value per entry of `rows`, which gives the row each
+    /// value came from. The metadata is a `Dictionary(Int32, Binary)` array
+    /// holding the distinct metadata buffers of those rows.
+    pub(crate) fn with_row_values(&self, rows: &[usize], values: ArrayRef) -> ArrayRef {
+        let metadata = Arc::new(self.metadata.take_dictionary(rows)) as ArrayRef;
+        let fields = Fields::from(vec![
+            Field::new("metadata", metadata.data_type().clone(), false),
+            Field::new("values", values.data_type().clone(), true),
+        ]);
+        Arc::new(StructArray::new(fields, vec![metadata, values], None))
+    }
+
+    /// Convert the array to the given layout.
+    ///
+    /// The array is returned as is if it already has the layout. Otherwise
+    /// the metadata buffers are shared where the encodings allow it, and the
+    /// values are copied.
+    ///
+    /// # Errors
+    ///
+    /// If the array has too many rows or distinct metadata buffers for the
+    /// layout's key type, or too much value data for 32-bit offsets.
+    pub fn to_layout(&self, layout: &VariantLayout) -> Result<ArrayRef, ArrowError> {
+        if self.inner.data_type() == &layout.data_type() {
+            return Ok(Arc::new(self.inner.clone()));
+        }
+        let metadata = self.metadata.to_layout(layout)?;
+        let values = self.values.to_type(&layout.values_type())?;
+        Ok(Arc::new(StructArray::try_new(
+            layout.fields(),
+            vec![metadata, values],
+            self.nulls().cloned(),
+        )?))
+    }
+
+    /// The metadata buffers used by the non-null rows.
+    ///
+    /// Dictionary and run-end encoded metadata is deduplicated, so this is
+    /// usually much shorter than the array. Plain binary metadata has one
+    /// buffer per row.
+    pub fn metadata_buffers(&self) -> Vec<&[u8]> {
+        self.metadata
+            .distinct_buffers((0..self.len()).filter(|i| !self.is_null(*i)))
+    }
+
+    pub fn inner(&self) -> &StructArray {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> StructArray {
+        self.inner
+    }
+}
+
+impl VariantArrayReader for VariantArray {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn is_null(&self, i: usize) -> bool {
+        self.nulls.as_ref().is_some_and(|nulls| nulls.is_null(i))
+    }
+
+    fn metadata(&self, i: usize) -> &[u8] {
+        self.metadata.value(i)
+    }
+
+    fn value(&self, i: usize) -> &[u8] {
+        if self.is_null(i) {
+            return &[];
+        }
+        self.values.value(i)
+    }
+}
+
+/// A value computed from a metadata buffer, kept while consecutive rows
+/// share that buffer.
+///
+/// Rows usually share metadata, so kernels use this to resolve keys once per
+/// distinct buffer rather than once per row. Plain metadata has a buffer per
+/// row, so the contents are compared too.
+#[derive(Debug)]
+pub struct MetadataCache<'a, T> {
+    cached: Option<(&'a [u8], T)>,
+}
+
+impl<T> Default for MetadataCache<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> MetadataCache<'a, T> {
+    pub fn new() -> Self {
+        Self { cached: None }
+    }
+
+    /// The value for `metadata`, computed with `f` unless the last call was
+    /// for the same metadata.
+    pub fn get_or_insert_with(&mut self, metadata: &'a [u8], f: impl FnOnce() -> T) -> &mut T {
+        let cached = matches!(
+            &self.cached,
+            Some((bytes, _)) if std::ptr::eq(*bytes, metadata) || *bytes == metadata
+        );
+        if !cached {
+            self.cached = Some((metadata, f()));
+        }
+        &mut self.cached.as_mut().unwrap().1
+    }
+}
+
+/// A path resolved against the metadata of each row of a [`VariantArray`],
+/// once per distinct metadata buffer. See [`VariantArray::path_resolver`].
+#[derive(Debug)]
+pub struct PathResolver<'a> {
+    array: &'a VariantArray,
+    path: &'a VariantPath,
+    cache: MetadataCache<'a, ResolvedPath>,
+}
+
+impl PathResolver<'_> {
+    /// The path resolved against the metadata of row `i`.
+    pub fn resolve(&mut self, i: usize) -> &mut ResolvedPath {
+        let (array, path) = (self.array, self.path);
+        self.cache
+            .get_or_insert_with(array.metadata(i), || array.resolve_path(i, path))
+    }
+}
+
+impl<V: Into<Variant>> FromIterator<Option<V>> for VariantArray {
+    /// Build a variant array from owned values, where `None` is a null row.
+    ///
+    /// The rows share a single metadata dictionary with the keys of all the
+    /// values, in the default [`VariantLayout`] (with large offsets if the
+    /// values need them).
+    fn from_iter<I: IntoIterator<Item = Option<V>>>(iter: I) -> Self {
+        let values = iter
+            .into_iter()
+            .map(|value| value.map(Into::into))
+            .collect::<Vec<Option<Variant>>>();
+        let mut keys = BTreeSet::new();
+        for value in values.iter().flatten() {
+            value.collect_keys(&mut keys);
+        }
+        let metadata = build_metadata(keys.into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        let mut buffer = Vec::new();
+        let mut offsets = Vec::with_capacity(values.len() + 1);
+        offsets.push(0);
+        for value in &values {
+            if let Some(value) = value {
+                value
+                    .write(&mut buffer, &metadata_ref)
+                    .expect("every key is in the metadata");
+            }
+            offsets.push(buffer.len());
+        }
+        let nulls = NullBuffer::from(values.iter().map(Option::is_some).collect::<Vec<_>>());
+        let nulls = (nulls.null_count() > 0).then_some(nulls);
+        let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+
+        let layout = VariantLayout::builder()
+            .large_offsets(values.data_type() == &DataType::LargeBinary)
+            .build()
+            .expect("layout is valid");
+        let metadata = DictionaryArray::new(
+            Int8Array::from(vec![0; values.len()]),
+            Arc::new(BinaryArray::from_iter_values([metadata])),
+        );
+        let array = StructArray::new(
+            layout.fields(),
+            vec![Arc::new(metadata) as ArrayRef, values],
+            nulls,
+        );
+        Self::try_new(&array).expect("array has the variant layout")
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::{ArrayRef, Int32Array, RunArray, StringArray};
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    #[test]
+    fn test_variant_array_from_iter() {
+        let variant_array = VariantArray::from_iter([
+            Some(Variant::from_iter([("a", 1)])),
+            None,
+            Some(Variant::from(vec!["x", "y"])),
+            Some(Variant::from_iter([(
+                "b",
+                Variant::from_iter([("a", true)]),
+            )])),
+        ]);
+        assert_eq!(variant_array.len(), 4);
+        assert_eq!(
+            variant_array.clone().into_inner().data_type(),
+            &crate::variant_type()
+        );
+        assert!(variant_array.is_null(1));
+        assert_eq!(variant_array.metadata(0), variant_array.metadata(3));
+        let metadata = MetadataRef::new(variant_array.metadata(0));
+        assert_eq!(metadata.dictionary_len(), 2);
+
+        let row = variant_array.variant(3).unwrap();
+        let path = open_variant::path::VariantPath::parse("b.a").unwrap();
+        assert!(row.get_path(&path, &metadata).unwrap().get_bool());
+        assert_eq!(
+            variant_array.variant(2).unwrap().get_array().unwrap().len(),
+            2
+        );
+
+        let ints = VariantArray::from_iter([Some(1i64), Some(2), None]);
+        assert_eq!(ints.variant(1).unwrap().get_i64(), 2);
+    }
+
+    #[test]
+    fn test_variant_array() {
+        let jsons = StringArray::from(vec![Some(r#"{"a": 1}"#), None, Some("\"x\"")]);
+        let array = variant_from_json(&jsons).unwrap();
+        let variant_array = VariantArray::try_new(&array).unwrap();
+
+        assert_eq!(variant_array.len(), 3);
+        assert!(variant_array.variant(1).is_none());
+        assert_eq!(variant_array.variant(2).unwrap().get_string(), "x");
+        assert_eq!(variant_array.metadata(0), variant_array.metadata(2));
+
+        assert_eq!(
+            variant_array.get_buffer_memory_size(),
+            array.get_buffer_memory_size()
+        );
+        // Object: header, size, field id, 2 offsets, i64 value.
+        // String: header, 4 byte length, data.
+        assert_eq!(
+            variant_array.values_encoded_len(),
+            (1 + 1 + 1 + 2 + 9) + (1 + 4 + 1)
+        );
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        let array = StringArray::from(vec!["x"]);
+        let err = VariantArray::try_new(&array).unwrap_err();
+        assert!(err.to_string().contains("Expected a struct array"));
+    }
+
+    #[test]
+    fn test_layouts() {
+        let jsons = StringArray::from(vec![r#"{"a": 1}"#, r#"{"a": 2}"#]);
+        let array = variant_from_json(&jsons).unwrap();
+        let expected = VariantArray::try_new(&array).unwrap();
+        let metadata = expected.metadata(0);
+        let values = array.as_struct().column(1).as_binary::<i32>();
+
+        let metadata_layouts = [
+            Arc::new(BinaryArray::from_iter_values([metadata, metadata])) as ArrayRef,
+            Arc::new(
+                RunArray::<Int32Type>::try_new(
+                    &Int32Array::from(vec![2]),
+                    &BinaryArray::from_iter_values([metadata]),
+                )
+                .unwrap(),
+            ) as ArrayRef,
+        ];
+        let values_layouts = [
+            Arc::new(LargeBinaryArray::from_iter(values.iter())) as ArrayRef,
+            Arc::new(BinaryViewArray::from_iter(values.iter())) as ArrayRef,
+        ];
+
+        for metadata_array in &metadata_layouts {
+            for values_array in &values_layouts {
+                let struct_array = StructArray::try_from(vec![
+                    ("metadata", metadata_array.clone()),
+                    ("values", values_array.clone()),
+                ])
+                .unwrap();
+                let variant_array = VariantArray::try_new(&struct_array).unwrap();
+                assert_eq!(variant_array.len(), 2);
+                for i in 0..2 {
+                    assert_eq!(variant_array.metadata(i), expected.metadata(i));
+                    assert_eq!(variant_array.value(i), expected.value(i));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_indices() {
+        let metadata = build_metadata(["a"].into_iter());
+        let keys = Int8Array::from(vec![0, 0, 0]);
+        let buffers = BinaryArray::from_iter_values([metadata.as_slice()]);
+        let dictionary = DictionaryArray::new(keys, Arc::new(buffers));
+        let values = BinaryArray::from_iter_values([[0u8], [0u8], [0u8]]);
+        let struct_array = StructArray::try_from(vec![
+            ("metadata", Arc::new(dictionary) as ArrayRef),
+            ("values", Arc::new(values) as ArrayRef),
+        ])
+        .unwrap();
+
+        // Wrapping the same array again with a cache reuses the resolved
+        // indices.
+        let cache = IndexCache::new();
+        let first = VariantArray::try_new_with_cache(&struct_array, &cache).unwrap();
+        let second = VariantArray::try_new_with_cache(&struct_array, &cache).unwrap();
+        let indices = |array: &VariantArray| array.metadata.indices.clone().unwrap();
+        assert!(Arc::ptr_eq(&indices(&first), &indices(&second)));
+        let uncached = VariantArray::try_new(&struct_array).unwrap();
+        assert!(!Arc::ptr_eq(&indices(&first), &indices(&uncached)));
+
+        // A slice has other indices.
+        let sliced = VariantArray::try_new_with_cache(&struct_array.slice(1, 2), &cache).unwrap();
+        assert_eq!(indices(&sliced).len(), 2);
+        assert_eq!(sliced.metadata(1), metadata.as_slice());
+    }
+
+    #[test]
+    fn test_to_layout() {
+        use crate::layout::ValuesEncoding;
+
+        let jsons = StringArray::from(vec![Some(r#"{"a": 1}"#), None, Some("[true]")]);
+        let array = variant_from_json(&jsons).unwrap();
+        let expected = VariantArray::try_new(&array).unwrap();
+
+        for values_encoding in [
+            ValuesEncoding::Offsets,
+            ValuesEncoding::View,
+            ValuesEncoding::Dictionary,
+        ] {
+            for (metadata_encoding, key_type) in [
+                (MetadataEncoding::Plain, DataType::Int8),
+                (MetadataEncoding::Dictionary, DataType::UInt32),
+                (MetadataEncoding::RunEnd, DataType::Int16),
+            ] {
+                let layout = VariantLayout::builder()
+                    .values_encoding(values_encoding)
+                    .metadata_encoding(metadata_encoding)
+                    .metadata_key_type(key_type)
+                    .build()
+                    .unwrap();
+                let converted = expected.to_layout(&layout).unwrap();
+                assert_eq!(converted.data_type(), &layout.data_type());
+
+                let variant_array = VariantArray::try_new(&converted).unwrap();
+                assert_eq!(variant_array.len(), 3);
+                for i in 0..3 {
+                    assert_eq!(variant_array.is_null(i), expected.is_null(i));
+                    assert_eq!(variant_array.metadata(i), expected.metadata(i));
+                    if !expected.is_null(i) {
+                        assert_eq!(variant_array.value(i), expected.value(i));
+                    }
+                }
+            }
+        }
+
+        // Plain metadata is deduplicated when dictionary encoded.
+        let plain = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let plain = expected.to_layout(&plain).unwrap();
+        let dictionary = VariantArray::try_new(&plain)
+            .unwrap()
+            .to_layout(&VariantLayout::default())
+            .unwrap();
+        let metadata = dictionary.as_struct().column(0).as_any_dictionary();
+        assert_eq!(metadata.values().len(), 1);
+    }
+
+    #[test]
+    fn test_metadata_cache() {
+        // Equal buffers are cached together, even at different addresses.
+        let first = build_metadata(["a"].into_iter());
+        let second = first.clone();
+        let other = build_metadata(["b"].into_iter());
+        let mut cache = MetadataCache::new();
+        let mut misses = 0;
+        for metadata in [&first, &second, &other, &first] {
+            cache.get_or_insert_with(metadata, || misses += 1);
+        }
+        assert_eq!(misses, 3);
+
+        let variant_array = VariantArray::from_iter([
+            Some(Variant::from_iter([("a", "x")])),
+            None,
+            Some(Variant::from_iter([("b", "y"), ("a", "z")])),
+        ]);
+        let path = VariantPath::parse("a").unwrap();
+        let mut resolver = variant_array.path_resolver(&path);
+        let values = [0, 2].map(|i| {
+            let variant = variant_array.variant(i).unwrap();
+            let value = resolver.resolve(i).get(&variant).unwrap();
+            value.get_str().unwrap().to_string()
+        });
+        assert_eq!(values, ["x", "z"]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Canonical encoding of variant arrays, for byte-identical output.
+//!
+//! See [`open_variant::canonical`] for what the canonical form of a value is.
+
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, BinaryArray, StructArray};
+use arrow_schema::{ArrowError, DataType};
+use open_variant::metadata::{build_metadata, MetadataRef};
+
+use crate::array::{values_array_from_parts, VariantArray, VariantArrayReader};
+use crate::layout::{MetadataEncoding, VariantLayout};
+
+/// Encode every row of a variant array in canonical form.
+///
+/// Each row gets its own sorted metadata with only the keys it uses, so the
+/// metadata and value of a row only depend on the logical value, and not on
+/// the other rows of the batch or on how the array was written. This is for
+/// applications that content-address or deduplicate payloads.
+///
+/// Rows rarely share their metadata afterwards, so the output has plain
+/// metadata, with large offsets if needed. Null rows have empty metadata.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or a value is invalid.
+pub fn canonicalize_variant(array: &dyn Array) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let empty = build_metadata(std::iter::empty());
+    let mut metadata = Vec::with_capacity(variant_array.len());
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    offsets.push(0);
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows have empty values.
+        offsets.resize(start + 1, buffer.len());
+        metadata.resize(start, empty.clone());
+        for i in start..end {
+            match variant_array.variant(i) {
+                Some(variant) => {
+                    let row_metadata = MetadataRef::new(variant_array.metadata(i));
+                    let (row_metadata, value) = variant
+                        .canonicalize(&row_metadata)
+                        .map_err(ArrowError::InvalidArgumentError)?;
+                    buffer.extend_from_slice(&value);
+                    metadata.push(row_metadata);
+                }
+                None => metadata.push(empty.clone()),
+            }
+            offsets.push(buffer.len());
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+    metadata.resize(variant_array.len(), empty);
+
+    let nulls = variant_array.nulls().cloned();
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    let layout = VariantLayout::builder()
+        .metadata_encoding(MetadataEncoding::Plain)
+        .large_offsets(values.data_type() == &DataType::LargeBinary)
+        .build()?;
+    Ok(Arc::new(StructArray::try_new(
+        layout.fields(),
+        vec![
+            Arc::new(BinaryArray::from_iter_values(metadata)) as ArrayRef,
+            values,
+        ],
+        nulls,
+    )?))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::{
+        variant_from_json, variant_from_json_parallel, variant_from_json_with_options,
+        JsonParseOptions,
+    };
+
+    #[test]
+    fn test_canonicalize_variant() {
+        let a = variant_from_json(&StringArray::from(vec![
+            Some(r#"{"b": [1, {"a": 2}], "c": 3}"#),
+            None,
+        ]))
+        .unwrap();
+        let b = variant_from_json(&StringArray::from(vec![
+            Some(r#"{"z": 0, "x": {"y": 1}}"#),
+            Some(r#"{"c": 3, "b": [1, {"a": 2}]}"#),
+        ]))
+        .unwrap();
+        // The batches have different dictionaries, so the same document is
+        // encoded differently.
+        let (a_array, b_array) = (
+            VariantArray::try_new(&a).unwrap(),
+            VariantArray::try_new(&b).unwrap(),
+        );
+        assert_ne!(a_array.metadata(0), b_array.metadata(1));
+
+        let a = canonicalize_variant(&a).unwrap();
+        let b = canonicalize_variant(&b).unwrap();
+        let layout = VariantLayout::try_from_data_type(a.data_type()).unwrap();
+        assert_eq!(layout.metadata_encoding(), MetadataEncoding::Plain);
+        let (a_array, b_array) = (
+            VariantArray::try_new(&a).unwrap(),
+            VariantArray::try_new(&b).unwrap(),
+        );
+        assert_eq!(a_array.metadata(0), b_array.metadata(1));
+        assert_eq!(a_array.value(0), b_array.value(1));
+        assert_eq!(MetadataRef::new(b_array.metadata(0)).dictionary_len(), 3);
+        assert!(a_array.is_null(1));
+    }
+
+    #[test]
+    fn test_deterministic_json() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"id": 1, "tags": ["a"]}"#),
+            Some(r#"{"other": true}"#),
+            None,
+            Some(r#"{"id": 1, "tags": ["a"]}"#),
+        ]);
+        let options = JsonParseOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        let serial = variant_from_json_with_options(&jsons, &options).unwrap();
+        let parallel = variant_from_json_parallel(&jsons, &options, 2).unwrap();
+        assert_eq!(serial.as_ref(), parallel.as_ref());
+
+        let single = variant_from_json_with_options(&jsons.slice(0, 1), &options).unwrap();
+        let serial = VariantArray::try_new(&serial).unwrap();
+        let single = VariantArray::try_new(&single).unwrap();
+        for i in [0, 3] {
+            assert_eq!(serial.metadata(i), single.metadata(0));
+            assert_eq!(serial.value(i), single.value(0));
+        }
+    }
+}
@@ -0,0 +1,1331 @@
+//! Convert Arrow arrays into variant arrays.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::ops::Range;
+use std::sync::Arc;
+
+use arrow_array::builder::IntervalMonthDayNanoBuilder;
+use arrow_array::cast::{as_union_array, AsArray};
+use arrow_array::types::{
+    Date32Type, Decimal128Type, DurationMicrosecondType, DurationMillisecondType,
+    DurationNanosecondType, DurationSecondType, Float32Type, Float64Type, Int16Type, Int32Type,
+    Int64Type, Int8Type, IntervalDayTimeType, IntervalMonthDayNano, IntervalMonthDayNanoType,
+    IntervalYearMonthType, TimestampMicrosecondType, TimestampMillisecondType,
+    TimestampNanosecondType, TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, DictionaryArray, Int8Array, IntervalMonthDayNanoArray,
+    RecordBatch, StructArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, IntervalUnit, TimeUnit};
+use open_variant::coerce::CoerceOptions;
+use open_variant::metadata::{build_metadata, MetadataRef};
+use open_variant::validate::validate_metadata;
+use open_variant::values::write::{self, ArrayBuilder, ObjectBuilder};
+use open_variant::values::{BasicType, VariantRef};
+
+use crate::array::{values_array_from_parts, MetadataCache, VariantArray, VariantArrayReader};
+use crate::layout::{VariantLayout, EXTENSION_NAME};
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// The keys of the object encoding of intervals, see
+/// [`IntervalEncoding::Object`].
+const INTERVAL_KEYS: [&str; 3] = ["days", "months", "nanos"];
+
+/// The field metadata key of Arrow extension type names.
+const EXTENSION_NAME_KEY: &str = "ARROW:extension:name";
+
+/// The Arrow extension type name of UUIDs, stored as `FixedSizeBinary(16)`.
+pub(crate) const UUID_EXTENSION_NAME: &str = "arrow.uuid";
+
+/// Options for [`cast_to_variant_with_options`].
+///
+/// `Time32` and `Time64` have no time primitive to be written as, so the
+/// cast fails for them whatever the options.
+#[derive(Debug, Clone, Default)]
+pub struct CastOptions {
+    /// How `Interval` and `Duration` values are written.
+    pub interval_encoding: IntervalEncoding,
+    /// The layout of the output. If `None`, the default layout is used,
+    /// promoted to large offsets if needed.
+    pub layout: Option<VariantLayout>,
+    /// Leave null struct fields out of objects, rather than writing them as
+    /// variant nulls. This keeps wide, sparse tables small.
+    pub omit_null_fields: bool,
+    /// Write every `FixedSizeBinary(16)` value as a UUID. Otherwise only
+    /// fields with the `arrow.uuid` extension type are UUIDs, and other
+    /// fixed-size binary values are written as binary.
+    pub fixed_size_binary_as_uuid: bool,
+    /// Fail the cast for `Timestamp(Nanosecond, Some(_))` values that aren't
+    /// a whole number of microseconds. There is no nanosecond timestamp with
+    /// timezone, so these values are written in microseconds, and otherwise
+    /// they are rounded down silently.
+    pub reject_timestamp_precision_loss: bool,
+}
+
+/// How Arrow `Interval` and `Duration` values, which have no variant
+/// primitive, are written.
+///
+/// Both are treated as a number of months, days and nanoseconds, like
+/// `Interval(MonthDayNano)`. [`variant_to_interval`] reads either encoding
+/// back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntervalEncoding {
+    /// An ISO-8601 duration string, such as `P1M2DT3.5S`. Components are not
+    /// normalized, so months are not converted to years and seconds are not
+    /// converted to hours or minutes.
+    #[default]
+    Iso8601,
+    /// An object with `months`, `days` and `nanos` integer fields.
+    Object,
+    /// Fail the cast.
+    Error,
+}
+
+/// Convert an Arrow array into a variant array.
+///
+/// See [`cast_to_variant_with_options`].
+pub fn cast_to_variant(array: &dyn Array) -> Result<ArrayRef, ArrowError> {
+    cast_to_variant_with_options(array, &CastOptions::default())
+}
+
+/// Convert an Arrow array into a variant array, with the given options.
+///
+/// Types are mapped as follows:
+///
+/// | Arrow type                  | Variant value |
+/// |-----------------------------|---------------|
+/// | Null                        | Variant null (nested) or Arrow null (top-level) |
+/// | Boolean                     | Variant boolean |
+/// | Int8, Int16, Int32, Int64   | Variant integer of the same width |
+/// | UInt8, UInt16, UInt32       | Variant integer of the next wider width |
+/// | UInt64                      | Variant i64, or Decimal16 for values above `i64::MAX` |
+/// | Float32, Float64            | Variant float, double |
+/// | Decimal128                  | Variant decimal, with the same scale |
+/// | Utf8, LargeUtf8, Utf8View   | Variant string |
+/// | Binary, LargeBinary, BinaryView, FixedSizeBinary | Variant binary |
+/// | FixedSizeBinary(16) with the `arrow.uuid` extension type | Variant UUID, see [`CastOptions::fixed_size_binary_as_uuid`] |
+/// | Date32                      | Variant date |
+/// | Timestamp(Nanosecond) without timezone | Variant timestamp without timezone, in nanoseconds |
+/// | Other timestamps without timezone | Variant timestamp without timezone, in microseconds |
+/// | Timestamp with timezone     | Variant timestamp with timezone, in microseconds; nanoseconds are rounded down, see [`CastOptions::reject_timestamp_precision_loss`] |
+/// | Interval, Duration          | See [`IntervalEncoding`] |
+/// | Struct                      | Variant object |
+/// | List, LargeList             | Variant array |
+/// | Union                       | The value of the member of each row |
+/// | Field with the variant extension type | The same value |
+///
+/// Top-level nulls are Arrow nulls, and nested nulls are variant nulls.
+///
+/// # Errors
+///
+/// If the array has a type that isn't supported, or a value that can't be
+/// represented, such as a timestamp out of the microsecond range.
+pub fn cast_to_variant_with_options(
+    array: &dyn Array,
+    options: &CastOptions,
+) -> Result<ArrayRef, ArrowError> {
+    let mut keys = BTreeSet::new();
+    collect_keys(array.data_type(), options, &mut keys)?;
+    let mut variant_columns = Vec::new();
+    collect_variant_columns(array, &mut variant_columns)?;
+    let variant_keys = collect_variant_keys(&variant_columns)?;
+    keys.extend(variant_keys.iter().map(String::as_str));
+    let metadata = build_metadata(keys.into_iter());
+    let metadata_ref = MetadataRef::new(&metadata);
+    let mut variants = VariantWriter::new(&variant_columns);
+
+    let nulls = array.logical_nulls();
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(array.len() + 1);
+    offsets.push(0);
+    for i in 0..array.len() {
+        if nulls.as_ref().map_or(true, |nulls| nulls.is_valid(i)) {
+            let kind = match options.fixed_size_binary_as_uuid {
+                true => FieldKind::Uuid,
+                false => FieldKind::Plain,
+            };
+            write_value(
+                array,
+                i,
+                kind,
+                &mut buffer,
+                &metadata_ref,
+                options,
+                &mut variants,
+            )?;
+        }
+        offsets.push(buffer.len());
+    }
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+
+    let layout = VariantLayout::builder()
+        .large_offsets(values.data_type() == &DataType::LargeBinary)
+        .build()?;
+    let metadata = DictionaryArray::new(
+        Int8Array::from(vec![0; array.len()]),
+        Arc::new(BinaryArray::from_iter_values([metadata])),
+    );
+    let output = Arc::new(StructArray::new(
+        layout.fields(),
+        vec![Arc::new(metadata) as ArrayRef, values],
+        nulls,
+    )) as ArrayRef;
+    match &options.layout {
+        Some(requested) if requested != &layout => {
+            VariantArray::try_new(&output)?.to_layout(requested)
+        }
+        _ => Ok(output),
+    }
+}
+
+/// Convert every column of a record batch into a single variant array, with
+/// an object per row keyed by column name.
+///
+/// This is useful for archiving wide, sparse tables, especially with
+/// [`CastOptions::omit_null_fields`]. Columns are converted as by
+/// [`cast_to_variant_with_options`], and can be extracted again with
+/// [`flatten_variant`](crate::extract::flatten_variant).
+///
+/// # Errors
+///
+/// If two columns have the same name, or a column has a type that isn't
+/// supported.
+pub fn record_batch_to_variant(
+    batch: &RecordBatch,
+    options: &CastOptions,
+) -> Result<ArrayRef, ArrowError> {
+    let mut names = BTreeSet::new();
+    for field in batch.schema().fields() {
+        if !names.insert(field.name().clone()) {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Duplicate column name '{}' can't be an object key",
+                field.name()
+            )));
+        }
+    }
+    cast_to_variant_with_options(&StructArray::from(batch.clone()), options)
+}
+
+/// Collect the object keys needed for values of a type, checking the type is
+/// supported.
+fn collect_keys<'a>(
+    data_type: &'a DataType,
+    options: &CastOptions,
+    keys: &mut BTreeSet<&'a str>,
+) -> Result<(), ArrowError> {
+    match data_type {
+        DataType::Struct(fields) => {
+            for field in fields {
+                keys.insert(field.name().as_str());
+                collect_field_keys(field, options, keys)?;
+            }
+        }
+        DataType::List(field) | DataType::LargeList(field) => {
+            collect_field_keys(field, options, keys)?
+        }
+        DataType::Union(fields, _) => {
+            for (_, field) in fields.iter() {
+                collect_field_keys(field, options, keys)?;
+            }
+        }
+        DataType::Interval(_) | DataType::Duration(_) => match options.interval_encoding {
+            IntervalEncoding::Iso8601 => {}
+            IntervalEncoding::Object => keys.extend(INTERVAL_KEYS),
+            IntervalEncoding::Error => {
+                return Err(ArrowError::CastError(format!(
+                    "Casting {} to variant is disabled by the interval encoding",
+                    data_type
+                )))
+            }
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Collect the object keys needed for the values of a field. The keys of
+/// variant fields depend on the data, see [`collect_variant_keys`].
+fn collect_field_keys<'a>(
+    field: &'a Field,
+    options: &CastOptions,
+    keys: &mut BTreeSet<&'a str>,
+) -> Result<(), ArrowError> {
+    match is_variant_field(field) {
+        true => Ok(()),
+        false => collect_keys(field.data_type(), options, keys),
+    }
+}
+
+/// A column with the variant extension type nested in the array being cast,
+/// wrapped once for the whole cast.
+struct VariantColumn<'a> {
+    /// The column, to find it by address when its rows are written.
+    array: &'a dyn Array,
+    variant_array: VariantArray,
+}
+
+/// Collect the variant fields nested in an array. These are the arrays that
+/// [`write_value`] reaches with [`FieldKind::Variant`].
+fn collect_variant_columns<'a>(
+    array: &'a dyn Array,
+    columns: &mut Vec<VariantColumn<'a>>,
+) -> Result<(), ArrowError> {
+    let mut collect = |field: &Field, array: &'a dyn Array| match is_variant_field(field) {
+        true => {
+            let variant_array = VariantArray::try_new(array)?;
+            columns.push(VariantColumn {
+                array,
+                variant_array,
+            });
+            Ok(())
+        }
+        false => collect_variant_columns(array, columns),
+    };
+    match array.data_type() {
+        DataType::Struct(fields) => {
+            for (field, column) in fields.iter().zip(array.as_struct().columns()) {
+                collect(field, column.as_ref())?;
+            }
+        }
+        DataType::List(field) => collect(field, array.as_list::<i32>().values().as_ref())?,
+        DataType::LargeList(field) => collect(field, array.as_list::<i64>().values().as_ref())?,
+        DataType::Union(fields, _) => {
+            let array = as_union_array(array);
+            for (type_id, field) in fields.iter() {
+                collect(field, array.child(type_id).as_ref())?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Collect the object keys of variant columns, which are the keys of their
+/// metadata, checking that the metadata is valid.
+fn collect_variant_keys(columns: &[VariantColumn]) -> Result<BTreeSet<String>, ArrowError> {
+    let mut keys = BTreeSet::new();
+    for column in columns {
+        let variant_array = &column.variant_array;
+        let mut cache = MetadataCache::new();
+        for (start, end) in variant_array.valid_slices() {
+            for i in start..end {
+                let metadata = variant_array.metadata(i);
+                cache
+                    .get_or_insert_with(metadata, || {
+                        validate_metadata(metadata)?;
+                        let metadata = MetadataRef::new(metadata);
+                        let strings = (0..metadata.dictionary_len())
+                            .filter_map(|id| metadata.get_string(id))
+                            .map(str::to_string);
+                        keys.extend(strings);
+                        Ok(())
+                    })
+                    .clone()
+                    .map_err(|err: String| {
+                        ArrowError::CastError(format!(
+                            "The metadata of a nested variant is invalid: {}",
+                            err
+                        ))
+                    })?;
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Writes the rows of the variant columns of a cast, with their field ids
+/// changed to those of the output metadata.
+struct VariantWriter<'a> {
+    columns: &'a [VariantColumn<'a>],
+    /// The field id mapping of each column, per metadata buffer.
+    mappings: Vec<MetadataCache<'a, Result<Vec<usize>, String>>>,
+}
+
+impl<'a> VariantWriter<'a> {
+    fn new(columns: &'a [VariantColumn<'a>]) -> Self {
+        Self {
+            columns,
+            mappings: columns.iter().map(|_| MetadataCache::new()).collect(),
+        }
+    }
+
+    /// Write row `i` of a variant column, changing its field ids to those of
+    /// `metadata`, which has every key of the column.
+    fn write(
+        &mut self,
+        array: &dyn Array,
+        i: usize,
+        buffer: &mut Vec<u8>,
+        metadata: &MetadataRef,
+    ) -> Result<(), ArrowError> {
+        // Compare addresses without the vtables, which can differ between
+        // codegen units.
+        let address = array as *const dyn Array as *const u8;
+        let columns = self.columns;
+        let index = columns
+            .iter()
+            .position(|column| column.array as *const dyn Array as *const u8 == address)
+            .expect("variant columns are collected before they are written");
+        let variant_array = &columns[index].variant_array;
+        let Some(variant) = variant_array.variant(i) else {
+            write::write_null(buffer);
+            return Ok(());
+        };
+        let source = variant_array.metadata(i);
+        let mapping = self.mappings[index]
+            .get_or_insert_with(source, || {
+                field_id_mapping(&MetadataRef::new(source), metadata)
+            })
+            .as_ref()
+            .map_err(|err| ArrowError::CastError(err.clone()))?;
+        write::remap_field_ids(buffer, &variant, mapping, metadata).map_err(ArrowError::CastError)
+    }
+}
+
+/// The id in `target` of each key of `source`.
+fn field_id_mapping(source: &MetadataRef, target: &MetadataRef) -> Result<Vec<usize>, String> {
+    (0..source.dictionary_len())
+        .map(|id| {
+            let key = source.get_string(id).ok_or_else(|| {
+                format!(
+                    "Key id {} is missing from the metadata of a nested variant",
+                    id
+                )
+            })?;
+            target.find_string(key).ok_or_else(|| {
+                format!(
+                    "Key {:?} of a nested variant is missing from the output metadata",
+                    key
+                )
+            })
+        })
+        .collect()
+}
+
+/// Whether a field has the variant extension type.
+fn is_variant_field(field: &Field) -> bool {
+    field.metadata().get(EXTENSION_NAME_KEY).map(String::as_str) == Some(EXTENSION_NAME)
+}
+
+/// Whether a field has the `arrow.uuid` extension type.
+pub(crate) fn is_uuid_field(field: &Field) -> bool {
+    field.metadata().get(EXTENSION_NAME_KEY).map(String::as_str) == Some(UUID_EXTENSION_NAME)
+}
+
+/// How the values of a field are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Plain,
+    /// `FixedSizeBinary(16)` values are written as UUIDs.
+    Uuid,
+    /// The values are variant values, and are copied.
+    Variant,
+}
+
+impl FieldKind {
+    fn of(field: &Field, options: &CastOptions) -> Self {
+        if is_variant_field(field) {
+            Self::Variant
+        } else if options.fixed_size_binary_as_uuid || is_uuid_field(field) {
+            Self::Uuid
+        } else {
+            Self::Plain
+        }
+    }
+}
+
+/// A `FixedSizeBinary(16)` field with the `arrow.uuid` extension type, which
+/// [`cast_to_variant`] writes as UUIDs.
+pub(crate) fn uuid_field(name: &str) -> Field {
+    Field::new(name, DataType::FixedSizeBinary(16), true).with_metadata(
+        [(
+            EXTENSION_NAME_KEY.to_string(),
+            UUID_EXTENSION_NAME.to_string(),
+        )]
+        .into(),
+    )
+}
+
+/// Write row `i` of an array as a variant value, with `kind` giving how the
+/// values of its field are written.
+///
+/// Nested types recurse, which is bounded by the depth of the type rather
+/// than the data.
+fn write_value(
+    array: &dyn Array,
+    i: usize,
+    kind: FieldKind,
+    buffer: &mut Vec<u8>,
+    metadata: &MetadataRef,
+    options: &CastOptions,
+    variants: &mut VariantWriter,
+) -> Result<(), ArrowError> {
+    if array.is_null(i) {
+        write::write_null(buffer);
+        return Ok(());
+    }
+    if kind == FieldKind::Variant {
+        return variants.write(array, i, buffer, metadata);
+    }
+    match array.data_type() {
+        DataType::Null => write::write_null(buffer),
+        DataType::Boolean => write::write_bool(buffer, array.as_boolean().value(i)),
+        DataType::Int8 => write::write_i8(buffer, array.as_primitive::<Int8Type>().value(i)),
+        DataType::Int16 => write::write_i16(buffer, array.as_primitive::<Int16Type>().value(i)),
+        DataType::Int32 => write::write_i32(buffer, array.as_primitive::<Int32Type>().value(i)),
+        DataType::Int64 => write::write_i64(buffer, array.as_primitive::<Int64Type>().value(i)),
+        DataType::UInt8 => {
+            write::write_i16(buffer, array.as_primitive::<UInt8Type>().value(i).into())
+        }
+        DataType::UInt16 => {
+            write::write_i32(buffer, array.as_primitive::<UInt16Type>().value(i).into())
+        }
+        DataType::UInt32 => {
+            write::write_i64(buffer, array.as_primitive::<UInt32Type>().value(i).into())
+        }
+        DataType::UInt64 => {
+            let value = array.as_primitive::<UInt64Type>().value(i);
+            match i64::try_from(value) {
+                Ok(value) => write::write_i64(buffer, value),
+                Err(_) => write::write_decimal(buffer, value.into(), 0),
+            }
+        }
+        DataType::Float32 => write::write_f32(buffer, array.as_primitive::<Float32Type>().value(i)),
+        DataType::Float64 => write::write_f64(buffer, array.as_primitive::<Float64Type>().value(i)),
+        DataType::Decimal128(_, scale) => {
+            let scale = u8::try_from(*scale).map_err(|_| {
+                ArrowError::CastError(format!(
+                    "Decimals with negative scale {} can't be cast to variant",
+                    scale
+                ))
+            })?;
+            let value = array.as_primitive::<Decimal128Type>().value(i);
+            write::write_decimal(buffer, value, scale)
+        }
+        DataType::Utf8 => write::write_string(buffer, array.as_string::<i32>().value(i)),
+        DataType::LargeUtf8 => write::write_string(buffer, array.as_string::<i64>().value(i)),
+        DataType::Utf8View => write::write_string(buffer, array.as_string_view().value(i)),
+        DataType::Binary => write::write_binary(buffer, array.as_binary::<i32>().value(i)),
+        DataType::LargeBinary => write::write_binary(buffer, array.as_binary::<i64>().value(i)),
+        DataType::BinaryView => write::write_binary(buffer, array.as_binary_view().value(i)),
+        DataType::FixedSizeBinary(16) if kind == FieldKind::Uuid => {
+            let value = array.as_fixed_size_binary().value(i);
+            write::write_uuid(buffer, value.try_into().expect("value has 16 bytes"))
+        }
+        DataType::FixedSizeBinary(_) => {
+            write::write_binary(buffer, array.as_fixed_size_binary().value(i))
+        }
+        DataType::Date32 => {
+            write::write_date32(buffer, array.as_primitive::<Date32Type>().value(i))
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, None) => write::write_timestamp_nanos_ntz(
+            buffer,
+            array.as_primitive::<TimestampNanosecondType>().value(i),
+        ),
+        DataType::Timestamp(unit, timezone) => {
+            // Arrow timestamps with a timezone are stored in UTC, like
+            // variant ones.
+            let micros = match unit {
+                TimeUnit::Second => array
+                    .as_primitive::<TimestampSecondType>()
+                    .value(i)
+                    .checked_mul(1_000_000),
+                TimeUnit::Millisecond => array
+                    .as_primitive::<TimestampMillisecondType>()
+                    .value(i)
+                    .checked_mul(1_000),
+                TimeUnit::Microsecond => {
+                    Some(array.as_primitive::<TimestampMicrosecondType>().value(i))
+                }
+                TimeUnit::Nanosecond => {
+                    let nanos = array.as_primitive::<TimestampNanosecondType>().value(i);
+                    if options.reject_timestamp_precision_loss && nanos.rem_euclid(1_000) != 0 {
+                        return Err(ArrowError::CastError(format!(
+                            "Timestamp {} ns with a timezone can't be written in microseconds \
+                             without losing precision",
+                            nanos
+                        )));
+                    }
+                    Some(nanos.div_euclid(1_000))
+                }
+            };
+            let micros = micros.ok_or_else(|| {
+                ArrowError::CastError("Timestamp is out of the microsecond range".into())
+            })?;
+            match timezone {
+                Some(_) => write::write_timestamp_micros(buffer, micros),
+                None => write::write_timestamp_micros_ntz(buffer, micros),
+            }
+        }
+        DataType::Duration(unit) => {
+            let nanos = match unit {
+                TimeUnit::Second => array
+                    .as_primitive::<DurationSecondType>()
+                    .value(i)
+                    .checked_mul(NANOS_PER_SECOND),
+                TimeUnit::Millisecond => array
+                    .as_primitive::<DurationMillisecondType>()
+                    .value(i)
+                    .checked_mul(1_000_000),
+                TimeUnit::Microsecond => array
+                    .as_primitive::<DurationMicrosecondType>()
+                    .value(i)
+                    .checked_mul(1_000),
+                TimeUnit::Nanosecond => {
+                    Some(array.as_primitive::<DurationNanosecondType>().value(i))
+                }
+            };
+            let nanos = nanos.ok_or_else(|| {
+                ArrowError::CastError("Duration is out of the nanosecond range".into())
+            })?;
+            write_interval(buffer, (0, 0, nanos), metadata, options)?
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            let months = array.as_primitive::<IntervalYearMonthType>().value(i);
+            write_interval(buffer, (months, 0, 0), metadata, options)?
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let value = array.as_primitive::<IntervalDayTimeType>().value(i);
+            let (days, millis) = IntervalDayTimeType::to_parts(value);
+            let nanos = i64::from(millis) * 1_000_000;
+            write_interval(buffer, (0, days, nanos), metadata, options)?
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let value = array.as_primitive::<IntervalMonthDayNanoType>().value(i);
+            let parts = IntervalMonthDayNanoType::to_parts(value);
+            write_interval(buffer, parts, metadata, options)?
+        }
+        DataType::Struct(_) => {
+            let array = array.as_struct();
+            let fields = array
+                .fields()
+                .iter()
+                .zip(array.columns())
+                .filter(|(_, column)| !(options.omit_null_fields && column.is_null(i)))
+                .collect::<Vec<_>>();
+            let mut object = ObjectBuilder::with_capacity(buffer, metadata, fields.len());
+            let mut value = Vec::new();
+            for (field, column) in fields {
+                value.clear();
+                let kind = FieldKind::of(field, options);
+                // The column itself rather than its `Arc`, which is an
+                // `Array` too, so variant columns are found by address.
+                let column = column.as_ref();
+                write_value(column, i, kind, &mut value, metadata, options, variants)?;
+                object
+                    .append_value(field.name(), &value)
+                    .map_err(ArrowError::CastError)?;
+            }
+            object.finish();
+        }
+        // Elements are written from the values of the list rather than a
+        // slice, so variant columns are found by address.
+        DataType::List(field) => {
+            let list = array.as_list::<i32>();
+            let offsets = list.value_offsets();
+            let elements = offsets[i] as usize..offsets[i + 1] as usize;
+            let kind = FieldKind::of(field, options);
+            let values = list.values().as_ref();
+            write_list(values, elements, kind, buffer, metadata, options, variants)?
+        }
+        DataType::LargeList(field) => {
+            let list = array.as_list::<i64>();
+            let offsets = list.value_offsets();
+            let elements = offsets[i] as usize..offsets[i + 1] as usize;
+            let kind = FieldKind::of(field, options);
+            let values = list.values().as_ref();
+            write_list(values, elements, kind, buffer, metadata, options, variants)?
+        }
+        DataType::Union(fields, _) => {
+            let array = as_union_array(array);
+            let type_id = array.type_id(i);
+            let (_, field) = fields
+                .iter()
+                .find(|(id, _)| *id == type_id)
+                .expect("union has a field for every type id");
+            let child = array.child(type_id).as_ref();
+            let kind = FieldKind::of(field, options);
+            let offset = array.value_offset(i);
+            write_value(child, offset, kind, buffer, metadata, options, variants)?
+        }
+        other => {
+            return Err(ArrowError::CastError(format!(
+                "Casting {} to variant is not supported",
+                other
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn write_list(
+    values: &dyn Array,
+    elements: Range<usize>,
+    kind: FieldKind,
+    buffer: &mut Vec<u8>,
+    metadata: &MetadataRef,
+    options: &CastOptions,
+    variants: &mut VariantWriter,
+) -> Result<(), ArrowError> {
+    let mut builder = ArrayBuilder::new(buffer, elements.len());
+    let mut value = Vec::new();
+    for i in elements {
+        value.clear();
+        write_value(values, i, kind, &mut value, metadata, options, variants)?;
+        builder.append_value(&value);
+    }
+    builder.finish();
+    Ok(())
+}
+
+fn write_interval(
+    buffer: &mut Vec<u8>,
+    (months, days, nanos): (i32, i32, i64),
+    metadata: &MetadataRef,
+    options: &CastOptions,
+) -> Result<(), ArrowError> {
+    match options.interval_encoding {
+        IntervalEncoding::Iso8601 => {
+            write::write_string(buffer, &format_iso8601_interval(months, days, nanos))
+        }
+        IntervalEncoding::Object => {
+            let mut object = ObjectBuilder::with_capacity(buffer, metadata, INTERVAL_KEYS.len());
+            for (key, value) in [
+                ("months", months.into()),
+                ("days", days.into()),
+                ("nanos", nanos),
+            ] {
+                object
+                    .append_i64(key, value)
+                    .map_err(ArrowError::CastError)?;
+            }
+            object.finish();
+        }
+        IntervalEncoding::Error => unreachable!("rejected by collect_keys"),
+    }
+    Ok(())
+}
+
+/// Read an interval written with either [`IntervalEncoding`], returning
+/// `None` if the value is not an interval.
+///
+/// The parts of an object can be any number that coerces to an integer, as
+/// by [`VariantRef::coerce_i64`], so objects ingested from JSON or narrowed
+/// to smaller integers are read too.
+///
+/// Use [`IntervalMonthDayNanoType::to_parts`] to get the months, days and
+/// nanoseconds.
+pub fn variant_to_interval(
+    variant: &VariantRef,
+    metadata: &MetadataRef,
+) -> Option<IntervalMonthDayNano> {
+    let (months, days, nanos) = match variant.basic_type() {
+        BasicType::Object => {
+            let object = variant.get_object().ok()?;
+            let mut parts = [0_i64; 3];
+            for (part, key) in parts.iter_mut().zip(["months", "days", "nanos"]) {
+                let value = object.find_field(key, metadata)?;
+                *part = value.coerce_i64(&CoerceOptions::default())?;
+            }
+            if object.len() != parts.len() {
+                return None;
+            }
+            let [months, days, nanos] = parts;
+            (
+                i32::try_from(months).ok()?,
+                i32::try_from(days).ok()?,
+                nanos,
+            )
+        }
+        _ => parse_iso8601_interval(variant.get_str()?)?,
+    };
+    Some(IntervalMonthDayNanoType::make_value(months, days, nanos))
+}
+
+/// Convert a column of variant intervals into `Interval(MonthDayNano)`.
+///
+/// Values that are not intervals are null. See [`variant_to_interval`].
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_intervals(array: &dyn Array) -> Result<IntervalMonthDayNanoArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = IntervalMonthDayNanoBuilder::with_capacity(variant_array.len());
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            let interval = variant_array.variant(i).and_then(|variant| {
+                let metadata = MetadataRef::new(variant_array.metadata(i));
+                variant_to_interval(&variant, &metadata)
+            });
+            builder.append_option(interval);
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+    Ok(builder.finish())
+}
+
+/// Format an interval as an ISO-8601 duration, such as `P1M2DT3.5S`.
+///
+/// Each component keeps its own sign, as in `P-1MT0.5S`, and a zero interval
+/// is `PT0S`.
+pub fn format_iso8601_interval(months: i32, days: i32, nanos: i64) -> String {
+    let mut output = String::from("P");
+    if months != 0 {
+        write!(output, "{}M", months).unwrap();
+    }
+    if days != 0 {
+        write!(output, "{}D", days).unwrap();
+    }
+    if nanos != 0 || output.len() == 1 {
+        let sign = if nanos < 0 { "-" } else { "" };
+        let nanos = nanos.unsigned_abs();
+        let seconds = nanos / NANOS_PER_SECOND as u64;
+        let fraction = nanos % NANOS_PER_SECOND as u64;
+        write!(output, "T{}{}", sign, seconds).unwrap();
+        if fraction != 0 {
+            let fraction = format!("{:09}", fraction);
+            write!(output, ".{}", fraction.trim_end_matches('0')).unwrap();
+        }
+        output.push('S');
+    }
+    output
+}
+
+/// Parse an ISO-8601 duration into months, days and nanoseconds.
+///
+/// Years are converted to 12 months and weeks to 7 days; hours, minutes and
+/// seconds are converted to nanoseconds. Only seconds may have a fraction.
+/// Returns `None` if the string is not a duration or overflows.
+pub fn parse_iso8601_interval(value: &str) -> Option<(i32, i32, i64)> {
+    let value = value.strip_prefix('P')?;
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) if !time.is_empty() => (date, time),
+        Some(_) => return None,
+        None if !value.is_empty() => (value, ""),
+        None => return None,
+    };
+
+    let (mut months, mut days, mut nanos) = (0_i32, 0_i32, 0_i64);
+    for (number, unit) in designators(date)? {
+        let number: i32 = number.parse().ok()?;
+        match unit {
+            'Y' => months = months.checked_add(number.checked_mul(12)?)?,
+            'M' => months = months.checked_add(number)?,
+            'W' => days = days.checked_add(number.checked_mul(7)?)?,
+            'D' => days = days.checked_add(number)?,
+            _ => return None,
+        }
+    }
+    for (number, unit) in designators(time)? {
+        let unit_nanos = match unit {
+            'H' => 3600 * NANOS_PER_SECOND,
+            'M' => 60 * NANOS_PER_SECOND,
+            'S' => NANOS_PER_SECOND,
+            _ => return None,
+        };
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() || !whole.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut value = whole.parse::<i64>().ok()?.checked_mul(unit_nanos)?;
+        if !fraction.is_empty() {
+            if unit != 'S' || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            value = value.checked_add(format!("{:0<9}", fraction).parse().ok()?)?;
+        }
+        nanos = nanos.checked_add(if negative { -value } else { value })?;
+    }
+    Some((months, days, nanos))
+}
+
+/// Split a run of designators such as `1Y2M` into `[("1", 'Y'), ("2", 'M')]`.
+fn designators(value: &str) -> Option<Vec<(&str, char)>> {
+    let mut designators = Vec::new();
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        if c.is_ascii_alphabetic() {
+            if i == start {
+                return None;
+            }
+            designators.push((&value[start..i], c));
+            start = i + 1;
+        }
+    }
+    (start == value.len()).then_some(designators)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        BinaryArray, DurationMillisecondArray, Int32Array, IntervalYearMonthArray, ListArray,
+        StringArray,
+    };
+    use arrow_buffer::OffsetBuffer;
+    use open_variant::values::PrimitiveTypeId;
+
+    use super::*;
+    use crate::json::variant_from_json;
+    use crate::layout::MetadataEncoding;
+
+    #[test]
+    fn test_iso8601_interval() {
+        for (months, days, nanos, formatted) in [
+            (0, 0, 0, "PT0S"),
+            (14, 0, 0, "P14M"),
+            (0, 3, 0, "P3D"),
+            (0, 0, 1_500_000_000, "PT1.5S"),
+            (0, 0, -500_000_000, "PT-0.5S"),
+            (-2, 1, 7, "P-2M1DT0.000000007S"),
+        ] {
+            assert_eq!(format_iso8601_interval(months, days, nanos), formatted);
+            assert_eq!(
+                parse_iso8601_interval(formatted),
+                Some((months, days, nanos)),
+                "{}",
+                formatted
+            );
+        }
+        assert_eq!(parse_iso8601_interval("P1Y2W"), Some((12, 14, 0)));
+        assert_eq!(
+            parse_iso8601_interval("PT1H30M"),
+            Some((0, 0, 5400 * NANOS_PER_SECOND))
+        );
+        for invalid in [
+            "", "P", "PT", "1D", "P1", "PD", "P1.5D", "PT1.5M", "PT1S2", "P1X",
+        ] {
+            assert_eq!(parse_iso8601_interval(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_cast_to_variant() {
+        let ids = Arc::new(Int32Array::from(vec![Some(1), None, Some(3)])) as ArrayRef;
+        let names = Arc::new(StringArray::from(vec![Some("a"), Some("b"), None])) as ArrayRef;
+        let tags = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), Some(2)]),
+            None,
+            Some(vec![]),
+        ])) as ArrayRef;
+        let array =
+            StructArray::try_from(vec![("id", ids), ("name", names), ("tags", tags)]).unwrap();
+
+        let output = cast_to_variant(&array).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        assert_eq!(variant_array.len(), 3);
+        let metadata = MetadataRef::new(variant_array.metadata(0));
+
+        let row = variant_array.variant(0).unwrap();
+        let object = row.get_object().unwrap();
+        let id = object.find_field("id", &metadata).unwrap();
+        assert_eq!(id.primitive_type_id(), PrimitiveTypeId::Int32);
+        assert_eq!(id.coerce_i64(&Default::default()), Some(1));
+        let tags = object.find_field("tags", &metadata).unwrap();
+        assert_eq!(tags.get_array().unwrap().len(), 2);
+
+        // Nested nulls are variant nulls.
+        let row = variant_array.variant(1).unwrap();
+        let object = row.get_object().unwrap();
+        assert!(object.find_field("id", &metadata).unwrap().is_null());
+        assert!(object.find_field("tags", &metadata).unwrap().is_null());
+    }
+
+    #[test]
+    fn test_cast_top_level_nulls() {
+        let array = Int32Array::from(vec![Some(1), None]);
+        let output = cast_to_variant(&array).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let value = variant_array.variant(0).unwrap();
+        assert_eq!(value.coerce_i64(&Default::default()), Some(1));
+        assert!(variant_array.is_null(1));
+    }
+
+    #[test]
+    fn test_cast_intervals() {
+        let durations = DurationMillisecondArray::from(vec![1_500, -250]);
+        let months = IntervalYearMonthArray::from(vec![14, 0]);
+
+        let output = cast_to_variant(&durations).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        assert_eq!(variant_array.variant(0).unwrap().get_string(), "PT1.5S");
+        assert_eq!(variant_array.variant(1).unwrap().get_string(), "PT-0.25S");
+        let intervals = variant_intervals(&output).unwrap();
+        assert_eq!(
+            IntervalMonthDayNanoType::to_parts(intervals.value(0)),
+            (0, 0, 1_500_000_000)
+        );
+
+        let options = CastOptions {
+            interval_encoding: IntervalEncoding::Object,
+            ..Default::default()
+        };
+        let output = cast_to_variant_with_options(&months, &options).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        assert_eq!(
+            variant_array.variant(0).unwrap().basic_type(),
+            BasicType::Object
+        );
+        let intervals = variant_intervals(&output).unwrap();
+        assert_eq!(
+            IntervalMonthDayNanoType::to_parts(intervals.value(0)),
+            (14, 0, 0)
+        );
+
+        let options = CastOptions {
+            interval_encoding: IntervalEncoding::Error,
+            ..Default::default()
+        };
+        let err = cast_to_variant_with_options(&months, &options).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("disabled by the interval encoding"));
+    }
+
+    #[test]
+    fn test_interval_object_parts_coerce() {
+        let json = StringArray::from(vec![
+            r#"{"months": 14, "days": 3, "nanos": 1500000000}"#,
+            r#"{"months": 1.0, "days": -2, "nanos": 7}"#,
+            r#"{"months": 1, "days": 2, "nanos": 0.5}"#,
+            r#"{"months": 1, "days": 2, "nanos": "7"}"#,
+        ]);
+        let output = variant_from_json(&json).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let interval = |i: usize| {
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            variant_to_interval(&variant_array.variant(i).unwrap(), &metadata)
+                .map(IntervalMonthDayNanoType::to_parts)
+        };
+        // Small integers and integral decimals are parts, but fractions and
+        // strings are not.
+        assert_eq!(interval(0), Some((14, 3, 1_500_000_000)));
+        assert_eq!(interval(1), Some((1, -2, 7)));
+        assert_eq!(interval(2), None);
+        assert_eq!(interval(3), None);
+    }
+
+    #[test]
+    fn test_cast_nested_variants() {
+        let json = StringArray::from(vec![
+            Some(r#"{"a": 1}"#),
+            Some(r#"{"b": [2, {"c": true}]}"#),
+            None,
+            Some(r#"{"a": 3, "c": null}"#),
+        ]);
+        let values = variant_from_json(&json).unwrap();
+        let field = Arc::new(
+            Field::new("v", values.data_type().clone(), true).with_metadata(
+                [(EXTENSION_NAME_KEY.to_string(), EXTENSION_NAME.to_string())].into(),
+            ),
+        );
+        let list = Arc::new(ListArray::new(
+            field.clone(),
+            OffsetBuffer::from_lengths([3, 1]),
+            values.clone(),
+            None,
+        )) as ArrayRef;
+        let array = StructArray::from(vec![
+            (field, values.slice(0, 2)),
+            (
+                Arc::new(Field::new("list", list.data_type().clone(), true)),
+                list,
+            ),
+        ]);
+
+        let output = cast_to_variant(&array).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let metadata = MetadataRef::new(variant_array.metadata(0));
+        let get = |row: usize, path: &str| {
+            let row = variant_array.variant(row).unwrap();
+            let mut value = row;
+            for key in path.split('.') {
+                value = match key.parse::<usize>() {
+                    Ok(index) => value.get_array().unwrap().get_element(index).unwrap(),
+                    Err(_) => value
+                        .get_object()
+                        .unwrap()
+                        .find_field(key, &metadata)
+                        .unwrap(),
+                };
+            }
+            value
+        };
+        assert_eq!(get(0, "v.a").coerce_i64(&Default::default()), Some(1));
+        assert!(get(0, "list.2").is_null());
+        assert!(get(0, "list.1.b.1.c").get_bool());
+        assert_eq!(get(1, "v.b.0").coerce_i64(&Default::default()), Some(2));
+        assert_eq!(get(1, "list.0.a").coerce_i64(&Default::default()), Some(3));
+        assert!(get(1, "list.0.c").is_null());
+    }
+
+    #[test]
+    fn test_cast_nested_variant_invalid_metadata() {
+        let mut metadata = build_metadata(["a"].into_iter());
+        *metadata.last_mut().unwrap() = 0xff;
+        let mut value = Vec::new();
+        write::write_null(&mut value);
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let values = Arc::new(StructArray::new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&metadata[..]])) as ArrayRef,
+                Arc::new(BinaryArray::from_vec(vec![&value[..]])),
+            ],
+            None,
+        )) as ArrayRef;
+        let array = StructArray::from(vec![(Arc::new(layout.field("v", true)), values)]);
+
+        let err = cast_to_variant(&array).unwrap_err();
+        assert!(err.to_string().contains("not valid UTF-8"), "{}", err);
+    }
+
+    #[test]
+    fn test_record_batch_to_variant() {
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "id",
+                Arc::new(Int32Array::from(vec![Some(1), Some(2)])) as ArrayRef,
+            ),
+            (
+                "name",
+                Arc::new(StringArray::from(vec![Some("a"), None])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+
+        let output = record_batch_to_variant(&batch, &CastOptions::default()).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        assert_eq!(variant_array.len(), 2);
+        assert_eq!(variant_array.nulls(), None);
+        let metadata = MetadataRef::new(variant_array.metadata(1));
+        let row = variant_array.variant(1).unwrap();
+        let object = row.get_object().unwrap();
+        let id = object.find_field("id", &metadata).unwrap();
+        assert_eq!(id.coerce_i64(&Default::default()), Some(2));
+        assert!(object.find_field("name", &metadata).unwrap().is_null());
+
+        let options = CastOptions {
+            omit_null_fields: true,
+            ..Default::default()
+        };
+        let output = record_batch_to_variant(&batch, &options).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let metadata = MetadataRef::new(variant_array.metadata(1));
+        let row = variant_array.variant(1).unwrap();
+        let object = row.get_object().unwrap();
+        assert_eq!(object.len(), 1);
+        assert!(object.find_field("name", &metadata).is_none());
+        let row = variant_array.variant(0).unwrap();
+        assert_eq!(row.get_object().unwrap().len(), 2);
+
+        let ids = Arc::new(Int32Array::from(vec![1])) as ArrayRef;
+        let duplicate = RecordBatch::try_new(
+            Arc::new(arrow_schema::Schema::new(vec![
+                arrow_schema::Field::new("a", DataType::Int32, false),
+                arrow_schema::Field::new("a", DataType::Int32, false),
+            ])),
+            vec![ids.clone(), ids],
+        )
+        .unwrap();
+        let err = record_batch_to_variant(&duplicate, &CastOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Duplicate column name"));
+    }
+
+    #[test]
+    fn test_record_batch_to_variant_mixed_types() {
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "day",
+                Arc::new(arrow_array::Date32Array::from(vec![Some(19_000), None])) as ArrayRef,
+            ),
+            (
+                "payload",
+                Arc::new(BinaryArray::from_opt_vec(vec![
+                    Some(&b"\x01"[..]),
+                    Some(&b""[..]),
+                ])),
+            ),
+            (
+                "at",
+                Arc::new(
+                    arrow_array::TimestampMillisecondArray::from(vec![Some(1_500), None])
+                        .with_timezone("America/New_York"),
+                ),
+            ),
+            (
+                "score",
+                Arc::new(arrow_array::Float32Array::from(vec![Some(0.5), Some(1.0)])),
+            ),
+        ])
+        .unwrap();
+
+        let output = record_batch_to_variant(&batch, &CastOptions::default()).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let metadata = MetadataRef::new(variant_array.metadata(0));
+        let row = variant_array.variant(0).unwrap();
+        let object = row.get_object().unwrap();
+        let field = |name| object.find_field(name, &metadata).unwrap();
+        assert_eq!(field("day").type_name(), "date");
+        assert_eq!(field("payload").type_name(), "binary");
+        assert_eq!(field("at").type_name(), "timestamp");
+        assert_eq!(field("at").as_bytes()[1..], 1_500_000_i64.to_le_bytes());
+        assert_eq!(field("score").type_name(), "float");
+
+        let row = variant_array.variant(1).unwrap();
+        let object = row.get_object().unwrap();
+        assert!(object.find_field("day", &metadata).unwrap().is_null());
+        assert!(object.find_field("at", &metadata).unwrap().is_null());
+    }
+
+    #[test]
+    fn test_cast_types() {
+        let type_names = |array: &dyn Array, options: &CastOptions| {
+            let output = cast_to_variant_with_options(array, options).unwrap();
+            let variant_array = VariantArray::try_new(&output).unwrap();
+            (0..variant_array.len())
+                .map(|i| variant_array.variant(i).unwrap().type_name())
+                .collect::<Vec<_>>()
+        };
+        let options = CastOptions::default();
+        for (array, type_name) in [
+            (
+                Arc::new(arrow_array::Int8Array::from(vec![-1])) as ArrayRef,
+                "int8",
+            ),
+            (Arc::new(arrow_array::Int16Array::from(vec![-1])), "int16"),
+            (Arc::new(Int32Array::from(vec![-1])), "int32"),
+            (Arc::new(arrow_array::UInt8Array::from(vec![255])), "int16"),
+            (Arc::new(arrow_array::UInt16Array::from(vec![1])), "int32"),
+            (Arc::new(arrow_array::UInt32Array::from(vec![1])), "int64"),
+            (
+                Arc::new(arrow_array::Float32Array::from(vec![1.5])),
+                "float",
+            ),
+            (
+                Arc::new(arrow_array::Date32Array::from(vec![19_000])),
+                "date",
+            ),
+            (
+                Arc::new(BinaryArray::from_iter_values([b"\x00\x01"])),
+                "binary",
+            ),
+            (
+                Arc::new(arrow_array::LargeBinaryArray::from_iter_values([b"x"])),
+                "binary",
+            ),
+            (
+                Arc::new(arrow_array::TimestampSecondArray::from(vec![0]).with_timezone("UTC")),
+                "timestamp",
+            ),
+            (
+                Arc::new(arrow_array::TimestampMillisecondArray::from(vec![0])),
+                "timestamp_ntz",
+            ),
+            (
+                Arc::new(arrow_array::TimestampNanosecondArray::from(vec![0])),
+                "timestamp_ntz_nanos",
+            ),
+        ] {
+            assert_eq!(
+                type_names(array.as_ref(), &options),
+                vec![type_name],
+                "{}",
+                array.data_type()
+            );
+        }
+
+        // Nanosecond timestamps with a timezone are rounded down to
+        // microseconds.
+        let array = arrow_array::TimestampNanosecondArray::from(vec![-1]).with_timezone("+01:00");
+        let output = cast_to_variant(&array).unwrap();
+        let value = VariantArray::try_new(&output).unwrap().value(0).to_vec();
+        assert_eq!(value[1..], (-1_i64).to_le_bytes());
+        // Unless precision loss is rejected, which keeps whole microseconds.
+        let options = CastOptions {
+            reject_timestamp_precision_loss: true,
+            ..Default::default()
+        };
+        let err = cast_to_variant_with_options(&array, &options).unwrap_err();
+        assert!(err.to_string().contains("losing precision"));
+        let array =
+            arrow_array::TimestampNanosecondArray::from(vec![-2_000]).with_timezone("+01:00");
+        let output = cast_to_variant_with_options(&array, &options).unwrap();
+        let value = VariantArray::try_new(&output).unwrap().value(0).to_vec();
+        assert_eq!(value[1..], (-2_i64).to_le_bytes());
+
+        // Microsecond timestamps are kept as is, even if they don't fit in
+        // nanoseconds.
+        let micros = i64::MAX / 1000 + 1;
+        let array = arrow_array::TimestampMicrosecondArray::from(vec![micros]);
+        let output = cast_to_variant(&array).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let value = variant_array.variant(0).unwrap();
+        assert_eq!(
+            value.primitive_type_id(),
+            PrimitiveTypeId::TimestampMicroNTZ
+        );
+        assert_eq!(value.get_timestamp_nanos_ntz(), None);
+        let seconds = arrow_array::TimestampSecondArray::from(vec![i64::MAX]);
+        let err = cast_to_variant(&seconds).unwrap_err();
+        assert!(err.to_string().contains("out of the microsecond range"));
+
+        let array = arrow_array::Time32SecondArray::from(vec![1]);
+        let err = cast_to_variant(&array).unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+        let array = arrow_array::Time64NanosecondArray::from(vec![1]);
+        let err = cast_to_variant(&array).unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+    }
+
+    #[test]
+    fn test_cast_uuids() {
+        let bytes = [7_u8; 16];
+        let values = Arc::new(
+            arrow_array::FixedSizeBinaryArray::try_from_iter([bytes].into_iter()).unwrap(),
+        ) as ArrayRef;
+
+        // Fixed-size binary is only a UUID with the extension type or the
+        // option.
+        let output = cast_to_variant(&values).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        assert_eq!(variant_array.variant(0).unwrap().type_name(), "binary");
+        let options = CastOptions {
+            fixed_size_binary_as_uuid: true,
+            ..Default::default()
+        };
+        let output = cast_to_variant_with_options(&values, &options).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        assert_eq!(variant_array.variant(0).unwrap().get_uuid(), bytes);
+
+        let array = StructArray::from(vec![
+            (Arc::new(uuid_field("id")), values.clone()),
+            (
+                Arc::new(Field::new("hash", DataType::FixedSizeBinary(16), true)),
+                values,
+            ),
+        ]);
+        let output = cast_to_variant(&array).unwrap();
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let metadata = MetadataRef::new(variant_array.metadata(0));
+        let row = variant_array.variant(0).unwrap();
+        let object = row.get_object().unwrap();
+        let id = object.find_field("id", &metadata).unwrap();
+        assert_eq!(id.get_uuid(), bytes);
+        let hash = object.find_field("hash", &metadata).unwrap();
+        assert_eq!(hash.type_name(), "binary");
+    }
+}
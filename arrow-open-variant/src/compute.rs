@@ -0,0 +1,153 @@
+//! Kernels in the style of `arrow::compute`, for engines other than
+//! DataFusion.
+//!
+//! This crate only depends on Arrow, so query engines and dataframe
+//! libraries can call these plain functions on Arrow arrays directly, for
+//! example from a Polars plugin. They cover the common operations with short
+//! names; the other modules have the full set of kernels and options.
+//!
+//! ```
+//! # #[cfg(feature = "json")]
+//! # {
+//! use arrow_array::StringArray;
+//! use arrow_open_variant::compute::{exists, from_json, get_path, to_json};
+//! use open_variant::path::VariantPath;
+//!
+//! let jsons = StringArray::from(vec![r#"{"a": {"b": 1}}"#, r#"{"c": 2}"#]);
+//! let array = from_json(&jsons).unwrap();
+//!
+//! let b = get_path(&array, &VariantPath::parse("a.b").unwrap()).unwrap();
+//! let b = to_json(&b).unwrap();
+//! assert_eq!(b.iter().collect::<Vec<_>>(), vec![Some("1"), None]);
+//!
+//! let has_c = exists(&array, "c").unwrap();
+//! assert_eq!(has_c.iter().collect::<Vec<_>>(), vec![Some(false), Some(true)]);
+//! # }
+//! ```
+
+use arrow_array::builder::BooleanBuilder;
+use arrow_array::{Array, ArrayRef, BooleanArray};
+use arrow_schema::ArrowError;
+use open_variant::path::{PathElement, VariantPath};
+use open_variant::values::BasicType;
+
+use crate::array::{VariantArray, VariantArrayReader};
+use crate::extract::flatten_variant;
+
+/// The value at `path` in each row, as a variant array in the default
+/// [`VariantLayout`](crate::VariantLayout).
+///
+/// The values keep the metadata of their row. Rows where the path doesn't
+/// exist, and null rows, are null. See
+/// [`flatten_variant`] to extract several paths at once, or to convert the
+/// values to other types.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn get_path(array: &dyn Array, path: &VariantPath) -> Result<ArrayRef, ArrowError> {
+    let columns = [(path.clone(), crate::variant_type(), "value")];
+    let batch = flatten_variant(array, &columns)?;
+    Ok(batch.column(0).clone())
+}
+
+/// Whether each row is an object with a field named `key`.
+///
+/// Rows with other values are false, and null rows are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn exists(array: &dyn Array, key: &str) -> Result<BooleanArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let path = VariantPath::new(vec![PathElement::Field(key.to_string())]);
+    let mut builder = BooleanBuilder::with_capacity(variant_array.len());
+    let mut resolver = variant_array.path_resolver(&path);
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            let Some(variant) = variant_array.variant(i) else {
+                builder.append_null();
+                continue;
+            };
+            if variant.basic_type() != BasicType::Object {
+                builder.append_value(false);
+                continue;
+            }
+            builder.append_value(resolver.resolve(i).get(&variant).is_some());
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+    Ok(builder.finish())
+}
+
+/// Convert each row to JSON text. See
+/// [`variant_to_json`](crate::json::variant_to_json).
+///
+/// # Errors
+///
+/// If the array is not a variant array, or a value is invalid.
+#[cfg(feature = "json")]
+pub fn to_json(array: &dyn Array) -> Result<arrow_array::StringArray, ArrowError> {
+    crate::json::variant_to_json(array)
+}
+
+/// Parse each row of a string or binary array of JSON text into a variant
+/// array. See [`variant_from_json`](crate::json::variant_from_json).
+///
+/// # Errors
+///
+/// If the array is not a string or binary array, or the JSON is invalid.
+#[cfg(feature = "json")]
+pub fn from_json(array: &dyn Array) -> Result<ArrayRef, ArrowError> {
+    crate::json::variant_from_json(array)
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+
+    #[test]
+    fn test_get_path() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": {"b": [1, "x"]}}"#),
+            None,
+            Some(r#"{"a": 2}"#),
+            Some("[3]"),
+        ]);
+        let array = from_json(&jsons).unwrap();
+
+        let path = VariantPath::parse("a.b").unwrap();
+        let values = to_json(&get_path(&array, &path).unwrap()).unwrap();
+        assert_eq!(
+            values.iter().collect::<Vec<_>>(),
+            vec![Some(r#"[1,"x"]"#), None, None, None]
+        );
+
+        let values = to_json(&get_path(&array, &VariantPath::default()).unwrap()).unwrap();
+        assert_eq!(values, to_json(&array).unwrap());
+    }
+
+    #[test]
+    fn test_exists() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": null}"#),
+            None,
+            Some(r#"{"b": 1}"#),
+            Some(r#"["a"]"#),
+            Some("null"),
+        ]);
+        let array = from_json(&jsons).unwrap();
+        let a = exists(&array, "a").unwrap();
+        assert_eq!(
+            a.iter().collect::<Vec<_>>(),
+            vec![Some(true), None, Some(false), Some(false), None]
+        );
+        let missing = exists(&array, "missing").unwrap();
+        assert_eq!(missing.true_count(), 0);
+    }
+}
@@ -0,0 +1,178 @@
+//! Store identical variant values once.
+//!
+//! Telemetry and event streams often repeat the same payload across many
+//! rows. [`variant_dedup`] dictionary encodes the `values` child so each
+//! distinct value is stored once, keeping the metadata as is. Every kernel
+//! reads the result, since [`VariantArray`] supports the
+//! [`ValuesEncoding::Dictionary`](crate::layout::ValuesEncoding::Dictionary)
+//! layout.
+
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, ArrayRef};
+use arrow_schema::ArrowError;
+
+use crate::array::{dictionary_values_array, VariantArray, VariantArrayReader};
+
+/// How much [`variant_dedup`] saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DedupStats {
+    /// The number of non-null rows.
+    pub values: usize,
+    /// The number of distinct values among them.
+    pub distinct_values: usize,
+    /// The total size of the values of the non-null rows, in bytes.
+    pub value_bytes: usize,
+    /// The total size of the distinct values, in bytes.
+    pub distinct_value_bytes: usize,
+}
+
+impl DedupStats {
+    /// How many times smaller the stored values are, as `value_bytes /
+    /// distinct_value_bytes`. This is 1.0 if there are no values.
+    ///
+    /// The keys take 4 bytes per row, the same as the offsets they replace,
+    /// so they are not counted.
+    pub fn ratio(&self) -> f64 {
+        if self.distinct_value_bytes == 0 {
+            return 1.0;
+        }
+        self.value_bytes as f64 / self.distinct_value_bytes as f64
+    }
+}
+
+/// Dictionary encode the values of a variant array, so byte-identical values
+/// are stored once.
+///
+/// The output has `Dictionary(Int32, Binary)` values and the same metadata
+/// and nulls as the input. Values are compared as bytes, so equal values
+/// with different encodings, such as different field orders or widths, are
+/// kept apart. Use [`canonicalize_variant`](crate::canonical::canonicalize_variant)
+/// first to merge them.
+///
+/// # Errors
+///
+/// If the array is not a variant array, has more distinct values than fit in
+/// `Int32` keys, or has too much distinct value data for 32-bit offsets.
+pub fn variant_dedup(array: &dyn Array) -> Result<(ArrayRef, DedupStats), ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut values = Vec::with_capacity(variant_array.len());
+    for (start, end) in variant_array.valid_slices() {
+        values.resize(start, None);
+        values.extend((start..end).map(|i| Some(variant_array.value(i))));
+    }
+    values.resize(variant_array.len(), None);
+    let dictionary = dictionary_values_array(values.iter().copied())?;
+
+    let stats = DedupStats {
+        values: variant_array.len() - dictionary.null_count(),
+        distinct_values: dictionary.values().len(),
+        value_bytes: values.iter().flatten().map(|value| value.len()).sum(),
+        distinct_value_bytes: dictionary.values().as_binary::<i32>().values().len(),
+    };
+    let nulls = variant_array.nulls().cloned();
+    Ok((
+        variant_array.with_values(Arc::new(dictionary), nulls),
+        stats,
+    ))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::{StringArray, StructArray};
+    use arrow_buffer::NullBuffer;
+    use arrow_schema::DataType;
+
+    use super::*;
+    use crate::json::{variant_from_json, variant_to_json};
+    use crate::layout::{ValuesEncoding, VariantLayout};
+
+    #[test]
+    fn test_variant_dedup() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"event": "click", "x": 1}"#),
+            Some(r#"{"event": "click", "x": 1}"#),
+            None,
+            Some(r#"{"event": "view"}"#),
+            Some(r#"{"event": "click", "x": 1}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let (deduped, stats) = variant_dedup(&array).unwrap();
+
+        let layout = VariantLayout::try_from_data_type(deduped.data_type()).unwrap();
+        assert_eq!(layout.values_encoding(), ValuesEncoding::Dictionary);
+        let values = deduped.as_struct().column_by_name("values").unwrap();
+        assert_eq!(values.as_any_dictionary().values().len(), 2);
+        assert_eq!(deduped.null_count(), 1);
+
+        let click = VariantArray::try_new(&array).unwrap().value(0).len();
+        let view = VariantArray::try_new(&array).unwrap().value(3).len();
+        assert_eq!(
+            stats,
+            DedupStats {
+                values: 4,
+                distinct_values: 2,
+                value_bytes: 3 * click + view,
+                distinct_value_bytes: click + view,
+            }
+        );
+        assert!(stats.ratio() > 1.0);
+
+        // The deduplicated array reads like the input.
+        assert_eq!(
+            variant_to_json(&deduped).unwrap(),
+            variant_to_json(&array).unwrap()
+        );
+        let converted = VariantArray::try_new(&deduped)
+            .unwrap()
+            .to_layout(&VariantLayout::default())
+            .unwrap();
+        assert_eq!(
+            converted.as_struct().column(1).data_type(),
+            &DataType::Binary
+        );
+        assert_eq!(
+            converted.as_struct().column(1).to_data(),
+            array.as_struct().column(1).to_data()
+        );
+    }
+
+    #[test]
+    fn test_dedup_stats_null_values() {
+        // A null row keeps the bytes of its value.
+        let jsons = StringArray::from(vec![Some("1"), Some(r#""null row""#)]);
+        let array = variant_from_json(&jsons).unwrap();
+        let array = array.as_struct();
+        let array = StructArray::new(
+            array.fields().clone(),
+            array.columns().to_vec(),
+            Some(NullBuffer::from(vec![true, false])),
+        );
+        assert!(!array.column(1).as_binary::<i32>().value(1).is_empty());
+
+        let (_, stats) = variant_dedup(&array).unwrap();
+        let one = VariantArray::try_new(&array).unwrap().value(0).len();
+        assert_eq!(
+            stats,
+            DedupStats {
+                values: 1,
+                distinct_values: 1,
+                value_bytes: one,
+                distinct_value_bytes: one,
+            }
+        );
+    }
+
+    #[test]
+    fn test_dedup_stats_ratio() {
+        assert_eq!(DedupStats::default().ratio(), 1.0);
+        let stats = DedupStats {
+            values: 4,
+            distinct_values: 1,
+            value_bytes: 40,
+            distinct_value_bytes: 10,
+        };
+        assert_eq!(stats.ratio(), 4.0);
+    }
+}
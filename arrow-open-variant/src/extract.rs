@@ -0,0 +1,1943 @@
+//! Extract values at paths from variant arrays into typed Arrow arrays.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BooleanBuilder, FixedSizeBinaryBuilder, Float64Builder, Int64Builder, StringBuilder,
+    TimestampNanosecondBuilder,
+};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, ListArray, MapArray, NullArray, RecordBatch, StringArray,
+    StructArray, UInt64Array, UnionArray,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema, TimeUnit, UnionFields};
+use open_variant::coerce::CoerceOptions;
+use open_variant::json::{write_json, JsonWriteOptions};
+use open_variant::metadata::MetadataRef;
+use open_variant::path::{CaseInsensitiveKeys, VariantPath};
+use open_variant::values::{BasicType, PrimitiveTypeId, VariantRef};
+
+use crate::array::{values_array_from_parts, MetadataCache, VariantArray, VariantArrayReader};
+use crate::cast::uuid_field;
+use crate::layout::VariantLayout;
+
+/// Extract several paths from a variant array into a [`RecordBatch`].
+///
+/// Each entry of `columns` gives the path to extract, the type of the output
+/// column, and the name of the output column. The variant array is traversed
+/// once for all the columns.
+///
+/// Supported output types are `Boolean`, `Int64`, `Float64`, `Utf8`,
+/// `FixedSizeBinary(16)` for UUIDs (the output field has the `arrow.uuid`
+/// extension type), `Timestamp(Nanosecond, None)` for
+/// timestamps without timezone, and any variant type (see
+/// [`VariantLayout`]), which extracts the sub-value without converting it.
+/// Values are converted to `Boolean`, `Int64`, `Float64` and `Utf8` with the
+/// rules in [`coerce`](open_variant::coerce), so for example integers are
+/// converted to `Float64`, and UUIDs to their canonical string form for
+/// `Utf8`. Rows where the path doesn't exist, or where the value doesn't
+/// coerce to the type, are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if an output type is not supported.
+pub fn flatten_variant(
+    array: &dyn Array,
+    columns: &[(VariantPath, DataType, &str)],
+) -> Result<RecordBatch, ArrowError> {
+    flatten_variant_with_options(array, columns, &ExtractOptions::default())
+}
+
+/// Options for [`flatten_variant_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// Match object keys ignoring case, for sources that are inconsistent
+    /// about key casing. If an object has several keys that match, the one
+    /// with the lowest field id is used.
+    pub case_insensitive_keys: bool,
+    /// How values are coerced to the output types, such as whether strings
+    /// are parsed as numbers.
+    pub coerce: CoerceOptions,
+}
+
+/// Extract several paths from a variant array into a [`RecordBatch`], with
+/// the given options.
+///
+/// See [`flatten_variant`].
+pub fn flatten_variant_with_options(
+    array: &dyn Array,
+    columns: &[(VariantPath, DataType, &str)],
+    options: &ExtractOptions,
+) -> Result<RecordBatch, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builders = columns
+        .iter()
+        .map(|(_, data_type, _)| ColumnBuilder::try_new(data_type, variant_array.len()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut resolved_paths = MetadataCache::new();
+    let mut case_insensitive_keys = MetadataCache::new();
+    // Null rows are appended in bulk, a run at a time.
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        for builder in &mut builders {
+            builder.append_nulls(start - valid_end);
+        }
+        valid_end = end;
+        for i in start..end {
+            let metadata_bytes = variant_array.metadata(i);
+            let metadata = MetadataRef::new(metadata_bytes);
+            let Some(variant) = variant_array.variant(i) else {
+                builders
+                    .iter_mut()
+                    .for_each(|builder| builder.append(None, &metadata, &options.coerce));
+                continue;
+            };
+            if !options.case_insensitive_keys {
+                let paths = resolved_paths.get_or_insert_with(metadata_bytes, || {
+                    columns
+                        .iter()
+                        .map(|(path, _, _)| variant_array.resolve_path(i, path))
+                        .collect::<Vec<_>>()
+                });
+                for (path, builder) in paths.iter_mut().zip(builders.iter_mut()) {
+                    builder.append(path.get(&variant), &metadata, &options.coerce);
+                }
+                continue;
+            }
+
+            let keys = case_insensitive_keys
+                .get_or_insert_with(metadata_bytes, || CaseInsensitiveKeys::new(&metadata));
+            for ((path, _, _), builder) in columns.iter().zip(builders.iter_mut()) {
+                builder.append(
+                    variant.get_path_case_insensitive(path, keys),
+                    &metadata,
+                    &options.coerce,
+                );
+            }
+        }
+    }
+    for builder in &mut builders {
+        builder.append_nulls(variant_array.len() - valid_end);
+    }
+
+    let arrays = builders
+        .into_iter()
+        .map(|builder| builder.finish(&variant_array))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Variant columns are built in the canonical form of the requested
+    // layout, so the field types come from the arrays. UUID columns are
+    // marked as such, so casting back writes UUIDs.
+    let fields = columns
+        .iter()
+        .zip(&arrays)
+        .map(|((_, _, name), array)| match array.data_type() {
+            DataType::FixedSizeBinary(16) => uuid_field(name),
+            data_type => Field::new(*name, data_type.clone(), true),
+        })
+        .collect::<Vec<_>>();
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+}
+
+/// Extract all the values matching a path, which may contain wildcards and
+/// descendants, into a list per row.
+///
+/// For example, `items[*].price` gives the price of every item, and `..price`
+/// every price at any depth. The list
+/// elements have the type `data_type`, which supports the same types as
+/// [`flatten_variant`]. Elements that have a different type are null, and
+/// branches where the path doesn't exist are skipped. Null rows are null
+/// lists.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if the type is not supported.
+pub fn variant_get_list(
+    array: &dyn Array,
+    path: &VariantPath,
+    data_type: &DataType,
+) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut matches = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    offsets.push(0);
+    let mut resolver = variant_array.path_resolver(path);
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows are empty lists.
+        offsets.resize(start + 1, i32_offset(matches.len())?);
+        for i in start..end {
+            if let Some(variant) = variant_array.variant(i) {
+                let values = resolver.resolve(i).get_all(&variant);
+                matches.extend(values.into_iter().map(|value| (i, Some(value))));
+            }
+            offsets.push(i32_offset(matches.len())?);
+        }
+    }
+    offsets.resize(variant_array.len() + 1, i32_offset(matches.len())?);
+
+    let values = column_from_values(&variant_array, matches, data_type)?;
+    let field = Arc::new(Field::new("item", values.data_type().clone(), true));
+    let list = ListArray::new(
+        field,
+        OffsetBuffer::new(offsets.into()),
+        values,
+        variant_array.nulls().cloned(),
+    );
+    Ok(Arc::new(list))
+}
+
+/// Extract all the values matching a path into a list of variants per row.
+///
+/// Paths can use `[*]` for every element of an array and `..` for any depth,
+/// so `..author` gives every `author` field at any depth, like the JSONPath
+/// `$..author`. The list elements are variants in the default
+/// [`VariantLayout`], and keep the metadata of their row. Branches where the
+/// path doesn't exist are skipped. Null rows are null lists.
+///
+/// Use [`variant_get_list`] to extract the matches as a typed list instead.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_extract_all(array: &dyn Array, path: &VariantPath) -> Result<ArrayRef, ArrowError> {
+    variant_get_list(array, path, &crate::variant_type())
+}
+
+/// What [`variant_get_map`] does when the values of the objects have
+/// different types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeterogeneousValues {
+    /// Return an error.
+    #[default]
+    Error,
+    /// Convert every value to a `Utf8` string: strings and UUIDs as with
+    /// [`coerce_string_with_metadata`](VariantRef::coerce_string_with_metadata),
+    /// and other values as JSON text.
+    Stringify,
+}
+
+/// Extract the object at a path into a `Map<Utf8, _>` per row.
+///
+/// The map values have a single type for the whole array, taken from the
+/// values of the objects with the same kinds as [`variant_to_union`]: for
+/// example `Int64` if every value is an integer, and `Float64` if there are
+/// both integers and floats. Objects, arrays and other values without an
+/// Arrow type are kept as variants in the default [`VariantLayout`]. Variant
+/// nulls are null values, and if every value is null the values have the
+/// `Null` type. If the values have different types, `heterogeneous` decides
+/// what happens.
+///
+/// Rows where the path doesn't exist or isn't an object, and null rows, are
+/// null maps. Entries are in the order of the object fields, which is sorted
+/// by key.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if the values have different
+/// types and `heterogeneous` is [`HeterogeneousValues::Error`].
+pub fn variant_get_map(
+    array: &dyn Array,
+    path: &VariantPath,
+    heterogeneous: HeterogeneousValues,
+) -> Result<MapArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut keys = StringBuilder::new();
+    let mut entries = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    offsets.push(0);
+    let mut nulls = Vec::with_capacity(variant_array.len());
+    let mut resolver = variant_array.path_resolver(path);
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows are null maps.
+        nulls.resize(start, false);
+        offsets.resize(start + 1, i32_offset(entries.len())?);
+        for i in start..end {
+            let object = variant_array.variant(i).and_then(|variant| {
+                resolver
+                    .resolve(i)
+                    .get(&variant)
+                    .filter(|value| value.basic_type() == BasicType::Object)
+            });
+            nulls.push(object.is_some());
+            if let Some(object) = object {
+                let metadata = MetadataRef::new(variant_array.metadata(i));
+                let object = object
+                    .get_object()
+                    .map_err(ArrowError::InvalidArgumentError)?;
+                for (key, value) in object.with_metadata(&metadata).entries() {
+                    keys.append_value(key);
+                    entries.push((i, value));
+                }
+            }
+            offsets.push(i32_offset(entries.len())?);
+        }
+    }
+    nulls.resize(variant_array.len(), false);
+    offsets.resize(variant_array.len() + 1, i32_offset(entries.len())?);
+
+    let mut value_kind = None;
+    let mut mixed = None;
+    for (_, value) in &entries {
+        let kind = match (value_kind, UnionMember::of(value)) {
+            (_, UnionMember::Null) => continue,
+            (None, kind) => kind,
+            (Some(previous), kind) if previous == kind => continue,
+            (Some(UnionMember::Int64), UnionMember::Float64)
+            | (Some(UnionMember::Float64), UnionMember::Int64) => UnionMember::Float64,
+            (Some(previous), kind) => {
+                mixed = Some((previous, kind));
+                break;
+            }
+        };
+        value_kind = Some(kind);
+    }
+    let values = match (mixed, heterogeneous) {
+        (None, _) => {
+            let data_type = value_kind.map_or(DataType::Null, |kind| kind.data_type());
+            let entries = entries
+                .into_iter()
+                .map(|(i, value)| (i, Some(value).filter(|value| !value.is_null())))
+                .collect();
+            column_from_values(&variant_array, entries, &data_type)?
+        }
+        (Some((previous, kind)), HeterogeneousValues::Error) => {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "The values of the objects at '{}' have different types: {} and {}",
+                path,
+                previous.name(),
+                kind.name()
+            )));
+        }
+        (Some(_), HeterogeneousValues::Stringify) => {
+            Arc::new(stringify_values(&variant_array, &entries)?)
+        }
+    };
+
+    let keys: ArrayRef = Arc::new(keys.finish());
+    let fields = Fields::from(vec![
+        Field::new("keys", DataType::Utf8, false),
+        Field::new("values", values.data_type().clone(), true),
+    ]);
+    let entries = StructArray::try_new(fields.clone(), vec![keys, values], None)?;
+    let field = Arc::new(Field::new("entries", DataType::Struct(fields), false));
+    let nulls = NullBuffer::from(nulls);
+    MapArray::try_new(
+        field,
+        OffsetBuffer::new(offsets.into()),
+        entries,
+        (nulls.null_count() > 0).then_some(nulls),
+        false,
+    )
+}
+
+/// `len` as an offset into the values of a list, map or union array, whose
+/// offsets are `i32`.
+fn i32_offset(len: usize) -> Result<i32, ArrowError> {
+    i32::try_from(len).map_err(|_| {
+        ArrowError::InvalidArgumentError(format!(
+            "{} values don't fit in the i32 offsets of the output",
+            len
+        ))
+    })
+}
+
+/// Convert values taken from the rows of a variant array to strings, for
+/// [`HeterogeneousValues::Stringify`]. Variant nulls are null.
+fn stringify_values(
+    variant_array: &VariantArray,
+    values: &[(usize, VariantRef)],
+) -> Result<StringArray, ArrowError> {
+    let mut builder = StringBuilder::with_capacity(values.len(), 0);
+    let mut json = String::new();
+    for (i, value) in values {
+        if value.is_null() {
+            builder.append_null();
+            continue;
+        }
+        let metadata = MetadataRef::new(variant_array.metadata(*i));
+        if let Some(string) = value.coerce_string_with_metadata(&metadata) {
+            builder.append_value(string);
+        } else {
+            json.clear();
+            write_json(&mut json, value, &metadata, &JsonWriteOptions::default())
+                .map_err(ArrowError::InvalidArgumentError)?;
+            builder.append_value(&json);
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Coerce each value of a variant array to `data_type`.
+///
+/// This is [`flatten_variant_with_options`] for the whole value, so it
+/// supports the same types. Values that don't coerce, and null rows, are
+/// null. See [`coerce`](open_variant::coerce) for the rules.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if the type is not supported.
+pub fn variant_coerce(
+    array: &dyn Array,
+    data_type: &DataType,
+    options: &CoerceOptions,
+) -> Result<ArrayRef, ArrowError> {
+    let options = ExtractOptions {
+        coerce: *options,
+        ..Default::default()
+    };
+    let columns = [(VariantPath::default(), data_type.clone(), "value")];
+    let batch = flatten_variant_with_options(array, &columns, &options)?;
+    Ok(batch.column(0).clone())
+}
+
+/// Whether the value at `path` in each row is one of the values of `list`.
+///
+/// This is `CAST(variant_get(array, path) AS t) IN (list)` for the type `t`
+/// of `list`, which can be `Boolean`, `Int64`, `Float64` or `Utf8`. The list
+/// is turned into a set once, and each value is coerced and looked up
+/// directly, without building an intermediate array. Values are coerced with
+/// `options`, so for example floats with an integral value match `Int64`
+/// lists.
+///
+/// As in SQL, rows where the path doesn't exist or the value doesn't coerce
+/// are null, and so are rows without a match when `list` has a null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if the type of `list` is not
+/// supported.
+pub fn variant_in_list(
+    array: &dyn Array,
+    path: &VariantPath,
+    list: &dyn Array,
+    options: &CoerceOptions,
+) -> Result<BooleanArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let probe_set = ProbeSet::try_new(list)?;
+    let list_has_null = list.null_count() > 0;
+
+    let mut builder = BooleanBuilder::with_capacity(variant_array.len());
+    let mut resolver = variant_array.path_resolver(path);
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            let Some(variant) = variant_array.variant(i) else {
+                builder.append_null();
+                continue;
+            };
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            let found = resolver
+                .resolve(i)
+                .get(&variant)
+                .and_then(|value| probe_set.contains(&value, &metadata, options));
+            builder.append_option(match found {
+                Some(false) if list_has_null => None,
+                found => found,
+            });
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+
+    Ok(builder.finish())
+}
+
+/// The non-null values of an `IN` list, for [`variant_in_list`].
+enum ProbeSet {
+    /// Whether `false` and `true` are in the list.
+    Boolean([bool; 2]),
+    Int64(HashSet<i64>),
+    /// The bits of the floats, see [`float_key`].
+    Float64(HashSet<u64>),
+    Utf8(HashSet<String>),
+}
+
+impl ProbeSet {
+    fn try_new(list: &dyn Array) -> Result<Self, ArrowError> {
+        let probe_set = match list.data_type() {
+            DataType::Boolean => {
+                let mut present = [false; 2];
+                for value in list.as_boolean().iter().flatten() {
+                    present[value as usize] = true;
+                }
+                ProbeSet::Boolean(present)
+            }
+            DataType::Int64 => {
+                ProbeSet::Int64(list.as_primitive::<Int64Type>().iter().flatten().collect())
+            }
+            DataType::Float64 => ProbeSet::Float64(
+                list.as_primitive::<Float64Type>()
+                    .iter()
+                    .flatten()
+                    .map(float_key)
+                    .collect(),
+            ),
+            DataType::Utf8 => ProbeSet::Utf8(
+                list.as_string::<i32>()
+                    .iter()
+                    .flatten()
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            data_type => {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Unsupported type for a variant IN list: {}",
+                    data_type
+                )))
+            }
+        };
+        Ok(probe_set)
+    }
+
+    /// Whether the value is in the set, or `None` if it doesn't coerce to
+    /// the type of the set. `metadata` is the metadata of the value's row,
+    /// for strings stored in the dictionary.
+    fn contains(
+        &self,
+        value: &VariantRef,
+        metadata: &MetadataRef,
+        options: &CoerceOptions,
+    ) -> Option<bool> {
+        match self {
+            ProbeSet::Boolean(present) => value
+                .coerce_bool(options)
+                .map(|value| present[value as usize]),
+            ProbeSet::Int64(set) => value.coerce_i64(options).map(|value| set.contains(&value)),
+            ProbeSet::Float64(set) => value
+                .coerce_f64(options)
+                .map(|value| set.contains(&float_key(value))),
+            ProbeSet::Utf8(set) => value
+                .coerce_string_with_metadata(metadata)
+                .map(|value| set.contains(value.as_ref())),
+        }
+    }
+}
+
+/// The bits of a float, with `-0.0` and `0.0` the same.
+fn float_key(value: f64) -> u64 {
+    if value == 0.0 {
+        0
+    } else {
+        value.to_bits()
+    }
+}
+
+/// Whether the string at `path` in each row matches the SQL `LIKE` pattern
+/// `pattern`.
+///
+/// In the pattern, `%` matches any run of characters, `_` matches a single
+/// character, and `\\` escapes the next character. The pattern is compiled
+/// once, and each string is matched in place, without building an
+/// intermediate string array. Values are coerced to strings as in
+/// [`coerce`](open_variant::coerce), so UUIDs match their canonical form.
+/// Rows where the path doesn't exist or the value doesn't coerce are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_like(
+    array: &dyn Array,
+    path: &VariantPath,
+    pattern: &str,
+) -> Result<BooleanArray, ArrowError> {
+    let pattern = LikePattern::new(pattern);
+    match_strings(array, path, |value| pattern.matches(value))
+}
+
+/// Whether the string at `path` in each row matches the regular expression
+/// `regex`.
+///
+/// As with [`Regex::is_match`](regex::Regex::is_match), the expression can
+/// match anywhere in the string, so anchor it with `^` and `$` to match the
+/// whole string. Each string is matched in place, without building an
+/// intermediate string array. Values are coerced to strings as for
+/// [`variant_like`].
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+#[cfg(feature = "regex")]
+pub fn variant_matches(
+    array: &dyn Array,
+    path: &VariantPath,
+    regex: &regex::Regex,
+) -> Result<BooleanArray, ArrowError> {
+    match_strings(array, path, |value| regex.is_match(value))
+}
+
+/// Apply `matches` to the string at `path` in each row, coerced with
+/// [`coerce_string_with_metadata`](VariantRef::coerce_string_with_metadata).
+/// Rows where the path doesn't exist or the value doesn't coerce are null.
+fn match_strings(
+    array: &dyn Array,
+    path: &VariantPath,
+    matches: impl Fn(&str) -> bool,
+) -> Result<BooleanArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = BooleanBuilder::with_capacity(variant_array.len());
+    let mut resolver = variant_array.path_resolver(path);
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            let Some(variant) = variant_array.variant(i) else {
+                builder.append_null();
+                continue;
+            };
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            let matched = resolver
+                .resolve(i)
+                .get(&variant)
+                .and_then(|value| value.coerce_string_with_metadata(&metadata))
+                .map(|value| matches(&value));
+            builder.append_option(matched);
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+
+    Ok(builder.finish())
+}
+
+/// A compiled SQL `LIKE` pattern, see [`variant_like`].
+struct LikePattern(Vec<LikeToken>);
+
+enum LikeToken {
+    Literal(String),
+    /// `_`
+    AnyChar,
+    /// `%`
+    AnyRun,
+}
+
+impl LikePattern {
+    fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            let literal = match c {
+                '%' => {
+                    if !matches!(tokens.last(), Some(LikeToken::AnyRun)) {
+                        tokens.push(LikeToken::AnyRun);
+                    }
+                    continue;
+                }
+                '_' => {
+                    tokens.push(LikeToken::AnyChar);
+                    continue;
+                }
+                '\\' => chars.next().unwrap_or('\\'),
+                c => c,
+            };
+            match tokens.last_mut() {
+                Some(LikeToken::Literal(string)) => string.push(literal),
+                _ => tokens.push(LikeToken::Literal(literal.to_string())),
+            }
+        }
+        Self(tokens)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        // Match greedily, and on a mismatch go back to the last `%` and let
+        // it match one more character.
+        let (mut position, mut token) = (0, 0);
+        let mut last_run: Option<(usize, usize)> = None;
+        loop {
+            let advance = match self.0.get(token) {
+                Some(LikeToken::AnyRun) => {
+                    last_run = Some((token + 1, position));
+                    Some(0)
+                }
+                Some(LikeToken::AnyChar) => text[position..].chars().next().map(char::len_utf8),
+                Some(LikeToken::Literal(literal)) => text[position..]
+                    .starts_with(literal.as_str())
+                    .then_some(literal.len()),
+                None if position == text.len() => return true,
+                None => None,
+            };
+            if let Some(advance) = advance {
+                position += advance;
+                token += 1;
+                continue;
+            }
+            match last_run {
+                Some((run_end, run_position)) if run_position < text.len() => {
+                    let next = text[run_position..].chars().next().unwrap();
+                    let run_position = run_position + next.len_utf8();
+                    last_run = Some((run_end, run_position));
+                    (position, token) = (run_position, run_end);
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Convert a variant array into a dense union with a member for each kind of
+/// value, for consumers that want Arrow types for heterogeneous columns.
+///
+/// The members are discovered from the data, in the order they first appear:
+///
+/// | Values                                | Member          | Type |
+/// |---------------------------------------|-----------------|------|
+/// | Null rows and variant nulls           | `null`          | `Null` |
+/// | Booleans                              | `boolean`       | `Boolean` |
+/// | Integers                              | `int64`         | `Int64` |
+/// | Floats                                | `double`        | `Float64` |
+/// | Strings                               | `string`        | `Utf8` |
+/// | Timestamps without timezone           | `timestamp_ntz` | `Timestamp(Nanosecond, None)` |
+/// | UUIDs                                 | `uuid`          | `FixedSizeBinary(16)` |
+/// | Anything else                         | `variant`       | The default [`VariantLayout`] |
+///
+/// If there would be more than `max_members` members, only the most frequent
+/// kinds keep their own member, and the values of the others go in the
+/// `variant` member, where null rows are null. The `uuid` and `variant`
+/// members have the `arrow.uuid` and variant extension types, so
+/// [`cast_to_variant`](crate::cast::cast_to_variant) converts the union
+/// back.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or `max_members` is not between 1
+/// and 127.
+pub fn variant_to_union(array: &dyn Array, max_members: usize) -> Result<UnionArray, ArrowError> {
+    if !(1..=i8::MAX as usize).contains(&max_members) {
+        return Err(ArrowError::InvalidArgumentError(format!(
+            "A union must have between 1 and {} members, got {}",
+            i8::MAX,
+            max_members
+        )));
+    }
+    let variant_array = VariantArray::try_new(array)?;
+    let mut kinds = Vec::with_capacity(variant_array.len());
+    for (start, end) in variant_array.valid_slices() {
+        kinds.resize(start, UnionMember::Null);
+        kinds.extend((start..end).map(|i| {
+            variant_array
+                .variant(i)
+                .map_or(UnionMember::Null, |value| UnionMember::of(&value))
+        }));
+    }
+    kinds.resize(variant_array.len(), UnionMember::Null);
+
+    let mut counts: Vec<(UnionMember, usize)> = Vec::new();
+    for kind in &kinds {
+        match counts.iter_mut().find(|(member, _)| member == kind) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((*kind, 1)),
+        }
+    }
+    if counts.len() > max_members {
+        // Keep the most frequent kinds, and the earliest on ties.
+        counts.retain(|(member, _)| *member != UnionMember::Variant);
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts.truncate(max_members - 1);
+    }
+    let own_member = |kind: UnionMember| counts.iter().any(|(member, _)| *member == kind);
+
+    // The values of each member, in the order the members first appear.
+    let mut members: Vec<(UnionMember, RowValues)> = Vec::new();
+    let mut type_ids = Vec::with_capacity(kinds.len());
+    let mut offsets = Vec::with_capacity(kinds.len());
+    for (i, kind) in kinds.into_iter().enumerate() {
+        let kind = if own_member(kind) {
+            kind
+        } else {
+            UnionMember::Variant
+        };
+        let type_id = match members.iter().position(|(member, _)| *member == kind) {
+            Some(type_id) => type_id,
+            None => {
+                members.push((kind, Vec::new()));
+                members.len() - 1
+            }
+        };
+        let values = &mut members[type_id].1;
+        type_ids.push(type_id as i8);
+        offsets.push(i32_offset(values.len())?);
+        values.push((i, variant_array.variant(i)));
+    }
+
+    let mut fields = Vec::with_capacity(members.len());
+    let mut children = Vec::with_capacity(members.len());
+    for (member, values) in members {
+        let child = column_from_values(&variant_array, values, &member.data_type())?;
+        fields.push(match member {
+            UnionMember::Uuid => uuid_field(member.name()),
+            UnionMember::Variant => {
+                VariantLayout::try_from_data_type(child.data_type())?.field(member.name(), true)
+            }
+            _ => Field::new(member.name(), child.data_type().clone(), true),
+        });
+        children.push(child);
+    }
+    let fields = UnionFields::new(0..fields.len() as i8, fields);
+    UnionArray::try_new(fields, type_ids.into(), Some(offsets.into()), children)
+}
+
+/// A member of the union built by [`variant_to_union`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnionMember {
+    Null,
+    Boolean,
+    Int64,
+    Float64,
+    Utf8,
+    TimestampNanoNTZ,
+    Uuid,
+    Variant,
+}
+
+impl UnionMember {
+    fn of(value: &VariantRef) -> Self {
+        if value.basic_type() == BasicType::ShortString {
+            return Self::Utf8;
+        }
+        match primitive_type_id(value) {
+            Some(PrimitiveTypeId::Null) => Self::Null,
+            Some(PrimitiveTypeId::BoolTrue | PrimitiveTypeId::BoolFalse) => Self::Boolean,
+            Some(
+                PrimitiveTypeId::Int8
+                | PrimitiveTypeId::Int16
+                | PrimitiveTypeId::Int32
+                | PrimitiveTypeId::Int64,
+            ) => Self::Int64,
+            Some(PrimitiveTypeId::Float32 | PrimitiveTypeId::Float64) => Self::Float64,
+            Some(PrimitiveTypeId::String | PrimitiveTypeId::StringFromDictionary) => Self::Utf8,
+            // Microsecond timestamps that don't fit in nanoseconds stay
+            // variants.
+            Some(PrimitiveTypeId::TimestampNanoNTZ | PrimitiveTypeId::TimestampMicroNTZ)
+                if value.get_timestamp_nanos_ntz().is_some() =>
+            {
+                Self::TimestampNanoNTZ
+            }
+            Some(PrimitiveTypeId::Uuid) => Self::Uuid,
+            _ => Self::Variant,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean => "boolean",
+            Self::Int64 => "int64",
+            Self::Float64 => "double",
+            Self::Utf8 => "string",
+            Self::TimestampNanoNTZ => "timestamp_ntz",
+            Self::Uuid => "uuid",
+            Self::Variant => "variant",
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            Self::Null => DataType::Null,
+            Self::Boolean => DataType::Boolean,
+            Self::Int64 => DataType::Int64,
+            Self::Float64 => DataType::Float64,
+            Self::Utf8 => DataType::Utf8,
+            Self::TimestampNanoNTZ => DataType::Timestamp(TimeUnit::Nanosecond, None),
+            Self::Uuid => DataType::FixedSizeBinary(16),
+            Self::Variant => crate::variant_type(),
+        }
+    }
+}
+
+/// Values taken from the rows of a variant array, each paired with the row it
+/// came from.
+type RowValues<'a> = Vec<(usize, Option<VariantRef<'a>>)>;
+
+/// Build a column of `data_type` from values taken from the rows of a variant
+/// array.
+///
+/// Variant columns keep the metadata of the rows. `None` values are null.
+fn column_from_values(
+    variant_array: &VariantArray,
+    values: RowValues,
+    data_type: &DataType,
+) -> Result<ArrayRef, ArrowError> {
+    if data_type == &DataType::Null {
+        return Ok(Arc::new(NullArray::new(values.len())));
+    }
+    if let Ok(layout) = VariantLayout::try_from_data_type(data_type) {
+        let mut buffer = Vec::new();
+        let mut offsets = Vec::with_capacity(values.len() + 1);
+        offsets.push(0);
+        for (_, value) in &values {
+            if let Some(value) = value {
+                buffer.extend_from_slice(value.as_bytes());
+            }
+            offsets.push(buffer.len());
+        }
+        let nulls = NullBuffer::from(
+            values
+                .iter()
+                .map(|(_, value)| value.is_some())
+                .collect::<Vec<_>>(),
+        );
+        let nulls = (nulls.null_count() > 0).then_some(nulls);
+        let rows = values.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let values = values_array_from_parts(buffer, &offsets, nulls);
+        let values = variant_array.with_row_values(&rows, values);
+        return VariantArray::try_new(&values)?.to_layout(&layout);
+    }
+    let mut builder = ColumnBuilder::try_new(data_type, values.len())?;
+    for (i, value) in values {
+        let metadata = MetadataRef::new(variant_array.metadata(i));
+        builder.append(value, &metadata, &CoerceOptions::default());
+    }
+    builder.finish(variant_array)
+}
+
+/// Flatten a variant array into one row per leaf value.
+///
+/// The output has a `row` column with the index of the input row, the `path`
+/// of the leaf, its `type` name, and its `value` as a variant in the default
+/// [`VariantLayout`]. Leaves are primitives, strings, and empty objects and
+/// arrays, in document order; see [`VariantRef::leaves`]. A row that is not an
+/// object or array is a single leaf at the empty path. Null rows have no
+/// leaves.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_leaves(array: &dyn Array) -> Result<RecordBatch, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut rows = Vec::new();
+    let mut paths = StringBuilder::new();
+    let mut types = StringBuilder::new();
+    let mut buffer = Vec::new();
+    let mut offsets = vec![0];
+    let valid_rows = variant_array
+        .valid_slices()
+        .flat_map(|(start, end)| start..end);
+    for i in valid_rows {
+        let Some(variant) = variant_array.variant(i) else {
+            continue;
+        };
+        let metadata = MetadataRef::new(variant_array.metadata(i));
+        let leaves = variant
+            .leaves(&metadata)
+            .map_err(ArrowError::InvalidArgumentError)?;
+        for (path, value) in leaves {
+            rows.push(i);
+            paths.append_value(path.to_string());
+            types.append_value(value.type_name());
+            buffer.extend_from_slice(value.as_bytes());
+            offsets.push(buffer.len());
+        }
+    }
+
+    let values = values_array_from_parts(buffer, &offsets, None);
+    let values = variant_array.with_row_values(&rows, values);
+    let values = VariantArray::try_new(&values)?.to_layout(&VariantLayout::default())?;
+    let rows = UInt64Array::from_iter_values(rows.iter().map(|i| *i as u64));
+    let schema = Schema::new(vec![
+        Field::new("row", DataType::UInt64, false),
+        Field::new("path", DataType::Utf8, false),
+        Field::new("type", DataType::Utf8, false),
+        VariantLayout::default().field("value", false),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(rows),
+            Arc::new(paths.finish()),
+            Arc::new(types.finish()),
+            values,
+        ],
+    )
+}
+
+/// The primitive type of a variant, or `None` if it isn't a primitive.
+fn primitive_type_id(variant: &VariantRef) -> Option<PrimitiveTypeId> {
+    (variant.basic_type() == BasicType::Primitive).then(|| variant.primitive_type_id())
+}
+
+/// Builds a typed output column from extracted values.
+enum ColumnBuilder {
+    Boolean(BooleanBuilder),
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+    Uuid(FixedSizeBinaryBuilder),
+    TimestampNanoNTZ(TimestampNanosecondBuilder),
+    Variant {
+        layout: VariantLayout,
+        buffer: Vec<u8>,
+        offsets: Vec<usize>,
+        validity: Vec<bool>,
+    },
+}
+
+impl ColumnBuilder {
+    fn try_new(data_type: &DataType, capacity: usize) -> Result<Self, ArrowError> {
+        match data_type {
+            DataType::Boolean => Ok(Self::Boolean(BooleanBuilder::with_capacity(capacity))),
+            DataType::Int64 => Ok(Self::Int64(Int64Builder::with_capacity(capacity))),
+            DataType::Float64 => Ok(Self::Float64(Float64Builder::with_capacity(capacity))),
+            DataType::Utf8 => Ok(Self::Utf8(StringBuilder::with_capacity(capacity, 0))),
+            DataType::Timestamp(TimeUnit::Nanosecond, None) => Ok(Self::TimestampNanoNTZ(
+                TimestampNanosecondBuilder::with_capacity(capacity),
+            )),
+            DataType::FixedSizeBinary(16) => Ok(Self::Uuid(FixedSizeBinaryBuilder::with_capacity(
+                capacity, 16,
+            ))),
+            DataType::Struct(_) => {
+                let layout = VariantLayout::try_from_data_type(data_type)?;
+                let mut offsets = Vec::with_capacity(capacity + 1);
+                offsets.push(0);
+                Ok(Self::Variant {
+                    layout,
+                    buffer: Vec::new(),
+                    offsets,
+                    validity: Vec::with_capacity(capacity),
+                })
+            }
+            _ => Err(ArrowError::InvalidArgumentError(format!(
+                "Unsupported type for variant extraction: {}",
+                data_type
+            ))),
+        }
+    }
+
+    /// Append a value coerced to the type of the column. `metadata` is the
+    /// metadata of the row the value came from.
+    fn append(
+        &mut self,
+        value: Option<VariantRef>,
+        metadata: &MetadataRef,
+        options: &CoerceOptions,
+    ) {
+        match self {
+            Self::Boolean(builder) => {
+                builder.append_option(value.and_then(|value| value.coerce_bool(options)))
+            }
+            Self::Int64(builder) => {
+                builder.append_option(value.and_then(|value| value.coerce_i64(options)))
+            }
+            Self::Float64(builder) => {
+                builder.append_option(value.and_then(|value| value.coerce_f64(options)))
+            }
+            Self::Utf8(builder) => builder
+                .append_option(value.and_then(|value| value.coerce_string_with_metadata(metadata))),
+            Self::TimestampNanoNTZ(builder) => {
+                builder.append_option(value.and_then(|value| match primitive_type_id(&value)? {
+                    PrimitiveTypeId::TimestampNanoNTZ | PrimitiveTypeId::TimestampMicroNTZ => {
+                        value.get_timestamp_nanos_ntz()
+                    }
+                    _ => None,
+                }))
+            }
+            Self::Uuid(builder) => match value.and_then(|value| match primitive_type_id(&value)? {
+                PrimitiveTypeId::Uuid => Some(value.get_uuid()),
+                _ => None,
+            }) {
+                Some(uuid) => builder.append_value(uuid).expect("UUIDs are 16 bytes"),
+                None => builder.append_null(),
+            },
+            Self::Variant {
+                buffer,
+                offsets,
+                validity,
+                ..
+            } => {
+                if let Some(value) = &value {
+                    buffer.extend_from_slice(value.as_bytes());
+                }
+                offsets.push(buffer.len());
+                validity.push(value.is_some());
+            }
+        }
+    }
+
+    fn append_nulls(&mut self, n: usize) {
+        match self {
+            Self::Boolean(builder) => builder.append_nulls(n),
+            Self::Int64(builder) => builder.append_nulls(n),
+            Self::Float64(builder) => builder.append_nulls(n),
+            Self::TimestampNanoNTZ(builder) => builder.append_nulls(n),
+            Self::Utf8(builder) => (0..n).for_each(|_| builder.append_null()),
+            Self::Uuid(builder) => (0..n).for_each(|_| builder.append_null()),
+            Self::Variant {
+                buffer,
+                offsets,
+                validity,
+                ..
+            } => {
+                offsets.resize(offsets.len() + n, buffer.len());
+                validity.resize(validity.len() + n, false);
+            }
+        }
+    }
+
+    fn finish(self, variant_array: &VariantArray) -> Result<ArrayRef, ArrowError> {
+        match self {
+            Self::Boolean(mut builder) => Ok(Arc::new(builder.finish())),
+            Self::Int64(mut builder) => Ok(Arc::new(builder.finish())),
+            Self::Float64(mut builder) => Ok(Arc::new(builder.finish())),
+            Self::Utf8(mut builder) => Ok(Arc::new(builder.finish())),
+            Self::Uuid(mut builder) => Ok(Arc::new(builder.finish())),
+            Self::TimestampNanoNTZ(mut builder) => Ok(Arc::new(builder.finish())),
+            Self::Variant {
+                layout,
+                buffer,
+                offsets,
+                validity,
+            } => {
+                let nulls = NullBuffer::from(validity);
+                let nulls = (nulls.null_count() > 0).then_some(nulls);
+                let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+                // Extracted values share the metadata of the rows they came from.
+                let values = variant_array.with_values(values, nulls);
+                VariantArray::try_new(&values)?.to_layout(&layout)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::types::{Int8Type, UInt64Type};
+    use arrow_array::{BinaryArray, Int64Array, StringArray};
+    use open_variant::metadata::build_metadata;
+    use open_variant::values::write::{write_string, ArrayBuilder, ObjectBuilder};
+
+    use super::*;
+    use crate::json::variant_from_json;
+    use crate::layout::MetadataEncoding;
+
+    #[test]
+    fn test_flatten_variant() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"id": 1, "name": "a", "tags": {"ok": true}, "score": 1.5}"#),
+            Some(r#"{"id": 2, "name": 3, "tags": {"ok": false}, "score": 2}"#),
+            Some(r#"{"other": 1}"#),
+            None,
+            Some("[1, 2]"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = |path: &str| VariantPath::parse(path).unwrap();
+        let batch = flatten_variant(
+            &array,
+            &[
+                (path("id"), DataType::Int64, "id"),
+                (path("name"), DataType::Utf8, "name"),
+                (path("tags.ok"), DataType::Boolean, "ok"),
+                (path("score"), DataType::Float64, "score"),
+                (path("tags"), crate::variant_type(), "tags"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(batch.num_rows(), 5);
+        assert_eq!(
+            batch.schema().field(0),
+            &Field::new("id", DataType::Int64, true)
+        );
+
+        let ids = batch.column(0).as_primitive::<Int64Type>();
+        assert_eq!(
+            ids.iter().collect::<Vec<_>>(),
+            vec![Some(1), Some(2), None, None, None]
+        );
+
+        // Wrong types are null
+        let names = batch.column(1).as_string::<i32>();
+        assert_eq!(
+            names.iter().collect::<Vec<_>>(),
+            vec![Some("a"), None, None, None, None]
+        );
+
+        let ok = batch.column(2).as_boolean();
+        assert_eq!(
+            ok.iter().collect::<Vec<_>>(),
+            vec![Some(true), Some(false), None, None, None]
+        );
+
+        // Integers are converted to floats
+        let scores = batch.column(3).as_primitive::<Float64Type>();
+        assert_eq!(
+            scores.iter().collect::<Vec<_>>(),
+            vec![Some(1.5), Some(2.0), None, None, None]
+        );
+
+        let tags = VariantArray::try_new(batch.column(4)).unwrap();
+        assert_eq!(tags.len(), 5);
+        let metadata = MetadataRef::new(tags.metadata(0));
+        let ok = tags
+            .variant(0)
+            .unwrap()
+            .get_path(&path("ok"), &metadata)
+            .unwrap();
+        assert!(ok.get_bool());
+        assert!(tags.variant(2).is_none());
+    }
+
+    #[test]
+    fn test_flatten_null_runs() {
+        // Runs of null rows at the start, middle and end are appended in bulk.
+        let jsons = StringArray::from(vec![
+            None,
+            None,
+            Some(r#"{"id": 1, "name": "a"}"#),
+            None,
+            None,
+            None,
+            Some(r#"{"id": 2}"#),
+            Some(r#"{"id": 3, "name": "c"}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = |path: &str| VariantPath::parse(path).unwrap();
+        let batch = flatten_variant(
+            &array,
+            &[
+                (path("id"), DataType::Int64, "id"),
+                (path("name"), DataType::Utf8, "name"),
+                (path("name"), crate::variant_type(), "variant"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(batch.num_rows(), 9);
+        let ids = batch.column(0).as_primitive::<Int64Type>();
+        assert_eq!(
+            ids.iter().collect::<Vec<_>>(),
+            vec![
+                None,
+                None,
+                Some(1),
+                None,
+                None,
+                None,
+                Some(2),
+                Some(3),
+                None
+            ]
+        );
+        let names = batch.column(1).as_string::<i32>();
+        assert_eq!(
+            names.iter().collect::<Vec<_>>(),
+            vec![
+                None,
+                None,
+                Some("a"),
+                None,
+                None,
+                None,
+                None,
+                Some("c"),
+                None
+            ]
+        );
+        let variants = VariantArray::try_new(batch.column(2)).unwrap();
+        let valid = (0..variants.len())
+            .map(|i| variants.variant(i).map(|value| value.get_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(valid, names.iter().collect::<Vec<_>>());
+        assert_eq!(
+            variants.valid_slices().collect::<Vec<_>>(),
+            vec![(2, 3), (7, 8)]
+        );
+
+        let all_null = variant_from_json(&StringArray::from(vec![None::<&str>; 3])).unwrap();
+        let likes = variant_like(&all_null, &path("name"), "%").unwrap();
+        assert_eq!(likes.null_count(), 3);
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let jsons = StringArray::from(vec!["1"]);
+        let array = variant_from_json(&jsons).unwrap();
+        let result = flatten_variant(
+            &array,
+            &[(VariantPath::default(), DataType::Date32, "date")],
+        );
+        assert!(
+            matches!(result, Err(ArrowError::InvalidArgumentError(message))
+            if message.contains("Unsupported type for variant extraction: Date32"))
+        );
+    }
+
+    #[test]
+    fn test_variant_coerce() {
+        let jsons = StringArray::from(vec![
+            Some("1"),
+            Some("2.0"),
+            Some("2.5"),
+            Some(r#""3""#),
+            Some("true"),
+            None,
+            Some("100000000000000000000"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+
+        let ints = variant_coerce(&array, &DataType::Int64, &CoerceOptions::default()).unwrap();
+        assert_eq!(
+            ints.as_primitive::<Int64Type>().iter().collect::<Vec<_>>(),
+            vec![Some(1), Some(2), None, None, None, None, None]
+        );
+        let options = CoerceOptions {
+            parse_strings: true,
+        };
+        let doubles = variant_coerce(&array, &DataType::Float64, &options).unwrap();
+        assert_eq!(
+            doubles
+                .as_primitive::<Float64Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![
+                Some(1.0),
+                Some(2.0),
+                Some(2.5),
+                Some(3.0),
+                None,
+                None,
+                Some(1e20)
+            ]
+        );
+        let bools = variant_coerce(&array, &DataType::Boolean, &options).unwrap();
+        assert_eq!(bools.as_boolean().true_count(), 1);
+    }
+
+    #[test]
+    fn test_dictionary_strings_coerce() {
+        // "apple pie" and ["apple pie", "x"], with "apple pie" stored in the
+        // metadata.
+        let metadata = build_metadata(["apple pie"].into_iter());
+        let mut dictionary_string = vec![(PrimitiveTypeId::StringFromDictionary as u8) << 2];
+        dictionary_string.extend_from_slice(&0_u32.to_le_bytes());
+        let mut inline_string = Vec::new();
+        write_string(&mut inline_string, "x");
+        let mut list = Vec::new();
+        let mut builder = ArrayBuilder::new(&mut list, 2);
+        builder.append_value(&dictionary_string);
+        builder.append_value(&inline_string);
+        builder.finish();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = StructArray::new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&metadata[..], &metadata[..]])) as ArrayRef,
+                Arc::new(BinaryArray::from_vec(vec![
+                    &dictionary_string[..],
+                    &list[..],
+                ])),
+            ],
+            None,
+        );
+        let strings = |array: &ArrayRef| {
+            let strings = array.as_string::<i32>().iter();
+            strings
+                .map(|string| string.map(str::to_string))
+                .collect::<Vec<_>>()
+        };
+
+        let coerced = variant_coerce(&array, &DataType::Utf8, &CoerceOptions::default()).unwrap();
+        assert_eq!(strings(&coerced), vec![Some("apple pie".to_string()), None]);
+
+        let columns = [(VariantPath::parse("[0]").unwrap(), DataType::Utf8, "first")];
+        let batch = flatten_variant(&array, &columns).unwrap();
+        assert_eq!(
+            strings(batch.column(0)),
+            vec![None, Some("apple pie".to_string())]
+        );
+
+        let path = VariantPath::parse("[*]").unwrap();
+        let lists = variant_get_list(&array, &path, &DataType::Utf8).unwrap();
+        assert_eq!(
+            strings(lists.as_list::<i32>().values()),
+            vec![Some("apple pie".to_string()), Some("x".to_string())]
+        );
+
+        let union = variant_to_union(&array, 8).unwrap();
+        assert_eq!(union.type_id(0), 0);
+        assert_eq!(strings(union.child(0)), vec![Some("apple pie".to_string())]);
+    }
+
+    #[test]
+    fn test_variant_to_union() {
+        let jsons = StringArray::from(vec![
+            Some("1"),
+            Some(r#""a""#),
+            Some("2.5"),
+            None,
+            Some("null"),
+            Some(r#"{"x": 1}"#),
+            Some("[1]"),
+            Some("3"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+
+        let union = variant_to_union(&array, 10).unwrap();
+        let names = |union: &UnionArray| match union.data_type() {
+            DataType::Union(fields, _) => fields
+                .iter()
+                .map(|(_, field)| field.name().clone())
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            names(&union),
+            vec!["int64", "string", "double", "null", "variant"]
+        );
+        let type_ids = |union: &UnionArray| {
+            (0..union.len())
+                .map(|i| union.type_id(i))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(type_ids(&union), vec![0, 1, 2, 3, 3, 4, 4, 0]);
+        assert_eq!(
+            (0..union.len())
+                .map(|i| union.value_offset(i))
+                .collect::<Vec<_>>(),
+            vec![0, 0, 0, 0, 1, 0, 1, 1]
+        );
+        assert_eq!(
+            union.child(0).as_primitive::<Int64Type>().values().to_vec(),
+            vec![1, 3]
+        );
+        assert_eq!(union.child(1).as_string::<i32>().value(0), "a");
+        assert_eq!(union.child(3).len(), 2);
+        let objects = VariantArray::try_new(union.child(4)).unwrap();
+        assert_eq!(objects.variant(0).unwrap().type_name(), "object");
+        assert_eq!(objects.variant(1).unwrap().type_name(), "array");
+
+        // Casting the union back gives the same values, except that the null
+        // row becomes a variant null.
+        let back = crate::cast::cast_to_variant(&union).unwrap();
+        let back = VariantArray::try_new(&back).unwrap();
+        assert_eq!(back.variant(0).unwrap().get_i64(), 1);
+        assert_eq!(back.variant(1).unwrap().get_str(), Some("a"));
+        assert!(back.is_null_or_variant_null(3));
+        let metadata = MetadataRef::new(back.metadata(5));
+        let object = back.variant(5).unwrap().get_object().unwrap();
+        assert_eq!(object.find_field("x", &metadata).unwrap().get_i64(), 1);
+        assert_eq!(back.variant(6).unwrap().type_name(), "array");
+        assert_eq!(back.variant(7).unwrap().get_i64(), 3);
+
+        // Only the most frequent kind keeps its own member.
+        let union = variant_to_union(&array, 2).unwrap();
+        assert_eq!(names(&union), vec!["int64", "variant"]);
+        assert_eq!(type_ids(&union), vec![0, 1, 1, 1, 1, 1, 1, 0]);
+        let variants = VariantArray::try_new(union.child(1)).unwrap();
+        assert_eq!(variants.len(), 6);
+        assert!(variants.is_null(2));
+        assert!(variants.is_null_or_variant_null(3));
+
+        assert!(variant_to_union(&array, 0).is_err());
+    }
+
+    #[test]
+    fn test_micro_timestamps_out_of_nanosecond_range() {
+        let micros = arrow_array::TimestampMicrosecondArray::from(vec![1, i64::MAX / 1000 + 1]);
+        let array = crate::cast::cast_to_variant(&micros).unwrap();
+
+        let nanos = DataType::Timestamp(TimeUnit::Nanosecond, None);
+        let columns = [(VariantPath::default(), nanos, "ts")];
+        let batch = flatten_variant(&array, &columns).unwrap();
+        let column = batch
+            .column(0)
+            .as_primitive::<arrow_array::types::TimestampNanosecondType>();
+        assert_eq!(column.iter().collect::<Vec<_>>(), vec![Some(1000), None]);
+
+        // The timestamp that doesn't fit stays a variant.
+        let union = variant_to_union(&array, 10).unwrap();
+        assert_eq!(
+            (0..union.len())
+                .map(|i| union.type_id(i))
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        let variants = VariantArray::try_new(union.child(1)).unwrap();
+        assert_eq!(variants.variant(0).unwrap().type_name(), "timestamp_ntz");
+    }
+
+    #[test]
+    fn test_variant_extract_all() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"store": {"book": [{"author": "a", "x": {"author": "b"}}], "author": 1}}"#),
+            Some(r#"{"book": []}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = VariantPath::parse("..author").unwrap();
+        let authors = variant_extract_all(&array, &path).unwrap();
+        let authors = authors.as_list::<i32>();
+        assert_eq!(authors.values().data_type(), &crate::variant_type());
+        assert_eq!(authors.value_offsets(), &[0, 3, 3, 3]);
+        assert!(authors.is_null(2));
+
+        let values = VariantArray::try_new(authors.values()).unwrap();
+        let values = (0..values.len())
+            .map(|i| values.variant(i).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values[0].get_i64(), 1);
+        assert_eq!(values[1].get_str(), Some("a"));
+        assert_eq!(values[2].get_str(), Some("b"));
+    }
+
+    #[test]
+    fn test_variant_get_list() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"items": [{"price": 1}, {"price": 2.5}, {"other": 3}]}"#),
+            Some(r#"{"items": []}"#),
+            None,
+            Some(r#"{"items": [{"price": "x"}]}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = VariantPath::parse("items[*].price").unwrap();
+
+        let prices = variant_get_list(&array, &path, &DataType::Float64).unwrap();
+        let prices = prices.as_list::<i32>();
+        assert_eq!(prices.len(), 4);
+        assert!(prices.is_null(2));
+        let row = |i| {
+            let values = prices.value(i);
+            values
+                .as_primitive::<Float64Type>()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(row(0), vec![Some(1.0), Some(2.5)]);
+        assert_eq!(row(1), Vec::<Option<f64>>::new());
+        assert_eq!(row(3), vec![None]);
+
+        let prices = variant_get_list(&array, &path, &crate::variant_type()).unwrap();
+        let prices = prices.as_list::<i32>();
+        assert_eq!(prices.values().data_type(), &crate::variant_type());
+        let values = VariantArray::try_new(prices.values()).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.variant(1).unwrap().get_f64(), 2.5);
+        assert_eq!(values.variant(2).unwrap().get_string(), "x");
+        // Elements share the metadata of their row.
+        assert_eq!(
+            values.metadata(2),
+            VariantArray::try_new(&array).unwrap().metadata(3)
+        );
+    }
+
+    #[test]
+    fn test_variant_output_with_plain_metadata() {
+        // More rows than an Int8 dictionary has keys, all with the same
+        // metadata.
+        let jsons = StringArray::from_iter_values(
+            (0..200).map(|i| format!(r#"{{"items": [{{"price": {}}}]}}"#, i)),
+        );
+        let array = variant_from_json(&jsons).unwrap();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = VariantArray::try_new(&array)
+            .unwrap()
+            .to_layout(&layout)
+            .unwrap();
+        let path = VariantPath::parse("items[*]").unwrap();
+
+        let items = variant_get_list(&array, &path, &crate::variant_type()).unwrap();
+        let items = items.as_list::<i32>();
+        let values = VariantArray::try_new(items.values()).unwrap();
+        assert_eq!(values.len(), 200);
+        assert_eq!(values.metadata(199), values.metadata(0));
+        let values = items
+            .values()
+            .as_struct()
+            .column(0)
+            .as_dictionary::<Int8Type>();
+        assert_eq!(values.values().len(), 1);
+
+        let path = VariantPath::parse("..price").unwrap();
+        let prices = variant_extract_all(&array, &path).unwrap();
+        let values = VariantArray::try_new(prices.as_list::<i32>().values()).unwrap();
+        assert_eq!(values.variant(199).unwrap().get_i64(), 199);
+
+        let union = variant_to_union(&array, 10).unwrap();
+        assert_eq!(union.len(), 200);
+    }
+
+    #[test]
+    fn test_variant_get_map() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"tags": {"b": 2, "a": 1.5}}"#),
+            Some(r#"{"tags": {}}"#),
+            None,
+            Some(r#"{"tags": [1]}"#),
+            Some(r#"{"tags": {"c": null}}"#),
+            Some(r#"{"other": 1}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = VariantPath::parse("tags").unwrap();
+
+        let map = variant_get_map(&array, &path, HeterogeneousValues::Error).unwrap();
+        assert_eq!(map.len(), 6);
+        assert_eq!(
+            (0..6).map(|i| map.is_valid(i)).collect::<Vec<_>>(),
+            vec![true, true, false, false, true, false]
+        );
+        assert_eq!(map.value_offsets(), &[0, 2, 2, 2, 2, 3, 3]);
+        assert_eq!(
+            map.keys().as_string::<i32>().iter().collect::<Vec<_>>(),
+            vec![Some("a"), Some("b"), Some("c")]
+        );
+        // Integers and floats give Float64 values.
+        assert_eq!(
+            map.values()
+                .as_primitive::<Float64Type>()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![Some(1.5), Some(2.0), None]
+        );
+
+        let jsons = StringArray::from(vec![
+            r#"{"a": "x", "b": 1}"#,
+            r#"{"c": {"d": [true]}, "e": null}"#,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let err = variant_get_map(&array, &VariantPath::default(), HeterogeneousValues::Error)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("different types: string and int64"));
+
+        let map = variant_get_map(
+            &array,
+            &VariantPath::default(),
+            HeterogeneousValues::Stringify,
+        )
+        .unwrap();
+        assert_eq!(
+            map.values().as_string::<i32>().iter().collect::<Vec<_>>(),
+            vec![Some("x"), Some("1"), Some(r#"{"d":[true]}"#), None]
+        );
+
+        // Only nulls, and objects with the same kind of values.
+        let jsons = StringArray::from(vec![r#"{"a": null}"#, r#"{"b": {"x": 1}, "c": [2]}"#]);
+        let array = variant_from_json(&jsons).unwrap();
+        let map = variant_get_map(
+            &array.slice(0, 1),
+            &VariantPath::default(),
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(map.values().data_type(), &DataType::Null);
+        let map = variant_get_map(&array, &VariantPath::default(), Default::default()).unwrap();
+        assert_eq!(map.values().data_type(), &crate::variant_type());
+    }
+
+    #[test]
+    fn test_variant_get_map_dictionary_strings() {
+        // {"a": "x", "k": "apple pie"}, with "apple pie" stored in the
+        // metadata.
+        let metadata = build_metadata(["a", "apple pie", "k"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let id = metadata_ref.find_string("apple pie").unwrap() as u32;
+        let mut dictionary_string = vec![(PrimitiveTypeId::StringFromDictionary as u8) << 2];
+        dictionary_string.extend_from_slice(&id.to_le_bytes());
+        let mut inline_string = Vec::new();
+        write_string(&mut inline_string, "x");
+        let mut value = Vec::new();
+        let mut object = ObjectBuilder::with_capacity(&mut value, &metadata_ref, 2);
+        object.append_value("a", &inline_string).unwrap();
+        object.append_value("k", &dictionary_string).unwrap();
+        object.finish();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = StructArray::new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&metadata[..]])) as ArrayRef,
+                Arc::new(BinaryArray::from_vec(vec![&value[..]])),
+            ],
+            None,
+        );
+
+        let map =
+            variant_get_map(&array, &VariantPath::default(), HeterogeneousValues::Error).unwrap();
+        assert_eq!(
+            map.keys().as_string::<i32>().iter().collect::<Vec<_>>(),
+            vec![Some("a"), Some("k")]
+        );
+        assert_eq!(
+            map.values().as_string::<i32>().iter().collect::<Vec<_>>(),
+            vec![Some("x"), Some("apple pie")]
+        );
+    }
+
+    #[test]
+    fn test_variant_leaves() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": {"b": 1, "c": []}, "d": ["x", true]}"#),
+            None,
+            Some("2.5"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let batch = variant_leaves(&array).unwrap();
+        assert_eq!(batch.num_rows(), 5);
+
+        let rows = batch.column(0).as_primitive::<UInt64Type>();
+        assert_eq!(rows.values().to_vec(), vec![0, 0, 0, 0, 2]);
+        let strings = |i: usize| {
+            let column = batch.column(i).as_string::<i32>();
+            column.iter().map(Option::unwrap).collect::<Vec<_>>()
+        };
+        assert_eq!(strings(1), vec!["a.b", "a.c", "d[0]", "d[1]", ""]);
+        assert_eq!(
+            strings(2),
+            vec!["int64", "array", "string", "boolean", "double"]
+        );
+
+        let values = VariantArray::try_new(batch.column(3)).unwrap();
+        assert_eq!(values.variant(0).unwrap().get_i64(), 1);
+        assert_eq!(values.variant(2).unwrap().get_string(), "x");
+        assert_eq!(values.variant(4).unwrap().get_f64(), 2.5);
+    }
+
+    #[test]
+    fn test_variant_leaves_plain_metadata() {
+        // More leaves than an Int8 dictionary has keys, from rows with
+        // their own copy of the same metadata.
+        let jsons =
+            StringArray::from_iter_values((0..200).map(|i| format!(r#"{{"a": {}, "b": "x"}}"#, i)));
+        let array = variant_from_json(&jsons).unwrap();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = VariantArray::try_new(&array)
+            .unwrap()
+            .to_layout(&layout)
+            .unwrap();
+
+        let batch = variant_leaves(&array).unwrap();
+        assert_eq!(batch.num_rows(), 400);
+        let values = VariantArray::try_new(batch.column(3)).unwrap();
+        assert_eq!(values.variant(398).unwrap().get_i64(), 199);
+        assert_eq!(values.variant(399).unwrap().get_string(), "x");
+        let metadata = batch
+            .column(3)
+            .as_struct()
+            .column(0)
+            .as_dictionary::<Int8Type>();
+        assert_eq!(metadata.values().len(), 1);
+    }
+
+    #[test]
+    fn test_flatten_case_insensitive() {
+        let jsons = StringArray::from(vec![r#"{"Name": "a"}"#, r#"{"NAME": "b"}"#, r#"{}"#]);
+        let array = variant_from_json(&jsons).unwrap();
+        let columns = [(VariantPath::parse("name").unwrap(), DataType::Utf8, "name")];
+
+        let batch = flatten_variant(&array, &columns).unwrap();
+        assert_eq!(batch.column(0).null_count(), 3);
+
+        let options = ExtractOptions {
+            case_insensitive_keys: true,
+            ..Default::default()
+        };
+        let batch = flatten_variant_with_options(&array, &columns, &options).unwrap();
+        let names = batch.column(0).as_string::<i32>();
+        assert_eq!(
+            names.iter().collect::<Vec<_>>(),
+            vec![Some("a"), Some("b"), None]
+        );
+    }
+
+    #[test]
+    fn test_flatten_uuid() {
+        use crate::json::{variant_from_json_with_options, JsonParseOptions};
+
+        let uuid = "f81d4fae-7dec-11d0-a765-00a0c91e6bf6";
+        let jsons = StringArray::from(vec![format!("{{\"id\": \"{}\"}}", uuid)]);
+        let options = JsonParseOptions {
+            uuid_strings: true,
+            ..Default::default()
+        };
+        let array = variant_from_json_with_options(&jsons, &options).unwrap();
+        let path = |path: &str| VariantPath::parse(path).unwrap();
+        let batch = flatten_variant(
+            &array,
+            &[
+                (path("id"), DataType::FixedSizeBinary(16), "bytes"),
+                (path("id"), DataType::Utf8, "string"),
+            ],
+        )
+        .unwrap();
+
+        let bytes = batch.column(0).as_fixed_size_binary();
+        assert_eq!(bytes.value(0)[0], 0xf8);
+        assert_eq!(bytes.value(0).len(), 16);
+        assert_eq!(batch.column(1).as_string::<i32>().value(0), uuid);
+    }
+
+    #[test]
+    fn test_variant_in_list() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"k": 1}"#),
+            Some(r#"{"k": 2.0}"#),
+            Some(r#"{"k": "3"}"#),
+            Some(r#"{"k": 5}"#),
+            Some(r#"{"other": 1}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = VariantPath::parse("k").unwrap();
+        let in_list = |list: &dyn Array, options: &CoerceOptions| {
+            let result = variant_in_list(&array, &path, list, options).unwrap();
+            result.iter().collect::<Vec<_>>()
+        };
+
+        let list = Int64Array::from(vec![1, 2, 3]);
+        assert_eq!(
+            in_list(&list, &CoerceOptions::default()),
+            vec![Some(true), Some(true), None, Some(false), None, None]
+        );
+        let options = CoerceOptions {
+            parse_strings: true,
+        };
+        assert_eq!(in_list(&list, &options)[2], Some(true));
+
+        // Without a match, a null in the list gives null.
+        let list = Int64Array::from(vec![Some(1), None]);
+        assert_eq!(
+            in_list(&list, &CoerceOptions::default()),
+            vec![Some(true), None, None, None, None, None]
+        );
+
+        let list = StringArray::from(vec!["3", "x"]);
+        assert_eq!(
+            in_list(&list, &CoerceOptions::default()),
+            vec![None, None, Some(true), None, None, None]
+        );
+
+        let list = arrow_array::Float64Array::from(vec![2.0, 5.5]);
+        assert_eq!(
+            in_list(&list, &CoerceOptions::default()),
+            vec![Some(false), Some(true), None, Some(false), None, None]
+        );
+
+        let list = arrow_array::Date32Array::from(vec![1]);
+        assert!(variant_in_list(&array, &path, &list, &CoerceOptions::default()).is_err());
+
+        // {"k": "apple pie"}, with the string stored in the metadata.
+        let metadata = build_metadata(["apple pie", "k"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let id = metadata_ref.find_string("apple pie").unwrap() as u32;
+        let mut string = vec![(PrimitiveTypeId::StringFromDictionary as u8) << 2];
+        string.extend_from_slice(&id.to_le_bytes());
+        let mut value = Vec::new();
+        let mut object = ObjectBuilder::with_capacity(&mut value, &metadata_ref, 1);
+        object.append_value("k", &string).unwrap();
+        object.finish();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = StructArray::new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&metadata[..]])) as ArrayRef,
+                Arc::new(BinaryArray::from_vec(vec![&value[..]])),
+            ],
+            None,
+        );
+        let path = VariantPath::parse("k").unwrap();
+        let in_list = |list: &[&str]| {
+            let list = StringArray::from(list.to_vec());
+            let result = variant_in_list(&array, &path, &list, &CoerceOptions::default());
+            result.unwrap().value(0)
+        };
+        assert!(in_list(&["x", "apple pie"]));
+        assert!(!in_list(&["apple"]));
+    }
+
+    #[test]
+    fn test_variant_like() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"k": "apple pie"}"#),
+            Some(r#"{"k": "applé"}"#),
+            Some(r#"{"k": "50%"}"#),
+            Some(r#"{"k": 1}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = VariantPath::parse("k").unwrap();
+        let like = |pattern: &str| {
+            let result = variant_like(&array, &path, pattern).unwrap();
+            result.iter().collect::<Vec<_>>()
+        };
+
+        let t = Some(true);
+        let f = Some(false);
+        assert_eq!(like("app%"), vec![t, t, f, None, None]);
+        assert_eq!(like("%pie"), vec![t, f, f, None, None]);
+        assert_eq!(like("appl_"), vec![f, t, f, None, None]);
+        assert_eq!(like("%p%p%"), vec![t, t, f, None, None]);
+        assert_eq!(like(r"%\%"), vec![f, f, t, None, None]);
+        assert_eq!(like("%"), vec![t, t, t, None, None]);
+        assert_eq!(like("apple"), vec![f, f, f, None, None]);
+
+        // {"k": "apple pie"}, with the string stored in the metadata.
+        let metadata = build_metadata(["apple pie", "k"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let id = metadata_ref.find_string("apple pie").unwrap() as u32;
+        let mut string = vec![(PrimitiveTypeId::StringFromDictionary as u8) << 2];
+        string.extend_from_slice(&id.to_le_bytes());
+        let mut value = Vec::new();
+        let mut object = ObjectBuilder::with_capacity(&mut value, &metadata_ref, 1);
+        object.append_value("k", &string).unwrap();
+        object.finish();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = StructArray::new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&metadata[..]])) as ArrayRef,
+                Arc::new(BinaryArray::from_vec(vec![&value[..]])),
+            ],
+            None,
+        );
+        let result = variant_like(&array, &path, "%pie").unwrap();
+        assert_eq!(result.iter().collect::<Vec<_>>(), vec![t]);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn test_variant_matches() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"k": "apple pie"}"#),
+            Some(r#"{"k": "applé"}"#),
+            Some(r#"{"k": "50%"}"#),
+            Some(r#"{"k": 1}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = VariantPath::parse("k").unwrap();
+        let matches = |regex: &str| {
+            let regex = regex::Regex::new(regex).unwrap();
+            let result = variant_matches(&array, &path, &regex).unwrap();
+            result.iter().collect::<Vec<_>>()
+        };
+
+        let t = Some(true);
+        let f = Some(false);
+        assert_eq!(matches("^app"), vec![t, t, f, None, None]);
+        assert_eq!(matches("pie$"), vec![t, f, f, None, None]);
+        assert_eq!(matches(r"^appl\w$"), vec![f, t, f, None, None]);
+        assert_eq!(matches(r"\d+%"), vec![f, f, t, None, None]);
+        assert_eq!(matches(""), vec![t, t, t, None, None]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Exchange variant arrays through the Arrow C data interface.
+//!
+//! Other engines, such as DuckDB extensions or C++ engines, pass arrays as
+//! [`FFI_ArrowArray`] and [`FFI_ArrowSchema`] structs. The schema of a variant
+//! array is a field tagged with the variant
+//! [`EXTENSION_NAME`](crate::layout::EXTENSION_NAME), so the consumer can
+//! tell it from any other struct. The readers of single values are in the C
+//! interface of `open-variant`, with its `ffi` feature.
+
+use arrow_array::ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::{make_array, Array, ArrayRef};
+use arrow_schema::{ArrowError, Field};
+
+use crate::layout::{VariantLayout, EXTENSION_NAME};
+
+/// Export a variant array, with a schema for a nullable field called `name`
+/// that carries the variant extension name.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn export_variant_array(
+    array: &dyn Array,
+    name: &str,
+) -> Result<(FFI_ArrowArray, FFI_ArrowSchema), ArrowError> {
+    let field = VariantLayout::try_from_data_type(array.data_type())?.field(name, true);
+    let schema = FFI_ArrowSchema::try_from(&field)?;
+    Ok((FFI_ArrowArray::new(&array.to_data()), schema))
+}
+
+/// Import a variant array exported by [`export_variant_array`], or by
+/// another producer following the same convention.
+///
+/// # Errors
+///
+/// If the schema is not a field with the variant extension name, or its type
+/// is not a variant layout.
+///
+/// # Safety
+///
+/// `array` and `schema` must be valid C data interface structs, and `array`
+/// must match `schema`.
+pub unsafe fn import_variant_array(
+    array: FFI_ArrowArray,
+    schema: &FFI_ArrowSchema,
+) -> Result<ArrayRef, ArrowError> {
+    let field = Field::try_from(schema)?;
+    let extension_name = field.metadata().get("ARROW:extension:name");
+    if extension_name.map(String::as_str) != Some(EXTENSION_NAME) {
+        return Err(ArrowError::CDataInterface(format!(
+            "Expected the {} extension type, got field {} without it",
+            EXTENSION_NAME,
+            field.name()
+        )));
+    }
+    VariantLayout::try_from_data_type(field.data_type())?;
+    Ok(make_array(from_ffi(array, schema)?))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int64Array, StringArray, StructArray};
+    use arrow_schema::DataType;
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    #[test]
+    fn test_variant_ffi_round_trip() {
+        let jsons = StringArray::from(vec![Some(r#"{"a": [1, "x"]}"#), None, Some("2.5")]);
+        let array = variant_from_json(&jsons).unwrap();
+
+        let (ffi_array, ffi_schema) = export_variant_array(&array, "v").unwrap();
+        assert_eq!(ffi_schema.name(), "v");
+        let imported = unsafe { import_variant_array(ffi_array, &ffi_schema) }.unwrap();
+        assert_eq!(imported.to_data(), array.to_data());
+
+        let ints = Int64Array::from(vec![1]);
+        assert!(export_variant_array(&ints, "v").is_err());
+    }
+
+    #[test]
+    fn test_variant_ffi_import_without_extension() {
+        let jsons = StringArray::from(vec![Some("1")]);
+        let array = variant_from_json(&jsons).unwrap();
+        let import = |field: &Field, array: &dyn Array| {
+            let schema = FFI_ArrowSchema::try_from(field).unwrap();
+            unsafe { import_variant_array(FFI_ArrowArray::new(&array.to_data()), &schema) }
+        };
+
+        // A struct of the right type, but not tagged as a variant.
+        let field = Field::new("v", array.data_type().clone(), true);
+        assert!(import(&field, &array).is_err());
+
+        // Tagged, but not a variant layout.
+        let ints = StructArray::from(vec![(
+            Arc::new(Field::new("metadata", DataType::Int64, false)),
+            Arc::new(Int64Array::from(vec![1])) as ArrayRef,
+        )]);
+        let field = VariantLayout::default()
+            .field("v", true)
+            .with_data_type(ints.data_type().clone());
+        assert!(import(&field, &ints).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Count the types of values found at each path of variant arrays.
+//!
+//! This is useful to monitor the quality of heterogeneous data, for example
+//! to find a `price` field that is sometimes a string and sometimes a number.
+
+use std::collections::BTreeMap;
+
+use arrow_array::Array;
+use arrow_schema::ArrowError;
+use open_variant::metadata::MetadataRef;
+use open_variant::path::VariantPath;
+
+use crate::array::{VariantArray, VariantArrayReader};
+
+/// Counts of each type, by name (see
+/// [`VariantRef::type_name`](open_variant::values::VariantRef::type_name)).
+pub type TypeCounts = BTreeMap<&'static str, u64>;
+
+/// A histogram of value types per path, over one or more variant arrays.
+///
+/// Every value is counted, including objects, arrays, and the top-level value
+/// at the empty path. Array elements are counted at the path of the array
+/// followed by `[*]`. Null rows are not counted, but variant nulls are.
+///
+/// ```rust
+/// # #[cfg(feature = "json")]
+/// # {
+/// use arrow_array::StringArray;
+/// use arrow_open_variant::histogram::TypeHistogram;
+/// use arrow_open_variant::json::variant_from_json;
+///
+/// let jsons = StringArray::from(vec![r#"{"price": 1.5}"#, r#"{"price": "2"}"#]);
+/// let array = variant_from_json(&jsons).unwrap();
+///
+/// let mut histogram = TypeHistogram::new();
+/// histogram.update(&array).unwrap();
+/// let price = histogram.get("price").unwrap();
+/// assert_eq!(price.get("double"), Some(&1));
+/// assert_eq!(price.get("string"), Some(&1));
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TypeHistogram {
+    counts: BTreeMap<String, TypeCounts>,
+}
+
+impl TypeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the values of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        let valid_rows = variant_array
+            .valid_slices()
+            .flat_map(|(start, end)| start..end);
+        for i in valid_rows {
+            let Some(variant) = variant_array.variant(i) else {
+                continue;
+            };
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            variant
+                .visit_paths(&metadata, |path, value| {
+                    let path = VariantPath::new(path.to_vec()).to_string();
+                    *self
+                        .counts
+                        .entry(path)
+                        .or_default()
+                        .entry(value.type_name())
+                        .or_default() += 1;
+                })
+                .map_err(ArrowError::InvalidArgumentError)?;
+        }
+        Ok(())
+    }
+
+    /// Add the counts of another histogram, such as one built over another
+    /// partition.
+    pub fn merge(&mut self, other: TypeHistogram) {
+        for (path, counts) in other.counts {
+            let entry = self.counts.entry(path).or_default();
+            for (type_name, count) in counts {
+                *entry.entry(type_name).or_default() += count;
+            }
+        }
+    }
+
+    /// The type counts at a path, written as in [`VariantPath`]'s `Display`.
+    pub fn get(&self, path: &str) -> Option<&TypeCounts> {
+        self.counts.get(path)
+    }
+
+    /// The type counts of every path, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TypeCounts)> {
+        self.counts
+            .iter()
+            .map(|(path, counts)| (path.as_str(), counts))
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    #[test]
+    fn test_type_histogram() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"price": 1.5, "items": [{"id": 1}, {"id": "x"}]}"#),
+            Some(r#"{"price": "2", "a.b": null}"#),
+            None,
+            Some("3"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+
+        let mut histogram = TypeHistogram::new();
+        histogram.update(&array.slice(0, 2)).unwrap();
+        let mut other = TypeHistogram::new();
+        other.update(&array.slice(2, 2)).unwrap();
+        histogram.merge(other);
+
+        let counts = |pairs: &[(&'static str, u64)]| pairs.iter().copied().collect::<TypeCounts>();
+        let paths = histogram.iter().collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                ("", &counts(&[("int64", 1), ("object", 2)])),
+                (r#"["a.b"]"#, &counts(&[("null", 1)])),
+                ("items", &counts(&[("array", 1)])),
+                ("items[*]", &counts(&[("object", 2)])),
+                ("items[*].id", &counts(&[("int64", 1), ("string", 1)])),
+                ("price", &counts(&[("double", 1), ("string", 1)])),
+            ]
+        );
+    }
+}
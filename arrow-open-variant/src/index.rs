@@ -0,0 +1,387 @@
+//! An inverted index from paths to the rows that contain them.
+//!
+//! The index answers existence predicates, such as "which rows have a
+//! `user.email` field", without reading the values again. Paths are written
+//! as in [`VariantPath`]'s `Display`, with `[*]` for array elements.
+//!
+//! Indexes can be saved with [`KeyIndex::write`] and loaded with
+//! [`KeyIndex::read`], for example as a sidecar file next to the data.
+//!
+//! # Format
+//!
+//! All integers are unsigned LEB128 varints.
+//!
+//! ```text
+//! "OVIX" version:u8 num_rows num_paths
+//! num_paths x (path_len path_bytes num_rows row_deltas...)
+//! ```
+//!
+//! Rows of each path are stored as the difference from the previous row (the
+//! first row is stored as is), which keeps dense postings small.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use arrow_array::{Array, BooleanArray};
+use arrow_buffer::BooleanBufferBuilder;
+use arrow_schema::ArrowError;
+use open_variant::metadata::MetadataRef;
+use open_variant::path::VariantPath;
+
+use crate::array::{VariantArray, VariantArrayReader};
+
+/// An inverted index from paths to row numbers, over one or more variant
+/// arrays.
+///
+/// Rows are numbered in the order they are appended, continuing across
+/// arrays, so appending each batch of a file in order gives row numbers
+/// within the file.
+///
+/// ```rust
+/// # #[cfg(feature = "json")]
+/// # {
+/// use arrow_array::StringArray;
+/// use arrow_open_variant::index::KeyIndex;
+/// use arrow_open_variant::json::variant_from_json;
+///
+/// let mut index = KeyIndex::new();
+/// let batch = StringArray::from(vec![r#"{"a": 1}"#, r#"{"b": {"c": 2}}"#]);
+/// index.append(&variant_from_json(&batch).unwrap()).unwrap();
+/// let batch = StringArray::from(vec![r#"{"a": 3}"#]);
+/// index.append(&variant_from_json(&batch).unwrap()).unwrap();
+///
+/// assert_eq!(index.num_rows(), 3);
+/// assert_eq!(index.rows("a"), &[0, 2]);
+/// assert_eq!(index.rows("b.c"), &[1]);
+/// assert!(index.rows("d").is_empty());
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyIndex {
+    num_rows: usize,
+    postings: BTreeMap<String, Vec<usize>>,
+}
+
+impl KeyIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index the rows of a variant array, numbered after the rows already
+    /// indexed.
+    ///
+    /// Every path below the top-level value is indexed, including paths of
+    /// objects and arrays. A path exists in a row even if its value is a
+    /// variant null. Null rows contain no paths.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn append(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        let valid_rows = variant_array
+            .valid_slices()
+            .flat_map(|(start, end)| start..end);
+        for i in valid_rows {
+            let row = self.num_rows + i;
+            let Some(variant) = variant_array.variant(i) else {
+                continue;
+            };
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            variant
+                .visit_paths(&metadata, |path, _| {
+                    if path.is_empty() {
+                        return;
+                    }
+                    let path = VariantPath::new(path.to_vec()).to_string();
+                    let rows = self.postings.entry(path).or_default();
+                    if rows.last() != Some(&row) {
+                        rows.push(row);
+                    }
+                })
+                .map_err(ArrowError::InvalidArgumentError)?;
+        }
+        self.num_rows += variant_array.len();
+        Ok(())
+    }
+
+    /// The number of rows indexed.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// The rows that contain `path`, in increasing order.
+    pub fn rows(&self, path: &str) -> &[usize] {
+        self.postings.get(path).map_or(&[], |rows| rows.as_slice())
+    }
+
+    /// Whether each row in `offset..offset + len` contains `path`.
+    ///
+    /// This can be used directly as a filter for the batch holding those
+    /// rows.
+    pub fn selection(&self, path: &str, offset: usize, len: usize) -> BooleanArray {
+        let mut builder = BooleanBufferBuilder::new(len);
+        builder.append_n(len, false);
+        let rows = self.rows(path);
+        let start = rows.partition_point(|row| *row < offset);
+        for row in rows[start..].iter().take_while(|row| **row < offset + len) {
+            builder.set_bit(row - offset, true);
+        }
+        BooleanArray::new(builder.finish(), None)
+    }
+
+    /// The indexed paths, in sorted order.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.postings.keys().map(|path| path.as_str())
+    }
+
+    /// Write the index in its serialized form. See the [module](self) docs.
+    ///
+    /// # Errors
+    ///
+    /// If writing fails.
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), ArrowError> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(MAGIC);
+        buffer.push(FORMAT_VERSION);
+        write_varint(&mut buffer, self.num_rows);
+        write_varint(&mut buffer, self.postings.len());
+        for (path, rows) in &self.postings {
+            write_varint(&mut buffer, path.len());
+            buffer.extend_from_slice(path.as_bytes());
+            write_varint(&mut buffer, rows.len());
+            let mut previous = 0;
+            for row in rows {
+                write_varint(&mut buffer, row - previous);
+                previous = *row;
+            }
+        }
+        writer.write_all(&buffer)?;
+        Ok(())
+    }
+
+    /// Read an index written by [`KeyIndex::write`].
+    ///
+    /// # Errors
+    ///
+    /// If reading fails, or the data is not a valid index of a supported
+    /// version.
+    pub fn read(reader: &mut impl Read) -> Result<Self, ArrowError> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        let invalid =
+            |message: &str| ArrowError::ParseError(format!("Invalid key index: {}", message));
+
+        let data = buffer
+            .strip_prefix(&MAGIC[..])
+            .ok_or_else(|| invalid("missing magic bytes"))?;
+        let (version, mut data) = data.split_first().ok_or_else(|| invalid("truncated"))?;
+        if *version != FORMAT_VERSION {
+            return Err(ArrowError::ParseError(format!(
+                "Unsupported key index version {}",
+                version
+            )));
+        }
+
+        let num_rows = read_varint(&mut data).ok_or_else(|| invalid("truncated"))?;
+        let num_paths = read_varint(&mut data).ok_or_else(|| invalid("truncated"))?;
+
+        let mut postings = BTreeMap::new();
+        for _ in 0..num_paths {
+            let path_len = read_varint(&mut data).ok_or_else(|| invalid("truncated"))?;
+            if data.len() < path_len {
+                return Err(invalid("truncated"));
+            }
+            let (path, rest) = data.split_at(path_len);
+            data = rest;
+            let path =
+                String::from_utf8(path.to_vec()).map_err(|_| invalid("path is not UTF-8"))?;
+
+            let len = read_varint(&mut data).ok_or_else(|| invalid("truncated"))?;
+            // Both lengths come from the data, so only preallocate as many
+            // rows as the remaining bytes can hold, one byte per row.
+            let mut rows = Vec::with_capacity(len.min(num_rows).min(data.len()));
+            let mut row = 0usize;
+            for _ in 0..len {
+                let delta = read_varint(&mut data).ok_or_else(|| invalid("truncated"))?;
+                row = row
+                    .checked_add(delta)
+                    .ok_or_else(|| invalid("row out of range"))?;
+                if row >= num_rows {
+                    return Err(invalid("row out of range"));
+                }
+                rows.push(row);
+            }
+            postings.insert(path, rows);
+        }
+        if !data.is_empty() {
+            return Err(invalid("unexpected data after the end"));
+        }
+
+        Ok(Self { num_rows, postings })
+    }
+}
+
+const MAGIC: &[u8; 4] = b"OVIX";
+const FORMAT_VERSION: u8 = 1;
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Read a varint from the start of `data`, advancing it. Returns `None` if the
+/// data ends first or the value overflows.
+fn read_varint(data: &mut &[u8]) -> Option<usize> {
+    let mut value = 0usize;
+    for (i, byte) in data.iter().enumerate() {
+        let shift = 7 * i as u32;
+        let bits = (*byte & 0x7f) as usize;
+        if shift >= usize::BITS || (bits << shift) >> shift != bits {
+            return None;
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            *data = &data[i + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    #[test]
+    fn test_key_index() {
+        let mut index = KeyIndex::new();
+        let batch = StringArray::from(vec![
+            Some(r#"{"a": 1, "items": [{"id": 1}, {"id": 2}]}"#),
+            None,
+            Some(r#"{"a": null}"#),
+        ]);
+        index.append(&variant_from_json(&batch).unwrap()).unwrap();
+        let batch = StringArray::from(vec![Some(r#"[{"id": 3}]"#), Some("1")]);
+        index.append(&variant_from_json(&batch).unwrap()).unwrap();
+
+        assert_eq!(index.num_rows(), 5);
+        assert_eq!(
+            index.paths().collect::<Vec<_>>(),
+            vec!["[*]", "[*].id", "a", "items", "items[*]", "items[*].id"]
+        );
+        assert_eq!(index.rows("a"), &[0, 2]);
+        assert_eq!(index.rows("items[*].id"), &[0]);
+        assert_eq!(index.rows("[*].id"), &[3]);
+
+        let selection = index.selection("a", 0, 3);
+        assert_eq!(
+            selection.iter().collect::<Vec<_>>(),
+            vec![Some(true), Some(false), Some(true)]
+        );
+        let selection = index.selection("a", 3, 2);
+        assert_eq!(selection.true_count(), 0);
+        assert_eq!(selection.len(), 2);
+    }
+
+    #[test]
+    fn test_key_index_roundtrip() {
+        let mut index = KeyIndex::new();
+        let jsons = (0..300)
+            .map(|i| match i % 3 {
+                0 => r#"{"a": 1}"#,
+                1 => r#"{"b": [{"c": 2}]}"#,
+                _ => "null",
+            })
+            .collect::<Vec<_>>();
+        let jsons = StringArray::from(jsons);
+        index.append(&variant_from_json(&jsons).unwrap()).unwrap();
+
+        let mut bytes = Vec::new();
+        index.write(&mut bytes).unwrap();
+        let loaded = KeyIndex::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.rows("b[*].c").len(), 100);
+
+        let mut empty = Vec::new();
+        KeyIndex::new().write(&mut empty).unwrap();
+        assert_eq!(
+            KeyIndex::read(&mut empty.as_slice()).unwrap(),
+            KeyIndex::new()
+        );
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        let result = KeyIndex::read(&mut wrong_version.as_slice());
+        assert!(matches!(result, Err(ArrowError::ParseError(message))
+            if message == "Unsupported key index version 2"));
+
+        for truncated in [&bytes[..3], &bytes[..bytes.len() - 1]] {
+            let result = KeyIndex::read(&mut &truncated[..]);
+            assert!(matches!(result, Err(ArrowError::ParseError(_))));
+        }
+    }
+
+    #[test]
+    fn test_key_index_read_invalid() {
+        let mut index = KeyIndex::new();
+        let jsons = StringArray::from(vec![r#"{"a": 1, "b": 2}"#, r#"{"a": 3}"#]);
+        index.append(&variant_from_json(&jsons).unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        index.write(&mut bytes).unwrap();
+        for end in 0..bytes.len() {
+            let result = KeyIndex::read(&mut &bytes[..end]);
+            assert!(matches!(result, Err(ArrowError::ParseError(_))), "{}", end);
+        }
+
+        let header = |num_rows| {
+            let mut data = b"OVIX\x01".to_vec();
+            write_varint(&mut data, num_rows);
+            write_varint(&mut data, 1);
+            write_varint(&mut data, 1);
+            data.push(b'a');
+            data
+        };
+        let message = |data: Vec<u8>| match KeyIndex::read(&mut data.as_slice()) {
+            Err(ArrowError::ParseError(message)) => message,
+            result => panic!("expected a parse error, got {:?}", result),
+        };
+
+        // A huge declared length doesn't preallocate.
+        let mut huge = header(usize::MAX);
+        write_varint(&mut huge, usize::MAX);
+        huge.push(0);
+        assert_eq!(message(huge), "Invalid key index: truncated");
+
+        // Row deltas that overflow are rejected rather than wrapped.
+        let mut overflow = header(usize::MAX);
+        write_varint(&mut overflow, 2);
+        write_varint(&mut overflow, 2);
+        write_varint(&mut overflow, usize::MAX - 1);
+        assert_eq!(message(overflow), "Invalid key index: row out of range");
+
+        let mut out_of_range = header(2);
+        write_varint(&mut out_of_range, 1);
+        write_varint(&mut out_of_range, 2);
+        assert_eq!(message(out_of_range), "Invalid key index: row out of range");
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as usize, usize::MAX] {
+            let mut buffer = Vec::new();
+            write_varint(&mut buffer, value);
+            let mut data = buffer.as_slice();
+            assert_eq!(read_varint(&mut data), Some(value));
+            assert!(data.is_empty());
+        }
+        assert_eq!(read_varint(&mut &[0x80, 0x80][..]), None);
+        assert_eq!(read_varint(&mut &[0xff; 11][..]), None);
+    }
+}
@@ -1,17 +1,81 @@
-//! Parse JSON data into variant data.
+//! Convert between JSON data and variant data.
 
 use std::borrow::Cow;
 use std::{collections::BTreeSet, sync::Arc};
 
-use arrow_array::builder::BinaryBuilder;
+use arrow_array::builder::{BooleanBuilder, StringBuilder};
 use arrow_array::{
-    cast::AsArray, Array, ArrayRef, BinaryArray, DictionaryArray, Scalar, StructArray,
+    cast::AsArray, Array, ArrayRef, BinaryArray, BooleanArray, DictionaryArray, Scalar,
+    StringArray, StructArray,
 };
 use arrow_buffer::NullBuffer;
-use arrow_schema::{ArrowError, DataType, Field};
+use arrow_schema::{ArrowError, DataType};
 use jiter::JsonValue;
-use open_variant::metadata::{build_metadata, MetadataRef};
-use open_variant::values::write::{self, ArrayBuilder, ObjectBuilder};
+use open_variant::json::{write_json, JsonWriteOptions};
+use open_variant::metadata::{build_metadata, MetadataRef, StreamingMetadataBuilder};
+use open_variant::values::uuid::parse_uuid;
+use open_variant::values::write::{self, remap_field_ids_in_value_order};
+
+use crate::array::{values_array_from_parts, VariantArray, VariantArrayReader};
+use crate::canonical::canonicalize_variant;
+use crate::layout::VariantLayout;
+use crate::nulls::NullConvention;
+
+/// Options for [`variant_from_json_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct JsonParseOptions {
+    /// How JSON `null` documents are represented. Input rows that are Arrow
+    /// nulls are always output as Arrow nulls.
+    pub top_level_null: NullConvention,
+    /// The maximum number of nested objects and arrays in a document. A
+    /// top-level object or array has a depth of 1.
+    pub max_depth: Option<usize>,
+    /// The maximum number of keys in any single object.
+    pub max_object_keys: Option<usize>,
+    /// The maximum encoded size of a document's variant value, in bytes.
+    pub max_value_bytes: Option<usize>,
+    /// What to do with documents that exceed one of the limits.
+    pub limit_policy: LimitPolicy,
+    /// Whether to write strings in the canonical UUID form,
+    /// `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`, as variant UUIDs.
+    pub uuid_strings: bool,
+    /// The layout of the output. If `None`, the default layout is used,
+    /// promoted to large offsets if needed, or plain metadata with
+    /// [`deterministic`](Self::deterministic).
+    pub layout: Option<VariantLayout>,
+    /// Encode each row in canonical form, with its own metadata, so the
+    /// bytes of a row only depend on its document and not on the rest of
+    /// the batch or the number of threads. See
+    /// [`canonicalize_variant`](crate::canonical::canonicalize_variant).
+    pub deterministic: bool,
+    /// How JSON numbers are encoded.
+    pub numeric_policy: NumericPolicy,
+}
+
+/// What to do with a document that exceeds a limit in [`JsonParseOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    /// Fail the whole conversion.
+    #[default]
+    Error,
+    /// Output an Arrow null for the document.
+    Null,
+}
+
+/// How [`JsonParseOptions`] encodes JSON numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumericPolicy {
+    /// Integers as i64 and floats as f64.
+    #[default]
+    Default,
+    /// The smallest encoding that reads back as the same value, to reduce
+    /// storage. Integers use the narrowest integer type that fits. Floats
+    /// are f32 if that is exact and prints the same, or else Decimal4 if
+    /// the decimal converts back to the same f64, or else f64.
+    ///
+    /// Decimal8 is never used for floats, as it is larger than an f64.
+    Smallest,
+}
 
 /// Create a variant array from an array of JSON data.
 ///
@@ -23,38 +87,85 @@ use open_variant::values::write::{self, ArrayBuilder, ObjectBuilder};
 ///
 /// | JSON value       | Variant value |
 /// |------------------|---------------|
-/// | null             | Arrow null (top-level) or variant null (nested) |
+/// | null             | Arrow null (top-level, by default) or variant null (nested) |
 /// | boolean          | Variant boolean |
-/// | integer          | Variant i64 |
+/// | integer          | Variant i64, or narrower with [`NumericPolicy::Smallest`] |
 /// | big integer      | Variant Decimal16, with scale 0 |
-/// | float            | Variant f64 |
-/// | string           | Variant string |
+/// | float            | Variant f64, or f32 or Decimal4 with [`NumericPolicy::Smallest`] |
+/// | string           | Variant string, or UUID with [`JsonParseOptions::uuid_strings`] |
 /// | object           | Variant object |
 /// | array            | Variant array |
 ///
+/// The output has the type [`variant_type`](crate::variant_type), unless the
+/// values exceed what can be addressed with 32-bit offsets, in which case it is
+/// promoted to [`large_variant_type`](crate::large_variant_type). Use
+/// [`JsonParseOptions::layout`] to choose another layout.
+///
 /// # Errors
 ///
 /// If the JSON data is invalid.
 pub fn variant_from_json(array: &dyn Array) -> Result<ArrayRef, ArrowError> {
-    // Create a generic iterator so we don't have to monomorphize over every
-    // string and binary array type.
-    let bytes_iter = bytes_iter_from_array(array)?;
-    let jsons: Vec<JsonValue<'_>> = bytes_iter
-        .map(|bytes| match bytes {
-            Some(bytes) => jiter::JsonValue::parse(bytes, true)
-                .map_err(|e| ArrowError::ComputeError(format!("Failed to parse JSON: {}", e))),
-            None => Ok(jiter::JsonValue::Null),
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    let jsons_ref = jsons.as_slice();
+    variant_from_json_with_options(array, &JsonParseOptions::default())
+}
+
+/// Create a variant array from an array of JSON data, with the given options.
+///
+/// See [`variant_from_json`].
+pub fn variant_from_json_with_options(
+    array: &dyn Array,
+    options: &JsonParseOptions,
+) -> Result<ArrayRef, ArrowError> {
+    variant_from_json_rows(array, 0, options)
+}
+
+/// Like [`variant_from_json_with_options`], for rows of a larger array that
+/// start at row `first_row`, which errors report row numbers in.
+fn variant_from_json_rows(
+    array: &dyn Array,
+    first_row: usize,
+    options: &JsonParseOptions,
+) -> Result<ArrayRef, ArrowError> {
+    let jsons = parse_jsons(array, first_row)?;
+    variant_from_jsons(&jsons, array.nulls(), first_row, options)
+}
+
+/// Like [`variant_from_json`], but rows that are not valid JSON, or that
+/// exceed a limit in `options`, are null rather than failing the whole array.
+///
+/// [`JsonParseOptions::limit_policy`] is ignored, since documents over a
+/// limit are always null.
+///
+/// # Errors
+///
+/// If the input is not a string or binary array.
+pub fn try_variant_from_json(
+    array: &dyn Array,
+    options: &JsonParseOptions,
+) -> Result<ArrayRef, ArrowError> {
+    let (jsons, nulls) = try_parse_jsons(array)?;
+    let options = JsonParseOptions {
+        limit_policy: LimitPolicy::Null,
+        ..options.clone()
+    };
+    variant_from_jsons(&jsons, nulls.as_ref(), 0, &options)
+}
 
+/// Convert parsed JSON documents into a variant array with a sorted
+/// dictionary of all their keys. Rows that are null in `nulls` are null, and
+/// errors number rows from `first_row`.
+fn variant_from_jsons(
+    jsons: &[JsonValue],
+    nulls: Option<&NullBuffer>,
+    first_row: usize,
+    options: &JsonParseOptions,
+) -> Result<ArrayRef, ArrowError> {
     // We iterate once to collect all the object keys for the metadata.
     // TODO: also support collecting common strings from values.
-    let strings = collect_all_keys(jsons_ref)?;
+    let strings = collect_all_keys(jsons)?;
 
     let metadata = build_metadata(strings.iter().map(|x| x.as_ref()));
     let metadata = BinaryArray::new_scalar(metadata);
-    let metadata = make_repeated_dict_array(metadata, array.len());
+    let metadata = make_repeated_dict_array(metadata, jsons.len());
     let metadata_ref = metadata
         .as_any_dictionary()
         .values()
@@ -62,22 +173,454 @@ pub fn variant_from_json(array: &dyn Array) -> Result<ArrayRef, ArrowError> {
         .value(0);
     let metadata_ref = MetadataRef::new(metadata_ref);
 
-    let data: BinaryArray =
-        values_from_json(jsons_ref, array.null_count(), array.nulls(), &metadata_ref)?;
-    let fields = vec![
-        Field::new(
-            "metadata",
-            DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Binary)),
-            false,
-        ),
-        Field::new("values", DataType::Binary, true),
-    ];
+    let key_ids = KeyIds::Metadata;
+    let data = values_from_json(jsons, nulls, &metadata_ref, key_ids, first_row, options)?;
+    variant_array_from_parts(metadata, data, options)
+}
+
+/// Create a variant array from an array of JSON data, using several threads.
+///
+/// The input is split into one chunk per thread. Each chunk is parsed and
+/// encoded with its own dictionary, and the dictionaries are then merged and
+/// the field ids of each chunk remapped, also in parallel. The output is the
+/// same as [`variant_from_json_with_options`].
+///
+/// `threads` is the number of threads to use, or 0 for
+/// [`std::thread::available_parallelism`]. When called from an engine that
+/// already runs one task per core, such as DataFusion, prefer 1, which
+/// converts on the calling thread, or a small number.
+///
+/// # Errors
+///
+/// If the JSON data is invalid.
+pub fn variant_from_json_parallel(
+    array: &dyn Array,
+    options: &JsonParseOptions,
+    threads: usize,
+) -> Result<ArrayRef, ArrowError> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+    let chunk_len = ((array.len() + threads - 1) / threads).max(1);
+    if chunk_len >= array.len() {
+        return variant_from_json_with_options(array, options);
+    }
+    let chunks = (0..array.len())
+        .step_by(chunk_len)
+        .map(|start| {
+            (
+                start,
+                array.slice(start, chunk_len.min(array.len() - start)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // The chunks are converted to the default layout, and the requested
+    // layout and canonical form are applied once at the end.
+    let chunk_options = &JsonParseOptions {
+        layout: None,
+        deterministic: false,
+        ..options.clone()
+    };
+    let outputs = std::thread::scope(|scope| {
+        let handles = chunks
+            .iter()
+            .map(|(start, chunk)| {
+                scope.spawn(move || variant_from_json_rows(chunk, *start, chunk_options))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("JSON conversion panicked"))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    // Every row of a chunk has the same metadata, so merge the first row of
+    // each.
+    let chunk_metadata = outputs
+        .iter()
+        .map(|output| {
+            let metadata = output.as_struct().column(0).as_any_dictionary().values();
+            metadata.as_binary::<i32>().value(0)
+        })
+        .collect::<Vec<_>>();
+    let strings = chunk_metadata
+        .iter()
+        .flat_map(|metadata| {
+            let metadata = MetadataRef::new(metadata);
+            (0..metadata.dictionary_len())
+                .filter_map(|id| metadata.get_string(id))
+                .collect::<Vec<_>>()
+        })
+        .collect::<BTreeSet<_>>();
+    let metadata = build_metadata(strings.into_iter());
+    let metadata_ref = MetadataRef::new(&metadata);
+
+    let remapped = std::thread::scope(|scope| {
+        let handles = outputs
+            .iter()
+            .zip(&chunk_metadata)
+            .map(|(output, chunk_metadata)| {
+                let metadata_ref = &metadata_ref;
+                scope.spawn(move || remap_chunk(output, chunk_metadata, metadata_ref))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("field id remapping panicked"))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let mut buffer = Vec::with_capacity(remapped.iter().map(|(chunk, _)| chunk.len()).sum());
+    let mut offsets = Vec::with_capacity(array.len() + 1);
+    offsets.push(0);
+    for (chunk, chunk_offsets) in remapped {
+        let start = buffer.len();
+        buffer.extend_from_slice(&chunk);
+        offsets.extend(chunk_offsets[1..].iter().map(|offset| start + offset));
+    }
+    let validity = outputs
+        .iter()
+        .flat_map(|output| (0..output.len()).map(|i| output.is_valid(i)))
+        .collect::<Vec<_>>();
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+
+    let data = values_array_from_parts(buffer, &offsets, nulls);
+    let metadata = make_repeated_dict_array(BinaryArray::new_scalar(metadata), array.len());
+    variant_array_from_parts(metadata, data, options)
+}
+
+/// Copy the values of a chunk converted by [`variant_from_json_parallel`],
+/// changing its field ids to those of the merged `metadata`.
+///
+/// Returns the concatenated values and their offsets.
+fn remap_chunk(
+    output: &ArrayRef,
+    chunk_metadata: &[u8],
+    metadata: &MetadataRef,
+) -> Result<(Vec<u8>, Vec<usize>), ArrowError> {
+    let chunk_metadata = MetadataRef::new(chunk_metadata);
+    let mapping = (0..chunk_metadata.dictionary_len())
+        .map(|id| {
+            chunk_metadata
+                .get_string(id)
+                .and_then(|key| metadata.find_string(key))
+                .expect("merged metadata has every key")
+        })
+        .collect::<Vec<_>>();
+    let unchanged = mapping.iter().enumerate().all(|(id, new_id)| id == *new_id);
+
+    let variant_array = VariantArray::try_new(output)?;
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    offsets.push(0);
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows have empty values.
+        offsets.resize(start + 1, buffer.len());
+        for i in start..end {
+            match variant_array.variant(i) {
+                Some(variant) if unchanged => buffer.extend_from_slice(variant.as_bytes()),
+                Some(variant) => {
+                    remap_field_ids_in_value_order(&mut buffer, &variant, &mapping, metadata)
+                        .map_err(ArrowError::ComputeError)?
+                }
+                None => {}
+            }
+            offsets.push(buffer.len());
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+    Ok((buffer, offsets))
+}
+
+/// Converts a stream of JSON batches into variant arrays that share a string
+/// table.
+///
+/// [`variant_from_json`] builds a new, sorted metadata dictionary for every
+/// batch. When ingesting many batches with the same keys, that work is
+/// repeated for every batch, and every batch has a different dictionary. A
+/// session keeps a single dictionary for all batches instead. Keys keep their
+/// ids once added, so the dictionary of a batch extends the dictionaries of
+/// all earlier batches, and batches that add no keys share the same metadata
+/// buffer.
+///
+/// The dictionary is in insertion order rather than sorted, which makes key
+/// lookups slower for readers.
+///
+/// ```rust
+/// use arrow_array::StringArray;
+/// use arrow_open_variant::json::JsonIngestSession;
+///
+/// let mut session = JsonIngestSession::default();
+/// session.ingest(&StringArray::from(vec![r#"{"b": 1}"#])).unwrap();
+/// assert_eq!(session.take_delta(), ["b"]);
+/// session.ingest(&StringArray::from(vec![r#"{"a": 2, "b": 3}"#])).unwrap();
+/// assert_eq!(session.take_delta(), ["a"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JsonIngestSession {
+    options: JsonParseOptions,
+    keys: StreamingMetadataBuilder,
+    /// The metadata for the current keys, built when keys were last added.
+    metadata: Option<Scalar<BinaryArray>>,
+}
+
+impl JsonIngestSession {
+    /// Create a session that converts batches with `options`.
+    ///
+    /// Between batches, the session keeps the keys seen so far, in the order
+    /// they were first seen, the keys added since the last
+    /// [`take_delta`](Self::take_delta), and the metadata buffer built for the
+    /// current keys. That metadata has an unsorted dictionary, which readers
+    /// detect from its header, so object fields of its values are found by
+    /// scanning their ids rather than by binary search, as described in
+    /// [`ObjectRef::get_field`](open_variant::values::ObjectRef::get_field).
+    pub fn new(options: JsonParseOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Convert a batch of JSON data, adding any new keys to the string table.
+    ///
+    /// See [`variant_from_json`] for the supported inputs and the output type.
+    ///
+    /// # Errors
+    ///
+    /// If the JSON data is invalid. Keys of the batch are added to the string
+    /// table even if converting it fails.
+    pub fn ingest(&mut self, array: &dyn Array) -> Result<ArrayRef, ArrowError> {
+        let jsons = parse_jsons(array, 0)?;
+        let len = self.keys.len();
+        visit_keys(&jsons, |key| {
+            self.keys.get_or_insert(key);
+        });
+        if self.metadata.is_none() || self.keys.len() != len {
+            self.metadata = Some(BinaryArray::new_scalar(self.keys.build()));
+        }
+        let metadata = self.metadata.clone().unwrap();
+        let metadata = make_repeated_dict_array(metadata, array.len());
+        let metadata_ref = metadata
+            .as_any_dictionary()
+            .values()
+            .as_binary::<i32>()
+            .value(0);
+        let metadata_ref = MetadataRef::new(metadata_ref);
+
+        let data = values_from_json(
+            &jsons,
+            array.nulls(),
+            &metadata_ref,
+            KeyIds::Session(&self.keys),
+            0,
+            &self.options,
+        )?;
+        variant_array_from_parts(metadata, data, &self.options)
+    }
+
+    /// The keys added since the last call to this method, for writers that
+    /// send dictionary deltas rather than the full dictionary each batch.
+    ///
+    /// See [`StreamingMetadataBuilder::take_delta`].
+    pub fn take_delta(&mut self) -> &[String] {
+        self.keys.take_delta()
+    }
+
+    /// The number of keys in the string table.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Write each value of a variant array as JSON text.
+///
+/// Object keys are sorted, and floats, dates and timestamps are written in
+/// their default formats. See [`variant_to_json_with_options`] to change
+/// these. Null rows are null, and variant nulls are the JSON text `null`.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_to_json(array: &dyn Array) -> Result<StringArray, ArrowError> {
+    variant_to_json_with_options(array, &JsonWriteOptions::default())
+}
+
+/// Write each value of a variant array as JSON text, with the given options.
+///
+/// See [`variant_to_json`] and [`JsonWriteOptions`].
+pub fn variant_to_json_with_options(
+    array: &dyn Array,
+    options: &JsonWriteOptions,
+) -> Result<StringArray, ArrowError> {
+    variant_array_to_json(array, options, false)
+}
+
+/// Like [`variant_to_json`], but rows whose value can't be written, such as
+/// objects with field ids that are not in the metadata, are null rather than
+/// failing the whole array.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn try_variant_to_json(array: &dyn Array) -> Result<StringArray, ArrowError> {
+    variant_array_to_json(array, &JsonWriteOptions::default(), true)
+}
+
+/// Whether each value of a variant array is structurally equal to a JSON
+/// document.
+///
+/// The document is parsed once and compared to each row with
+/// [`VariantRef::structural_eq`](open_variant::values::VariantRef::structural_eq),
+/// without writing the rows as JSON. Numbers compare by value, so `1` equals
+/// `1.0`, and object keys can be in any order. Rows that are Arrow nulls or
+/// variant nulls are null in the output.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or `json` is not valid JSON.
+pub fn variant_eq_json(array: &dyn Array, json: &str) -> Result<BooleanArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let options = JsonParseOptions {
+        top_level_null: NullConvention::VariantNull,
+        ..Default::default()
+    };
+    let literal = variant_from_json_with_options(&StringArray::from(vec![json]), &options)?;
+    let literal = VariantArray::try_new(&literal)?;
+    let literal_metadata = MetadataRef::new(literal.metadata(0));
+    let literal_value = literal
+        .variant(0)
+        .expect("the document is not an Arrow null");
+
+    let mut builder = BooleanBuilder::with_capacity(variant_array.len());
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            if variant_array.is_null_or_variant_null(i) {
+                builder.append_null();
+                continue;
+            }
+            let variant = variant_array.variant(i).unwrap();
+            let metadata = MetadataRef::new(variant_array.metadata(i));
+            let equal = variant
+                .structural_eq(&metadata, &literal_value, &literal_metadata)
+                .map_err(ArrowError::InvalidArgumentError)?;
+            builder.append_value(equal);
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+    Ok(builder.finish())
+}
+
+/// Write a variant array as JSON text. If `invalid_as_null`, rows that fail
+/// are null, otherwise the first failure is returned.
+fn variant_array_to_json(
+    array: &dyn Array,
+    options: &JsonWriteOptions,
+    invalid_as_null: bool,
+) -> Result<StringArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = StringBuilder::with_capacity(variant_array.len(), 0);
+    let mut json = String::new();
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        (0..start - valid_end).for_each(|_| builder.append_null());
+        valid_end = end;
+        for i in start..end {
+            match variant_array.variant(i) {
+                Some(variant) => {
+                    let metadata = MetadataRef::new(variant_array.metadata(i));
+                    json.clear();
+                    match write_json(&mut json, &variant, &metadata, options) {
+                        Ok(()) => builder.append_value(&json),
+                        Err(_) if invalid_as_null => builder.append_null(),
+                        Err(message) => return Err(ArrowError::InvalidArgumentError(message)),
+                    }
+                }
+                None => builder.append_null(),
+            }
+        }
+    }
+    (0..variant_array.len() - valid_end).for_each(|_| builder.append_null());
+    Ok(builder.finish())
+}
+
+/// Parse each row of a string or binary array as JSON. Null rows are JSON
+/// nulls. Errors number rows from `first_row`.
+fn parse_jsons(array: &dyn Array, first_row: usize) -> Result<Vec<JsonValue<'_>>, ArrowError> {
+    // Create a generic iterator so we don't have to monomorphize over every
+    // string and binary array type.
+    let bytes_iter = bytes_iter_from_array(array)?;
+    bytes_iter
+        .enumerate()
+        .map(|(i, bytes)| match bytes {
+            Some(bytes) => jiter::JsonValue::parse(bytes, true).map_err(|e| {
+                ArrowError::ComputeError(format!(
+                    "Failed to parse JSON at row {}: {}",
+                    first_row + i,
+                    e
+                ))
+            }),
+            None => Ok(jiter::JsonValue::Null),
+        })
+        .collect()
+}
+
+/// Like [`parse_jsons`], but rows that fail to parse are JSON nulls, and are
+/// null in the returned null buffer along with the null rows of the input.
+fn try_parse_jsons(
+    array: &dyn Array,
+) -> Result<(Vec<JsonValue<'_>>, Option<NullBuffer>), ArrowError> {
+    let bytes_iter = bytes_iter_from_array(array)?;
+    let mut validity = Vec::with_capacity(array.len());
+    let jsons = bytes_iter
+        .map(|bytes| {
+            let json = bytes.and_then(|bytes| jiter::JsonValue::parse(bytes, true).ok());
+            validity.push(json.is_some());
+            json.unwrap_or(jiter::JsonValue::Null)
+        })
+        .collect();
+    let nulls = NullBuffer::from(validity);
+    Ok((jsons, (nulls.null_count() > 0).then_some(nulls)))
+}
+
+/// Assemble the output of [`variant_from_json`] from the metadata and values
+/// columns, converting it to the requested layout.
+fn variant_array_from_parts(
+    metadata: ArrayRef,
+    data: ArrayRef,
+    options: &JsonParseOptions,
+) -> Result<ArrayRef, ArrowError> {
+    let layout = VariantLayout::builder()
+        .large_offsets(data.data_type() == &DataType::LargeBinary)
+        .build()?;
     let null_buffer = data.nulls().cloned();
-    Ok(Arc::new(StructArray::new(
-        fields.into(),
-        vec![metadata, Arc::new(data) as ArrayRef],
+    let output = Arc::new(StructArray::new(
+        layout.fields(),
+        vec![metadata, data],
         null_buffer,
-    )) as ArrayRef)
+    )) as ArrayRef;
+    if options.deterministic {
+        let output = canonicalize_variant(&output)?;
+        return match &options.layout {
+            Some(requested) => VariantArray::try_new(&output)?.to_layout(requested),
+            None => Ok(output),
+        };
+    }
+    match &options.layout {
+        Some(requested) if requested != &layout => {
+            VariantArray::try_new(&output)?.to_layout(requested)
+        }
+        _ => Ok(output),
+    }
 }
 
 fn bytes_iter_from_array(
@@ -114,6 +657,14 @@ fn bytes_iter_from_array(
 
 fn collect_all_keys<'a>(jsons: &[JsonValue<'a>]) -> Result<BTreeSet<Cow<'a, str>>, ArrowError> {
     let mut seen = BTreeSet::new();
+    visit_keys(jsons, |key| {
+        seen.insert(key.clone());
+    });
+    Ok(seen)
+}
+
+/// Call `f` with every object key in the documents, at any depth.
+fn visit_keys<'a>(jsons: &[JsonValue<'a>], mut f: impl FnMut(&Cow<'a, str>)) {
     let mut stack = Vec::new();
 
     let is_nested = |json: &JsonValue| matches!(json, JsonValue::Object(_) | JsonValue::Array(_));
@@ -121,7 +672,7 @@ fn collect_all_keys<'a>(jsons: &[JsonValue<'a>]) -> Result<BTreeSet<Cow<'a, str>
         match json {
             JsonValue::Object(object) => {
                 for (key, value) in object.iter() {
-                    seen.insert(key.clone());
+                    f(key);
                     if is_nested(value) {
                         stack.push(value);
                     }
@@ -142,7 +693,7 @@ fn collect_all_keys<'a>(jsons: &[JsonValue<'a>]) -> Result<BTreeSet<Cow<'a, str>
         match json {
             JsonValue::Object(object) => {
                 for (key, value) in object.iter() {
-                    seen.insert(key.clone());
+                    f(key);
                     if is_nested(value) {
                         stack.push(value);
                     }
@@ -158,8 +709,6 @@ fn collect_all_keys<'a>(jsons: &[JsonValue<'a>]) -> Result<BTreeSet<Cow<'a, str>
             _ => {}
         }
     }
-
-    Ok(seen)
 }
 
 fn make_repeated_dict_array(scalar: Scalar<BinaryArray>, length: usize) -> ArrayRef {
@@ -171,78 +720,357 @@ fn make_repeated_dict_array(scalar: Scalar<BinaryArray>, length: usize) -> Array
 
 fn values_from_json(
     jsons: &[jiter::JsonValue],
-    null_count: usize,
     null_buffer: Option<&NullBuffer>,
     key_map: &MetadataRef,
-) -> Result<BinaryArray, ArrowError> {
-    let mut builder = BinaryBuilder::with_capacity(
-        jsons.len(),
-        jsons.len() - null_count, // For now, just one byte per item that isn't null.
-    );
-    // TODO: Instead of using a temporary buffer, we could use the builder's buffer.
-    let mut buffer = Vec::new();
+    key_ids: KeyIds,
+    first_row: usize,
+    options: &JsonParseOptions,
+) -> Result<ArrayRef, ArrowError> {
+    // All values are written into a single buffer, so we only pick the offset
+    // width once we know the total size.
+    let mut buffer = Vec::with_capacity(jsons.len());
+    let mut offsets = Vec::with_capacity(jsons.len() + 1);
+    let mut validity = Vec::with_capacity(jsons.len());
+    let mut arena = FrameArena::default();
+    offsets.push(0);
     for (i, json) in jsons.iter().enumerate() {
-        if null_buffer.map(|b| b.is_valid(i)).unwrap_or(true) {
-            convert_value(json, &mut buffer, key_map)?;
-            if buffer == [0] {
-                // Special case for nulls, which are represented as "0" in the variant format.
-                builder.append_null();
-            } else {
-                builder.append_value(&buffer);
+        let is_valid = null_buffer.map(|b| b.is_valid(i)).unwrap_or(true)
+            && (options.top_level_null == NullConvention::VariantNull
+                || !matches!(json, jiter::JsonValue::Null));
+        let is_valid = is_valid && {
+            let start = buffer.len();
+            let within_limits = match check_limits(json, options) {
+                Ok(()) => {
+                    convert_value(json, &mut buffer, key_map, key_ids, options, &mut arena)?;
+                    check_value_bytes(buffer.len() - start, options)
+                }
+                Err(message) => Err(message),
+            };
+            match (within_limits, options.limit_policy) {
+                (Ok(()), _) => true,
+                (Err(message), LimitPolicy::Error) => {
+                    return Err(ArrowError::ComputeError(format!(
+                        "JSON document at row {} exceeds limit: {}",
+                        first_row + i,
+                        message
+                    )))
+                }
+                (Err(_), LimitPolicy::Null) => {
+                    buffer.truncate(start);
+                    false
+                }
             }
-            buffer.clear();
-        } else {
-            builder.append_null();
+        };
+        offsets.push(buffer.len());
+        validity.push(is_valid);
+    }
+
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    Ok(values_array_from_parts(buffer, &offsets, nulls))
+}
+
+/// Check the depth and object size limits of a document before converting it.
+fn check_limits(json: &JsonValue, options: &JsonParseOptions) -> Result<(), String> {
+    if options.max_depth.is_none() && options.max_object_keys.is_none() {
+        return Ok(());
+    }
+    let max_depth = options.max_depth.unwrap_or(usize::MAX);
+    let max_object_keys = options.max_object_keys.unwrap_or(usize::MAX);
+
+    let mut stack = vec![(json, 1)];
+    while let Some((json, depth)) = stack.pop() {
+        let children: Box<dyn Iterator<Item = &JsonValue> + '_> = match json {
+            JsonValue::Object(object) => {
+                if object.len() > max_object_keys {
+                    return Err(format!(
+                        "object has {} keys, more than the maximum of {}",
+                        object.len(),
+                        max_object_keys
+                    ));
+                }
+                Box::new(object.iter().map(|(_, value)| value))
+            }
+            JsonValue::Array(array) => Box::new(array.iter()),
+            _ => continue,
+        };
+        if depth > max_depth {
+            return Err(format!(
+                "nesting is deeper than the maximum of {}",
+                max_depth
+            ));
         }
+        stack.extend(children.map(|child| (child, depth + 1)));
     }
+    Ok(())
+}
 
-    Ok(builder.finish())
+fn check_value_bytes(len: usize, options: &JsonParseOptions) -> Result<(), String> {
+    match options.max_value_bytes {
+        Some(max_value_bytes) if len > max_value_bytes => Err(format!(
+            "value is {} bytes, more than the maximum of {}",
+            len, max_value_bytes
+        )),
+        _ => Ok(()),
+    }
 }
 
-fn convert_value(
-    json: &jiter::JsonValue,
+/// Convert a JSON value into a variant value.
+fn convert_value<'a, 's>(
+    json: &'a JsonValue<'s>,
     buffer: &mut Vec<u8>,
     metadata: &MetadataRef,
+    key_ids: KeyIds,
+    options: &JsonParseOptions,
+    arena: &mut FrameArena,
+) -> Result<(), ArrowError> {
+    let mut stack: Vec<Frame<'a, 's>> = Vec::new();
+    let mut pending = Some(json);
+    loop {
+        if let Some(json) = pending.take() {
+            match json {
+                JsonValue::Array(array) => {
+                    stack.push(Frame::new(Children::Array(array), arena));
+                }
+                JsonValue::Object(object) => {
+                    let fields = object.iter().as_slice();
+                    stack.push(Frame::new(Children::Object(fields), arena));
+                }
+                _ => match stack.last() {
+                    Some(frame) => {
+                        convert_scalar(json, &mut arena.bytes, options)?;
+                        arena.offsets.push(arena.bytes.len() - frame.start);
+                    }
+                    None => return convert_scalar(json, buffer, options),
+                },
+            }
+        }
+
+        let frame = stack
+            .last()
+            .expect("stack is empty only after the top-level value");
+        if let Some(child) = frame.child(arena.offsets.len() - frame.offsets_start) {
+            pending = Some(child);
+            continue;
+        }
+
+        // All children are converted, so close the container.
+        let frame = stack.pop().unwrap();
+        match stack.last() {
+            Some(parent) => {
+                frame.finish_in_arena(arena, metadata, key_ids)?;
+                arena.offsets.push(arena.bytes.len() - parent.start);
+            }
+            None => return frame.finish(buffer, arena, metadata, key_ids),
+        }
+    }
+}
+
+/// How [`convert_value`] finds the field ids of object keys.
+#[derive(Clone, Copy)]
+enum KeyIds<'k> {
+    /// Search the metadata dictionary.
+    Metadata,
+    /// Look up the string table of a [`JsonIngestSession`], which has the same
+    /// ids as the metadata and avoids scanning its unsorted dictionary.
+    Session(&'k StreamingMetadataBuilder),
+}
+
+/// Memory for converting containers, reused across containers and rows.
+///
+/// Containers are converted depth first, so the converted children of the
+/// open containers form a stack: the children of each container are
+/// concatenated at the end of `bytes`, after those of its parent, and their
+/// end offsets at the end of `offsets`. Closing a container inserts its
+/// header before its children, which turns them into the container's value
+/// among its parent's children. Nothing is allocated per container once the
+/// buffers have grown to fit the deepest document.
+#[derive(Default)]
+struct FrameArena {
+    bytes: Vec<u8>,
+    offsets: Vec<usize>,
+    /// The header of the container being closed.
+    header: Vec<u8>,
+    /// The field ids and offsets of the object being closed.
+    fields: Vec<(usize, usize)>,
+}
+
+/// The children of a container being converted by [`convert_value`].
+#[derive(Clone, Copy)]
+enum Children<'a, 's> {
+    Array(&'a [JsonValue<'s>]),
+    Object(&'a [(Cow<'s, str>, JsonValue<'s>)]),
+}
+
+/// An object or array being converted by [`convert_value`].
+struct Frame<'a, 's> {
+    children: Children<'a, 's>,
+    /// Where the converted children start in [`FrameArena::bytes`].
+    start: usize,
+    /// Where the end offsets of the converted children start in
+    /// [`FrameArena::offsets`].
+    offsets_start: usize,
+}
+
+impl<'a, 's> Frame<'a, 's> {
+    fn new(children: Children<'a, 's>, arena: &FrameArena) -> Self {
+        Self {
+            children,
+            start: arena.bytes.len(),
+            offsets_start: arena.offsets.len(),
+        }
+    }
+
+    fn child(&self, index: usize) -> Option<&'a JsonValue<'s>> {
+        match self.children {
+            Children::Array(array) => array.get(index),
+            Children::Object(object) => object.get(index).map(|(_, value)| value),
+        }
+    }
+
+    /// Write the container to `buffer`, and release its space in the arena.
+    fn finish(
+        self,
+        buffer: &mut Vec<u8>,
+        arena: &mut FrameArena,
+        metadata: &MetadataRef,
+        key_ids: KeyIds,
+    ) -> Result<(), ArrowError> {
+        self.write_header(arena, metadata, key_ids)?;
+        buffer.extend_from_slice(&arena.header);
+        buffer.extend_from_slice(&arena.bytes[self.start..]);
+        arena.bytes.truncate(self.start);
+        arena.offsets.truncate(self.offsets_start);
+        Ok(())
+    }
+
+    /// Write the container in place of its children in the arena, where it
+    /// becomes a child of its parent.
+    fn finish_in_arena(
+        self,
+        arena: &mut FrameArena,
+        metadata: &MetadataRef,
+        key_ids: KeyIds,
+    ) -> Result<(), ArrowError> {
+        self.write_header(arena, metadata, key_ids)?;
+        arena
+            .bytes
+            .splice(self.start..self.start, arena.header.iter().copied());
+        arena.offsets.truncate(self.offsets_start);
+        Ok(())
+    }
+
+    /// Write the header of the container to [`FrameArena::header`].
+    fn write_header(
+        &self,
+        arena: &mut FrameArena,
+        metadata: &MetadataRef,
+        key_ids: KeyIds,
+    ) -> Result<(), ArrowError> {
+        arena.header.clear();
+        let offsets = &arena.offsets[self.offsets_start..];
+        match self.children {
+            Children::Array(_) => write::write_array_header(&mut arena.header, offsets),
+            Children::Object(object) => {
+                arena.fields.clear();
+                let mut start = 0;
+                for ((key, _), end) in object.iter().zip(offsets) {
+                    let field_id = match key_ids {
+                        KeyIds::Metadata => metadata.find_string(key).ok_or_else(|| {
+                            ArrowError::ComputeError(format!(
+                                "Key '{}' is not present in metadata dictionary.",
+                                key
+                            ))
+                        })?,
+                        KeyIds::Session(ids) => ids.get(key).expect("session has every key"),
+                    };
+                    arena.fields.push((field_id, start));
+                    start = *end;
+                }
+                write::write_object_header(&mut arena.header, metadata, &mut arena.fields, start)
+            }
+        }
+        .map_err(ArrowError::ComputeError)
+    }
+}
+
+fn convert_scalar(
+    json: &JsonValue,
+    buffer: &mut Vec<u8>,
+    options: &JsonParseOptions,
 ) -> Result<(), ArrowError> {
     match json {
-        jiter::JsonValue::Null => write::write_null(buffer),
-        jiter::JsonValue::Bool(true) => write::write_bool(buffer, true),
-        jiter::JsonValue::Bool(false) => write::write_bool(buffer, false),
-        jiter::JsonValue::Int(value) => write::write_i64(buffer, *value),
-        jiter::JsonValue::Float(value) => write::write_f64(buffer, *value),
-        jiter::JsonValue::BigInt(value) => {
+        JsonValue::Null => write::write_null(buffer),
+        JsonValue::Bool(true) => write::write_bool(buffer, true),
+        JsonValue::Bool(false) => write::write_bool(buffer, false),
+        JsonValue::Int(value) => match options.numeric_policy {
+            NumericPolicy::Default => write::write_i64(buffer, *value),
+            NumericPolicy::Smallest => write_smallest_integer(buffer, *value),
+        },
+        JsonValue::Float(value) => match options.numeric_policy {
+            NumericPolicy::Default => write::write_f64(buffer, *value),
+            NumericPolicy::Smallest => write_smallest_float(buffer, *value),
+        },
+        JsonValue::BigInt(value) => {
             let value: i128 = i128::try_from(value).map_err(|_| {
                 ArrowError::ComputeError(format!("Could not fit value {} into an i128", value))
             })?;
             write::write_decimal(buffer, value, 0)
         }
-        jiter::JsonValue::Str(value) => write::write_string(buffer, value),
-        jiter::JsonValue::Array(array) => {
-            let mut array_builder = ArrayBuilder::new(buffer, array.len());
-            let mut tmp_buffer = Vec::new();
-            for value in array.iter() {
-                convert_value(value, &mut tmp_buffer, metadata)?;
-                array_builder.append_value(&tmp_buffer);
-                tmp_buffer.clear();
-            }
-            array_builder.finish();
+        JsonValue::Str(value) => match options.uuid_strings.then(|| parse_uuid(value)).flatten() {
+            Some(uuid) => write::write_uuid(buffer, &uuid),
+            None => write::write_string(buffer, value),
+        },
+        JsonValue::Array(_) | JsonValue::Object(_) => unreachable!("not a scalar"),
+    }
+    Ok(())
+}
+
+/// Write an integer with the narrowest integer type that holds it.
+fn write_smallest_integer(buffer: &mut Vec<u8>, value: i64) {
+    if let Ok(value) = i8::try_from(value) {
+        write::write_i8(buffer, value)
+    } else if let Ok(value) = i16::try_from(value) {
+        write::write_i16(buffer, value)
+    } else if let Ok(value) = i32::try_from(value) {
+        write::write_i32(buffer, value)
+    } else {
+        write::write_i64(buffer, value)
+    }
+}
+
+/// Write a float as f32 or Decimal4 if that is lossless, or as f64.
+fn write_smallest_float(buffer: &mut Vec<u8>, value: f64) {
+    match shortest_decimal(value) {
+        // An f32 prints its shortest round-trip form, which is only the
+        // same as the f64's for short decimals.
+        Some((unscaled, _))
+            if (value as f32) as f64 == value && unscaled.unsigned_abs() < 1_000_000 =>
+        {
+            write::write_f32(buffer, value as f32)
         }
-        jiter::JsonValue::Object(object) => {
-            let mut object_builder = ObjectBuilder::with_capacity(buffer, metadata, object.len());
-
-            let mut tmp_buffer = Vec::new();
-            for (key, value) in object.iter() {
-                convert_value(value, &mut tmp_buffer, metadata)?;
-                object_builder
-                    .append_value(key, &tmp_buffer)
-                    .map_err(ArrowError::ComputeError)?;
-                tmp_buffer.clear();
-            }
+        Some((unscaled, scale)) => write::write_decimal(buffer, unscaled as i128, scale),
+        None => write::write_f64(buffer, value),
+    }
+}
 
-            object_builder.finish();
+/// The Decimal4 with the smallest scale that converts back to `value`. The
+/// scale is at least 1, so integral floats still print as floats.
+fn shortest_decimal(value: f64) -> Option<(i32, u8)> {
+    // Powers of ten up to 1e22 are exact, so the division is correctly
+    // rounded and only gives `value` back if the decimal is its nearest f64.
+    let mut power = 10.0;
+    for scale in 1..=22 {
+        let scaled = (value * power).round();
+        if scaled.abs() > i32::MAX as f64 {
+            return None;
         }
+        if scaled / power == value {
+            return Some((scaled as i32, scale));
+        }
+        power *= 10.0;
     }
-    Ok(())
+    None
 }
 
 #[cfg(test)]
@@ -250,9 +1078,11 @@ mod tests {
     use arrow_array::{
         types::Int8Type, BinaryViewArray, Int8Array, LargeStringArray, StringArray, StringViewArray,
     };
+    use arrow_schema::Field;
     use open_variant::values::{BasicType, PrimitiveTypeId, VariantRef};
 
     use super::*;
+    use crate::array::binary_array_from_parts;
 
     fn check_parsing(jsons: &[&str]) -> ArrayRef {
         let string_array = StringArray::from_iter_values(jsons);
@@ -288,7 +1118,17 @@ mod tests {
         let values = output.as_struct().column(1).as_binary::<i32>();
         let variant = VariantRef::try_new(values.value(0)).unwrap();
         assert_eq!(variant.basic_type(), BasicType::Object);
-        let variant = variant.field(0).unwrap().unwrap();
+        let metadata = output
+            .as_struct()
+            .column(0)
+            .as_dictionary::<Int8Type>()
+            .values()
+            .as_binary::<i32>()
+            .value(0);
+        let variant = variant
+            .field(0, &MetadataRef::new(metadata))
+            .unwrap()
+            .unwrap();
         assert_eq!(variant.basic_type(), BasicType::Primitive);
         assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Null);
     }
@@ -354,7 +1194,7 @@ mod tests {
         key: &str,
     ) -> VariantRef<'a> {
         let field_id = meta_ref.find_string(key).unwrap();
-        variant.field(field_id).unwrap().unwrap()
+        variant.field(field_id, meta_ref).unwrap().unwrap()
     }
 
     #[test]
@@ -397,7 +1237,7 @@ mod tests {
     }
 
     fn get_element<'a>(variant: &'a VariantRef<'a>, i: usize) -> VariantRef<'a> {
-        variant.field(i).unwrap().unwrap()
+        variant.get_array().unwrap().get_element(i).unwrap()
     }
 
     #[test]
@@ -527,4 +1367,454 @@ mod tests {
         assert!(matches!(output, Err(ArrowError::ComputeError(message))
             if message.contains("Failed to parse JSON")));
     }
+
+    #[test]
+    fn test_values_offset_widths() {
+        // Promotion to LargeBinary only happens past 2GB of values, so check
+        // both offset widths produce the same values directly.
+        let mut buffer = Vec::new();
+        write::write_i64(&mut buffer, 1);
+        write::write_string(&mut buffer, "x");
+        let offsets = [0, 9, 9, 15];
+        let nulls = Some(NullBuffer::from(vec![true, false, true]));
+
+        let small = binary_array_from_parts::<i32>(buffer.clone(), &offsets, nulls.clone());
+        let large = binary_array_from_parts::<i64>(buffer, &offsets, nulls);
+        assert_eq!(small.len(), 3);
+        assert_eq!(small.null_count(), 1);
+        for i in 0..3 {
+            assert_eq!(small.is_null(i), large.is_null(i));
+            assert_eq!(small.value(i), large.value(i));
+        }
+        assert_eq!(
+            VariantRef::try_new(large.value(2)).unwrap().get_string(),
+            "x"
+        );
+    }
+
+    #[test]
+    fn test_uuid_strings() {
+        let uuid = "f81d4fae-7dec-11d0-a765-00a0c91e6bf6";
+        let jsons = StringArray::from(vec![format!("{{\"id\": \"{}\"}}", uuid)]);
+        let id_type = |options: &JsonParseOptions| {
+            let output = variant_from_json_with_options(&jsons, options).unwrap();
+            let value = output.as_struct().column(1).as_binary::<i32>().value(0);
+            let variant = VariantRef::try_new(value).unwrap();
+            let (_, id) = variant.get_object().unwrap().fields().next().unwrap();
+            id.type_name()
+        };
+        assert_eq!(id_type(&JsonParseOptions::default()), "string");
+        let options = JsonParseOptions {
+            uuid_strings: true,
+            ..Default::default()
+        };
+        assert_eq!(id_type(&options), "uuid");
+    }
+
+    #[test]
+    fn test_numeric_policy() {
+        let jsons = StringArray::from(vec![concat!(
+            "[1, -300, 70000, 5000000000, 1.5, 100.0, ",
+            "0.1, 12.345, 16777217.0, 1e-300, 3.141592653589793]"
+        )]);
+        let options = JsonParseOptions {
+            numeric_policy: NumericPolicy::Smallest,
+            ..Default::default()
+        };
+        let output = variant_from_json_with_options(&jsons, &options).unwrap();
+        let value = output.as_struct().column(1).as_binary::<i32>().value(0);
+        let variant = VariantRef::try_new(value).unwrap();
+        let array = variant.get_array().unwrap();
+        let types = (0..array.len())
+            .map(|i| array.get_element(i).unwrap().type_name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                "int8", "int16", "int32", "int64", "float", "float", "decimal4", "decimal4",
+                "decimal4", "double", "double"
+            ]
+        );
+
+        // The numbers print the same as with the default policy.
+        let default_output = variant_from_json(&jsons).unwrap();
+        assert_eq!(
+            variant_to_json(&output).unwrap(),
+            variant_to_json(&default_output).unwrap()
+        );
+        assert!(
+            value.len()
+                < default_output
+                    .as_struct()
+                    .column(1)
+                    .as_binary::<i32>()
+                    .value(0)
+                    .len()
+        );
+    }
+
+    #[test]
+    fn test_layout_option() {
+        use crate::layout::{MetadataEncoding, ValuesEncoding};
+
+        let jsons = StringArray::from(vec![r#"{"a": 1}"#, "2"]);
+        let layout = VariantLayout::builder()
+            .values_encoding(ValuesEncoding::View)
+            .metadata_encoding(MetadataEncoding::RunEnd)
+            .metadata_key_type(DataType::Int32)
+            .build()
+            .unwrap();
+        let options = JsonParseOptions {
+            layout: Some(layout.clone()),
+            ..Default::default()
+        };
+        let output = variant_from_json_with_options(&jsons, &options).unwrap();
+        assert_eq!(output.data_type(), &layout.data_type());
+
+        let output = variant_from_json(&jsons).unwrap();
+        assert_eq!(output.data_type(), &crate::variant_type());
+    }
+
+    #[test]
+    fn test_limits() {
+        let jsons = StringArray::from(vec![
+            r#"{"a": {"b": [1]}}"#,
+            r#"{"a": 1, "b": 2, "c": 3}"#,
+            r#""a string value that is longer than forty bytes""#,
+            "[[1]]",
+        ]);
+        let limited = |max_depth, max_object_keys, max_value_bytes, limit_policy| {
+            let options = JsonParseOptions {
+                max_depth,
+                max_object_keys,
+                max_value_bytes,
+                limit_policy,
+                ..Default::default()
+            };
+            variant_from_json_with_options(&jsons, &options)
+        };
+
+        let output = limited(Some(2), None, None, LimitPolicy::Null).unwrap();
+        let nulls = (0..4).map(|i| output.is_null(i)).collect::<Vec<_>>();
+        assert_eq!(nulls, vec![true, false, false, false]);
+
+        let output = limited(None, Some(2), None, LimitPolicy::Null).unwrap();
+        let nulls = (0..4).map(|i| output.is_null(i)).collect::<Vec<_>>();
+        assert_eq!(nulls, vec![false, true, false, false]);
+
+        let output = limited(None, None, Some(40), LimitPolicy::Null).unwrap();
+        let nulls = (0..4).map(|i| output.is_null(i)).collect::<Vec<_>>();
+        assert_eq!(nulls, vec![false, false, true, false]);
+        // Dropped documents don't leave data behind.
+        let values = output.as_struct().column(1).as_binary::<i32>();
+        assert_eq!(values.value_length(2), 0);
+
+        let result = limited(Some(1), None, None, LimitPolicy::Error);
+        assert!(matches!(result, Err(ArrowError::ComputeError(message))
+            if message == "JSON document at row 0 exceeds limit: nesting is deeper than the maximum of 1"));
+
+        // Limits that aren't reached don't change the output.
+        let output = limited(Some(3), Some(3), Some(100), LimitPolicy::Error).unwrap();
+        assert_eq!(&output, &variant_from_json(&jsons).unwrap());
+    }
+
+    #[test]
+    fn test_ingest_session() {
+        let mut session = JsonIngestSession::default();
+        let batches = [
+            vec![Some(r#"{"z": 1, "a": {"m": 2}}"#), None],
+            vec![Some(r#"{"a": 3}"#), Some(r#"{"z": 4}"#)],
+            vec![Some(r#"{"b": 5, "z": [{"a": 6}]}"#)],
+        ];
+        let outputs = batches
+            .iter()
+            .map(|batch| session.ingest(&StringArray::from(batch.clone())).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(session.len(), 4);
+        assert_eq!(session.take_delta(), ["z", "a", "m", "b"]);
+        assert!(session.take_delta().is_empty());
+
+        let metadata_values = |output: &ArrayRef| {
+            let metadata = output.as_struct().column(0).as_any_dictionary();
+            metadata.values().to_data()
+        };
+        // A batch without new keys shares the metadata of the previous one.
+        let first = metadata_values(&outputs[0]);
+        let second = metadata_values(&outputs[1]);
+        assert_eq!(first.buffers()[1].as_ptr(), second.buffers()[1].as_ptr());
+
+        for (output, batch) in outputs.iter().zip(&batches) {
+            let variant_array = VariantArray::try_new(output).unwrap();
+            assert_eq!(output.len(), batch.len());
+            for (i, json) in batch.iter().enumerate() {
+                let Some(json) = json else {
+                    assert!(output.is_null(i));
+                    continue;
+                };
+                // The same document converted on its own has the same keys.
+                let expected = variant_from_json(&StringArray::from(vec![*json])).unwrap();
+                let expected = VariantArray::try_new(&expected).unwrap();
+                let metadata = MetadataRef::new(variant_array.metadata(i));
+                let expected_metadata = MetadataRef::new(expected.metadata(0));
+                let mut keys = Vec::new();
+                variant_array
+                    .variant(i)
+                    .unwrap()
+                    .visit_paths(&metadata, |path, value| {
+                        keys.push((path.to_vec(), value.type_name()))
+                    })
+                    .unwrap();
+                let mut expected_keys = Vec::new();
+                expected
+                    .variant(0)
+                    .unwrap()
+                    .visit_paths(&expected_metadata, |path, value| {
+                        expected_keys.push((path.to_vec(), value.type_name()))
+                    })
+                    .unwrap();
+                assert_eq!(keys, expected_keys);
+            }
+        }
+        // Keys keep the ids they were given in the first batch.
+        let last = VariantArray::try_new(&outputs[2]).unwrap();
+        let metadata = MetadataRef::new(last.metadata(0));
+        assert!(!metadata.sorted_strings());
+        assert_eq!(metadata.find_string("z"), Some(0));
+        assert_eq!(metadata.find_string("b"), Some(3));
+    }
+
+    #[test]
+    fn test_variant_from_json_parallel() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"b": 1, "c": {"a": [true]}}"#),
+            None,
+            Some("null"),
+            Some(r#"{"z": "x", "b": 2}"#),
+            Some("[1, 2]"),
+            Some(r#"{"c": 3}"#),
+            Some(r#"{"a": {"z": 4}}"#),
+        ]);
+        for top_level_null in [NullConvention::ArrowNull, NullConvention::VariantNull] {
+            let options = JsonParseOptions {
+                top_level_null,
+                ..Default::default()
+            };
+            let expected = variant_from_json_with_options(&jsons, &options).unwrap();
+            for threads in [0, 1, 2, 3, 7, 100] {
+                let output = variant_from_json_parallel(&jsons, &options, threads).unwrap();
+                assert_eq!(&output, &expected, "{} threads", threads);
+            }
+        }
+
+        let options = JsonParseOptions {
+            layout: Some(
+                VariantLayout::builder()
+                    .large_offsets(true)
+                    .build()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let output = variant_from_json_parallel(&jsons, &options, 3).unwrap();
+        assert_eq!(
+            &output,
+            &variant_from_json_with_options(&jsons, &options).unwrap()
+        );
+
+        let empty = StringArray::from(Vec::<&str>::new());
+        assert_eq!(
+            variant_from_json_parallel(&empty, &options, 4)
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_variant_from_json_parallel_errors() {
+        // Errors in a later chunk report the row of the whole array.
+        let error = |jsons: &StringArray, options: &JsonParseOptions, threads| {
+            let result = match threads {
+                None => variant_from_json_with_options(jsons, options),
+                Some(threads) => variant_from_json_parallel(jsons, options, threads),
+            };
+            result.unwrap_err().to_string()
+        };
+        let jsons = StringArray::from(vec!["1", "2", "3", "4", "[5]", r#"{"a": "#, "7"]);
+        let options = JsonParseOptions::default();
+        let serial = error(&jsons, &options, None);
+        assert!(
+            serial.contains("Failed to parse JSON at row 5"),
+            "{}",
+            serial
+        );
+        for threads in [2, 3, 7] {
+            assert_eq!(error(&jsons, &options, Some(threads)), serial);
+        }
+
+        let jsons = StringArray::from(vec!["1", "2", "3", "4", "[[5]]", "6", "7"]);
+        let options = JsonParseOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let serial = error(&jsons, &options, None);
+        assert!(serial.contains("at row 4 exceeds limit"), "{}", serial);
+        for threads in [2, 3, 7] {
+            assert_eq!(error(&jsons, &options, Some(threads)), serial);
+        }
+    }
+
+    #[test]
+    fn test_variant_to_json() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"b": [1, 2.5, "é"], "a": {"c": null}}"#),
+            None,
+            Some("null"),
+            Some("1e100"),
+        ]);
+        let array = variant_from_json_with_options(
+            &jsons,
+            &JsonParseOptions {
+                top_level_null: NullConvention::VariantNull,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let output = variant_to_json(&array).unwrap();
+        assert_eq!(
+            output,
+            StringArray::from(vec![
+                Some(r#"{"a":{"c":null},"b":[1,2.5,"é"]}"#),
+                None,
+                Some("null"),
+                Some("10000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000.0"),
+            ])
+        );
+
+        let options = JsonWriteOptions {
+            ascii_only: true,
+            float_format: open_variant::json::FloatFormat::Scientific,
+            ..Default::default()
+        };
+        let output = variant_to_json_with_options(&array, &options).unwrap();
+        assert_eq!(
+            output.value(0),
+            r#"{"a":{"c":null},"b":[1,2.5e0,"\u00e9"]}"#
+        );
+        assert_eq!(output.value(3), "1e100");
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        // jiter limits how deep JSON it parses, so deeper input is an error
+        // rather than a crash.
+        let depth = 150;
+        let json = format!("{}1{}", "[".repeat(depth), "]".repeat(depth));
+        let array = variant_from_json(&StringArray::from(vec![json.as_str()])).unwrap();
+        assert_eq!(variant_to_json(&array).unwrap().value(0), json);
+
+        let json = format!("{}1{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(variant_from_json(&StringArray::from(vec![json.as_str()])).is_err());
+
+        // [[ ... [1] ... ]], built from the inside out. The bytes are
+        // collected in reverse so each level only writes its header.
+        let depth = 100_000;
+        let mut reversed = Vec::new();
+        write::write_i64(&mut reversed, 1);
+        reversed.reverse();
+        let mut header = Vec::new();
+        for _ in 0..depth {
+            header.clear();
+            write::write_array_header(&mut header, &[reversed.len()]).unwrap();
+            reversed.extend(header.iter().rev());
+        }
+        reversed.reverse();
+
+        let metadata = BinaryArray::new_scalar(build_metadata(std::iter::empty()));
+        let metadata = make_repeated_dict_array(metadata, 1);
+        let values = Arc::new(BinaryArray::from_vec(vec![&reversed[..]])) as ArrayRef;
+        let array =
+            variant_array_from_parts(metadata, values, &JsonParseOptions::default()).unwrap();
+        let json = variant_to_json(&array).unwrap();
+        assert_eq!(
+            json.value(0),
+            format!("{}1{}", "[".repeat(depth), "]".repeat(depth))
+        );
+    }
+
+    #[test]
+    fn test_try_variant_from_json() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": 1}"#),
+            Some("{not json"),
+            None,
+            Some("[[[1]]]"),
+            Some("null"),
+        ]);
+        assert!(variant_from_json(&jsons).is_err());
+        let options = JsonParseOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let array = try_variant_from_json(&jsons, &options).unwrap();
+        assert_eq!(array.len(), 5);
+        let valid = (0..5).map(|i| array.is_valid(i)).collect::<Vec<_>>();
+        assert_eq!(valid, vec![true, false, false, false, false]);
+
+        let json = try_variant_to_json(&array).unwrap();
+        assert_eq!(json.value(0), r#"{"a":1}"#);
+        assert_eq!(json.null_count(), 4);
+    }
+
+    #[test]
+    fn test_try_variant_to_json() {
+        // The object refers to field id 0, but the metadata is empty.
+        let array = variant_from_json(&StringArray::from(vec![r#"{"a": 1}"#, "2"])).unwrap();
+        let metadata = BinaryArray::new_scalar(build_metadata(std::iter::empty()));
+        let metadata = make_repeated_dict_array(metadata, 2);
+        let values = array.as_struct().column(1).clone();
+        let invalid =
+            variant_array_from_parts(metadata, values, &JsonParseOptions::default()).unwrap();
+
+        assert!(variant_to_json(&invalid).is_err());
+        let json = try_variant_to_json(&invalid).unwrap();
+        assert!(json.is_null(0));
+        assert_eq!(json.value(1), "2");
+    }
+
+    #[test]
+    fn test_variant_eq_json() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"b": [1, {"c": null}], "a": "x"}"#),
+            Some(r#"{"a": "x", "b": [1.0, {"c": null}]}"#),
+            Some(r#"{"a": "x", "b": [1, {"c": 0}]}"#),
+            Some(r#"{"a": "x"}"#),
+            Some("[1]"),
+            Some("null"),
+            None,
+        ]);
+        let options = JsonParseOptions {
+            top_level_null: NullConvention::VariantNull,
+            ..Default::default()
+        };
+        let array = variant_from_json_with_options(&jsons, &options).unwrap();
+        let equal = variant_eq_json(&array, r#"{"a": "x", "b": [1, {"c": null}]}"#).unwrap();
+        assert_eq!(
+            equal.iter().collect::<Vec<_>>(),
+            vec![
+                Some(true),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(false),
+                None,
+                None
+            ]
+        );
+
+        let equal = variant_eq_json(&array, "[1]").unwrap();
+        assert_eq!(equal.true_count(), 1);
+        assert!(variant_eq_json(&array, "{").is_err());
+    }
 }
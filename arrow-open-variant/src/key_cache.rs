@@ -0,0 +1,253 @@
+//! A process-wide cache of key lookups in metadata dictionaries.
+//!
+//! Analytic queries typically probe the same handful of keys over thousands
+//! of batches, and every kernel call resolves its paths against the metadata
+//! of each batch again. With the `key-cache` feature, kernels look up keys
+//! through this cache instead, which is shared by every call and thread.
+//! This saves the most for unsorted dictionaries, where finding a key means
+//! scanning the whole dictionary.
+//!
+//! Entries are keyed on the address of the metadata buffer and the key, and
+//! hold a reference to the Arrow buffer the metadata is stored in, so the
+//! address can't be reused by other metadata while the entry is cached. The
+//! least recently used entries are evicted once the cache holds
+//! [`key_cache_capacity`] entries.
+//!
+//! Only dictionary and run-end encoded metadata is cached. Plain metadata
+//! has a buffer per row, so its entries would never be hit again.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use arrow_buffer::Buffer;
+use open_variant::metadata::MetadataRef;
+use open_variant::path::{PathElement, ResolvedPath, VariantPath};
+
+/// The default [`key_cache_capacity`].
+pub const DEFAULT_KEY_CACHE_CAPACITY: usize = 4096;
+
+static KEY_CACHE: OnceLock<Mutex<KeyCache>> = OnceLock::new();
+
+fn key_cache() -> &'static Mutex<KeyCache> {
+    KEY_CACHE.get_or_init(|| Mutex::new(KeyCache::new(DEFAULT_KEY_CACHE_CAPACITY)))
+}
+
+/// The maximum number of keys the cache holds.
+pub fn key_cache_capacity() -> usize {
+    key_cache().lock().map_or(0, |cache| cache.capacity)
+}
+
+/// Set the maximum number of keys the cache holds, evicting the least
+/// recently used entries if it holds more. A capacity of 0 disables the
+/// cache.
+pub fn set_key_cache_capacity(capacity: usize) {
+    if let Ok(mut cache) = key_cache().lock() {
+        cache.capacity = capacity;
+        cache.evict(capacity);
+    }
+}
+
+/// Remove every entry from the cache, releasing the buffers they hold.
+pub fn clear_key_cache() {
+    if let Ok(mut cache) = key_cache().lock() {
+        cache.entries.clear();
+    }
+}
+
+/// Resolve `path` against `metadata_bytes`, which must be stored in `owner`,
+/// looking up its keys through the cache.
+pub(crate) fn resolve_path(
+    owner: &Buffer,
+    metadata_bytes: &[u8],
+    path: &VariantPath,
+) -> ResolvedPath {
+    let metadata = MetadataRef::new(metadata_bytes);
+    // Only the cached ids are read under the lock. Keys that miss are found
+    // after releasing it, so threads don't wait on each other's searches.
+    let cached = match key_cache().lock() {
+        Ok(mut cache) => path
+            .elements()
+            .iter()
+            .filter_map(|element| match element {
+                PathElement::Field(key) => Some((key.as_str(), cache.get(metadata_bytes, key)?)),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => return ResolvedPath::new(path, &metadata),
+    };
+    let mut missed = Vec::new();
+    let resolved = ResolvedPath::with_field_ids(path, &metadata, |key| {
+        if let Some((_, field_id)) = cached.iter().find(|(cached_key, _)| *cached_key == key) {
+            return *field_id;
+        }
+        let field_id = metadata.find_string(key);
+        missed.push((key.to_string(), field_id));
+        field_id
+    });
+    if !missed.is_empty() {
+        if let Ok(mut cache) = key_cache().lock() {
+            for (key, field_id) in missed {
+                cache.insert(owner, metadata_bytes, key, field_id);
+            }
+        }
+    }
+    resolved
+}
+
+/// The address and length of a metadata buffer, and a key.
+type CacheKey = (usize, usize, String);
+
+struct KeyCache {
+    capacity: usize,
+    /// Incremented on every lookup, to order entries by last use.
+    clock: u64,
+    entries: HashMap<CacheKey, CacheEntry>,
+}
+
+struct CacheEntry {
+    /// `None` if the key is not in the dictionary.
+    field_id: Option<usize>,
+    last_used: u64,
+    /// Keeps the metadata alive, so its address isn't reused.
+    _owner: Buffer,
+}
+
+impl KeyCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The cached id of `key` in `metadata_bytes`, or `None` if it isn't
+    /// cached. `Some(None)` means the key is not in the dictionary.
+    fn get(&mut self, metadata_bytes: &[u8], key: &str) -> Option<Option<usize>> {
+        self.clock += 1;
+        let cache_key = (
+            metadata_bytes.as_ptr() as usize,
+            metadata_bytes.len(),
+            key.to_string(),
+        );
+        let entry = self.entries.get_mut(&cache_key)?;
+        entry.last_used = self.clock;
+        Some(entry.field_id)
+    }
+
+    /// Cache the id of `key` in `metadata_bytes`, which is stored in `owner`.
+    fn insert(
+        &mut self,
+        owner: &Buffer,
+        metadata_bytes: &[u8],
+        key: String,
+        field_id: Option<usize>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if self.entries.len() >= self.capacity {
+            // Evict a quarter at a time, so evicting is amortized over many
+            // insertions.
+            self.evict(self.capacity - (self.capacity + 3) / 4);
+        }
+        let cache_key = (metadata_bytes.as_ptr() as usize, metadata_bytes.len(), key);
+        let entry = CacheEntry {
+            field_id,
+            last_used: self.clock,
+            _owner: owner.clone(),
+        };
+        self.entries.insert(cache_key, entry);
+    }
+
+    /// Evict the least recently used entries until at most `len` are left.
+    fn evict(&mut self, len: usize) {
+        if self.entries.len() <= len {
+            return;
+        }
+        if len == 0 {
+            self.entries.clear();
+            return;
+        }
+        let mut last_used = self
+            .entries
+            .values()
+            .map(|entry| entry.last_used)
+            .collect::<Vec<_>>();
+        let evicted = last_used.len() - len;
+        let (_, threshold, _) = last_used.select_nth_unstable(evicted - 1);
+        // Clock values are unique, so this keeps exactly `len` entries.
+        let threshold = *threshold;
+        self.entries.retain(|_, entry| entry.last_used > threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::BinaryArray;
+    use open_variant::metadata::build_metadata;
+
+    use super::*;
+
+    #[test]
+    fn test_key_cache() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let array = BinaryArray::from_iter_values([metadata.as_slice(), metadata.as_slice()]);
+        let owner = array.values();
+        let metadata_ref = MetadataRef::new(array.value(0));
+
+        // Look up a key like `resolve_path` does, caching it on a miss.
+        let field_id = |cache: &mut KeyCache, metadata_bytes: &[u8], key: &str| {
+            cache.get(metadata_bytes, key).unwrap_or_else(|| {
+                let field_id = metadata_ref.find_string(key);
+                cache.insert(owner, metadata_bytes, key.to_string(), field_id);
+                field_id
+            })
+        };
+
+        let mut cache = KeyCache::new(4);
+        assert_eq!(field_id(&mut cache, array.value(0), "b"), Some(1));
+        assert_eq!(field_id(&mut cache, array.value(0), "d"), None);
+        assert_eq!(cache.entries.len(), 2);
+
+        // Hits don't add entries, and equal metadata at another address is
+        // another entry.
+        assert_eq!(field_id(&mut cache, array.value(0), "b"), Some(1));
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(field_id(&mut cache, array.value(1), "b"), Some(1));
+        assert_eq!(cache.entries.len(), 3);
+
+        // The least recently used entries are evicted first.
+        field_id(&mut cache, array.value(0), "a");
+        field_id(&mut cache, array.value(0), "b");
+        assert_eq!(cache.entries.len(), 4);
+        field_id(&mut cache, array.value(0), "c");
+        assert_eq!(cache.entries.len(), 4);
+        let key = |key: &str| {
+            (
+                array.value(0).as_ptr() as usize,
+                metadata.len(),
+                key.to_string(),
+            )
+        };
+        assert!(!cache.entries.contains_key(&key("d")));
+        assert!(cache.entries.contains_key(&key("b")));
+        assert!(cache.entries.contains_key(&key("c")));
+
+        cache.capacity = 0;
+        cache.evict(0);
+        assert_eq!(field_id(&mut cache, array.value(0), "c"), Some(2));
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let array = BinaryArray::from_iter_values([metadata.as_slice()]);
+        let path = VariantPath::parse("b").unwrap();
+        let resolved = resolve_path(array.values(), array.value(0), &path);
+        let expected = ResolvedPath::new(&path, &MetadataRef::new(&metadata));
+        assert_eq!(format!("{:?}", resolved), format!("{:?}", expected));
+    }
+}
@@ -0,0 +1,322 @@
+//! Collect the object keys used in variant arrays.
+
+use std::collections::BTreeSet;
+
+use arrow_array::builder::BooleanBuilder;
+use arrow_array::{Array, BooleanArray};
+use arrow_schema::ArrowError;
+use open_variant::metadata::MetadataRef;
+use open_variant::values::{BasicType, ObjectRef};
+
+use crate::array::{MetadataCache, VariantArray, VariantArrayReader};
+
+/// Where [`KeySet`] reads keys from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeySource {
+    /// All strings in the metadata dictionaries of the non-null rows.
+    ///
+    /// This only reads the metadata, which is usually shared by many rows, so
+    /// it is nearly free. It includes keys at every depth, and can include
+    /// keys that no row uses, for example after the array was filtered.
+    #[default]
+    Metadata,
+    /// The keys of top-level objects. This reads every value.
+    TopLevel,
+}
+
+/// The distinct set of object keys observed over one or more variant arrays.
+///
+/// ```rust
+/// # #[cfg(feature = "json")]
+/// # {
+/// use arrow_array::StringArray;
+/// use arrow_open_variant::json::variant_from_json;
+/// use arrow_open_variant::keys::{KeySet, KeySource};
+///
+/// let jsons = StringArray::from(vec![r#"{"b": {"c": 1}}"#, r#"{"a": 2}"#]);
+/// let array = variant_from_json(&jsons).unwrap();
+///
+/// let mut keys = KeySet::new(KeySource::TopLevel);
+/// keys.update(&array).unwrap();
+/// assert_eq!(keys.into_sorted_vec(), vec!["a", "b"]);
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeySet {
+    source: KeySource,
+    keys: BTreeSet<String>,
+}
+
+impl KeySet {
+    pub fn new(source: KeySource) -> Self {
+        Self {
+            source,
+            keys: BTreeSet::new(),
+        }
+    }
+
+    /// Add the keys of a variant array.
+    ///
+    /// # Errors
+    ///
+    /// If the array is not a variant array, or if a value is invalid.
+    pub fn update(&mut self, array: &dyn Array) -> Result<(), ArrowError> {
+        let variant_array = VariantArray::try_new(array)?;
+        match self.source {
+            KeySource::Metadata => {
+                for buffer in variant_array.metadata_buffers() {
+                    let metadata = MetadataRef::new(buffer);
+                    let strings =
+                        (0..metadata.dictionary_len()).filter_map(|id| metadata.get_string(id));
+                    self.insert_all(strings);
+                }
+            }
+            KeySource::TopLevel => {
+                let rows = variant_array
+                    .valid_slices()
+                    .flat_map(|(start, end)| start..end);
+                for i in rows {
+                    let Some(variant) = variant_array.variant(i) else {
+                        continue;
+                    };
+                    if variant.basic_type() != BasicType::Object {
+                        continue;
+                    }
+                    let object = variant
+                        .get_object()
+                        .map_err(ArrowError::InvalidArgumentError)?;
+                    let metadata = MetadataRef::new(variant_array.metadata(i));
+                    let keys = object
+                        .fields()
+                        .filter_map(|(field_id, _)| metadata.get_string(field_id));
+                    self.insert_all(keys);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the keys of another set, such as one built over another partition.
+    pub fn merge(&mut self, other: KeySet) {
+        self.keys.extend(other.keys);
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The keys, in sorted order.
+    pub fn into_sorted_vec(self) -> Vec<String> {
+        self.keys.into_iter().collect()
+    }
+
+    fn insert_all<'a>(&mut self, keys: impl Iterator<Item = &'a str>) {
+        for key in keys {
+            if !self.keys.contains(key) {
+                self.keys.insert(key.to_string());
+            }
+        }
+    }
+}
+
+/// Whether the top-level object of each row has each of `keys`, as a boolean
+/// array per key.
+///
+/// This is the kernel behind testing many keys at once, as with the `?&` and
+/// `?|` operators of Postgres. Keys are resolved to field ids once per
+/// metadata dictionary, and each object is checked for all of them in a
+/// single pass over its field ids, rather than with a search per key. With a
+/// sorted dictionary the pass merges the field ids of the object with the
+/// sorted ids of the keys, and otherwise it uses a table from field id to
+/// keys.
+///
+/// Rows that are not objects have none of the keys, and null rows are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_object_keys_bitmask(
+    array: &dyn Array,
+    keys: &[&str],
+) -> Result<Vec<BooleanArray>, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builders = keys
+        .iter()
+        .map(|_| BooleanBuilder::with_capacity(variant_array.len()))
+        .collect::<Vec<_>>();
+
+    let mut resolved_keys = MetadataCache::new();
+    let mut present = vec![false; keys.len()];
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        for builder in &mut builders {
+            builder.append_nulls(start - valid_end);
+        }
+        valid_end = end;
+        for i in start..end {
+            let Some(variant) = variant_array.variant(i) else {
+                builders.iter_mut().for_each(BooleanBuilder::append_null);
+                continue;
+            };
+            present.fill(false);
+            if variant.basic_type() == BasicType::Object {
+                let object = variant
+                    .get_object()
+                    .map_err(ArrowError::InvalidArgumentError)?;
+                let metadata_bytes = variant_array.metadata(i);
+                let resolved = resolved_keys.get_or_insert_with(metadata_bytes, || {
+                    ResolvedKeys::new(keys, &MetadataRef::new(metadata_bytes))
+                });
+                resolved.mark_present(&object, &mut present);
+            }
+            for (builder, present) in builders.iter_mut().zip(&present) {
+                builder.append_value(*present);
+            }
+        }
+    }
+    for builder in &mut builders {
+        builder.append_nulls(variant_array.len() - valid_end);
+    }
+    Ok(builders
+        .into_iter()
+        .map(|mut builder| builder.finish())
+        .collect())
+}
+
+/// The keys of [`variant_object_keys_bitmask`] resolved against one metadata
+/// dictionary.
+enum ResolvedKeys {
+    /// Pairs of field id and key index, sorted by field id. Objects written
+    /// with a sorted dictionary have their field ids in increasing order, so
+    /// the two can be merged.
+    Sorted(Vec<(usize, usize)>),
+    /// The indices of the keys with each field id.
+    Unsorted(Vec<Vec<usize>>),
+}
+
+impl ResolvedKeys {
+    fn new(keys: &[&str], metadata: &MetadataRef) -> Self {
+        let ids = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(index, key)| Some((metadata.find_string(key)?, index)));
+        if metadata.sorted_strings() {
+            let mut ids = ids.collect::<Vec<_>>();
+            ids.sort_unstable();
+            Self::Sorted(ids)
+        } else {
+            let mut table = vec![Vec::new(); metadata.dictionary_len()];
+            for (field_id, index) in ids {
+                table[field_id].push(index);
+            }
+            Self::Unsorted(table)
+        }
+    }
+
+    /// Set `present` for each key the object has.
+    fn mark_present(&self, object: &ObjectRef, present: &mut [bool]) {
+        match self {
+            Self::Sorted(ids) => {
+                let mut pending = ids.iter().peekable();
+                for field_id in object.field_ids() {
+                    while let Some((id, index)) = pending.next_if(|(id, _)| *id <= field_id) {
+                        present[*index] |= *id == field_id;
+                    }
+                    if pending.peek().is_none() {
+                        break;
+                    }
+                }
+            }
+            Self::Unsorted(table) => {
+                for field_id in object.field_ids() {
+                    for index in table.get(field_id).into_iter().flatten() {
+                        present[*index] = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    #[test]
+    fn test_key_set() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"b": {"c": 1}}"#),
+            None,
+            Some(r#"[{"d": 1}]"#),
+            Some(r#"{"a": 2}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+
+        let mut metadata_keys = KeySet::new(KeySource::Metadata);
+        metadata_keys.update(&array).unwrap();
+        assert_eq!(metadata_keys.len(), 4);
+
+        let mut top_level_keys = KeySet::new(KeySource::TopLevel);
+        top_level_keys.update(&array.slice(0, 2)).unwrap();
+        assert_eq!(top_level_keys.clone().into_sorted_vec(), vec!["b"]);
+
+        let mut other = KeySet::new(KeySource::TopLevel);
+        other.update(&array.slice(2, 2)).unwrap();
+        top_level_keys.merge(other);
+        assert_eq!(top_level_keys.into_sorted_vec(), vec!["a", "b"]);
+
+        // The metadata is shared by the whole batch, so a slice still sees
+        // every key.
+        let mut sliced = KeySet::new(KeySource::Metadata);
+        sliced.update(&array.slice(3, 1)).unwrap();
+        assert_eq!(sliced.into_sorted_vec(), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_variant_object_keys_bitmask() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": 1, "b": 2}"#),
+            Some(r#"{"b": 1, "c": {"a": 1}}"#),
+            Some(r#"["a"]"#),
+            None,
+            Some("{}"),
+        ]);
+        let sorted = variant_from_json(&jsons).unwrap();
+        // A session adds keys in insertion order, so its dictionary is not
+        // sorted.
+        let mut session = crate::json::JsonIngestSession::default();
+        session
+            .ingest(&StringArray::from(vec![r#"{"z": 1, "c": 2}"#]))
+            .unwrap();
+        let unsorted = session.ingest(&jsons).unwrap();
+
+        let t = Some(true);
+        let f = Some(false);
+        for array in [sorted, unsorted] {
+            let bitmask = variant_object_keys_bitmask(&array, &["c", "a", "zz", "a"]).unwrap();
+            let columns = bitmask
+                .iter()
+                .map(|column| column.iter().collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                columns,
+                vec![
+                    vec![f, t, f, None, f],
+                    vec![t, f, f, None, f],
+                    vec![f, f, f, None, f],
+                    vec![t, f, f, None, f],
+                ]
+            );
+        }
+
+        let empty = variant_object_keys_bitmask(&variant_from_json(&jsons).unwrap(), &[]).unwrap();
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+//! Choosing the physical layout of variant arrays.
+//!
+//! [`VariantArrayReader`](crate::VariantArrayReader) reads every supported
+//! layout, but arrays that are produced need a single concrete type. A
+//! [`VariantLayout`] describes that type, and is built with
+//! [`VariantLayout::builder`]:
+//!
+//! ```
+//! use arrow_open_variant::layout::{MetadataEncoding, ValuesEncoding, VariantLayout};
+//! use arrow_schema::DataType;
+//!
+//! let layout = VariantLayout::builder()
+//!     .values_encoding(ValuesEncoding::View)
+//!     .metadata_encoding(MetadataEncoding::RunEnd)
+//!     .metadata_key_type(DataType::Int32)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(VariantLayout::try_from_data_type(&layout.data_type()).unwrap(), layout);
+//! ```
+
+use std::collections::HashMap;
+
+use arrow_schema::{ArrowError, DataType, Field, Fields};
+
+/// The name of the Arrow extension type for variant data, stored in the
+/// `ARROW:extension:name` field metadata.
+pub const EXTENSION_NAME: &str = "open_variant.variant";
+
+/// How the value buffers are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValuesEncoding {
+    /// `Binary`, or `LargeBinary` with large offsets.
+    #[default]
+    Offsets,
+    /// `BinaryView`.
+    View,
+    /// `Dictionary(Int32, Binary)`, storing identical values once. See
+    /// [`variant_dedup`](crate::dedup::variant_dedup).
+    Dictionary,
+}
+
+/// How the metadata buffers are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataEncoding {
+    /// One `Binary` buffer per row.
+    Plain,
+    /// A dictionary of `Binary` buffers.
+    #[default]
+    Dictionary,
+    /// Run-end encoded `Binary` buffers.
+    RunEnd,
+}
+
+/// The physical layout of a variant array.
+///
+/// The default is `Dictionary(Int8, Binary)` metadata with `Binary` values,
+/// which is what [`variant_type`](crate::variant_type) returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantLayout {
+    values_encoding: ValuesEncoding,
+    metadata_encoding: MetadataEncoding,
+    metadata_key_type: DataType,
+    large_offsets: bool,
+}
+
+impl Default for VariantLayout {
+    fn default() -> Self {
+        Self {
+            values_encoding: ValuesEncoding::Offsets,
+            metadata_encoding: MetadataEncoding::Dictionary,
+            metadata_key_type: DataType::Int8,
+            large_offsets: false,
+        }
+    }
+}
+
+impl VariantLayout {
+    pub fn builder() -> VariantLayoutBuilder {
+        VariantLayoutBuilder::default()
+    }
+
+    /// The layout of an existing variant data type.
+    ///
+    /// # Errors
+    ///
+    /// If the type is not a struct with `metadata` and `values` children of
+    /// supported types.
+    pub fn try_from_data_type(data_type: &DataType) -> Result<Self, ArrowError> {
+        let DataType::Struct(fields) = data_type else {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Expected a struct type for variant data, got {}",
+                data_type
+            )));
+        };
+        let child = |name: &str| {
+            fields
+                .iter()
+                .find(|field| field.name() == name)
+                .map(|field| field.data_type())
+                .ok_or_else(|| {
+                    ArrowError::InvalidArgumentError(format!(
+                        "Variant type is missing '{}' field",
+                        name
+                    ))
+                })
+        };
+
+        let mut builder = Self::builder();
+        builder = match child("values")? {
+            DataType::Binary => builder,
+            DataType::LargeBinary => builder.large_offsets(true),
+            DataType::BinaryView => builder.values_encoding(ValuesEncoding::View),
+            DataType::Dictionary(key_type, value_type)
+                if key_type.as_ref() == &DataType::Int32
+                    && value_type.as_ref() == &DataType::Binary =>
+            {
+                builder.values_encoding(ValuesEncoding::Dictionary)
+            }
+            other => {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Unsupported variant values type: {}",
+                    other
+                )))
+            }
+        };
+        builder = match child("metadata")? {
+            DataType::Binary => builder.metadata_encoding(MetadataEncoding::Plain),
+            DataType::Dictionary(key_type, value_type)
+                if value_type.as_ref() == &DataType::Binary =>
+            {
+                builder
+                    .metadata_encoding(MetadataEncoding::Dictionary)
+                    .metadata_key_type(key_type.as_ref().clone())
+            }
+            DataType::RunEndEncoded(run_ends, values)
+                if values.data_type() == &DataType::Binary =>
+            {
+                builder
+                    .metadata_encoding(MetadataEncoding::RunEnd)
+                    .metadata_key_type(run_ends.data_type().clone())
+            }
+            other => {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Unsupported variant metadata type: {}",
+                    other
+                )))
+            }
+        };
+        builder.build()
+    }
+
+    pub fn values_encoding(&self) -> ValuesEncoding {
+        self.values_encoding
+    }
+
+    pub fn metadata_encoding(&self) -> MetadataEncoding {
+        self.metadata_encoding
+    }
+
+    /// The dictionary key type or run end type of the metadata. Ignored for
+    /// [`MetadataEncoding::Plain`].
+    pub fn metadata_key_type(&self) -> &DataType {
+        &self.metadata_key_type
+    }
+
+    pub fn large_offsets(&self) -> bool {
+        self.large_offsets
+    }
+
+    /// The type of the `values` child.
+    pub fn values_type(&self) -> DataType {
+        match (self.values_encoding, self.large_offsets) {
+            (ValuesEncoding::Offsets, false) => DataType::Binary,
+            (ValuesEncoding::Offsets, true) => DataType::LargeBinary,
+            (ValuesEncoding::View, _) => DataType::BinaryView,
+            (ValuesEncoding::Dictionary, _) => {
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Binary))
+            }
+        }
+    }
+
+    /// The type of the `metadata` child.
+    pub fn metadata_type(&self) -> DataType {
+        match self.metadata_encoding {
+            MetadataEncoding::Plain => DataType::Binary,
+            MetadataEncoding::Dictionary => DataType::Dictionary(
+                Box::new(self.metadata_key_type.clone()),
+                Box::new(DataType::Binary),
+            ),
+            MetadataEncoding::RunEnd => DataType::RunEndEncoded(
+                Field::new("run_ends", self.metadata_key_type.clone(), false).into(),
+                Field::new("values", DataType::Binary, true).into(),
+            ),
+        }
+    }
+
+    /// The children of the variant struct.
+    pub fn fields(&self) -> Fields {
+        vec![
+            Field::new("metadata", self.metadata_type(), false),
+            Field::new("values", self.values_type(), true),
+        ]
+        .into()
+    }
+
+    pub fn data_type(&self) -> DataType {
+        DataType::Struct(self.fields())
+    }
+
+    /// A field of this layout, tagged with the variant [`EXTENSION_NAME`].
+    pub fn field(&self, name: impl Into<String>, nullable: bool) -> Field {
+        Field::new(name, self.data_type(), nullable).with_metadata(HashMap::from([(
+            "ARROW:extension:name".to_string(),
+            EXTENSION_NAME.to_string(),
+        )]))
+    }
+}
+
+/// Builds a [`VariantLayout`], starting from the default layout.
+#[derive(Debug, Clone, Default)]
+pub struct VariantLayoutBuilder {
+    layout: VariantLayout,
+}
+
+impl VariantLayoutBuilder {
+    pub fn values_encoding(mut self, encoding: ValuesEncoding) -> Self {
+        self.layout.values_encoding = encoding;
+        self
+    }
+
+    pub fn metadata_encoding(mut self, encoding: MetadataEncoding) -> Self {
+        self.layout.metadata_encoding = encoding;
+        self
+    }
+
+    /// The dictionary key type, or the run end type for run-end encoded
+    /// metadata.
+    pub fn metadata_key_type(mut self, key_type: DataType) -> Self {
+        self.layout.metadata_key_type = key_type;
+        self
+    }
+
+    /// Use 64-bit offsets for the values, needed once the values of a single
+    /// array exceed 2GB. Only applies to [`ValuesEncoding::Offsets`].
+    pub fn large_offsets(mut self, large_offsets: bool) -> Self {
+        self.layout.large_offsets = large_offsets;
+        self
+    }
+
+    /// # Errors
+    ///
+    /// If the key type is not valid for the metadata encoding, or large
+    /// offsets are requested for view or dictionary values.
+    pub fn build(self) -> Result<VariantLayout, ArrowError> {
+        let mut layout = self.layout;
+        let key_type = &layout.metadata_key_type;
+        let valid_key_type = match layout.metadata_encoding {
+            MetadataEncoding::Plain => true,
+            MetadataEncoding::Dictionary => key_type.is_dictionary_key_type(),
+            MetadataEncoding::RunEnd => key_type.is_run_ends_type(),
+        };
+        if !valid_key_type {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Invalid key type {} for {:?} variant metadata",
+                key_type, layout.metadata_encoding
+            )));
+        }
+        if layout.metadata_encoding == MetadataEncoding::Plain {
+            // The key type is unused, so reset it to keep layouts comparable.
+            layout.metadata_key_type = DataType::Int8;
+        }
+        if layout.large_offsets && layout.values_encoding != ValuesEncoding::Offsets {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Large offsets are not supported for {:?} values",
+                layout.values_encoding
+            )));
+        }
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_layout() {
+        let layout = VariantLayout::default();
+        assert_eq!(layout.data_type(), crate::variant_type());
+        assert_eq!(
+            VariantLayout::try_from_data_type(&crate::large_variant_type()).unwrap(),
+            VariantLayout::builder()
+                .large_offsets(true)
+                .build()
+                .unwrap()
+        );
+
+        let field = layout.field("v", true);
+        assert_eq!(
+            field.metadata().get("ARROW:extension:name").unwrap(),
+            EXTENSION_NAME
+        );
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        for values_encoding in [
+            ValuesEncoding::Offsets,
+            ValuesEncoding::View,
+            ValuesEncoding::Dictionary,
+        ] {
+            for (metadata_encoding, key_type) in [
+                (MetadataEncoding::Plain, DataType::Int32),
+                (MetadataEncoding::Dictionary, DataType::UInt16),
+                (MetadataEncoding::RunEnd, DataType::Int64),
+            ] {
+                let layout = VariantLayout::builder()
+                    .values_encoding(values_encoding)
+                    .metadata_encoding(metadata_encoding)
+                    .metadata_key_type(key_type)
+                    .build()
+                    .unwrap();
+                let data_type = layout.data_type();
+                assert_eq!(
+                    VariantLayout::try_from_data_type(&data_type).unwrap(),
+                    layout,
+                    "{}",
+                    data_type
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        let err = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::RunEnd)
+            .metadata_key_type(DataType::UInt8)
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid key type"));
+
+        let err = VariantLayout::builder()
+            .values_encoding(ValuesEncoding::View)
+            .large_offsets(true)
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("Large offsets"));
+
+        assert!(VariantLayout::try_from_data_type(&DataType::Utf8).is_err());
+    }
+}
@@ -1,2 +1,27 @@
+pub mod aggregate;
+pub mod array;
+pub mod canonical;
+pub mod cast;
+pub mod compute;
+pub mod dedup;
+pub mod extract;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod histogram;
+pub mod index;
 #[cfg(feature = "json")]
 pub mod json;
+#[cfg(feature = "key-cache")]
+pub mod key_cache;
+pub mod keys;
+pub mod layout;
+pub mod list;
+pub mod mask;
+pub mod nulls;
+pub mod promote;
+pub mod shape;
+pub mod transform;
+pub mod validate;
+
+pub use array::{large_variant_type, variant_type, VariantArray, VariantArrayReader};
+pub use layout::VariantLayout;
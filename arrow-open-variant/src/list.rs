@@ -0,0 +1,203 @@
+//! Operations on variant values that are arrays.
+
+use arrow_array::builder::BooleanBuilder;
+use arrow_array::{Array, ArrayRef, BooleanArray};
+use arrow_buffer::NullBuffer;
+use arrow_schema::ArrowError;
+use open_variant::metadata::MetadataRef;
+use open_variant::values::write::write_array_slice;
+use open_variant::values::{BasicType, VariantRef};
+
+use crate::array::{values_array_from_parts, VariantArray, VariantArrayReader};
+
+/// Whether each variant array value contains `needle` as an element.
+///
+/// Elements are compared with [`VariantRef::scalar_eq`], so integers and
+/// floats compare by numeric value, and strings stored in the metadata
+/// dictionary are resolved through the metadata of their row. Rows that are
+/// null or not arrays are null in the output.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_array_contains(
+    array: &dyn Array,
+    needle: &VariantRef,
+) -> Result<BooleanArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = BooleanBuilder::with_capacity(variant_array.len());
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            let contains = variant_array
+                .variant(i)
+                .filter(|variant| variant.basic_type() == BasicType::Array)
+                .map(|variant| {
+                    let metadata = MetadataRef::new(variant_array.metadata(i));
+                    variant
+                        .get_array()
+                        .map(|array| array.contains(needle, &metadata))
+                        .map_err(ArrowError::InvalidArgumentError)
+                })
+                .transpose()?;
+            builder.append_option(contains);
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+    Ok(builder.finish())
+}
+
+/// Slice each variant array value to the elements `start..end`.
+///
+/// The range is clamped to the length of each array. Only the selected
+/// elements are copied, without being decoded. Rows that are null or not
+/// arrays are null in the output.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_slice(array: &dyn Array, start: usize, end: usize) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    let mut validity = Vec::with_capacity(variant_array.len());
+    offsets.push(0);
+    for (valid_start, valid_end) in variant_array.valid_slices() {
+        // Null rows have empty values.
+        offsets.resize(valid_start + 1, buffer.len());
+        validity.resize(valid_start, false);
+        for i in valid_start..valid_end {
+            let is_valid = match variant_array.variant(i) {
+                Some(variant) if variant.basic_type() == BasicType::Array => {
+                    let array = variant
+                        .get_array()
+                        .map_err(ArrowError::InvalidArgumentError)?;
+                    write_array_slice(&mut buffer, &array, start, end);
+                    true
+                }
+                _ => false,
+            };
+            offsets.push(buffer.len());
+            validity.push(is_valid);
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+    validity.resize(variant_array.len(), false);
+
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    Ok(variant_array.with_values(values, nulls))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{BinaryArray, StringArray, StructArray};
+    use open_variant::metadata::build_metadata;
+    use open_variant::values::write::{write_f64, write_string, ArrayBuilder};
+    use open_variant::values::PrimitiveTypeId;
+
+    use super::*;
+    use crate::json::variant_from_json;
+    use crate::layout::{MetadataEncoding, VariantLayout};
+
+    #[test]
+    fn test_variant_array_contains() {
+        let jsons = StringArray::from(vec![
+            Some("[1, 2, 3]"),
+            Some(r#"["a", 2.0]"#),
+            Some("[]"),
+            Some(r#"{"a": 2}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+
+        let mut needle = Vec::new();
+        write_f64(&mut needle, 2.0);
+        let result = variant_array_contains(&array, &VariantRef::try_new(&needle).unwrap());
+        assert_eq!(
+            result.unwrap().iter().collect::<Vec<_>>(),
+            vec![Some(true), Some(true), Some(false), None, None]
+        );
+
+        let mut needle = Vec::new();
+        write_string(&mut needle, "a");
+        let result = variant_array_contains(&array, &VariantRef::try_new(&needle).unwrap());
+        assert_eq!(
+            result.unwrap().iter().collect::<Vec<_>>(),
+            vec![Some(false), Some(true), Some(false), None, None]
+        );
+    }
+
+    #[test]
+    fn test_variant_array_contains_dictionary_string() {
+        // ["b", "apple pie"], with "apple pie" stored in the metadata.
+        let metadata = build_metadata(["apple pie"].into_iter());
+        let mut dictionary_string = vec![(PrimitiveTypeId::StringFromDictionary as u8) << 2];
+        dictionary_string.extend_from_slice(&0_u32.to_le_bytes());
+        let mut inline_string = Vec::new();
+        write_string(&mut inline_string, "b");
+        let mut value = Vec::new();
+        let mut builder = ArrayBuilder::new(&mut value, 2);
+        builder.append_value(&inline_string);
+        builder.append_value(&dictionary_string);
+        builder.finish();
+        let layout = VariantLayout::builder()
+            .metadata_encoding(MetadataEncoding::Plain)
+            .build()
+            .unwrap();
+        let array = StructArray::new(
+            layout.fields(),
+            vec![
+                Arc::new(BinaryArray::from_vec(vec![&metadata[..]])) as ArrayRef,
+                Arc::new(BinaryArray::from_vec(vec![&value[..]])),
+            ],
+            None,
+        );
+
+        let contains = |string: &str| {
+            let mut needle = Vec::new();
+            write_string(&mut needle, string);
+            let result = variant_array_contains(&array, &VariantRef::try_new(&needle).unwrap());
+            result.unwrap().value(0)
+        };
+        assert!(contains("apple pie"));
+        assert!(contains("b"));
+        assert!(!contains("apple"));
+    }
+
+    #[test]
+    fn test_variant_slice() {
+        let jsons = StringArray::from(vec![
+            Some(r#"[1, "a", {"b": 2}, 4]"#),
+            Some("[1]"),
+            Some(r#"{"a": 2}"#),
+            None,
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let sliced = variant_slice(&array, 1, 3).unwrap();
+        assert_eq!(sliced.data_type(), array.data_type());
+
+        let sliced = VariantArray::try_new(&sliced).unwrap();
+        let metadata = MetadataRef::new(sliced.metadata(0));
+        let first = sliced.variant(0).unwrap();
+        let first = first.get_array().unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first.get_element(0).unwrap().get_string(), "a");
+        let object = first.get_element(1).unwrap();
+        let b = object
+            .get_object()
+            .unwrap()
+            .find_field("b", &metadata)
+            .unwrap();
+        assert_eq!(b.get_i64(), 2);
+
+        assert!(sliced.variant(1).unwrap().get_array().unwrap().is_empty());
+        assert!(sliced.is_null(2));
+        assert!(sliced.is_null(3));
+    }
+}
@@ -0,0 +1,229 @@
+//! Masking values in variant arrays, for example to redact personal data.
+//!
+//! [`variant_mask`] replaces the values at paths matching a set of patterns,
+//! and keeps everything else, including the shape of objects and arrays.
+//! Patterns are [`VariantPath`]s, where `[*]` matches any array index, `*` in
+//! a key matches any run of characters, and `..` matches any depth, so
+//! `..email` masks every `email` field (see [`VariantPath::matches`]).
+
+use std::fmt;
+
+use arrow_array::{Array, ArrayRef};
+use arrow_schema::ArrowError;
+use open_variant::path::VariantPath;
+use open_variant::values::write::{write_null, write_string};
+use open_variant::values::VariantRef;
+
+use crate::transform::transform_values;
+
+/// What [`variant_mask`] replaces matching values with.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub enum MaskStrategy {
+    /// A variant null.
+    #[default]
+    Null,
+    /// A string of 16 hex digits hashing the value with SipHash-2-4 under a
+    /// secret key, so equal values can still be joined or counted.
+    ///
+    /// Without the key, the hashes can't be recomputed, so low-entropy
+    /// values such as phone numbers can't be recovered by brute force. Use
+    /// the same key wherever hashes need to match, and keep it out of the
+    /// data. Strings are hashed by their content. Other values are hashed by
+    /// their encoding, so objects using different metadata can hash
+    /// differently.
+    Hash {
+        /// The 128-bit SipHash key.
+        key: [u8; 16],
+    },
+    /// A fixed string, such as `"REDACTED"`.
+    Token(String),
+}
+
+impl fmt::Debug for MaskStrategy {
+    // Keep the key out of logs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "Null"),
+            Self::Hash { .. } => write!(f, "Hash {{ key: <redacted> }}"),
+            Self::Token(token) => f.debug_tuple("Token").field(token).finish(),
+        }
+    }
+}
+
+impl MaskStrategy {
+    fn mask(&self, value: &VariantRef) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        match self {
+            Self::Null => write_null(&mut buffer),
+            Self::Hash { key } => {
+                let bytes = value.get_str().map_or(value.as_bytes(), str::as_bytes);
+                write_string(&mut buffer, &format!("{:016x}", siphash24(key, bytes)));
+            }
+            Self::Token(token) => write_string(&mut buffer, token),
+        }
+        buffer
+    }
+}
+
+/// SipHash-2-4 of `bytes`, a keyed hash designed for short inputs.
+fn siphash24(key: &[u8; 16], bytes: &[u8]) -> u64 {
+    fn round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+    fn compress(v: &mut [u64; 4], m: u64) {
+        v[3] ^= m;
+        round(v);
+        round(v);
+        v[0] ^= m;
+    }
+
+    let k0 = u64::from_le_bytes(key[..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(key[8..].try_into().unwrap());
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        compress(&mut v, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    // The last block holds the remaining bytes and the length.
+    let mut last = [0; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    compress(
+        &mut v,
+        u64::from_le_bytes(last) | (bytes.len() as u64) << 56,
+    );
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+/// Replace the values at paths matching any of `patterns` using `strategy`.
+///
+/// A pattern matching an object or array replaces it as a whole. Values that
+/// don't match are copied unchanged, and the metadata is shared with the
+/// input. Null rows stay null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_mask(
+    array: &dyn Array,
+    patterns: &[VariantPath],
+    strategy: &MaskStrategy,
+) -> Result<ArrayRef, ArrowError> {
+    transform_values(array, patterns, |value| Some(strategy.mask(value)))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use open_variant::metadata::MetadataRef;
+
+    use super::*;
+    use crate::array::{VariantArray, VariantArrayReader};
+    use crate::json::variant_from_json;
+
+    fn mask(jsons: Vec<Option<&str>>, patterns: &[&str], strategy: MaskStrategy) -> ArrayRef {
+        let array = variant_from_json(&StringArray::from(jsons)).unwrap();
+        let patterns = patterns
+            .iter()
+            .map(|pattern| VariantPath::parse(pattern).unwrap())
+            .collect::<Vec<_>>();
+        variant_mask(&array, &patterns, &strategy).unwrap()
+    }
+
+    /// The type name and string value of the value at `path` in row `i`.
+    fn get(array: &ArrayRef, i: usize, path: &str) -> Option<(&'static str, String)> {
+        let variant_array = VariantArray::try_new(array).unwrap();
+        let metadata = MetadataRef::new(variant_array.metadata(i));
+        let path = VariantPath::parse(path).unwrap();
+        let value = variant_array.variant(i)?.get_path(&path, &metadata)?;
+        let string = value.get_str().unwrap_or_default().to_string();
+        Some((value.type_name(), string))
+    }
+
+    #[test]
+    fn test_variant_mask() {
+        let masked = mask(
+            vec![
+                Some(
+                    r#"{"name": "a", "users": [{"home_email": "x", "id": 1}, {"work_email": {"v": 2}}]}"#,
+                ),
+                None,
+                Some("1"),
+            ],
+            &["name", "users[*].*_email"],
+            MaskStrategy::Token("REDACTED".into()),
+        );
+        let redacted = Some(("string", "REDACTED".to_string()));
+        assert_eq!(get(&masked, 0, "name"), redacted);
+        assert_eq!(get(&masked, 0, "users[0].home_email"), redacted);
+        assert_eq!(get(&masked, 0, "users[1].work_email"), redacted);
+        assert_eq!(get(&masked, 0, "users[0].id").unwrap().0, "int64");
+        assert!(masked.is_null(1));
+        assert_eq!(get(&masked, 2, "").unwrap().0, "int64");
+
+        let masked = mask(
+            vec![Some(r#"{"a": [1, 2]}"#)],
+            &["a[1]"],
+            MaskStrategy::Null,
+        );
+        assert_eq!(get(&masked, 0, "a[0]").unwrap().0, "int64");
+        assert_eq!(get(&masked, 0, "a[1]").unwrap().0, "null");
+        let masked = mask(vec![Some(r#"{"a": 1}"#)], &[""], MaskStrategy::default());
+        assert_eq!(get(&masked, 0, "").unwrap().0, "null");
+    }
+
+    #[test]
+    fn test_mask_hash() {
+        let masked = mask(
+            vec![
+                Some(r#"{"a": "short"}"#),
+                Some(r#"{"a": "short", "b": 1}"#),
+                Some(r#"{"a": "other"}"#),
+            ],
+            &["a"],
+            MaskStrategy::Hash { key: [1; 16] },
+        );
+        let hash = |i| get(&masked, i, "a").unwrap().1;
+        assert_eq!(hash(0).len(), 16);
+        assert_eq!(hash(0), hash(1));
+        assert_ne!(hash(0), hash(2));
+        assert_eq!(hash(0), format!("{:016x}", siphash24(&[1; 16], b"short")));
+
+        // Another key gives other hashes.
+        let other_key = mask(
+            vec![Some(r#"{"a": "short"}"#)],
+            &["a"],
+            MaskStrategy::Hash { key: [2; 16] },
+        );
+        assert_ne!(get(&other_key, 0, "a").unwrap().1, hash(0));
+
+        let strategy = MaskStrategy::Hash { key: [1; 16] };
+        assert_eq!(format!("{:?}", strategy), "Hash { key: <redacted> }");
+    }
+
+    #[test]
+    fn test_siphash24() {
+        // The test vector from the SipHash paper.
+        let key = std::array::from_fn(|i| i as u8);
+        let message = (0..15).collect::<Vec<u8>>();
+        assert_eq!(siphash24(&key, &message), 0xa129_ca61_49be_45e5);
+    }
+}
@@ -0,0 +1,323 @@
+//! Representation of top-level nulls in variant arrays.
+//!
+//! A top-level null can be written in two ways: as an Arrow null (the row is
+//! invalid in the null buffer), or as a valid row holding a variant null value.
+//! Nested nulls are always variant nulls.
+//!
+//! Different writers pick different conventions, so readers should treat both
+//! the same, for example with
+//! [`VariantArrayReader::is_null_or_variant_null`]. Use [`normalize_nulls`] to
+//! convert an array to a single convention.
+//!
+//! An Arrow null can itself be recorded in the struct's null buffer, in the
+//! null buffer of its `values` child, or both. Arrays written by this crate
+//! always have the same null buffer for the struct and the `values` child.
+//! Arrays from other writers may not, so [`VariantArray`] treats a row as
+//! null if either is null. Use [`normalize_struct_nulls`] to make the two
+//! null buffers agree, for consumers that only check one of them.
+
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, BinaryArray, StructArray};
+use arrow_buffer::NullBuffer;
+use arrow_schema::{ArrowError, DataType};
+use open_variant::values::write::write_null;
+
+use crate::array::{values_array_from_parts, VariantArray, VariantArrayReader};
+use crate::layout::{MetadataEncoding, VariantLayout};
+
+/// How top-level nulls are represented in a variant array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullConvention {
+    /// Top-level nulls are Arrow nulls.
+    #[default]
+    ArrowNull,
+    /// Top-level nulls are valid rows holding a variant null value.
+    VariantNull,
+}
+
+/// Convert all top-level nulls in a variant array to the given convention.
+///
+/// Both Arrow nulls and variant null values are considered nulls, so after
+/// normalizing to [`NullConvention::VariantNull`] the array has no Arrow nulls.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn normalize_nulls(
+    array: &dyn Array,
+    convention: NullConvention,
+) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    let mut validity = Vec::with_capacity(variant_array.len());
+    offsets.push(0);
+    let append_nulls = |buffer: &mut Vec<u8>,
+                        offsets: &mut Vec<usize>,
+                        validity: &mut Vec<bool>,
+                        n: usize| match convention {
+        NullConvention::ArrowNull => {
+            offsets.resize(offsets.len() + n, buffer.len());
+            validity.resize(validity.len() + n, false);
+        }
+        NullConvention::VariantNull => {
+            for _ in 0..n {
+                write_null(buffer);
+                offsets.push(buffer.len());
+            }
+            validity.resize(validity.len() + n, true);
+        }
+    };
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        append_nulls(&mut buffer, &mut offsets, &mut validity, start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            if variant_array.is_null_or_variant_null(i) {
+                append_nulls(&mut buffer, &mut offsets, &mut validity, 1);
+            } else {
+                buffer.extend_from_slice(variant_array.value(i));
+                offsets.push(buffer.len());
+                validity.push(true);
+            }
+        }
+    }
+    let remaining = variant_array.len() - valid_end;
+    append_nulls(&mut buffer, &mut offsets, &mut validity, remaining);
+
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+
+    Ok(variant_array.with_values(values, nulls))
+}
+
+/// Make the null buffers of the struct and its `values` child the same, the
+/// union of the two.
+///
+/// Arrays whose null buffers already agree are returned as is. Otherwise the
+/// values are copied, and null rows have empty values.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn normalize_struct_nulls(array: &dyn Array) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    if variant_array.has_aligned_nulls() {
+        return Ok(array.slice(0, array.len()));
+    }
+
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    offsets.push(0);
+    for (start, end) in variant_array.valid_slices() {
+        offsets.resize(start + 1, buffer.len());
+        for i in start..end {
+            buffer.extend_from_slice(variant_array.value(i));
+            offsets.push(buffer.len());
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+    let nulls = variant_array.nulls().cloned();
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    Ok(variant_array.with_values(values, nulls))
+}
+
+/// Pick the first non-null value of each row across several variant arrays,
+/// like SQL `COALESCE`.
+///
+/// Arrow nulls and variant nulls are both skipped. Rows that are null in
+/// every array are Arrow nulls. Values are copied without being decoded, and
+/// keep the metadata of the array they came from, so the arrays' metadata is
+/// never merged. The output has the default [`VariantLayout`], with large
+/// offsets if the values need them.
+///
+/// # Errors
+///
+/// If there are no arrays, if they have different lengths, or if one is not
+/// a variant array.
+pub fn coalesce_variant(arrays: &[ArrayRef]) -> Result<ArrayRef, ArrowError> {
+    let variant_arrays = arrays
+        .iter()
+        .map(|array| VariantArray::try_new(array.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = variant_arrays.first() else {
+        return Err(ArrowError::InvalidArgumentError(
+            "coalesce_variant needs at least one array".into(),
+        ));
+    };
+    if let Some(other) = variant_arrays
+        .iter()
+        .find(|array| array.len() != first.len())
+    {
+        return Err(ArrowError::InvalidArgumentError(format!(
+            "Arrays to coalesce must have the same length, got {} and {}",
+            first.len(),
+            other.len()
+        )));
+    }
+
+    let mut metadata = Vec::with_capacity(first.len());
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(first.len() + 1);
+    let mut validity = Vec::with_capacity(first.len());
+    offsets.push(0);
+    for i in 0..first.len() {
+        let source = variant_arrays
+            .iter()
+            .find(|array| !array.is_null_or_variant_null(i));
+        if let Some(source) = source {
+            buffer.extend_from_slice(source.value(i));
+        }
+        metadata.push(source.unwrap_or(first).metadata(i));
+        offsets.push(buffer.len());
+        validity.push(source.is_some());
+    }
+
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    let large_offsets = values.data_type() == &DataType::LargeBinary;
+    // Converting from plain metadata deduplicates the buffers, so rows from
+    // the same array share their metadata again.
+    let plain = VariantLayout::builder()
+        .metadata_encoding(MetadataEncoding::Plain)
+        .large_offsets(large_offsets)
+        .build()?;
+    let array = StructArray::try_new(
+        plain.fields(),
+        vec![
+            Arc::new(BinaryArray::from_iter_values(metadata)) as ArrayRef,
+            values,
+        ],
+        nulls,
+    )?;
+    let layout = VariantLayout::builder()
+        .large_offsets(large_offsets)
+        .build()?;
+    VariantArray::try_new(&array)?.to_layout(&layout)
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::{variant_from_json_with_options, JsonParseOptions};
+
+    #[test]
+    fn test_normalize_nulls() {
+        let jsons = StringArray::from(vec![Some("null"), None, Some("1"), Some("[null]")]);
+        let arrow_nulls = variant_from_json_with_options(
+            &jsons,
+            &JsonParseOptions {
+                top_level_null: NullConvention::ArrowNull,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let variant_nulls = variant_from_json_with_options(
+            &jsons,
+            &JsonParseOptions {
+                top_level_null: NullConvention::VariantNull,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(arrow_nulls.null_count(), 2);
+        // The input null is not a JSON null, so stays an Arrow null.
+        assert_eq!(variant_nulls.null_count(), 1);
+
+        // Both conventions read the same way.
+        for array in [&arrow_nulls, &variant_nulls] {
+            let variant_array = VariantArray::try_new(array).unwrap();
+            let nulls = (0..4)
+                .map(|i| variant_array.is_null_or_variant_null(i))
+                .collect::<Vec<_>>();
+            assert_eq!(nulls, vec![true, true, false, false]);
+        }
+
+        let normalized = normalize_nulls(&variant_nulls, NullConvention::ArrowNull).unwrap();
+        assert_eq!(normalized.null_count(), 2);
+        assert_eq!(normalized.data_type(), arrow_nulls.data_type());
+
+        let normalized = normalize_nulls(&arrow_nulls, NullConvention::VariantNull).unwrap();
+        assert_eq!(normalized.null_count(), 0);
+        let variant_array = VariantArray::try_new(&normalized).unwrap();
+        assert!(variant_array.variant(0).unwrap().is_null());
+        assert!(variant_array.variant(1).unwrap().is_null());
+        assert_eq!(
+            variant_array.value(2),
+            VariantArray::try_new(&arrow_nulls).unwrap().value(2)
+        );
+    }
+
+    #[test]
+    fn test_normalize_struct_nulls() {
+        let jsons = StringArray::from(vec![Some("1"), None, Some("2"), Some("3")]);
+        let array = variant_from_json_with_options(&jsons, &JsonParseOptions::default()).unwrap();
+        assert_eq!(&normalize_struct_nulls(&array).unwrap(), &array);
+
+        // Row 1 is null in the struct, row 2 only in the values child.
+        let struct_array = array.as_struct();
+        let values = struct_array.column(1).as_binary::<i32>();
+        let values = BinaryArray::new(
+            values.offsets().clone(),
+            values.values().clone(),
+            Some(NullBuffer::from(vec![true, true, false, true])),
+        );
+        let (fields, mut columns, nulls) = struct_array.clone().into_parts();
+        columns[1] = Arc::new(values);
+        let misaligned = StructArray::new(fields, columns, nulls);
+
+        let variant_array = VariantArray::try_new(&misaligned).unwrap();
+        let valid = (0..4)
+            .map(|i| !variant_array.is_null(i))
+            .collect::<Vec<_>>();
+        assert_eq!(valid, vec![true, false, false, true]);
+        assert_eq!(variant_array.value(2), &[] as &[u8]);
+        assert!(variant_array.variant(2).is_none());
+
+        let normalized = normalize_struct_nulls(&misaligned).unwrap();
+        let normalized = normalized.as_struct();
+        assert_eq!(normalized.nulls(), normalized.column(1).nulls());
+        assert_eq!(normalized.null_count(), 2);
+        let variant_array = VariantArray::try_new(normalized).unwrap();
+        assert_eq!(variant_array.variant(3).unwrap().get_i64(), 3);
+    }
+
+    #[test]
+    fn test_coalesce_variant() {
+        let parse = |jsons: Vec<Option<&str>>| {
+            variant_from_json_with_options(&StringArray::from(jsons), &JsonParseOptions::default())
+                .unwrap()
+        };
+        let a = parse(vec![Some(r#"{"a": 1}"#), None, Some("null"), None]);
+        let b = parse(vec![Some(r#"{"b": 2}"#), Some(r#"{"b": 3}"#), None, None]);
+        let c = parse(vec![None, Some("4"), Some(r#"{"c": [5]}"#), None]);
+
+        let output = coalesce_variant(&[a, b, c.clone()]).unwrap();
+        assert_eq!(output.data_type(), &crate::variant_type());
+        let variant_array = VariantArray::try_new(&output).unwrap();
+        let json = |i: usize| {
+            let metadata = open_variant::metadata::MetadataRef::new(variant_array.metadata(i));
+            open_variant::json::to_json(
+                &variant_array.variant(i).unwrap(),
+                &metadata,
+                &Default::default(),
+            )
+            .unwrap()
+        };
+        assert_eq!(json(0), r#"{"a":1}"#);
+        assert_eq!(json(1), r#"{"b":3}"#);
+        assert_eq!(json(2), r#"{"c":[5]}"#);
+        assert!(variant_array.is_null(3));
+        assert_eq!(output.null_count(), 1);
+
+        assert!(coalesce_variant(&[]).is_err());
+        assert!(coalesce_variant(&[c.slice(0, 2), c]).is_err());
+    }
+}
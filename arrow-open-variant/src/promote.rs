@@ -0,0 +1,512 @@
+//! Move top-level keys between variant values and typed columns.
+//!
+//! As a schema settles, frequently used keys can be promoted to real columns
+//! while the rest of the value stays in a variant column.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use arrow_array::types::Int8Type;
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, DictionaryArray, Int8Array, RecordBatch, StructArray,
+};
+use arrow_buffer::{BooleanBuffer, NullBuffer};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use open_variant::metadata::{build_metadata, MetadataRef};
+use open_variant::path::{PathElement, VariantPath};
+use open_variant::values::write::{remap_field_ids, ObjectBuilder};
+use open_variant::values::BasicType;
+
+use crate::array::{values_array_from_parts, MetadataCache, VariantArray, VariantArrayReader};
+use crate::cast::{cast_to_variant_with_options, is_uuid_field, CastOptions};
+use crate::extract::flatten_variant;
+use crate::layout::VariantLayout;
+
+/// Split top-level keys of a variant array into typed columns.
+///
+/// Each field of `schema` names a top-level key and the type of its column,
+/// which supports the same types as [`flatten_variant`]. The output has a
+/// column per field, followed by a variant column named `residual` holding
+/// each row with the promoted keys removed. The array is traversed once for
+/// the typed columns and once for the residual.
+///
+/// A key is only removed from a row when its value was written to the typed
+/// column, so values that don't coerce to the column's type stay in the
+/// residual, and no data is lost. Rows that are not objects are kept in the
+/// residual unchanged. The residual keeps the metadata of each row.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a type is not supported.
+pub fn promote_fields(
+    array: &dyn Array,
+    schema: &Schema,
+    residual: &str,
+) -> Result<RecordBatch, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let path = VariantPath::new(vec![PathElement::Field(field.name().clone())]);
+            (path, field.data_type().clone(), field.name().as_str())
+        })
+        .collect::<Vec<_>>();
+    let promoted = flatten_variant(array, &columns)?;
+
+    let mut buffer = Vec::new();
+    let mut offsets = vec![0];
+    let mut field_ids = MetadataCache::new();
+    let mut removed = Vec::with_capacity(columns.len());
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows have empty values.
+        offsets.resize(start + 1, buffer.len());
+        for i in start..end {
+            if let Some(variant) = variant_array.variant(i) {
+                let metadata_bytes = variant_array.metadata(i);
+                let metadata = MetadataRef::new(metadata_bytes);
+                removed.clear();
+                if variant.basic_type() == BasicType::Object {
+                    let ids = field_ids.get_or_insert_with(metadata_bytes, || {
+                        schema
+                            .fields()
+                            .iter()
+                            .map(|field| metadata.find_string(field.name()))
+                            .collect::<Vec<_>>()
+                    });
+                    removed.extend(
+                        ids.iter()
+                            .zip(promoted.columns())
+                            .filter(|(_, column)| column.is_valid(i))
+                            .filter_map(|(id, _)| *id),
+                    );
+                }
+
+                if removed.is_empty() {
+                    buffer.extend_from_slice(variant.as_bytes());
+                } else {
+                    let object = variant
+                        .get_object()
+                        .map_err(ArrowError::InvalidArgumentError)?;
+                    let kept = object
+                        .fields()
+                        .filter(|(id, _)| !removed.contains(id))
+                        .collect::<Vec<_>>();
+                    let mut object_builder =
+                        ObjectBuilder::with_capacity(&mut buffer, &metadata, kept.len());
+                    for (id, value) in kept {
+                        object_builder.append_value_with_field_id(id, value.as_bytes());
+                    }
+                    object_builder.finish();
+                }
+            }
+            offsets.push(buffer.len());
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+
+    let nulls = variant_array.nulls().cloned();
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    let residual_array = variant_array.with_values(values, nulls);
+
+    let mut fields = promoted.schema().fields().to_vec();
+    fields.push(Arc::new(Field::new(
+        residual,
+        residual_array.data_type().clone(),
+        true,
+    )));
+    let mut arrays = promoted.columns().to_vec();
+    arrays.push(residual_array);
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+}
+
+/// What [`demote_fields`] does when a column's value can't be added to a row
+/// because the row already has the key, or is not an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldConflict {
+    /// Fail the conversion.
+    #[default]
+    Error,
+    /// Keep the variant value and drop the column's value.
+    KeepVariant,
+    /// Keep the column's value. A row that is not an object is replaced by
+    /// an object of the columns.
+    KeepColumn,
+}
+
+/// A metadata dictionary merged by [`demote_fields`], with the mapping of a
+/// row's field ids and of each column's field ids into it.
+type MergedMetadata = (Vec<u8>, Vec<usize>, Vec<Vec<usize>>);
+
+/// Fold typed columns back into the top-level objects of a variant array.
+///
+/// This is the inverse of [`promote_fields`]: each column of `columns` is
+/// converted as by [`cast_to_variant_with_options`] and added to the object
+/// of each row under the column's name. `FixedSizeBinary(16)` columns are
+/// UUIDs if their field has the `arrow.uuid` extension type, as in the output
+/// of [`promote_fields`]. Null column values are not added, so rows where
+/// every column is null are unchanged. Null rows with a column value become
+/// objects of the columns. Conflicts are handled with `on_conflict`.
+///
+/// Rows that gain fields get new metadata holding the keys of the row and of
+/// the columns. Rows sharing metadata share the new metadata too.
+///
+/// # Errors
+///
+/// If the array is not a variant array, if `columns` has a different number
+/// of rows, if a column has a type that can't be cast to a variant, or on a
+/// conflict with [`FieldConflict::Error`].
+pub fn demote_fields(
+    array: &dyn Array,
+    columns: &RecordBatch,
+    on_conflict: FieldConflict,
+) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    if columns.num_rows() != variant_array.len() {
+        return Err(ArrowError::InvalidArgumentError(format!(
+            "Columns to demote must have the same length as the variant array, got {} and {}",
+            columns.num_rows(),
+            variant_array.len()
+        )));
+    }
+    let schema = columns.schema();
+    let names = schema
+        .fields()
+        .iter()
+        .map(|field| field.name().as_str())
+        .collect::<Vec<_>>();
+    let casts = schema
+        .fields()
+        .iter()
+        .zip(columns.columns())
+        .map(|(field, column)| {
+            let options = CastOptions {
+                fixed_size_binary_as_uuid: is_uuid_field(field),
+                ..Default::default()
+            };
+            cast_to_variant_with_options(column.as_ref(), &options)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let column_arrays = casts
+        .iter()
+        .map(|cast| VariantArray::try_new(cast.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // A cast writes a single dictionary for the whole array.
+    let column_metadata = column_arrays
+        .iter()
+        .map(|column| (!column.is_empty()).then(|| MetadataRef::new(column.metadata(0))))
+        .collect::<Vec<_>>();
+
+    // The merged dictionaries.
+    let mut merged: Vec<MergedMetadata> = Vec::new();
+    let mut merged_index = MetadataCache::new();
+    // The index of the merged dictionary of each row, or `None` to keep the
+    // row's metadata.
+    let mut row_metadata = Vec::with_capacity(variant_array.len());
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    let mut validity = Vec::with_capacity(variant_array.len());
+    offsets.push(0);
+    // Null rows only change where a column has a value, so visit the rows
+    // that are valid in the array or in any column.
+    let visited = std::iter::once(variant_array.nulls())
+        .chain(column_arrays.iter().map(|column| column.nulls()))
+        .try_fold(
+            BooleanBuffer::new_unset(variant_array.len()),
+            |visited, nulls| nulls.map(|nulls| &visited | nulls.inner()),
+        )
+        .map(NullBuffer::new);
+    let visited_slices: Box<dyn Iterator<Item = (usize, usize)>> = match &visited {
+        Some(visited) => Box::new(visited.valid_slices()),
+        None => Box::new(std::iter::once((0, variant_array.len()))),
+    };
+    for (start, end) in visited_slices {
+        // The rows in between are null and stay null, with empty values.
+        offsets.resize(start + 1, buffer.len());
+        validity.resize(start, false);
+        row_metadata.resize(start, None);
+        for i in start..end {
+            let present = column_arrays
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| !column.is_null_or_variant_null(i))
+                .map(|(c, _)| c)
+                .collect::<Vec<_>>();
+            let variant = variant_array
+                .variant(i)
+                .filter(|_| !variant_array.is_null_or_variant_null(i));
+            let is_object = variant
+                .as_ref()
+                .is_some_and(|variant| variant.basic_type() == BasicType::Object);
+            let replace =
+                variant.is_none() || (!is_object && on_conflict == FieldConflict::KeepColumn);
+            if present.is_empty()
+                || (!is_object && !replace && on_conflict == FieldConflict::KeepVariant)
+            {
+                buffer.extend_from_slice(variant_array.value(i));
+                offsets.push(buffer.len());
+                validity.push(!variant_array.is_null(i));
+                row_metadata.push(None);
+                continue;
+            }
+            if !is_object && !replace {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Can't add columns to row {}, which is not an object",
+                    i
+                )));
+            }
+
+            // Rows usually share metadata, so only merge the dictionaries when the
+            // metadata buffer changes. Rows being replaced don't need their keys.
+            let metadata_bytes = if replace {
+                &[][..]
+            } else {
+                variant_array.metadata(i)
+            };
+            let index = *merged_index.get_or_insert_with(metadata_bytes, || {
+                let row = (!replace).then(|| MetadataRef::new(metadata_bytes));
+                let dictionaries = row.iter().chain(column_metadata.iter().flatten());
+                let strings = dictionaries
+                    .flat_map(|metadata| {
+                        (0..metadata.dictionary_len()).map(|id| metadata.get_string(id).unwrap())
+                    })
+                    .chain(names.iter().copied());
+                let metadata = build_metadata(strings);
+                let merged_ref = MetadataRef::new(&metadata);
+                let mapping = |metadata: &MetadataRef| {
+                    (0..metadata.dictionary_len())
+                        .map(|id| {
+                            merged_ref
+                                .find_string(metadata.get_string(id).unwrap())
+                                .unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                };
+                let row_mapping = row.as_ref().map(mapping).unwrap_or_default();
+                let column_mappings = column_metadata
+                    .iter()
+                    .map(|metadata| metadata.as_ref().map(mapping).unwrap_or_default())
+                    .collect();
+                merged.push((metadata, row_mapping, column_mappings));
+                merged.len() - 1
+            });
+            let (metadata, row_mapping, column_mappings) = &merged[index];
+            let metadata = MetadataRef::new(metadata);
+
+            let mut fields: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+            if !replace {
+                let row = MetadataRef::new(metadata_bytes);
+                let object = variant
+                    .as_ref()
+                    .unwrap()
+                    .get_object()
+                    .map_err(ArrowError::InvalidArgumentError)?;
+                for (id, value) in object.fields() {
+                    let mut field = Vec::new();
+                    remap_field_ids(&mut field, &value, row_mapping, &metadata)
+                        .map_err(ArrowError::InvalidArgumentError)?;
+                    let name = row.get_string(id).ok_or_else(|| {
+                        ArrowError::InvalidArgumentError(format!(
+                            "Field id {} is not in the metadata",
+                            id
+                        ))
+                    })?;
+                    fields.insert(name, field);
+                }
+            }
+            for c in present {
+                if fields.contains_key(names[c]) {
+                    match on_conflict {
+                        FieldConflict::Error => {
+                            return Err(ArrowError::InvalidArgumentError(format!(
+                                "Field '{}' is in both the variant and a column at row {}",
+                                names[c], i
+                            )))
+                        }
+                        FieldConflict::KeepVariant => continue,
+                        FieldConflict::KeepColumn => {}
+                    }
+                }
+                let mut field = Vec::new();
+                let value = column_arrays[c].variant(i).unwrap();
+                remap_field_ids(&mut field, &value, &column_mappings[c], &metadata)
+                    .map_err(ArrowError::InvalidArgumentError)?;
+                fields.insert(names[c], field);
+            }
+
+            let mut object_builder =
+                ObjectBuilder::with_capacity(&mut buffer, &metadata, fields.len());
+            for (name, value) in &fields {
+                object_builder
+                    .append_value(name, value)
+                    .map_err(ArrowError::InvalidArgumentError)?;
+            }
+            object_builder.finish();
+            offsets.push(buffer.len());
+            validity.push(true);
+            row_metadata.push(Some(index));
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+    validity.resize(variant_array.len(), false);
+    row_metadata.resize(variant_array.len(), None);
+
+    // Store each distinct metadata buffer once, so rows that share metadata
+    // share it again. The buffer is only looked up when it changes.
+    let mut distinct: HashMap<&[u8], usize> = HashMap::new();
+    let mut buffers = Vec::new();
+    let mut row_keys = MetadataCache::new();
+    let keys = row_metadata
+        .iter()
+        .enumerate()
+        .map(|(i, index)| {
+            let metadata = match index {
+                Some(index) => merged[*index].0.as_slice(),
+                None => variant_array.metadata(i),
+            };
+            let key = *row_keys.get_or_insert_with(metadata, || {
+                *distinct.entry(metadata).or_insert_with(|| {
+                    buffers.push(metadata);
+                    buffers.len() - 1
+                })
+            });
+            i8::try_from(key).map_err(|_| ArrowError::DictionaryKeyOverflowError)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let metadata = DictionaryArray::<Int8Type>::try_new(
+        Int8Array::from(keys),
+        Arc::new(BinaryArray::from_iter_values(buffers)),
+    )?;
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    let layout = VariantLayout::builder()
+        .large_offsets(values.data_type() == &DataType::LargeBinary)
+        .build()?;
+    let array = StructArray::try_new(
+        layout.fields(),
+        vec![Arc::new(metadata) as ArrayRef, values],
+        nulls,
+    )?;
+    Ok(Arc::new(array))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::collections::HashSet;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::{variant_from_json, variant_to_json};
+
+    #[test]
+    fn test_promote_fields() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"id": 1, "name": "a", "x": true}"#),
+            Some(r#"{"id": "2", "name": "b"}"#),
+            None,
+            Some(r#""scalar""#),
+            Some(r#"{"other": {"id": 3}}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+        let batch = promote_fields(&array, &schema, "rest").unwrap();
+        assert_eq!(batch.num_columns(), 3);
+        assert_eq!(batch.schema().field(2).name(), "rest");
+
+        let ids = batch.column(0).as_primitive::<Int64Type>();
+        assert_eq!(
+            ids.iter().collect::<Vec<_>>(),
+            vec![Some(1), None, None, None, None]
+        );
+        let names = batch.column(1).as_string::<i32>();
+        assert_eq!(
+            names.iter().collect::<Vec<_>>(),
+            vec![Some("a"), Some("b"), None, None, None]
+        );
+
+        // The string id doesn't coerce to Int64, so it stays in the residual.
+        let rest = variant_to_json(batch.column(2)).unwrap();
+        assert_eq!(
+            rest.iter().collect::<Vec<_>>(),
+            vec![
+                Some(r#"{"x":true}"#),
+                Some(r#"{"id":"2"}"#),
+                None,
+                Some(r#""scalar""#),
+                Some(r#"{"other":{"id":3}}"#),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_demote_fields() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"id": 1, "name": "a", "x": true}"#),
+            Some(r#"{"id": "2", "name": "b"}"#),
+            None,
+            Some(r#""scalar""#),
+            Some(r#"{"other": {"id": 3}}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+        let batch = promote_fields(&array, &schema, "rest").unwrap();
+        let columns = batch.project(&[0, 1]).unwrap();
+
+        // Demoting the promoted columns gives back the original values.
+        let output = demote_fields(batch.column(2), &columns, FieldConflict::Error).unwrap();
+        assert_eq!(
+            variant_to_json(&output).unwrap(),
+            variant_to_json(&array).unwrap()
+        );
+        // Each metadata buffer is stored once.
+        assert_eq!(output.data_type(), &crate::variant_type());
+        let metadata = output.as_struct().column(0).as_any_dictionary().values();
+        let metadata = metadata.as_binary::<i32>();
+        assert_eq!(
+            metadata.iter().collect::<HashSet<_>>().len(),
+            metadata.len()
+        );
+
+        // A scalar row with a column value is a conflict.
+        let names = StringArray::from(vec![None, None, Some("c"), Some("d"), None]);
+        let columns = RecordBatch::try_from_iter([("name", Arc::new(names) as ArrayRef)]).unwrap();
+        let rest = batch.column(2);
+        assert!(demote_fields(rest, &columns, FieldConflict::Error).is_err());
+
+        let json = |on_conflict| {
+            let output = demote_fields(&array, &columns, on_conflict).unwrap();
+            variant_to_json(&output).unwrap()
+        };
+        let output = json(FieldConflict::KeepVariant);
+        assert_eq!(
+            output.iter().collect::<Vec<_>>(),
+            vec![
+                Some(r#"{"id":1,"name":"a","x":true}"#),
+                Some(r#"{"id":"2","name":"b"}"#),
+                Some(r#"{"name":"c"}"#),
+                Some(r#""scalar""#),
+                Some(r#"{"other":{"id":3}}"#),
+            ]
+        );
+        let output = json(FieldConflict::KeepColumn);
+        assert_eq!(output.value(3), r#"{"name":"d"}"#);
+
+        // An existing key is a conflict too.
+        let ids = arrow_array::Int64Array::from(vec![Some(5), None, None, None, None]);
+        let columns = RecordBatch::try_from_iter([("id", Arc::new(ids) as ArrayRef)]).unwrap();
+        assert!(demote_fields(&array, &columns, FieldConflict::Error).is_err());
+        let output = demote_fields(&array, &columns, FieldConflict::KeepColumn).unwrap();
+        let output = variant_to_json(&output).unwrap();
+        assert_eq!(output.value(0), r#"{"id":5,"name":"a","x":true}"#);
+    }
+}
@@ -0,0 +1,210 @@
+//! Describe the structure of variant values, to group rows by the shape of
+//! their payload or measure how deeply nested they are.
+
+use std::sync::Arc;
+
+use arrow_array::builder::{StringBuilder, UInt64Builder};
+use arrow_array::{Array, ArrayRef, StringArray, StructArray, UInt64Array};
+use arrow_schema::{ArrowError, DataType, Field};
+use open_variant::metadata::MetadataRef;
+
+use crate::array::{VariantArray, VariantArrayReader};
+
+/// The fingerprint of the shape of each value of a variant array.
+///
+/// See [`VariantRef::fingerprint`](open_variant::values::VariantRef::fingerprint) for what
+/// the shape includes. Null rows are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_fingerprint(array: &dyn Array) -> Result<UInt64Array, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = UInt64Builder::with_capacity(variant_array.len());
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        builder.append_nulls(start - valid_end);
+        valid_end = end;
+        for i in start..end {
+            let fingerprint = variant_array
+                .variant(i)
+                .map(|variant| {
+                    let metadata = MetadataRef::new(variant_array.metadata(i));
+                    variant
+                        .fingerprint(&metadata)
+                        .map_err(ArrowError::InvalidArgumentError)
+                })
+                .transpose()?;
+            builder.append_option(fingerprint);
+        }
+    }
+    builder.append_nulls(variant_array.len() - valid_end);
+    Ok(builder.finish())
+}
+
+/// The shape of each value of a variant array, as a canonical string.
+///
+/// Grouping by the shape shows how heterogeneous the rows are. See
+/// [`VariantRef::shape`](open_variant::values::VariantRef::shape) for the
+/// format. Values with the same shape have the same
+/// [`variant_fingerprint`], which is cheaper to group by. Null rows are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_shape(array: &dyn Array) -> Result<StringArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = StringBuilder::with_capacity(variant_array.len(), 0);
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        (0..start - valid_end).for_each(|_| builder.append_null());
+        valid_end = end;
+        for i in start..end {
+            let shape = variant_array
+                .variant(i)
+                .map(|variant| {
+                    let metadata = MetadataRef::new(variant_array.metadata(i));
+                    variant
+                        .shape(&metadata)
+                        .map_err(ArrowError::InvalidArgumentError)
+                })
+                .transpose()?;
+            builder.append_option(shape);
+        }
+    }
+    (0..variant_array.len() - valid_end).for_each(|_| builder.append_null());
+    Ok(builder.finish())
+}
+
+/// The nesting depth, leaf count and longest array length of each value of
+/// a variant array, as a struct with `depth`, `leaf_count` and
+/// `max_array_len` fields.
+///
+/// See [`StructureMetrics`](open_variant::shape::StructureMetrics) for how
+/// they are defined. All three come from one traversal of each value. Null
+/// rows are null.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_metrics(array: &dyn Array) -> Result<StructArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut depth = UInt64Builder::with_capacity(variant_array.len());
+    let mut leaf_count = UInt64Builder::with_capacity(variant_array.len());
+    let mut max_array_len = UInt64Builder::with_capacity(variant_array.len());
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        for builder in [&mut depth, &mut leaf_count, &mut max_array_len] {
+            builder.append_nulls(start - valid_end);
+        }
+        valid_end = end;
+        for i in start..end {
+            let metrics = variant_array
+                .variant(i)
+                .map(|variant| variant.metrics().map_err(ArrowError::InvalidArgumentError))
+                .transpose()?;
+            depth.append_option(metrics.map(|metrics| metrics.depth as u64));
+            leaf_count.append_option(metrics.map(|metrics| metrics.leaf_count as u64));
+            max_array_len.append_option(metrics.map(|metrics| metrics.max_array_len as u64));
+        }
+    }
+    for builder in [&mut depth, &mut leaf_count, &mut max_array_len] {
+        builder.append_nulls(variant_array.len() - valid_end);
+    }
+    let fields = vec![
+        Field::new("depth", DataType::UInt64, true),
+        Field::new("leaf_count", DataType::UInt64, true),
+        Field::new("max_array_len", DataType::UInt64, true),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(depth.finish()),
+        Arc::new(leaf_count.finish()),
+        Arc::new(max_array_len.finish()),
+    ];
+    StructArray::try_new(fields.into(), columns, variant_array.nulls().cloned())
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::variant_from_json;
+
+    #[test]
+    fn test_variant_fingerprint() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": 1, "b": ["x"]}"#),
+            Some(r#"{"b": ["y", "z"], "a": 2}"#),
+            Some(r#"{"a": "1", "b": ["x"]}"#),
+            None,
+            Some(r#"{"a": 1, "b": ["x"], "c": null}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let fingerprints = variant_fingerprint(&array).unwrap();
+        assert_eq!(fingerprints.value(0), fingerprints.value(1));
+        assert_ne!(fingerprints.value(0), fingerprints.value(2));
+        assert!(fingerprints.is_null(3));
+        assert_ne!(fingerprints.value(0), fingerprints.value(4));
+
+        // Fingerprints don't depend on the metadata of the batch.
+        let other = variant_from_json(&StringArray::from(vec![
+            r#"{"a": 5, "b": []}"#,
+            r#"{"b": ["q"], "a": 0}"#,
+        ]))
+        .unwrap();
+        let other = variant_fingerprint(&other).unwrap();
+        assert_ne!(other.value(0), fingerprints.value(0));
+        assert_eq!(other.value(1), fingerprints.value(0));
+    }
+
+    #[test]
+    fn test_variant_shape() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"b": [1, "x", 2], "a": {"c": null}}"#),
+            None,
+            Some("[]"),
+            Some("1.5"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let shapes = variant_shape(&array).unwrap();
+        assert_eq!(
+            shapes.iter().collect::<Vec<_>>(),
+            vec![
+                Some(r#"{"a":{"c":n},"b":[i|s]}"#),
+                None,
+                Some("[]"),
+                Some("f")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variant_metrics() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": [1, [2, 3], {"b": null}], "c": "x"}"#),
+            None,
+            Some("[]"),
+            Some("1.5"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let metrics = variant_metrics(&array).unwrap();
+        let column = |name: &str| {
+            metrics
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap()
+                .clone()
+        };
+        assert!(metrics.is_null(1));
+        assert_eq!(column("depth").values()[0], 3);
+        assert_eq!(column("depth").values()[2], 1);
+        assert_eq!(column("depth").values()[3], 0);
+        assert_eq!(column("leaf_count").values()[0], 5);
+        assert_eq!(column("leaf_count").values()[2], 0);
+        assert_eq!(column("max_array_len").values()[0], 3);
+        assert_eq!(column("max_array_len").values()[3], 0);
+    }
+}
@@ -0,0 +1,206 @@
+//! Transform the values at a path in place.
+//!
+//! The functions here rewrite the values at paths matching a pattern, and
+//! copy everything else unchanged, so cleanup pipelines don't need to extract
+//! values, transform them and insert them back. Patterns are
+//! [`VariantPath`]s as in [`variant_mask`](crate::mask::variant_mask), so
+//! `tags[*]` transforms every element of `tags`. Values of another type than
+//! the transform applies to, such as strings for [`variant_round`], are
+//! kept.
+
+use std::borrow::Cow;
+use std::slice;
+
+use arrow_array::{Array, ArrayRef};
+use arrow_schema::ArrowError;
+use open_variant::metadata::MetadataRef;
+use open_variant::path::VariantPath;
+use open_variant::values::write::{replace_values, write_string};
+use open_variant::values::VariantRef;
+
+use crate::array::{values_array_from_parts, VariantArray, VariantArrayReader};
+
+/// Convert the strings at `path` to lower case.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_lower(array: &dyn Array, path: &VariantPath) -> Result<ArrayRef, ArrowError> {
+    transform_strings(array, path, |value| Cow::Owned(value.to_lowercase()))
+}
+
+/// Convert the strings at `path` to upper case.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_upper(array: &dyn Array, path: &VariantPath) -> Result<ArrayRef, ArrowError> {
+    transform_strings(array, path, |value| Cow::Owned(value.to_uppercase()))
+}
+
+/// Remove leading and trailing whitespace from the strings at `path`.
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_trim(array: &dyn Array, path: &VariantPath) -> Result<ArrayRef, ArrowError> {
+    transform_strings(array, path, |value| Cow::Borrowed(value.trim()))
+}
+
+/// Replace the numbers at `path` with their absolute value.
+///
+/// Numbers keep their kind, see [`open_variant::numeric`].
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_abs(array: &dyn Array, path: &VariantPath) -> Result<ArrayRef, ArrowError> {
+    transform_values(array, slice::from_ref(path), |value| value.abs())
+}
+
+/// Round the numbers at `path` to `digits` decimal places, with halves
+/// rounded away from zero. A negative `digits` rounds to tens, hundreds and
+/// so on.
+///
+/// Numbers keep their kind, see [`VariantRef::round`].
+///
+/// # Errors
+///
+/// If the array is not a variant array, or if a value is invalid.
+pub fn variant_round(
+    array: &dyn Array,
+    path: &VariantPath,
+    digits: i32,
+) -> Result<ArrayRef, ArrowError> {
+    transform_values(array, slice::from_ref(path), |value| value.round(digits))
+}
+
+/// Apply `transform` to the strings at `path`. Strings it doesn't change are
+/// copied without being re-encoded.
+fn transform_strings(
+    array: &dyn Array,
+    path: &VariantPath,
+    transform: impl Fn(&str) -> Cow<str>,
+) -> Result<ArrayRef, ArrowError> {
+    transform_values(array, slice::from_ref(path), |value| {
+        let string = value.get_str()?;
+        let transformed = transform(string);
+        (transformed != string).then(|| {
+            let mut buffer = Vec::new();
+            write_string(&mut buffer, &transformed);
+            buffer
+        })
+    })
+}
+
+/// Replace the values at paths matching any of `patterns` with the encoded
+/// value returned by `transform`, or keep them if it returns `None`.
+///
+/// Field ids are kept, so the output shares the metadata of the input, and
+/// null rows stay null.
+pub(crate) fn transform_values(
+    array: &dyn Array,
+    patterns: &[VariantPath],
+    mut transform: impl FnMut(&VariantRef) -> Option<Vec<u8>>,
+) -> Result<ArrayRef, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    offsets.push(0);
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows have empty values.
+        offsets.resize(start + 1, buffer.len());
+        for i in start..end {
+            if let Some(variant) = variant_array.variant(i) {
+                let metadata = MetadataRef::new(variant_array.metadata(i));
+                replace_values(&mut buffer, &variant, &metadata, |path, value| {
+                    if patterns.iter().any(|pattern| pattern.matches(path)) {
+                        transform(value)
+                    } else {
+                        None
+                    }
+                })
+                .map_err(ArrowError::InvalidArgumentError)?;
+            }
+            offsets.push(buffer.len());
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+
+    let nulls = variant_array.nulls().cloned();
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    Ok(variant_array.with_values(values, nulls))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::json::{variant_from_json, variant_to_json};
+
+    fn json(array: &ArrayRef) -> Vec<Option<String>> {
+        let json = variant_to_json(array).unwrap();
+        json.iter().map(|json| json.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn test_string_transforms() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"name": "  Ab c ", "tags": ["X", 1, "y"]}"#),
+            None,
+            Some(r#"{"name": 1}"#),
+            Some(r#"" Top ""#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = |path: &str| VariantPath::parse(path).unwrap();
+
+        let output = variant_trim(&array, &path("name")).unwrap();
+        assert_eq!(
+            json(&output),
+            vec![
+                Some(r#"{"name":"Ab c","tags":["X",1,"y"]}"#.to_string()),
+                None,
+                Some(r#"{"name":1}"#.to_string()),
+                Some(r#"" Top ""#.to_string()),
+            ]
+        );
+
+        let output = variant_lower(&array, &path("tags[*]")).unwrap();
+        assert_eq!(
+            json(&output)[0].as_deref(),
+            Some(r#"{"name":"  Ab c ","tags":["x",1,"y"]}"#)
+        );
+
+        let output = variant_upper(&array, &path("")).unwrap();
+        assert_eq!(json(&output)[3].as_deref(), Some(r#"" TOP ""#));
+        assert_eq!(json(&output)[0], json(&array)[0]);
+    }
+
+    #[test]
+    fn test_numeric_transforms() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"price": -12.345, "qty": -3, "items": [{"price": 1.5}, {"price": "n/a"}]}"#),
+            None,
+            Some("-1250"),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let path = |path: &str| VariantPath::parse(path).unwrap();
+
+        let output = variant_abs(&array, &path("qty")).unwrap();
+        assert_eq!(
+            json(&output)[0].as_deref(),
+            Some(r#"{"items":[{"price":1.5},{"price":"n/a"}],"price":-12.345,"qty":3}"#)
+        );
+        assert!(output.is_null(1));
+
+        let output = variant_round(&array, &path("..price"), 1).unwrap();
+        assert_eq!(
+            json(&output)[0].as_deref(),
+            Some(r#"{"items":[{"price":1.5},{"price":"n/a"}],"price":-12.3,"qty":-3}"#)
+        );
+
+        let output = variant_round(&array, &path(""), -2).unwrap();
+        assert_eq!(json(&output)[2].as_deref(), Some("-1300"));
+    }
+}
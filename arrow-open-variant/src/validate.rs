@@ -0,0 +1,194 @@
+//! Check variant columns for malformed values.
+//!
+//! Other kernels trust their input and may panic on values that are
+//! truncated or have offsets out of bounds. [`variant_validate`] checks each
+//! row with [`open_variant::validate`] instead, so data from untrusted
+//! writers can be audited, or filtered down to its valid rows, first.
+//! [`variant_repair`] recovers what it can from invalid rows instead.
+
+use arrow_array::builder::{StringBuilder, UInt64Builder};
+use arrow_array::{Array, ArrayRef, StringArray, UInt64Array};
+use arrow_buffer::NullBuffer;
+use arrow_schema::ArrowError;
+use open_variant::metadata::MetadataRef;
+use open_variant::validate::{repair_value, validate_metadata, validate_value};
+
+use crate::array::{values_array_from_parts, MetadataCache, VariantArray, VariantArrayReader};
+
+/// Check each row of a variant array, returning null for valid rows and a
+/// description of the first problem found for invalid ones.
+///
+/// Null rows are valid. Metadata shared by consecutive rows is only checked
+/// once.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_validate(array: &dyn Array) -> Result<StringArray, ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut builder = StringBuilder::with_capacity(variant_array.len(), 0);
+    let mut checked_metadata = MetadataCache::new();
+    let mut valid_end = 0;
+    for (start, end) in variant_array.valid_slices() {
+        (0..start - valid_end).for_each(|_| builder.append_null());
+        valid_end = end;
+        for i in start..end {
+            let metadata_bytes = variant_array.metadata(i);
+            let metadata_result = checked_metadata
+                .get_or_insert_with(metadata_bytes, || validate_metadata(metadata_bytes));
+            let result = match metadata_result {
+                Ok(()) => validate_value(variant_array.value(i), &MetadataRef::new(metadata_bytes)),
+                Err(error) => Err(error.clone()),
+            };
+            builder.append_option(result.err());
+        }
+    }
+    (0..variant_array.len() - valid_end).for_each(|_| builder.append_null());
+    Ok(builder.finish())
+}
+
+/// Re-encode each row of a variant array, dropping the parts that can't be
+/// read, for recovering data from buggy writers.
+///
+/// Returns the repaired array and the number of values dropped from each
+/// row, which is 0 for rows that were already valid. See
+/// [`repair_value`] for what is dropped. Rows whose metadata is invalid
+/// can't be read at all, so they become null, with a count of 1. Null rows
+/// stay null, with a null count.
+///
+/// The repaired rows keep their metadata, and objects and arrays are rebuilt
+/// with the narrowest widths.
+///
+/// # Errors
+///
+/// If the array is not a variant array.
+pub fn variant_repair(array: &dyn Array) -> Result<(ArrayRef, UInt64Array), ArrowError> {
+    let variant_array = VariantArray::try_new(array)?;
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(variant_array.len() + 1);
+    let mut validity = Vec::with_capacity(variant_array.len());
+    let mut dropped = UInt64Builder::with_capacity(variant_array.len());
+    let mut checked_metadata = MetadataCache::new();
+    offsets.push(0);
+    for (start, end) in variant_array.valid_slices() {
+        // Null rows have empty values.
+        offsets.resize(start + 1, buffer.len());
+        dropped.append_nulls(start - validity.len());
+        validity.resize(start, false);
+        for i in start..end {
+            let metadata_bytes = variant_array.metadata(i);
+            let metadata_is_valid = *checked_metadata
+                .get_or_insert_with(metadata_bytes, || validate_metadata(metadata_bytes).is_ok());
+            if metadata_is_valid {
+                let metadata = MetadataRef::new(metadata_bytes);
+                let (repaired, count) = repair_value(variant_array.value(i), &metadata);
+                buffer.extend_from_slice(&repaired);
+                dropped.append_value(count as u64);
+            } else {
+                dropped.append_value(1);
+            }
+            offsets.push(buffer.len());
+            validity.push(metadata_is_valid);
+        }
+    }
+    offsets.resize(variant_array.len() + 1, buffer.len());
+    dropped.append_nulls(variant_array.len() - validity.len());
+    validity.resize(variant_array.len(), false);
+
+    let nulls = NullBuffer::from(validity);
+    let nulls = (nulls.null_count() > 0).then_some(nulls);
+    let values = values_array_from_parts(buffer, &offsets, nulls.clone());
+    Ok((variant_array.with_values(values, nulls), dropped.finish()))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::BinaryArray;
+
+    use super::*;
+    use crate::json::{variant_from_json, variant_to_json};
+
+    #[test]
+    fn test_variant_validate() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": [1, "x"]}"#),
+            None,
+            Some("1.5"),
+            Some(r#"{"a": [2]}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let diagnostics = variant_validate(&array).unwrap();
+        assert_eq!(diagnostics.null_count(), 4);
+
+        // Truncate the first value, and give the last an empty value.
+        let variant_array = VariantArray::try_new(&array).unwrap();
+        let first = variant_array.value(0);
+        let values = (0..4).map(|i| match i {
+            0 => Some(&first[..first.len() - 1]),
+            1 => None,
+            3 => Some(&[][..]),
+            _ => Some(variant_array.value(i)),
+        });
+        let values = Arc::new(BinaryArray::from_iter(values));
+        let array = variant_array.with_values(values, variant_array.nulls().cloned());
+        let diagnostics = variant_validate(&array).unwrap();
+        assert_eq!(
+            diagnostics.iter().collect::<Vec<_>>(),
+            vec![
+                Some("object is truncated"),
+                None,
+                None,
+                Some("value is empty"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variant_repair() {
+        let jsons = StringArray::from(vec![
+            Some(r#"{"a": [1, "x"], "b": 2}"#),
+            None,
+            Some("1.5"),
+            Some(r#"{"a": [2]}"#),
+        ]);
+        let array = variant_from_json(&jsons).unwrap();
+        let (repaired, dropped) = variant_repair(&array).unwrap();
+        assert_eq!(
+            variant_to_json(&repaired).unwrap(),
+            variant_to_json(&array).unwrap()
+        );
+        assert_eq!(
+            dropped.iter().collect::<Vec<_>>(),
+            vec![Some(0), None, Some(0), Some(0)]
+        );
+
+        // Truncate the first value.
+        let variant_array = VariantArray::try_new(&array).unwrap();
+        let first = variant_array.value(0);
+        let values = (0..4).map(|i| match i {
+            0 => Some(&first[..first.len() - 1]),
+            1 => None,
+            _ => Some(variant_array.value(i)),
+        });
+        let values = Arc::new(BinaryArray::from_iter(values));
+        let array = variant_array.with_values(values, variant_array.nulls().cloned());
+        let (repaired, dropped) = variant_repair(&array).unwrap();
+        assert_eq!(
+            dropped.iter().collect::<Vec<_>>(),
+            vec![Some(1), None, Some(0), Some(0)]
+        );
+        assert_eq!(
+            variant_validate(&repaired).unwrap().null_count(),
+            repaired.len()
+        );
+        assert_eq!(
+            variant_to_json(&repaired)
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![Some("null"), None, Some("1.5"), Some(r#"{"a":[2]}"#)]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Micro-benchmarks for building and reading variant buffers.
+//!
+//! Run with `cargo bench -p open-variant`, optionally followed by `--` and a
+//! regular expression matching the benchmarks to run, such as `find_string`.
+//! Criterion saves the results, and compares later runs against them.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use open_variant::metadata::{build_metadata, MetadataRef};
+use open_variant::values::write::{write_i64, ArrayBuilder, ObjectBuilder};
+use open_variant::values::{FieldLookup, VariantRef};
+
+fn keys(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("key_{:05}", i)).collect()
+}
+
+fn write_object(buffer: &mut Vec<u8>, metadata: &MetadataRef, keys: &[String]) {
+    let mut object_builder = ObjectBuilder::with_capacity(buffer, metadata, keys.len());
+    // Append in reverse so finish has to sort the fields.
+    for (i, key) in keys.iter().enumerate().rev() {
+        object_builder.append_i64(key, i as i64).unwrap();
+    }
+    object_builder.finish();
+}
+
+fn bench_metadata(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_metadata");
+    for n in [10, 100, 1000] {
+        let keys = keys(n);
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| build_metadata(keys.iter().map(|key| key.as_str())))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("find_string");
+    for n in [10, 1000] {
+        let keys = keys(n);
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata = MetadataRef::new(&metadata);
+        let key = &keys[n / 3];
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| metadata.find_string(black_box(key)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_object(c: &mut Criterion) {
+    let mut group = c.benchmark_group("object");
+    for n in [10, 100] {
+        let keys = keys(n);
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        group.bench_function(BenchmarkId::new("builder_finish", n), |b| {
+            b.iter(|| {
+                buffer.clear();
+                write_object(&mut buffer, &metadata, &keys);
+                black_box(&buffer);
+            })
+        });
+
+        let field_id = n / 3;
+        let key = &keys[field_id];
+        let object = || VariantRef::try_new(&buffer).unwrap().get_object().unwrap();
+        group.bench_function(BenchmarkId::new("get_field", n), |b| {
+            b.iter(|| object().get_field(black_box(field_id), &metadata))
+        });
+        group.bench_function(BenchmarkId::new("find_field", n), |b| {
+            b.iter(|| object().find_field(black_box(key), &metadata))
+        });
+        let mut lookup = FieldLookup::new(key, &metadata).unwrap();
+        group.bench_function(BenchmarkId::new("field_lookup", n), |b| {
+            b.iter(|| lookup.get(&object()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_array(c: &mut Criterion) {
+    let mut group = c.benchmark_group("array_builder_finish");
+    for n in [10, 1000] {
+        let mut element = Vec::new();
+        write_i64(&mut element, 1);
+        let mut buffer = Vec::new();
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                buffer.clear();
+                let mut array_builder = ArrayBuilder::new(&mut buffer, n);
+                for _ in 0..n {
+                    array_builder.append_value(&element);
+                }
+                array_builder.finish();
+                black_box(&buffer);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_metadata, bench_object, bench_array);
+criterion_main!(benches);
@@ -0,0 +1,73 @@
+/*
+ * C interface to the open-variant readers.
+ *
+ * Build open-variant with the `ffi` feature, as a staticlib or cdylib, to
+ * use these functions:
+ *
+ *   cargo rustc -p open-variant --release --features ffi --crate-type staticlib,cdylib
+ *
+ * See `src/ffi.rs` for details.
+ *
+ * Buffers are borrowed: slices returned through `out` parameters point into
+ * the input buffers and are valid as long as they are. Accessors return false
+ * if the value has a different type, or if the field or element doesn't
+ * exist. They also return false (or -1) for malformed data, such as truncated
+ * values or offsets out of bounds, instead of crashing.
+ */
+#ifndef OPEN_VARIANT_H
+#define OPEN_VARIANT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct OvSlice {
+    const uint8_t *data;
+    size_t len;
+} OvSlice;
+
+/* Basic types, as returned by ov_basic_type. */
+#define OV_BASIC_TYPE_PRIMITIVE 0
+#define OV_BASIC_TYPE_SHORT_STRING 1
+#define OV_BASIC_TYPE_OBJECT 2
+#define OV_BASIC_TYPE_ARRAY 3
+
+/* The basic type of a value, or -1 if the buffer is empty. */
+int32_t ov_basic_type(OvSlice value);
+
+/* The primitive type id of a value, or -1 if it isn't a primitive or the id
+ * is unknown. */
+int32_t ov_primitive_type_id(OvSlice value);
+
+bool ov_get_bool(OvSlice value, bool *out);
+
+/* A number as an int64_t: integers of any width, and integral decimals and
+ * floats in range. */
+bool ov_get_i64(OvSlice value, int64_t *out);
+
+/* A number as a double: floats of either width, and integers and decimals
+ * rounded to a nearby double. */
+bool ov_get_f64(OvSlice value, double *out);
+
+/* The UTF-8 bytes of a string value. */
+bool ov_get_string(OvSlice value, OvSlice *out);
+
+/* A field of an object, by field id. `metadata` is the dictionary the object
+ * was written with. */
+bool ov_get_field(OvSlice metadata, OvSlice object, size_t field_id, OvSlice *out);
+
+/* An element of an array. */
+bool ov_get_element(OvSlice array, size_t index, OvSlice *out);
+
+/* The id of a key in a metadata buffer. `key` is UTF-8. */
+bool ov_find_string(OvSlice metadata, OvSlice key, size_t *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* OPEN_VARIANT_H */
@@ -0,0 +1,149 @@
+//! A canonical encoding of values, for byte-identical output.
+//!
+//! The same logical value can be encoded in many ways: the metadata can hold
+//! unused keys or be unsorted, and objects and arrays can use wider offsets
+//! and field ids than needed. Canonicalizing a value gives it its own sorted
+//! metadata with only the keys it uses, and rebuilds every object and array
+//! with the narrowest widths, so equal values from any writer or batch are
+//! encoded with the same bytes.
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::metadata::{build_metadata, MetadataRef};
+use crate::values::write::remap_field_ids;
+use crate::values::{BasicType, VariantRef};
+
+impl<'a> VariantRef<'a> {
+    /// Encode the value in canonical form.
+    ///
+    /// Returns the metadata and the value. The metadata is sorted and only
+    /// has the keys the value uses, and objects and arrays are rebuilt with
+    /// fields ordered by key and the narrowest widths. Primitive values are
+    /// copied unchanged, so for example a short string and a string with the
+    /// same contents stay different.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in the metadata.
+    pub fn canonicalize(&self, metadata: &MetadataRef) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let mut field_ids = BTreeSet::new();
+        let mut stack = vec![self.clone()];
+        while let Some(value) = stack.pop() {
+            match value.basic_type() {
+                BasicType::Object => {
+                    for (field_id, field) in value.get_object()?.fields() {
+                        field_ids.insert(field_id);
+                        stack.push(field);
+                    }
+                }
+                BasicType::Array => stack.extend(value.get_array()?.elements()),
+                BasicType::Primitive | BasicType::ShortString => {}
+            }
+        }
+        let keys = field_ids
+            .iter()
+            .map(|field_id| {
+                metadata
+                    .get_string(*field_id)
+                    .map(|key| (*field_id, key))
+                    .ok_or_else(|| format!("Field id {} is not in the metadata", field_id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let new_metadata = build_metadata(keys.iter().map(|(_, key)| *key));
+        let new_metadata_ref = MetadataRef::new(&new_metadata);
+        let mut mapping = vec![0; field_ids.last().map_or(0, |field_id| field_id + 1)];
+        for (field_id, key) in keys {
+            mapping[field_id] = new_metadata_ref
+                .find_string(key)
+                .expect("every key was added to the metadata");
+        }
+
+        let mut value = Vec::with_capacity(self.as_bytes().len());
+        remap_field_ids(&mut value, self, &mapping, &new_metadata_ref)?;
+        Ok((new_metadata, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::metadata::StreamingMetadataBuilder;
+    use crate::values::write::{write_i64, ArrayBuilder, ObjectBuilder};
+
+    /// `{"b": [1, {"a": 2}], "c": 3}`
+    fn write_value(metadata: &MetadataRef) -> Vec<u8> {
+        let mut element = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut element, metadata, 1);
+        object_builder.append_i64("a", 2).unwrap();
+        object_builder.finish();
+        let mut one = Vec::new();
+        write_i64(&mut one, 1);
+        let mut b = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut b, 2);
+        array_builder.append_value(&one);
+        array_builder.append_value(&element);
+        array_builder.finish();
+
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, metadata, 2);
+        object_builder.append_value("b", &b).unwrap();
+        object_builder.append_i64("c", 3).unwrap();
+        object_builder.finish();
+        buffer
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let sorted_bytes = build_metadata(["a", "b", "c"].into_iter());
+        let sorted = MetadataRef::new(&sorted_bytes);
+        let value = write_value(&sorted);
+        let expected = VariantRef::try_new(&value)
+            .unwrap()
+            .canonicalize(&sorted)
+            .unwrap();
+        assert_eq!(expected.0, sorted_bytes);
+        assert_eq!(expected.1, value);
+
+        let mut null = Vec::new();
+        crate::values::write::write_null(&mut null);
+        let (metadata, value) = VariantRef::try_new(&null)
+            .unwrap()
+            .canonicalize(&sorted)
+            .unwrap();
+        assert_eq!(MetadataRef::new(&metadata).dictionary_len(), 0);
+        assert_eq!(value, null);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_canonicalize_unsorted() {
+        let sorted = build_metadata(["a", "b", "c"].into_iter());
+        let sorted = MetadataRef::new(&sorted);
+        let value = write_value(&sorted);
+        let expected = VariantRef::try_new(&value)
+            .unwrap()
+            .canonicalize(&sorted)
+            .unwrap();
+
+        // An unsorted dictionary with unused keys gives the same bytes.
+        let mut builder = StreamingMetadataBuilder::new();
+        for key in ["unused", "c", "b", "other", "a"] {
+            builder.get_or_insert(key);
+        }
+        let unsorted = builder.build();
+        let unsorted = MetadataRef::new(&unsorted);
+        assert!(!unsorted.sorted_strings());
+        let value = write_value(&unsorted);
+        assert_ne!(value, expected.1);
+        let canonical = VariantRef::try_new(&value)
+            .unwrap()
+            .canonicalize(&unsorted)
+            .unwrap();
+        assert_eq!(canonical, expected);
+    }
+}
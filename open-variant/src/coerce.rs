@@ -0,0 +1,488 @@
+//! Coercion of variant values to typed values.
+//!
+//! These rules are used wherever a variant value is read as a specific type,
+//! such as typed extraction and comparisons, so that they agree with each
+//! other. A value coerces if the conversion keeps its meaning:
+//!
+//! | From \ To  | `i64`                   | `f64`   | `bool`  | string        |
+//! |------------|-------------------------|---------|---------|---------------|
+//! | integers   | yes                     | rounded | no      | no            |
+//! | decimals   | if integral and in range| rounded | no      | no            |
+//! | floats     | if integral and in range| yes     | no      | no            |
+//! | booleans   | no                      | no      | yes     | no            |
+//! | strings    | opt-in                  | opt-in  | opt-in  | yes           |
+//! | UUIDs      | no                      | no      | no      | canonical form|
+//!
+//! Integers of any width, and decimals and floats of any precision, coerce
+//! the same way. Converting to `f64` rounds integers beyond 2^53, and
+//! decimals, to a nearby float, but floats never convert to integers
+//! inexactly. Strings are only parsed as numbers and booleans with
+//! [`CoerceOptions::parse_strings`]. Nulls, dates, timestamps, binary values,
+//! objects and arrays don't coerce to any of these types.
+//!
+//! Strings stored in the metadata dictionary need the metadata to be read, so
+//! they only coerce to strings, with
+//! [`coerce_string_with_metadata`](VariantRef::coerce_string_with_metadata).
+//! Readers of typed strings should use it rather than
+//! [`coerce_string`](VariantRef::coerce_string), so that they all agree.
+
+use alloc::borrow::Cow;
+
+use crate::metadata::MetadataRef;
+use crate::values::uuid::format_uuid;
+use crate::values::{BasicType, PrimitiveTypeId, VariantRef};
+
+/// Options for coercing variant values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CoerceOptions {
+    /// Parse strings as numbers and booleans. Integers and floats are parsed
+    /// with Rust's syntax, such as `-12` and `1.5e3`, and booleans are `true`
+    /// and `false` in any case. Strings that don't parse, or that parse to an
+    /// infinite or NaN float, don't coerce.
+    pub parse_strings: bool,
+}
+
+/// A numeric variant value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Number {
+    Integer(i64),
+    Decimal { unscaled: i128, scale: u8 },
+    Float(f64),
+}
+
+impl<'a> VariantRef<'a> {
+    /// The value as an `i64`, if it coerces. See the [module
+    /// documentation](self) for the rules.
+    pub fn coerce_i64(&self, options: &CoerceOptions) -> Option<i64> {
+        match self.number() {
+            Some(Number::Integer(value)) => Some(value),
+            Some(Number::Decimal { unscaled, scale }) => {
+                let divisor = 10i128.checked_pow(scale as u32)?;
+                if unscaled % divisor != 0 {
+                    return None;
+                }
+                (unscaled / divisor).try_into().ok()
+            }
+            // -2^63 is the smallest float in range, and 2^63 the smallest
+            // float above it.
+            Some(Number::Float(value))
+                if value >= i64::MIN as f64 && value < -(i64::MIN as f64) =>
+            {
+                Some(value as i64).filter(|integer| *integer as f64 == value)
+            }
+            Some(Number::Float(_)) => None,
+            None => self.parsed_string(options)?.parse().ok(),
+        }
+    }
+
+    /// The value as an `f64`, if it coerces. See the [module
+    /// documentation](self) for the rules.
+    pub fn coerce_f64(&self, options: &CoerceOptions) -> Option<f64> {
+        match self.number() {
+            Some(Number::Integer(value)) => Some(value as f64),
+            Some(Number::Decimal { unscaled, scale }) => {
+                Some(unscaled as f64 / 10i128.checked_pow(scale as u32)? as f64)
+            }
+            Some(Number::Float(value)) => Some(value),
+            None => self
+                .parsed_string(options)?
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite()),
+        }
+    }
+
+    /// The value as a `bool`, if it coerces. See the [module
+    /// documentation](self) for the rules.
+    pub fn coerce_bool(&self, options: &CoerceOptions) -> Option<bool> {
+        if self.basic_type() == BasicType::Primitive {
+            match self.primitive_type_id() {
+                PrimitiveTypeId::BoolTrue => return Some(true),
+                PrimitiveTypeId::BoolFalse => return Some(false),
+                _ => {}
+            }
+        }
+        let string = self.parsed_string(options)?;
+        if string.eq_ignore_ascii_case("true") {
+            Some(true)
+        } else if string.eq_ignore_ascii_case("false") {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// The value as a string, if it coerces. See the [module
+    /// documentation](self) for the rules.
+    pub fn coerce_string(&self) -> Option<Cow<'a, str>> {
+        if self.basic_type() == BasicType::Primitive
+            && self.primitive_type_id() == PrimitiveTypeId::Uuid
+        {
+            return Some(Cow::Owned(format_uuid(&self.get_uuid())));
+        }
+        self.get_str().map(Cow::Borrowed)
+    }
+
+    /// Like [`coerce_string`](Self::coerce_string), but also resolves strings
+    /// stored in the metadata dictionary.
+    pub fn coerce_string_with_metadata<'m>(
+        &self,
+        metadata: &MetadataRef<'m>,
+    ) -> Option<Cow<'m, str>>
+    where
+        'a: 'm,
+    {
+        match self.get_str_with_metadata(metadata) {
+            Some(string) => Some(Cow::Borrowed(string)),
+            None => self.coerce_string(),
+        }
+    }
+
+    /// Whether two numeric values are equal, or `None` if either is not a
+    /// number.
+    ///
+    /// Integers and decimals compare exactly. If either value is a float,
+    /// both are compared as `f64`.
+    pub(crate) fn numeric_eq(&self, other: &VariantRef) -> Option<bool> {
+        let (left, right) = (self.number()?, other.number()?);
+        let equal = match (left, right) {
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                let options = CoerceOptions::default();
+                self.coerce_f64(&options) == other.coerce_f64(&options)
+            }
+            _ => {
+                let (left, left_scale) = left.as_decimal();
+                let (right, right_scale) = right.as_decimal();
+                // Scale both to the larger scale. If that overflows, the
+                // value scaled up is larger than any value at that scale.
+                let scale = left_scale.max(right_scale);
+                let rescale = |value: i128, from: u8| {
+                    10i128
+                        .checked_pow((scale - from) as u32)
+                        .and_then(|factor| value.checked_mul(factor))
+                };
+                match (rescale(left, left_scale), rescale(right, right_scale)) {
+                    (Some(left), Some(right)) => left == right,
+                    _ => false,
+                }
+            }
+        };
+        Some(equal)
+    }
+
+    /// The numeric value of integers, decimals and floats of any width.
+    pub(crate) fn number(&self) -> Option<Number> {
+        if self.basic_type() != BasicType::Primitive {
+            return None;
+        }
+        // The bytes after the header.
+        let payload = &self.as_bytes()[1..];
+        let number = match self.primitive_type_id() {
+            PrimitiveTypeId::Int8 => Number::Integer(payload[0] as i8 as i64),
+            PrimitiveTypeId::Int16 => {
+                Number::Integer(i16::from_le_bytes(payload[..2].try_into().unwrap()) as i64)
+            }
+            PrimitiveTypeId::Int32 => {
+                Number::Integer(i32::from_le_bytes(payload[..4].try_into().unwrap()) as i64)
+            }
+            PrimitiveTypeId::Int64 => Number::Integer(self.get_i64()),
+            PrimitiveTypeId::Float32 => {
+                Number::Float(f32::from_le_bytes(payload[..4].try_into().unwrap()) as f64)
+            }
+            PrimitiveTypeId::Float64 => Number::Float(self.get_f64()),
+            PrimitiveTypeId::Decimal4 => Number::Decimal {
+                unscaled: i32::from_le_bytes(payload[1..5].try_into().unwrap()) as i128,
+                scale: payload[0],
+            },
+            PrimitiveTypeId::Decimal8 => Number::Decimal {
+                unscaled: i64::from_le_bytes(payload[1..9].try_into().unwrap()) as i128,
+                scale: payload[0],
+            },
+            PrimitiveTypeId::Decimal16 => Number::Decimal {
+                unscaled: self.get_i128(),
+                scale: payload[0],
+            },
+            _ => return None,
+        };
+        Some(number)
+    }
+
+    /// The string to parse, if strings are parsed and this is a string.
+    fn parsed_string(&self, options: &CoerceOptions) -> Option<&'a str> {
+        options.parse_strings.then(|| self.get_str()).flatten()
+    }
+}
+
+impl Number {
+    /// The unscaled value and scale of an integer or decimal.
+    fn as_decimal(self) -> (i128, u8) {
+        match self {
+            Number::Integer(value) => (value as i128, 0),
+            Number::Decimal { unscaled, scale } => (unscaled, scale),
+            Number::Float(_) => unreachable!("floats are compared as floats"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::metadata::build_metadata;
+    use crate::values::write::{
+        write_bool, write_decimal, write_f64, write_i64, write_null, write_string,
+        write_timestamp_nanos_ntz, write_uuid,
+    };
+
+    /// A primitive with the given type and payload.
+    fn primitive(type_id: PrimitiveTypeId, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = vec![(type_id as u8) << 2];
+        buffer.extend_from_slice(payload);
+        buffer
+    }
+
+    fn written(write: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write(&mut buffer);
+        buffer
+    }
+
+    /// What each value coerces to, as `(i64, f64, bool, string)`, without
+    /// and with parsing strings.
+    #[test]
+    fn test_coercion_matrix() {
+        type Coerced = (Option<i64>, Option<f64>, Option<bool>, Option<&'static str>);
+        let none: Coerced = (None, None, None, None);
+        let number = |i, f| (i, Some(f), None, None);
+        let cases: Vec<(&str, Vec<u8>, Coerced, Coerced)> = vec![
+            ("null", written(write_null), none, none),
+            (
+                "true",
+                written(|b| write_bool(b, true)),
+                (None, None, Some(true), None),
+                (None, None, Some(true), None),
+            ),
+            (
+                "int8",
+                primitive(PrimitiveTypeId::Int8, &[0xfe]),
+                number(Some(-2), -2.0),
+                number(Some(-2), -2.0),
+            ),
+            (
+                "int16",
+                primitive(PrimitiveTypeId::Int16, &300i16.to_le_bytes()),
+                number(Some(300), 300.0),
+                number(Some(300), 300.0),
+            ),
+            (
+                "int32",
+                primitive(PrimitiveTypeId::Int32, &(-70_000i32).to_le_bytes()),
+                number(Some(-70_000), -70_000.0),
+                number(Some(-70_000), -70_000.0),
+            ),
+            (
+                "int64",
+                written(|b| write_i64(b, i64::MAX)),
+                number(Some(i64::MAX), 9.223_372_036_854_776e18),
+                number(Some(i64::MAX), 9.223_372_036_854_776e18),
+            ),
+            (
+                "float32",
+                primitive(PrimitiveTypeId::Float32, &2.5f32.to_le_bytes()),
+                number(None, 2.5),
+                number(None, 2.5),
+            ),
+            (
+                "integral float",
+                written(|b| write_f64(b, -3.0)),
+                number(Some(-3), -3.0),
+                number(Some(-3), -3.0),
+            ),
+            (
+                "float out of range",
+                written(|b| write_f64(b, 9.3e18)),
+                number(None, 9.3e18),
+                number(None, 9.3e18),
+            ),
+            (
+                "float min",
+                written(|b| write_f64(b, -9.223_372_036_854_776e18)),
+                number(Some(i64::MIN), -9.223_372_036_854_776e18),
+                number(Some(i64::MIN), -9.223_372_036_854_776e18),
+            ),
+            (
+                "nan",
+                written(|b| write_f64(b, f64::NAN)),
+                number(None, f64::NAN),
+                number(None, f64::NAN),
+            ),
+            (
+                "decimal",
+                written(|b| write_decimal(b, 1250, 2)),
+                number(None, 12.5),
+                number(None, 12.5),
+            ),
+            (
+                "integral decimal",
+                written(|b| write_decimal(b, 1200, 2)),
+                number(Some(12), 12.0),
+                number(Some(12), 12.0),
+            ),
+            (
+                "decimal16",
+                primitive(
+                    PrimitiveTypeId::Decimal16,
+                    &[&[0u8][..], &(i64::MAX as i128 + 1).to_le_bytes()].concat(),
+                ),
+                number(None, 9.223_372_036_854_776e18),
+                number(None, 9.223_372_036_854_776e18),
+            ),
+            (
+                "integer string",
+                written(|b| write_string(b, "42")),
+                (None, None, None, Some("42")),
+                (Some(42), Some(42.0), None, Some("42")),
+            ),
+            (
+                "float string",
+                written(|b| write_string(b, "1.5e3")),
+                (None, None, None, Some("1.5e3")),
+                (None, Some(1500.0), None, Some("1.5e3")),
+            ),
+            (
+                "bool string",
+                written(|b| write_string(b, "FALSE")),
+                (None, None, None, Some("FALSE")),
+                (None, None, Some(false), Some("FALSE")),
+            ),
+            (
+                "infinite string",
+                written(|b| write_string(b, "inf")),
+                (None, None, None, Some("inf")),
+                (None, None, None, Some("inf")),
+            ),
+            (
+                "uuid",
+                written(|b| write_uuid(b, &[0x11; 16])),
+                (
+                    None,
+                    None,
+                    None,
+                    Some("11111111-1111-1111-1111-111111111111"),
+                ),
+                (
+                    None,
+                    None,
+                    None,
+                    Some("11111111-1111-1111-1111-111111111111"),
+                ),
+            ),
+            (
+                "timestamp",
+                written(|b| write_timestamp_nanos_ntz(b, 1)),
+                none,
+                none,
+            ),
+            (
+                "date",
+                primitive(PrimitiveTypeId::Date32, &1i32.to_le_bytes()),
+                none,
+                none,
+            ),
+        ];
+        for (name, buffer, expected, expected_parsed) in cases {
+            let value = VariantRef::try_new(&buffer).unwrap();
+            for (parse_strings, expected) in [(false, expected), (true, expected_parsed)] {
+                let options = CoerceOptions { parse_strings };
+                let coerced = (
+                    value.coerce_i64(&options),
+                    value.coerce_f64(&options),
+                    value.coerce_bool(&options),
+                    value.coerce_string(),
+                );
+                let expected = (
+                    expected.0,
+                    expected.1,
+                    expected.2,
+                    expected.3.map(Cow::Borrowed),
+                );
+                // NaN is not equal to itself, so compare floats by bits.
+                assert_eq!(
+                    (
+                        coerced.0,
+                        coerced.1.map(f64::to_bits),
+                        coerced.2,
+                        coerced.3.clone()
+                    ),
+                    (
+                        expected.0,
+                        expected.1.map(f64::to_bits),
+                        expected.2,
+                        expected.3
+                    ),
+                    "{} with parse_strings={}",
+                    name,
+                    parse_strings
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_numeric_eq() {
+        let int8 = primitive(PrimitiveTypeId::Int8, &[5]);
+        let int64 = written(|b| write_i64(b, 5));
+        let decimal = written(|b| write_decimal(b, 500, 2));
+        let other_decimal = written(|b| write_decimal(b, 50_001, 4));
+        let float = written(|b| write_f64(b, 5.0));
+        let huge = primitive(
+            PrimitiveTypeId::Decimal16,
+            &[&[0u8][..], &(i128::MAX / 10).to_le_bytes()].concat(),
+        );
+        let tiny = written(|b| write_decimal(b, 1, 38));
+        let string = written(|b| write_string(b, "5"));
+        let eq = |left: &[u8], right: &[u8]| {
+            VariantRef::try_new(left)
+                .unwrap()
+                .numeric_eq(&VariantRef::try_new(right).unwrap())
+        };
+
+        assert_eq!(eq(&int8, &int64), Some(true));
+        assert_eq!(eq(&int64, &decimal), Some(true));
+        assert_eq!(eq(&decimal, &other_decimal), Some(false));
+        assert_eq!(eq(&decimal, &float), Some(true));
+        assert_eq!(eq(&huge, &tiny), Some(false));
+        assert_eq!(eq(&int64, &string), None);
+    }
+
+    #[test]
+    fn test_coerce_string_with_metadata() {
+        let metadata = build_metadata(["apple"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let dictionary = primitive(PrimitiveTypeId::StringFromDictionary, &0u32.to_le_bytes());
+        let dictionary = VariantRef::try_new(&dictionary).unwrap();
+        assert_eq!(dictionary.coerce_string(), None);
+        assert_eq!(
+            dictionary.coerce_string_with_metadata(&metadata).as_deref(),
+            Some("apple")
+        );
+
+        let uuid = written(|b| write_uuid(b, &[0x11; 16]));
+        let uuid = VariantRef::try_new(&uuid).unwrap();
+        assert_eq!(
+            uuid.coerce_string_with_metadata(&metadata).as_deref(),
+            Some("11111111-1111-1111-1111-111111111111")
+        );
+        let string = written(|b| write_string(b, "x"));
+        let string = VariantRef::try_new(&string).unwrap();
+        assert_eq!(
+            string.coerce_string_with_metadata(&metadata).as_deref(),
+            Some("x")
+        );
+        let int = written(|b| write_i64(b, 1));
+        let int = VariantRef::try_new(&int).unwrap();
+        assert_eq!(int.coerce_string_with_metadata(&metadata), None);
+    }
+}
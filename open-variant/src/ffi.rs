@@ -0,0 +1,598 @@
+//! A C interface to the variant readers.
+//!
+//! The declarations are in `include/open_variant.h`. Buffers are passed as
+//! [`OvSlice`] values that borrow from the caller. Accessors return `false`
+//! if the value has a different type, or if the field or element doesn't
+//! exist.
+//!
+//! The data doesn't have to be valid. The Rust readers panic on malformed
+//! data, and a panic unwinding out of an `extern "C"` function is undefined
+//! behaviour before Rust 1.81, so these functions check the parts of the
+//! data they read first: type ids, the lengths of primitives and strings,
+//! the headers, field ids and offsets of objects and arrays, and the
+//! metadata passed to [`ov_find_string`]. Accessors return -1 or `false` for
+//! malformed data, as for a different type. They don't validate the rest of
+//! the data, so a value read from a malformed object can itself be
+//! malformed; see [`validate`](crate::validate) to check a whole value.
+//!
+//! Build a library to link against with
+//!
+//! ```text
+//! cargo rustc -p open-variant --release --features ffi --crate-type staticlib,cdylib
+//! ```
+
+use core::slice;
+
+use crate::coerce::CoerceOptions;
+use crate::metadata::MetadataRef;
+use crate::utils::read_integer;
+use crate::validate::validate_metadata;
+use crate::values::{BasicType, PrimitiveTypeId, VariantRef};
+
+/// A borrowed byte buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct OvSlice {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+impl OvSlice {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `data` must point to `len` bytes that outlive `'a`.
+    unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(self.data, self.len)
+        }
+    }
+
+    /// # Safety
+    ///
+    /// See [`OvSlice::as_bytes`].
+    unsafe fn as_variant<'a>(self) -> Option<VariantRef<'a>> {
+        VariantRef::try_new(self.as_bytes()).ok()
+    }
+}
+
+/// The primitive type id of a primitive value, or `None` for other values
+/// and unknown ids. The readers panic on unknown ids, which can't unwind out
+/// of an `extern "C"` function, so the header is decoded here.
+fn primitive_type_id(value: &[u8]) -> Option<PrimitiveTypeId> {
+    let header = *value.first()?;
+    if header & 0b11 != BasicType::Primitive as u8 {
+        return None;
+    }
+    PrimitiveTypeId::try_from(header >> 2).ok()
+}
+
+/// A numeric value with all of its bytes, which the coercions can read, or
+/// `None` for other values and truncated numbers.
+fn complete_number(value: &[u8]) -> Option<VariantRef<'_>> {
+    let payload_len = match primitive_type_id(value)? {
+        PrimitiveTypeId::Int8 => 1,
+        PrimitiveTypeId::Int16 => 2,
+        PrimitiveTypeId::Int32 | PrimitiveTypeId::Float32 => 4,
+        PrimitiveTypeId::Int64 | PrimitiveTypeId::Float64 => 8,
+        PrimitiveTypeId::Decimal4 => 5,
+        PrimitiveTypeId::Decimal8 => 9,
+        PrimitiveTypeId::Decimal16 => 17,
+        _ => return None,
+    };
+    if value.len() <= payload_len {
+        return None;
+    }
+    VariantRef::try_new(value).ok()
+}
+
+/// The bytes of a string value, or `None` for other values and truncated
+/// strings.
+fn complete_string(value: &[u8]) -> Option<&[u8]> {
+    let header = *value.first()?;
+    let (start, len): (usize, usize) = if header & 0b11 == BasicType::ShortString as u8 {
+        (1, (header >> 2) as usize)
+    } else if primitive_type_id(value)? == PrimitiveTypeId::String {
+        (5, read_integer(value.get(..5)?, 1, 4))
+    } else {
+        return None;
+    };
+    value.get(start..start.checked_add(len)?)
+}
+
+/// The layout of an object or array, read with bounds checks.
+struct Container<'a> {
+    len: usize,
+    offset_width: u8,
+    offsets: &'a [u8],
+    /// The bytes after the offsets.
+    data: &'a [u8],
+}
+
+impl<'a> Container<'a> {
+    /// Read the header of an object or array, and check that its field ids
+    /// and offsets are in the buffer, and that its last offset is in its
+    /// data. Returns `None` for other values and truncated containers.
+    fn try_new(value: &'a [u8], basic_type: BasicType) -> Option<Self> {
+        let header = *value.first()?;
+        if header & 0b11 != basic_type as u8 {
+            return None;
+        }
+        let header = header >> 2;
+        let offset_width = (header & 0b11) + 1;
+        let (field_id_width, is_large) = match basic_type {
+            BasicType::Object => (((header >> 2) & 0b11) as usize + 1, (header >> 4) & 1 == 1),
+            _ => (0, (header >> 2) & 1 == 1),
+        };
+        let (len, field_ids_start): (usize, usize) = if is_large {
+            (read_integer(value.get(..5)?, 1, 4), 5)
+        } else {
+            (*value.get(1)? as usize, 2)
+        };
+        let offsets_start = field_ids_start.checked_add(len.checked_mul(field_id_width)?)?;
+        let offsets_len = len.checked_add(1)?.checked_mul(offset_width as usize)?;
+        let data_start = offsets_start.checked_add(offsets_len)?;
+        let container = Self {
+            len,
+            offset_width,
+            offsets: value.get(offsets_start..data_start)?,
+            data: &value[data_start..],
+        };
+        (container.offset(len) <= container.data.len()).then_some(container)
+    }
+
+    fn offset(&self, index: usize) -> usize {
+        read_integer(
+            self.offsets,
+            index * self.offset_width as usize,
+            self.offset_width,
+        )
+    }
+}
+
+/// The basic type of a value, or -1 if the buffer is empty.
+///
+/// # Safety
+///
+/// `value` must point to valid memory.
+#[no_mangle]
+pub unsafe extern "C" fn ov_basic_type(value: OvSlice) -> i32 {
+    match value.as_variant() {
+        Some(variant) => variant.basic_type() as i32,
+        None => -1,
+    }
+}
+
+/// The primitive type id of a value, or -1 if it isn't a primitive or the
+/// id is unknown.
+///
+/// # Safety
+///
+/// `value` must point to valid memory.
+#[no_mangle]
+pub unsafe extern "C" fn ov_primitive_type_id(value: OvSlice) -> i32 {
+    match primitive_type_id(value.as_bytes()) {
+        Some(type_id) => type_id as i32,
+        None => -1,
+    }
+}
+
+/// # Safety
+///
+/// `value` must point to valid memory, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_get_bool(value: OvSlice, out: *mut bool) -> bool {
+    match primitive_type_id(value.as_bytes()) {
+        Some(PrimitiveTypeId::BoolTrue) => {
+            *out = true;
+            true
+        }
+        Some(PrimitiveTypeId::BoolFalse) => {
+            *out = false;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Get a number as an `i64`, if it coerces like
+/// [`VariantRef::coerce_i64`]: integers of any width, and integral decimals
+/// and floats in range.
+///
+/// # Safety
+///
+/// `value` must point to valid memory, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_get_i64(value: OvSlice, out: *mut i64) -> bool {
+    let options = CoerceOptions::default();
+    match complete_number(value.as_bytes()).and_then(|number| number.coerce_i64(&options)) {
+        Some(number) => {
+            *out = number;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Get a number as an `f64`, if it coerces like
+/// [`VariantRef::coerce_f64`]: floats of either width, and integers and
+/// decimals rounded to a nearby float.
+///
+/// # Safety
+///
+/// `value` must point to valid memory, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_get_f64(value: OvSlice, out: *mut f64) -> bool {
+    let options = CoerceOptions::default();
+    match complete_number(value.as_bytes()).and_then(|number| number.coerce_f64(&options)) {
+        Some(number) => {
+            *out = number;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Get the UTF-8 bytes of a string value. The output borrows from `value`.
+///
+/// # Safety
+///
+/// `value` must point to valid memory, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_get_string(value: OvSlice, out: *mut OvSlice) -> bool {
+    match complete_string(value.as_bytes()) {
+        Some(bytes) => {
+            *out = OvSlice::from_bytes(bytes);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Get a field of an object by field id. `metadata` is the dictionary the
+/// object was written with. The output borrows from `object`.
+///
+/// # Safety
+///
+/// `metadata` and `object` must point to valid memory, and `out` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_get_field(
+    metadata: OvSlice,
+    object: OvSlice,
+    field_id: usize,
+    out: *mut OvSlice,
+) -> bool {
+    // Only the sorted flag of the metadata is read.
+    let Some(&metadata_header) = metadata.as_bytes().first() else {
+        return false;
+    };
+    let sorted = metadata_header & 0b0001_0000 != 0;
+    let field = || {
+        let bytes = object.as_bytes();
+        let container = Container::try_new(bytes, BasicType::Object)?;
+        let object = VariantRef::try_new(bytes).ok()?.get_object().ok()?;
+        let index = object.field_index(field_id, sorted)?;
+        // Fields extend to the end of the object's data, as in `ObjectRef`.
+        let end = container.offset(container.len);
+        container.data.get(container.offset(index)..end)
+    };
+    match field() {
+        Some(field) => {
+            *out = OvSlice::from_bytes(field);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Get an element of an array. The output borrows from `array`.
+///
+/// # Safety
+///
+/// `array` must point to valid memory, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_get_element(array: OvSlice, index: usize, out: *mut OvSlice) -> bool {
+    let element = || {
+        let container = Container::try_new(array.as_bytes(), BasicType::Array)?;
+        if index >= container.len {
+            return None;
+        }
+        let start = container.offset(index);
+        let end = container.offset(index + 1);
+        container.data.get(start..end)
+    };
+    match element() {
+        Some(element) => {
+            *out = OvSlice::from_bytes(element);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Look up the id of a key in a metadata buffer.
+///
+/// # Safety
+///
+/// `metadata` and `key` must point to valid memory, and `out` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn ov_find_string(metadata: OvSlice, key: OvSlice, out: *mut usize) -> bool {
+    let metadata = metadata.as_bytes();
+    let Ok(key) = core::str::from_utf8(key.as_bytes()) else {
+        return false;
+    };
+    if validate_metadata(metadata).is_err() {
+        return false;
+    }
+    match MetadataRef::new(metadata).find_string(key) {
+        Some(id) => {
+            *out = id;
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::build_metadata;
+    use crate::values::write::{
+        write_decimal, write_f32, write_f64, write_i16, write_i32, write_i64, write_i8,
+        write_string, ArrayBuilder, ObjectBuilder,
+    };
+
+    #[test]
+    fn test_ffi_accessors() {
+        // {"a": [1, "x"]}
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut array_buffer = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut array_buffer, 2);
+        let mut tmp_buffer = Vec::new();
+        write_i64(&mut tmp_buffer, 1);
+        array_builder.append_value(&tmp_buffer);
+        tmp_buffer.clear();
+        write_string(&mut tmp_buffer, "x");
+        array_builder.append_value(&tmp_buffer);
+        array_builder.finish();
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 1);
+        object_builder.append_value("a", &array_buffer).unwrap();
+        object_builder.finish();
+
+        unsafe {
+            let value = OvSlice::from_bytes(&buffer);
+            assert_eq!(ov_basic_type(value), BasicType::Object as i32);
+            assert_eq!(ov_primitive_type_id(value), -1);
+            assert_eq!(ov_basic_type(OvSlice::from_bytes(&[])), -1);
+
+            let mut field_id = 0;
+            let metadata = OvSlice::from_bytes(&metadata);
+            assert!(ov_find_string(
+                metadata,
+                OvSlice::from_bytes(b"a"),
+                &mut field_id
+            ));
+            assert!(!ov_find_string(
+                metadata,
+                OvSlice::from_bytes(b"c"),
+                &mut field_id
+            ));
+
+            let mut field = OvSlice::from_bytes(&[]);
+            assert!(ov_get_field(metadata, value, field_id, &mut field));
+            assert!(!ov_get_field(metadata, value, 1, &mut field));
+            assert_eq!(ov_basic_type(field), BasicType::Array as i32);
+
+            let mut element = OvSlice::from_bytes(&[]);
+            assert!(ov_get_element(field, 0, &mut element));
+            let mut int = 0;
+            assert!(ov_get_i64(element, &mut int));
+            assert_eq!(int, 1);
+            let mut float = 0.0;
+            assert!(ov_get_f64(element, &mut float));
+            assert_eq!(float, 1.0);
+            let mut boolean = false;
+            assert!(!ov_get_bool(element, &mut boolean));
+
+            assert!(ov_get_element(field, 1, &mut element));
+            let mut string = OvSlice::from_bytes(&[]);
+            assert!(ov_get_string(element, &mut string));
+            assert_eq!(string.as_bytes(), b"x");
+            assert!(!ov_get_element(field, 2, &mut element));
+        }
+    }
+
+    #[test]
+    fn test_ffi_numbers() {
+        let get = |write: &dyn Fn(&mut Vec<u8>)| {
+            let mut buffer = Vec::new();
+            write(&mut buffer);
+            let value = OvSlice::from_bytes(&buffer);
+            let (mut int, mut float) = (0, 0.0);
+            unsafe {
+                (
+                    ov_get_i64(value, &mut int).then_some(int),
+                    ov_get_f64(value, &mut float).then_some(float),
+                )
+            }
+        };
+        assert_eq!(get(&|b| write_i8(b, -8)), (Some(-8), Some(-8.0)));
+        assert_eq!(get(&|b| write_i16(b, -16)), (Some(-16), Some(-16.0)));
+        assert_eq!(get(&|b| write_i32(b, 32)), (Some(32), Some(32.0)));
+        assert_eq!(get(&|b| write_i64(b, 64)), (Some(64), Some(64.0)));
+        assert_eq!(get(&|b| write_f32(b, 2.0)), (Some(2), Some(2.0)));
+        assert_eq!(get(&|b| write_f32(b, 2.5)), (None, Some(2.5)));
+        assert_eq!(get(&|b| write_f64(b, 1e300)), (None, Some(1e300)));
+        assert_eq!(get(&|b| write_decimal(b, 150, 1)), (Some(15), Some(15.0)));
+        assert_eq!(get(&|b| write_decimal(b, 15, 1)), (None, Some(1.5)));
+        assert_eq!(
+            get(&|b| write_decimal(b, i64::MAX as i128 * 1000, 3)),
+            (Some(i64::MAX), Some(i64::MAX as f64))
+        );
+        assert_eq!(get(&|b| write_string(b, "1")), (None, None));
+    }
+
+    #[test]
+    fn test_ffi_invalid_primitives() {
+        // A primitive with an unknown type id, and an i64 missing bytes.
+        let unknown = [63 << 2];
+        let mut truncated = Vec::new();
+        write_i64(&mut truncated, 1);
+        truncated.truncate(5);
+
+        unsafe {
+            let unknown = OvSlice::from_bytes(&unknown);
+            assert_eq!(ov_basic_type(unknown), BasicType::Primitive as i32);
+            assert_eq!(ov_primitive_type_id(unknown), -1);
+            let mut boolean = false;
+            assert!(!ov_get_bool(unknown, &mut boolean));
+            let mut int = 0;
+            assert!(!ov_get_i64(unknown, &mut int));
+            let mut float = 0.0;
+            assert!(!ov_get_f64(unknown, &mut float));
+            let mut string = OvSlice::from_bytes(&[]);
+            assert!(!ov_get_string(unknown, &mut string));
+
+            let truncated = OvSlice::from_bytes(&truncated);
+            assert_eq!(
+                ov_primitive_type_id(truncated),
+                PrimitiveTypeId::Int64 as i32
+            );
+            assert!(!ov_get_i64(truncated, &mut int));
+            assert!(!ov_get_f64(truncated, &mut float));
+
+            // Strings shorter than their length.
+            let mut string = OvSlice::from_bytes(&[]);
+            let short = [(3 << 2) | BasicType::ShortString as u8, b'a'];
+            assert!(!ov_get_string(OvSlice::from_bytes(&short), &mut string));
+            let mut long = Vec::new();
+            write_string(&mut long, &"x".repeat(100));
+            for len in [3, long.len() - 1] {
+                assert!(!ov_get_string(
+                    OvSlice::from_bytes(&long[..len]),
+                    &mut string
+                ));
+            }
+            assert!(ov_get_string(OvSlice::from_bytes(&long), &mut string));
+            assert_eq!(string.len, 100);
+        }
+    }
+
+    #[test]
+    fn test_ffi_truncated_containers() {
+        // {"a": 1, "b": 2} and [1, 2].
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut object = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut object, &metadata_ref, 2);
+        object_builder.append_i64("a", 1).unwrap();
+        object_builder.append_i64("b", 2).unwrap();
+        object_builder.finish();
+        let mut array = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut array, 2);
+        for value in [1, 2] {
+            let mut element = Vec::new();
+            write_i64(&mut element, value);
+            array_builder.append_value(&element);
+        }
+        array_builder.finish();
+
+        unsafe {
+            let metadata = OvSlice::from_bytes(&metadata);
+            let mut out = OvSlice::from_bytes(&[]);
+            // Every prefix cuts off the header, field ids, offsets or data.
+            for len in 0..object.len() {
+                let object = OvSlice::from_bytes(&object[..len]);
+                assert!(!ov_get_field(metadata, object, 0, &mut out));
+                assert!(!ov_get_field(metadata, object, 1, &mut out));
+            }
+            for len in 0..array.len() {
+                let array = OvSlice::from_bytes(&array[..len]);
+                assert!(!ov_get_element(array, 0, &mut out));
+                assert!(!ov_get_element(array, 1, &mut out));
+            }
+            assert!(ov_get_field(
+                metadata,
+                OvSlice::from_bytes(&object),
+                1,
+                &mut out
+            ));
+            assert!(ov_get_element(OvSlice::from_bytes(&array), 1, &mut out));
+
+            // An offset past the end of the data. The fields of an object are
+            // stored after a 1-byte header, a 1-byte count, 2 field ids and 3
+            // offsets.
+            let mut bad_offset = object.clone();
+            bad_offset[4] = 0xFF;
+            let bad_offset = OvSlice::from_bytes(&bad_offset);
+            assert!(!ov_get_field(metadata, bad_offset, 0, &mut out));
+            // Array offsets that decrease.
+            let mut bad_offsets = array.clone();
+            bad_offsets[3] = bad_offsets[4] + 1;
+            let bad_offsets = OvSlice::from_bytes(&bad_offsets);
+            assert!(!ov_get_element(bad_offsets, 1, &mut out));
+            // A large array whose count is cut off.
+            let large = [(1 << 4) | BasicType::Array as u8, 1, 0];
+            assert!(!ov_get_element(OvSlice::from_bytes(&large), 0, &mut out));
+
+            // Malformed metadata.
+            let mut id = 0;
+            let truncated = OvSlice::from_bytes(&metadata.as_bytes()[..2]);
+            assert!(!ov_find_string(
+                truncated,
+                OvSlice::from_bytes(b"a"),
+                &mut id
+            ));
+            let object = OvSlice::from_bytes(&object);
+            assert!(!ov_get_field(OvSlice::from_bytes(&[]), object, 0, &mut out));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_ffi_unsorted_metadata() {
+        // {"zebra": 1, "apple": 2, "mango": 3}, with ids in insertion order.
+        let mut metadata_builder = crate::metadata::StreamingMetadataBuilder::new();
+        for key in ["zebra", "apple", "mango"] {
+            metadata_builder.get_or_insert(key);
+        }
+        let metadata = metadata_builder.build();
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut object = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut object, &metadata_ref, 3);
+        for (key, value) in [("zebra", 1), ("apple", 2), ("mango", 3)] {
+            object_builder.append_i64(key, value).unwrap();
+        }
+        object_builder.finish();
+
+        unsafe {
+            let metadata = OvSlice::from_bytes(&metadata);
+            let object = OvSlice::from_bytes(&object);
+            for (key, expected) in [("zebra", 1), ("apple", 2), ("mango", 3)] {
+                let mut field_id = 0;
+                assert!(ov_find_string(
+                    metadata,
+                    OvSlice::from_bytes(key.as_bytes()),
+                    &mut field_id
+                ));
+                let mut field = OvSlice::from_bytes(&[]);
+                assert!(ov_get_field(metadata, object, field_id, &mut field));
+                let mut int = 0;
+                assert!(ov_get_i64(field, &mut int));
+                assert_eq!(int, expected);
+            }
+        }
+    }
+}
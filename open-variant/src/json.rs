@@ -0,0 +1,556 @@
+//! Write variant values as JSON text.
+//!
+//! ```rust
+//! use open_variant::json::{to_json, JsonWriteOptions};
+//! use open_variant::metadata::{build_metadata, MetadataRef};
+//! use open_variant::values::write::ObjectBuilder;
+//! use open_variant::values::VariantRef;
+//!
+//! let metadata = build_metadata(["name", "id"].into_iter());
+//! let metadata = MetadataRef::new(&metadata);
+//! let mut buffer = Vec::new();
+//! let mut builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+//! builder.append_string("name", "café").unwrap();
+//! builder.append_i64("id", 1).unwrap();
+//! builder.finish();
+//!
+//! let variant = VariantRef::try_new(&buffer).unwrap();
+//! let json = to_json(&variant, &metadata, &JsonWriteOptions::default()).unwrap();
+//! assert_eq!(json, r#"{"id":1,"name":"café"}"#);
+//!
+//! let options = JsonWriteOptions {
+//!     ascii_only: true,
+//!     ..Default::default()
+//! };
+//! let json = to_json(&variant, &metadata, &options).unwrap();
+//! assert_eq!(json, r#"{"id":1,"name":"caf\u00e9"}"#);
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::metadata::MetadataRef;
+use crate::values::uuid::format_uuid;
+use crate::values::{BasicType, PrimitiveTypeId, VariantRef};
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Options for writing JSON with [`write_json`].
+#[derive(Debug, Clone, Default)]
+pub struct JsonWriteOptions {
+    /// The order object keys are written in.
+    pub key_order: KeyOrder,
+    /// Escape all non-ASCII characters as `\uXXXX`, for consumers that don't
+    /// accept UTF-8.
+    pub ascii_only: bool,
+    /// How floating point numbers are written.
+    pub float_format: FloatFormat,
+    /// How dates and timestamps are written.
+    pub timestamp_format: TimestampFormat,
+}
+
+/// The order object keys are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyOrder {
+    /// Sorted by key, which is the order fields are stored in.
+    #[default]
+    Sorted,
+    /// In the order of the keys' ids in the metadata dictionary. For metadata
+    /// built incrementally, this is the order keys were first seen in.
+    Dictionary,
+}
+
+/// How floating point numbers are written. Infinities and NaN, which JSON
+/// can't represent, are always written as `null`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatFormat {
+    /// Plain decimal notation, such as `1500000.0`, with the fewest digits
+    /// that read back as the same number.
+    #[default]
+    Decimal,
+    /// Scientific notation, such as `1.5e6`, with the fewest digits that read
+    /// back as the same number.
+    Scientific,
+}
+
+/// How dates and timestamps are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    /// ISO-8601 strings, such as `2024-03-01` and
+    /// `2024-03-01T12:00:00.000000Z`. Timestamps without timezone have no `Z`.
+    #[default]
+    Iso8601,
+    /// Integers counting days for dates, and microseconds (or nanoseconds,
+    /// for nanosecond timestamps) for timestamps, since the Unix epoch.
+    Epoch,
+}
+
+/// Write a value as JSON to a string.
+///
+/// Decimals are written as exact JSON numbers, binary values as base64
+/// strings, and UUIDs in their canonical string form.
+///
+/// # Errors
+///
+/// If a nested value is invalid, or a field id or dictionary string is not
+/// in the metadata.
+pub fn to_json(
+    value: &VariantRef,
+    metadata: &MetadataRef,
+    options: &JsonWriteOptions,
+) -> Result<String, String> {
+    let mut output = String::new();
+    write_json(&mut output, value, metadata, options)?;
+    Ok(output)
+}
+
+/// Append a value as JSON to `output`.
+///
+/// See [`to_json`].
+pub fn write_json(
+    output: &mut String,
+    value: &VariantRef,
+    metadata: &MetadataRef,
+    options: &JsonWriteOptions,
+) -> Result<(), String> {
+    let mut stack: Vec<JsonFrame> = Vec::new();
+    let mut pending = Some(value.clone());
+    loop {
+        if let Some(value) = pending.take() {
+            match value.basic_type() {
+                BasicType::Object => {
+                    let mut fields = value.get_object()?.fields().collect::<Vec<_>>();
+                    if options.key_order == KeyOrder::Dictionary {
+                        fields.sort_unstable_by_key(|(field_id, _)| *field_id);
+                    }
+                    let (keys, children) = fields
+                        .into_iter()
+                        .map(|(field_id, field)| {
+                            metadata
+                                .get_string(field_id)
+                                .map(|key| (key, field))
+                                .ok_or_else(|| {
+                                    format!("Field id {} is not in the metadata", field_id)
+                                })
+                        })
+                        .collect::<Result<(Vec<_>, Vec<_>), _>>()?;
+                    output.push('{');
+                    stack.push(JsonFrame::new(Some(keys), children));
+                }
+                BasicType::Array => {
+                    let array = value.get_array()?;
+                    output.push('[');
+                    stack.push(JsonFrame::new(None, array.elements().collect()));
+                }
+                BasicType::Primitive | BasicType::ShortString => {
+                    write_scalar(output, &value, metadata, options)?
+                }
+            }
+        }
+
+        let Some(frame) = stack.last_mut() else {
+            return Ok(());
+        };
+        if let Some(child) = frame.children.get(frame.next) {
+            if frame.next > 0 {
+                output.push(',');
+            }
+            if let Some(keys) = &frame.keys {
+                write_string(output, keys[frame.next], options);
+                output.push(':');
+            }
+            frame.next += 1;
+            pending = Some(child.clone());
+            continue;
+        }
+
+        // All children are written, so close the container.
+        let frame = stack.pop().unwrap();
+        output.push(if frame.keys.is_some() { '}' } else { ']' });
+    }
+}
+
+/// An object or array being written by [`write_json`].
+struct JsonFrame<'a, 'm> {
+    /// The keys of an object, or `None` for an array.
+    keys: Option<Vec<&'m str>>,
+    children: Vec<VariantRef<'a>>,
+    /// The index of the next child to write.
+    next: usize,
+}
+
+impl<'a, 'm> JsonFrame<'a, 'm> {
+    fn new(keys: Option<Vec<&'m str>>, children: Vec<VariantRef<'a>>) -> Self {
+        Self {
+            keys,
+            children,
+            next: 0,
+        }
+    }
+}
+
+fn write_scalar(
+    output: &mut String,
+    value: &VariantRef,
+    metadata: &MetadataRef,
+    options: &JsonWriteOptions,
+) -> Result<(), String> {
+    if let Some(string) = value.get_str() {
+        write_string(output, string, options);
+        return Ok(());
+    }
+    // The bytes after the header. Primitives without getters are decoded
+    // from these directly.
+    let payload = &value.as_bytes()[1..];
+    let le_i32 = |bytes: &[u8]| i32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let le_i64 = |bytes: &[u8]| i64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let dictionary_string = || {
+        let id = le_i32(payload) as u32 as usize;
+        metadata
+            .get_string(id)
+            .ok_or_else(|| format!("Dictionary id {} is not in the metadata", id))
+    };
+    match value.primitive_type_id() {
+        PrimitiveTypeId::Null => output.push_str("null"),
+        PrimitiveTypeId::BoolTrue => output.push_str("true"),
+        PrimitiveTypeId::BoolFalse => output.push_str("false"),
+        PrimitiveTypeId::Int8 => write!(output, "{}", payload[0] as i8).unwrap(),
+        PrimitiveTypeId::Int16 => write!(
+            output,
+            "{}",
+            i16::from_le_bytes(payload[..2].try_into().unwrap())
+        )
+        .unwrap(),
+        PrimitiveTypeId::Int32 => write!(output, "{}", le_i32(payload)).unwrap(),
+        PrimitiveTypeId::Int64 => write!(output, "{}", value.get_i64()).unwrap(),
+        PrimitiveTypeId::Float32 => {
+            let value = f32::from_le_bytes(payload[..4].try_into().unwrap());
+            write_float(output, value, value.is_finite(), options.float_format)
+        }
+        PrimitiveTypeId::Float64 => {
+            let value = value.get_f64();
+            write_float(output, value, value.is_finite(), options.float_format)
+        }
+        PrimitiveTypeId::Decimal4 => {
+            write_decimal(output, le_i32(&payload[1..]) as i128, payload[0])
+        }
+        PrimitiveTypeId::Decimal8 => {
+            write_decimal(output, le_i64(&payload[1..]) as i128, payload[0])
+        }
+        PrimitiveTypeId::Decimal16 => write_decimal(output, value.get_i128(), payload[0]),
+        PrimitiveTypeId::Date32 => {
+            let days = le_i32(payload) as i64;
+            match options.timestamp_format {
+                TimestampFormat::Iso8601 => {
+                    output.push('"');
+                    write_date(output, days);
+                    output.push('"');
+                }
+                TimestampFormat::Epoch => write!(output, "{}", days).unwrap(),
+            }
+        }
+        PrimitiveTypeId::TimestampMicro => {
+            write_timestamp(output, le_i64(payload), MICROS_PER_SECOND, true, options)
+        }
+        PrimitiveTypeId::TimestampMicroNTZ => {
+            write_timestamp(output, le_i64(payload), MICROS_PER_SECOND, false, options)
+        }
+        PrimitiveTypeId::TimestampNanoNTZ => {
+            write_timestamp(output, le_i64(payload), NANOS_PER_SECOND, false, options)
+        }
+        PrimitiveTypeId::Binary => {
+            let len = le_i32(payload) as usize;
+            write_base64(output, &payload[4..4 + len]);
+        }
+        PrimitiveTypeId::BinaryFromDictionary => {
+            write_base64(output, dictionary_string()?.as_bytes())
+        }
+        PrimitiveTypeId::StringFromDictionary => {
+            write_string(output, dictionary_string()?, options)
+        }
+        PrimitiveTypeId::Uuid => {
+            output.push('"');
+            output.push_str(&format_uuid(&value.get_uuid()));
+            output.push('"');
+        }
+        PrimitiveTypeId::String => unreachable!("strings are handled above"),
+    }
+    Ok(())
+}
+
+fn write_float(
+    output: &mut String,
+    value: impl core::fmt::Display + core::fmt::LowerExp,
+    is_finite: bool,
+    format: FloatFormat,
+) {
+    if !is_finite {
+        output.push_str("null");
+        return;
+    }
+    let start = output.len();
+    match format {
+        FloatFormat::Decimal => {
+            write!(output, "{}", value).unwrap();
+            // Keep integral floats recognizable as floats.
+            if !output[start..].contains('.') {
+                output.push_str(".0");
+            }
+        }
+        FloatFormat::Scientific => write!(output, "{:e}", value).unwrap(),
+    }
+}
+
+/// Write an unscaled decimal value with `scale` digits after the point.
+fn write_decimal(output: &mut String, unscaled: i128, scale: u8) {
+    if unscaled < 0 {
+        output.push('-');
+    }
+    let digits = format!("{}", unscaled.unsigned_abs());
+    let scale = scale as usize;
+    if scale == 0 {
+        output.push_str(&digits);
+    } else if digits.len() > scale {
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(output, "{}.{}", integer, fraction).unwrap();
+    } else {
+        write!(output, "0.{:0>width$}", digits, width = scale).unwrap();
+    }
+}
+
+/// Write a number of days since the Unix epoch as `YYYY-MM-DD`.
+fn write_date(output: &mut String, days: i64) {
+    // From Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    write!(output, "{:04}-{:02}-{:02}", year, month, day).unwrap();
+}
+
+fn write_timestamp(
+    output: &mut String,
+    value: i64,
+    units_per_second: i64,
+    utc: bool,
+    options: &JsonWriteOptions,
+) {
+    if options.timestamp_format == TimestampFormat::Epoch {
+        write!(output, "{}", value).unwrap();
+        return;
+    }
+    let seconds = value.div_euclid(units_per_second);
+    let fraction = value.rem_euclid(units_per_second);
+    let day_seconds = seconds.rem_euclid(SECONDS_PER_DAY);
+    output.push('"');
+    write_date(output, seconds.div_euclid(SECONDS_PER_DAY));
+    let digits = if units_per_second == NANOS_PER_SECOND {
+        9
+    } else {
+        6
+    };
+    write!(
+        output,
+        "T{:02}:{:02}:{:02}.{:0width$}",
+        day_seconds / 3600,
+        day_seconds / 60 % 60,
+        day_seconds % 60,
+        fraction,
+        width = digits
+    )
+    .unwrap();
+    if utc {
+        output.push('Z');
+    }
+    output.push('"');
+}
+
+/// Write a JSON string, escaping as needed.
+fn write_string(output: &mut String, value: &str, options: &JsonWriteOptions) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\u{08}' => output.push_str("\\b"),
+            '\u{0c}' => output.push_str("\\f"),
+            c if (c as u32) < 0x20 || (options.ascii_only && !c.is_ascii()) => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    write!(output, "\\u{:04x}", unit).unwrap();
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+/// Write bytes as a standard base64 string, with padding.
+fn write_base64(output: &mut String, bytes: &[u8]) {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    output.push('"');
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::build_metadata;
+    #[cfg(feature = "std")]
+    use crate::metadata::StreamingMetadataBuilder;
+    use crate::values::write::{
+        write_decimal, write_f64, write_null, write_timestamp_nanos_ntz, write_uuid, ArrayBuilder,
+        ObjectBuilder,
+    };
+
+    fn scalar_json(write: impl FnOnce(&mut Vec<u8>), options: &JsonWriteOptions) -> String {
+        let metadata = build_metadata(core::iter::empty());
+        let metadata = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        write(&mut buffer);
+        to_json(&VariantRef::try_new(&buffer).unwrap(), &metadata, options).unwrap()
+    }
+
+    #[test]
+    fn test_scalars() {
+        let options = JsonWriteOptions::default();
+        let json = |write: &dyn Fn(&mut Vec<u8>)| scalar_json(write, &options);
+        assert_eq!(json(&write_null), "null");
+        assert_eq!(json(&|buffer| write_decimal(buffer, 12345, 2)), "123.45");
+        assert_eq!(json(&|buffer| write_decimal(buffer, -5, 3)), "-0.005");
+        assert_eq!(json(&|buffer| write_decimal(buffer, 7, 0)), "7");
+        assert_eq!(json(&|buffer| write_f64(buffer, 1.5e6)), "1500000.0");
+        assert_eq!(json(&|buffer| write_f64(buffer, f64::NAN)), "null");
+        assert_eq!(
+            json(&|buffer| write_timestamp_nanos_ntz(buffer, -1)),
+            r#""1969-12-31T23:59:59.999999999""#
+        );
+        assert_eq!(
+            json(&|buffer| write_uuid(buffer, &[0xab; 16])),
+            r#""abababab-abab-abab-abab-abababababab""#
+        );
+        assert_eq!(
+            json(&|buffer| crate::values::write::write_string(buffer, "a\"\\\n\u{1}é😀")),
+            "\"a\\\"\\\\\\n\\u0001é😀\""
+        );
+
+        let options = JsonWriteOptions {
+            ascii_only: true,
+            float_format: FloatFormat::Scientific,
+            timestamp_format: TimestampFormat::Epoch,
+            ..Default::default()
+        };
+        let json = |write: &dyn Fn(&mut Vec<u8>)| scalar_json(write, &options);
+        assert_eq!(json(&|buffer| write_f64(buffer, 1.5e6)), "1.5e6");
+        assert_eq!(json(&|buffer| write_timestamp_nanos_ntz(buffer, -1)), "-1");
+        assert_eq!(
+            json(&|buffer| crate::values::write::write_string(buffer, "é😀")),
+            r#""\u00e9\ud83d\ude00""#
+        );
+    }
+
+    #[test]
+    fn test_dates_and_base64() {
+        for (days, date) in [
+            (0, "1970-01-01"),
+            (-1, "1969-12-31"),
+            (19_783, "2024-03-01"),
+            (11_016, "2000-02-29"),
+        ] {
+            let mut output = String::new();
+            write_date(&mut output, days);
+            assert_eq!(output, date);
+        }
+        for (bytes, encoded) in [
+            (&b""[..], r#""""#),
+            (b"f", r#""Zg==""#),
+            (b"fo", r#""Zm8=""#),
+            (b"foo", r#""Zm9v""#),
+            (b"foobar", r#""Zm9vYmFy""#),
+        ] {
+            let mut output = String::new();
+            write_base64(&mut output, bytes);
+            assert_eq!(output, encoded);
+        }
+    }
+
+    #[test]
+    fn test_nested() {
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let mut array = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut array, 2);
+        let mut empty = Vec::new();
+        ArrayBuilder::new(&mut empty, 0).finish();
+        array_builder.append_value(&empty);
+        let mut null = Vec::new();
+        write_null(&mut null);
+        array_builder.append_value(&null);
+        array_builder.finish();
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+        object_builder.append_value("b", &array).unwrap();
+        object_builder.append_i64("a", 1).unwrap();
+        object_builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let json = to_json(&variant, &metadata, &JsonWriteOptions::default()).unwrap();
+        assert_eq!(json, r#"{"a":1,"b":[[],null]}"#);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_key_order() {
+        let mut metadata_builder = StreamingMetadataBuilder::new();
+        for key in ["zebra", "apple"] {
+            metadata_builder.get_or_insert(key);
+        }
+        let metadata = metadata_builder.build();
+        let metadata = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+        object_builder.append_i64("zebra", 1).unwrap();
+        object_builder.append_i64("apple", 2).unwrap();
+        object_builder.finish();
+        let variant = VariantRef::try_new(&buffer).unwrap();
+
+        let json = to_json(&variant, &metadata, &JsonWriteOptions::default()).unwrap();
+        assert_eq!(json, r#"{"apple":2,"zebra":1}"#);
+        let options = JsonWriteOptions {
+            key_order: KeyOrder::Dictionary,
+            ..Default::default()
+        };
+        let json = to_json(&variant, &metadata, &options).unwrap();
+        assert_eq!(json, r#"{"zebra":1,"apple":2}"#);
+    }
+}
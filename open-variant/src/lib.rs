@@ -1,4 +1,25 @@
 #![doc = include_str!("../README.md")]
+//!
+//! # Nesting
+//!
+//! Values can be nested arbitrarily deep, and often come from untrusted
+//! data. Every function that walks nested values, in this crate and in
+//! `arrow-open-variant`, does so with an explicit stack rather than
+//! recursion, so deeply nested values can't overflow the call stack.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod canonical;
+pub mod coerce;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod json;
 pub mod metadata;
+pub mod numeric;
+pub mod path;
+pub mod project;
+pub mod shape;
 mod utils;
+pub mod validate;
 pub mod values;
@@ -22,7 +22,12 @@
 //! assert_eq!(metadata.find_string("carrot"), Some(2));
 //! ```
 
-use std::collections::BTreeSet;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use alloc::{string::String, vec};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 
 /// Build the metadata buffer.
 ///
@@ -30,10 +35,15 @@ use std::collections::BTreeSet;
 /// the dictionary of strings.
 pub fn build_metadata<'a>(string_iter: impl Iterator<Item = &'a str>) -> Vec<u8> {
     let strings: BTreeSet<&str> = string_iter.collect();
+    write_metadata(&strings.into_iter().collect::<Vec<_>>(), true)
+}
+
+fn write_metadata(strings: &[&str], sorted_strings: bool) -> Vec<u8> {
     // https://github.com/apache/spark/tree/master/common/variant#metadata-encoding
     let total_buffer_size = strings.iter().map(|s| s.len()).sum::<usize>();
-    // The largest offset is the total buffer size.
-    let offset_size = crate::utils::determine_byte_width(total_buffer_size);
+    // The largest offset is the total buffer size, and the dictionary size is
+    // written with the same width.
+    let offset_size = crate::utils::determine_byte_width(total_buffer_size.max(strings.len()));
     // <header> <dictionary_size> <offsets> <data>
     let mut capacity = 1; // header byte
     capacity += offset_size as usize * (2 + strings.len()); // dictionary_size, n + 1 offsets
@@ -49,37 +59,134 @@ pub fn build_metadata<'a>(string_iter: impl Iterator<Item = &'a str>) -> Vec<u8>
     //      |         +-- sorted_strings
     //      +-- offset_size_minus_one
     let version: u8 = 1; // version
-    let sorted_strings = 1; // Hardcoded to 1 for now, since we always sort
+    let sorted_strings = sorted_strings as u8;
     let offset_size_minus_one = offset_size - 1;
     let header = version | (sorted_strings << 4) | (offset_size_minus_one << 6);
     output.push(header);
 
     // Dictionary size
-    let push_offset = |output: &mut Vec<u8>, offset: usize| match offset_size {
-        1 => output.extend_from_slice(&(offset as i8).to_le_bytes()),
-        2 => output.extend_from_slice(&(offset as i16).to_le_bytes()),
-        4 => output.extend_from_slice(&(offset as i32).to_le_bytes()),
-        8 => output.extend_from_slice(&(offset as i64).to_le_bytes()),
-        _ => unreachable!(),
+    let push_offset = |output: &mut Vec<u8>, offset: usize| {
+        crate::utils::write_integer(output, offset, offset_size)
     };
     push_offset(&mut output, strings.len());
 
     // Offsets
     let mut offset = 0;
     push_offset(&mut output, offset); // Always starts with 0
-    for s in &strings {
+    for s in strings {
         offset += s.len();
         push_offset(&mut output, offset);
     }
 
     // String data
-    for s in &strings {
+    for s in strings {
         output.extend_from_slice(s.as_bytes());
     }
 
     output
 }
 
+/// Builds metadata incrementally, for writers that see keys over time.
+///
+/// Requires the `std` feature.
+///
+/// Ids are assigned in the order keys are first inserted, so ids already used
+/// to write values never change as more keys are added. The resulting
+/// dictionary is unsorted, which makes key lookups slower for readers. Use
+/// [`StreamingMetadataBuilder::build_sorted`] to convert to the sorted form,
+/// for example when sealing a file.
+///
+/// ```rust
+/// use open_variant::metadata::{MetadataRef, StreamingMetadataBuilder};
+///
+/// let mut builder = StreamingMetadataBuilder::new();
+/// assert_eq!(builder.get_or_insert("zebra"), 0);
+/// assert_eq!(builder.get_or_insert("apple"), 1);
+/// assert_eq!(builder.get_or_insert("zebra"), 0);
+///
+/// let metadata = builder.build();
+/// let metadata = MetadataRef::new(&metadata);
+/// assert!(!metadata.sorted_strings());
+/// assert_eq!(metadata.find_string("apple"), Some(1));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct StreamingMetadataBuilder {
+    ids: HashMap<String, usize>,
+    strings: Vec<String>,
+    // Number of strings already returned by `take_delta`.
+    emitted: usize,
+}
+
+#[cfg(feature = "std")]
+impl StreamingMetadataBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the id of a key, adding it to the dictionary if not present.
+    pub fn get_or_insert(&mut self, key: &str) -> usize {
+        if let Some(id) = self.ids.get(key) {
+            return *id;
+        }
+        let id = self.strings.len();
+        self.ids.insert(key.to_string(), id);
+        self.strings.push(key.to_string());
+        id
+    }
+
+    /// Get the id of a key, if present.
+    pub fn get(&self, key: &str) -> Option<usize> {
+        self.ids.get(key).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// The keys inserted since the last call to this method.
+    ///
+    /// The ids of the returned keys are consecutive, starting at
+    /// `self.len() - delta.len()`. This is meant for writers that send
+    /// dictionary deltas rather than the full dictionary each batch.
+    pub fn take_delta(&mut self) -> &[String] {
+        let start = self.emitted;
+        self.emitted = self.strings.len();
+        &self.strings[start..]
+    }
+
+    /// Build the metadata buffer with ids in insertion order.
+    pub fn build(&self) -> Vec<u8> {
+        let strings = self.strings.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        write_metadata(&strings, false)
+    }
+
+    /// Build the sorted metadata buffer.
+    ///
+    /// Also returns a mapping from the ids of this builder to the ids in the
+    /// sorted dictionary. Values written with this builder's ids can be
+    /// converted with [`remap_field_ids`](crate::values::write::remap_field_ids).
+    pub fn build_sorted(&self) -> (Vec<u8>, Vec<usize>) {
+        let mut order = (0..self.strings.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|id| self.strings[*id].as_str());
+
+        let mut mapping = vec![0; self.strings.len()];
+        for (new_id, old_id) in order.iter().enumerate() {
+            mapping[*old_id] = new_id;
+        }
+
+        let strings = order
+            .iter()
+            .map(|id| self.strings[*id].as_str())
+            .collect::<Vec<_>>();
+        (write_metadata(&strings, true), mapping)
+    }
+}
+
 /// A view into the metadata buffer.
 pub struct MetadataRef<'a> {
     header: u8,
@@ -99,7 +206,7 @@ impl<'a> MetadataRef<'a> {
         // if not as long as expected.
         let header = data[0];
         let offset_size = ((header & 0b1100_0000) >> 6) + 1;
-        let dictionary_len = Self::read_integer(data, 1, offset_size);
+        let dictionary_len = crate::utils::read_integer(data, 1, offset_size);
         let offsets_start = 1 + offset_size as usize;
         let offsets_end = offsets_start + offset_size as usize * (dictionary_len + 1);
 
@@ -124,47 +231,34 @@ impl<'a> MetadataRef<'a> {
         self.dictionary_len
     }
 
-    fn read_integer(data: &[u8], offset: usize, byte_width: u8) -> usize {
-        let end = offset + byte_width as usize;
-        let slice = &data[offset..end];
-        match byte_width {
-            1 => i8::from_le_bytes(slice.try_into().unwrap()) as usize,
-            2 => i16::from_le_bytes(slice.try_into().unwrap()) as usize,
-            4 => i32::from_le_bytes(slice.try_into().unwrap()) as usize,
-            8 => i64::from_le_bytes(slice.try_into().unwrap()) as usize,
-            _ => unreachable!(),
-        }
-    }
-
     pub fn get_string<'b>(&'b self, id: usize) -> Option<&'a str> {
         if id >= self.dictionary_len {
             return None;
         }
-        let offset = Self::read_integer(
+        let offset = crate::utils::read_integer(
             self.offsets,
             id * self.offset_size as usize,
             self.offset_size,
         );
-        let next_offset = Self::read_integer(
+        let next_offset = crate::utils::read_integer(
             self.offsets,
             (id + 1) * self.offset_size as usize,
             self.offset_size,
         );
         let data = &self.data[offset..next_offset];
-        Some(std::str::from_utf8(data).expect("Invalid UTF-8"))
+        Some(core::str::from_utf8(data).expect("Invalid UTF-8"))
     }
 
     /// Given a string, return the position / id in the dictionary.
     ///
-    /// This uses binary search if the strings are sorted.
+    /// This uses binary search if the strings are sorted, and a linear scan
+    /// otherwise.
     ///
     /// If the string is not found, it returns `None`.
     pub fn find_string(&self, value: &str) -> Option<usize> {
-        // TODO: support unsorted strings
-        assert!(
-            self.sorted_strings(),
-            "Unsorted strings are not supported yet"
-        );
+        if !self.sorted_strings() {
+            return (0..self.dictionary_len()).find(|id| self.get_string(*id) == Some(value));
+        }
         let dict_size = self.dictionary_len();
         if dict_size == 0 {
             return None;
@@ -175,9 +269,10 @@ impl<'a> MetadataRef<'a> {
             let mid = left + (right - left) / 2;
             let mid_str = self.get_string(mid).unwrap();
             match mid_str.cmp(value) {
-                std::cmp::Ordering::Less => left = mid + 1,
-                std::cmp::Ordering::Greater => right = mid - 1,
-                std::cmp::Ordering::Equal => return Some(mid),
+                core::cmp::Ordering::Less => left = mid + 1,
+                core::cmp::Ordering::Greater if mid == 0 => return None,
+                core::cmp::Ordering::Greater => right = mid - 1,
+                core::cmp::Ordering::Equal => return Some(mid),
             }
         }
         None
@@ -220,5 +315,86 @@ mod tests {
         assert_eq!(metadata.find_string("brussel sprouts"), Some(1));
         assert_eq!(metadata.find_string("carrot"), Some(2));
         assert_eq!(metadata.find_string("daikon radish"), None);
+        assert_eq!(metadata.find_string("aardvark"), None);
+    }
+
+    #[test]
+    fn test_build_metadata_unsigned_offsets() {
+        // 240 bytes of strings fit in 1-byte unsigned offsets.
+        let keys = (0..40)
+            .map(|i| alloc::format!("key{:03}", i))
+            .collect::<Vec<_>>();
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata = MetadataRef::new(&metadata);
+        assert_eq!(metadata.offset_size, 1);
+        assert_eq!(metadata.dictionary_len(), 40);
+        for (id, key) in keys.iter().enumerate() {
+            assert_eq!(metadata.get_string(id), Some(key.as_str()));
+            assert_eq!(metadata.find_string(key), Some(id));
+        }
+    }
+
+    #[test]
+    fn test_read_metadata_offset_sizes() {
+        // Other writers can use any offset size from 1 to 4 bytes, including
+        // 3, and offsets above 127 in 1 byte.
+        let keys = (0..60)
+            .map(|i| alloc::format!("k{:02}", i))
+            .collect::<Vec<_>>();
+        for offset_size in 1..=4 {
+            let mut metadata = vec![1 | 1 << 4 | (offset_size - 1) << 6];
+            crate::utils::write_integer(&mut metadata, keys.len(), offset_size);
+            let mut offset = 0;
+            crate::utils::write_integer(&mut metadata, offset, offset_size);
+            for key in &keys {
+                offset += key.len();
+                crate::utils::write_integer(&mut metadata, offset, offset_size);
+            }
+            for key in &keys {
+                metadata.extend_from_slice(key.as_bytes());
+            }
+
+            let metadata = MetadataRef::new(&metadata);
+            assert_eq!(metadata.dictionary_len(), 60);
+            for (id, key) in keys.iter().enumerate() {
+                assert_eq!(metadata.get_string(id), Some(key.as_str()));
+                assert_eq!(metadata.find_string(key), Some(id));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_streaming_metadata() {
+        let mut builder = StreamingMetadataBuilder::new();
+        assert!(builder.is_empty());
+        assert_eq!(builder.get_or_insert("carrot"), 0);
+        assert_eq!(builder.get_or_insert("apple"), 1);
+        assert_eq!(builder.take_delta(), ["carrot", "apple"]);
+        assert!(builder.take_delta().is_empty());
+
+        assert_eq!(builder.get_or_insert("apple"), 1);
+        assert_eq!(builder.get_or_insert("banana"), 2);
+        assert_eq!(builder.get("banana"), Some(2));
+        assert_eq!(builder.get("daikon radish"), None);
+        assert_eq!(builder.len(), 3);
+        assert_eq!(builder.take_delta(), ["banana"]);
+
+        let metadata = builder.build();
+        let metadata = MetadataRef::new(&metadata);
+        assert!(!metadata.sorted_strings());
+        assert_eq!(metadata.dictionary_len(), 3);
+        assert_eq!(metadata.get_string(0), Some("carrot"));
+        assert_eq!(metadata.get_string(2), Some("banana"));
+        assert_eq!(metadata.find_string("apple"), Some(1));
+        assert_eq!(metadata.find_string("daikon radish"), None);
+
+        let (sorted, mapping) = builder.build_sorted();
+        let sorted = MetadataRef::new(&sorted);
+        assert!(sorted.sorted_strings());
+        assert_eq!(mapping, vec![2, 0, 1]);
+        for (old_id, new_id) in mapping.iter().enumerate() {
+            assert_eq!(sorted.get_string(*new_id), metadata.get_string(old_id));
+        }
     }
 }
@@ -0,0 +1,197 @@
+//! Numeric transforms of variant values.
+//!
+//! Numbers are read as in [`coerce`](crate::coerce), so integers, decimals
+//! and floats of any width are supported. Results keep the kind of the
+//! input: integers stay integers, decimals stay decimals and floats stay
+//! floats, written at their widest width. An integer result that doesn't fit
+//! an `i64` is written as a decimal with scale 0.
+
+use alloc::vec::Vec;
+
+use crate::coerce::Number;
+use crate::values::write::{write_decimal, write_f64, write_i64};
+use crate::values::VariantRef;
+
+impl<'a> VariantRef<'a> {
+    /// The encoded absolute value, or `None` if this is not a number.
+    pub fn abs(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::new();
+        match self.number()? {
+            Number::Integer(value) => write_integer(&mut buffer, (value as i128).abs()),
+            Number::Decimal { unscaled, scale } => {
+                write_decimal(&mut buffer, unscaled.abs(), scale)
+            }
+            Number::Float(value) => write_f64(&mut buffer, value.abs()),
+        }
+        Some(buffer)
+    }
+
+    /// The encoded value rounded to `digits` decimal places, with halves
+    /// rounded away from zero, or `None` if this is not a number.
+    ///
+    /// A negative `digits` rounds to tens, hundreds and so on, so rounding
+    /// `1250` to `-2` digits gives `1300`. Decimals keep their scale if it is
+    /// at most `digits`, and are otherwise written with scale `digits`, or 0
+    /// if `digits` is negative.
+    pub fn round(&self, digits: i32) -> Option<Vec<u8>> {
+        let mut buffer = Vec::new();
+        match self.number()? {
+            Number::Integer(value) if digits >= 0 => write_i64(&mut buffer, value),
+            Number::Integer(value) => {
+                let exponent = -(digits as i64);
+                let rounded = round_quotient(value as i128, exponent);
+                write_integer(&mut buffer, scale_up(rounded, exponent))
+            }
+            Number::Decimal { unscaled, scale } if digits >= scale as i32 => {
+                write_decimal(&mut buffer, unscaled, scale)
+            }
+            Number::Decimal { unscaled, scale } => {
+                let rounded = round_quotient(unscaled, scale as i64 - digits as i64);
+                match u8::try_from(digits) {
+                    Ok(digits) => write_decimal(&mut buffer, rounded, digits),
+                    Err(_) => write_decimal(&mut buffer, scale_up(rounded, -(digits as i64)), 0),
+                }
+            }
+            Number::Float(value) => write_f64(&mut buffer, round_f64(value, digits)),
+        }
+        Some(buffer)
+    }
+}
+
+/// Divide `value` by `10^exponent`, rounding halves away from zero.
+fn round_quotient(value: i128, exponent: i64) -> i128 {
+    let Some(factor) = pow10(exponent) else {
+        // The divisor is larger than any value, so everything rounds to 0.
+        return 0;
+    };
+    let (quotient, remainder) = (value / factor, value % factor);
+    if remainder.unsigned_abs() * 2 >= factor.unsigned_abs() {
+        quotient + value.signum()
+    } else {
+        quotient
+    }
+}
+
+/// Multiply `value` by `10^exponent`. This only overflows for a quotient of
+/// [`round_quotient`] that is 0, so overflows give 0.
+fn scale_up(value: i128, exponent: i64) -> i128 {
+    pow10(exponent)
+        .and_then(|factor| value.checked_mul(factor))
+        .unwrap_or(0)
+}
+
+fn pow10(exponent: i64) -> Option<i128> {
+    10i128.checked_pow(u32::try_from(exponent).ok()?)
+}
+
+/// Round a float to `digits` decimal places, with halves rounded away from
+/// zero. This avoids `f64::powi` and `f64::round`, which need `std`.
+fn round_f64(value: f64, digits: i32) -> f64 {
+    // Floats have no digits beyond 10^-330, and none above 10^310.
+    if !value.is_finite() || digits > 330 {
+        return value;
+    }
+    if digits < -330 {
+        return 0.0;
+    }
+    let factor = (0..digits.unsigned_abs()).fold(1.0, |factor, _| factor * 10.0);
+    let (scaled, rounded) = if digits >= 0 {
+        let rounded = round_half_away(value * factor);
+        (rounded, rounded / factor)
+    } else {
+        let rounded = round_half_away(value / factor);
+        (rounded, rounded * factor)
+    };
+    if scaled.is_finite() && rounded.is_finite() {
+        rounded
+    } else {
+        value
+    }
+}
+
+/// Round a float to an integer, with halves rounded away from zero.
+fn round_half_away(value: f64) -> f64 {
+    // Floats this large are already integers. `value` is finite.
+    if value.abs() >= (1u64 << 52) as f64 {
+        return value;
+    }
+    let truncated = value as i64 as f64;
+    let fraction = value - truncated;
+    if fraction >= 0.5 {
+        truncated + 1.0
+    } else if fraction <= -0.5 {
+        truncated - 1.0
+    } else {
+        truncated
+    }
+}
+
+/// Write an integer, as a decimal with scale 0 if it doesn't fit an `i64`.
+fn write_integer(buffer: &mut Vec<u8>, value: i128) {
+    match i64::try_from(value) {
+        Ok(value) => write_i64(buffer, value),
+        Err(_) => write_decimal(buffer, value, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::values::write::write_string;
+
+    fn written(write: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn test_abs() {
+        let abs = |value: Vec<u8>| VariantRef::try_new(&value).unwrap().abs();
+        assert_eq!(
+            abs(written(|b| write_i64(b, -3))),
+            Some(written(|b| write_i64(b, 3)))
+        );
+        assert_eq!(
+            abs(written(|b| write_i64(b, i64::MIN))),
+            Some(written(|b| write_decimal(b, 1 << 63, 0)))
+        );
+        assert_eq!(
+            abs(written(|b| write_decimal(b, -125, 2))),
+            Some(written(|b| write_decimal(b, 125, 2)))
+        );
+        assert_eq!(
+            abs(written(|b| write_f64(b, -0.5))),
+            Some(written(|b| write_f64(b, 0.5)))
+        );
+        assert_eq!(abs(written(|b| write_string(b, "-1"))), None);
+    }
+
+    #[test]
+    fn test_round() {
+        let round = |value: Vec<u8>, digits| VariantRef::try_new(&value).unwrap().round(digits);
+        let i64_value = |value| written(|b| write_i64(b, value));
+        let decimal = |value, scale| written(|b| write_decimal(b, value, scale));
+        let f64_value = |value| written(|b| write_f64(b, value));
+
+        assert_eq!(round(i64_value(1250), 0), Some(i64_value(1250)));
+        assert_eq!(round(i64_value(1250), -2), Some(i64_value(1300)));
+        assert_eq!(round(i64_value(-1249), -2), Some(i64_value(-1200)));
+        assert_eq!(round(i64_value(-1250), -2), Some(i64_value(-1300)));
+        assert_eq!(round(i64_value(5), -40), Some(i64_value(0)));
+
+        assert_eq!(round(decimal(12345, 3), 5), Some(decimal(12345, 3)));
+        assert_eq!(round(decimal(12345, 3), 2), Some(decimal(1235, 2)));
+        assert_eq!(round(decimal(-12345, 3), 0), Some(decimal(-12, 0)));
+        assert_eq!(round(decimal(12345, 3), -1), Some(decimal(10, 0)));
+
+        assert_eq!(round(f64_value(2.5), 0), Some(f64_value(3.0)));
+        assert_eq!(round(f64_value(-1.25), 1), Some(f64_value(-1.3)));
+        assert_eq!(round(f64_value(1234.0), -2), Some(f64_value(1200.0)));
+        assert_eq!(round(f64_value(1.5), 400), Some(f64_value(1.5)));
+
+        assert_eq!(round(vec![0], 0), None);
+    }
+}
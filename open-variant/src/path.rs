@@ -0,0 +1,871 @@
+//! Paths into nested variant values.
+//!
+//! A path is a sequence of object keys and array indices. Paths can be parsed
+//! from strings such as `a.b[0].c`. Keys that contain `.`, `[` or `]` can be
+//! quoted inside brackets, as in `a["b.c"]`. The wildcard `[*]` stands for
+//! every element of an array, and `..` for any number of keys and indices, as
+//! in `..author` or `store..price`.
+//!
+//! ```rust
+//! use open_variant::path::{PathElement, VariantPath};
+//!
+//! let path = VariantPath::parse(r#"items[0]["unit.price"]"#).unwrap();
+//! assert_eq!(
+//!     path.elements(),
+//!     &[
+//!         PathElement::Field("items".into()),
+//!         PathElement::Index(0),
+//!         PathElement::Field("unit.price".into()),
+//!     ]
+//! );
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::metadata::MetadataRef;
+use crate::values::{BasicType, FieldLookup, VariantRef};
+
+/// A single step in a [`VariantPath`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathElement {
+    /// A key in an object.
+    Field(String),
+    /// An index in an array.
+    Index(usize),
+    /// Every element of an array, written `[*]`.
+    Wildcard,
+    /// The value and every value nested in it, written `..` (recursive
+    /// descent in JSONPath).
+    Descendants,
+}
+
+/// A path into a nested variant value.
+///
+/// The empty path refers to the value itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct VariantPath(Vec<PathElement>);
+
+impl VariantPath {
+    pub fn new(elements: Vec<PathElement>) -> Self {
+        Self(elements)
+    }
+
+    /// Parse a path such as `a.b[0]["c.d"]` or `..author`.
+    ///
+    /// # Errors
+    ///
+    /// If the path is malformed, such as having an empty key or an unclosed
+    /// bracket, or ending with `..`.
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let mut elements = Vec::new();
+        let mut rest = path;
+        let mut expect_key = true;
+        while !rest.is_empty() {
+            if let Some(bracketed) = rest.strip_prefix('[') {
+                let (element, remaining) = Self::parse_bracket(bracketed, path)?;
+                elements.push(element);
+                rest = remaining;
+                expect_key = false;
+            } else if let Some(remaining) = rest.strip_prefix("..") {
+                if remaining.is_empty() || remaining.starts_with('.') {
+                    return Err(format!(
+                        "Expected a key or '[' after '..' in path '{}'",
+                        path
+                    ));
+                }
+                elements.push(PathElement::Descendants);
+                rest = remaining;
+                expect_key = true;
+            } else if let Some(remaining) = rest.strip_prefix('.') {
+                if expect_key {
+                    return Err(format!("Empty key in path '{}'", path));
+                }
+                rest = remaining;
+                expect_key = true;
+                if rest.is_empty() {
+                    return Err(format!("Path '{}' ends with '.'", path));
+                }
+            } else {
+                if !expect_key {
+                    return Err(format!("Expected '.' or '[' in path '{}'", path));
+                }
+                let end = rest.find(['.', '[', ']']).unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(format!("Unexpected ']' in path '{}'", path));
+                }
+                elements.push(PathElement::Field(rest[..end].to_string()));
+                rest = &rest[end..];
+                expect_key = false;
+            }
+        }
+        Ok(Self(elements))
+    }
+
+    /// Parse the inside of a bracket, returning the element and the rest of
+    /// the path after the closing bracket.
+    fn parse_bracket<'a>(bracketed: &'a str, path: &str) -> Result<(PathElement, &'a str), String> {
+        if let Some(quoted) = bracketed.strip_prefix('"') {
+            let end = quoted
+                .find("\"]")
+                .ok_or_else(|| format!("Unclosed quoted key in path '{}'", path))?;
+            let key = &quoted[..end];
+            Ok((PathElement::Field(key.to_string()), &quoted[end + 2..]))
+        } else if let Some(rest) = bracketed.strip_prefix("*]") {
+            Ok((PathElement::Wildcard, rest))
+        } else {
+            let end = bracketed
+                .find(']')
+                .ok_or_else(|| format!("Unclosed '[' in path '{}'", path))?;
+            let index = bracketed[..end]
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid array index in path '{}'", path))?;
+            Ok((PathElement::Index(index), &bracketed[end + 1..]))
+        }
+    }
+
+    pub fn elements(&self) -> &[PathElement] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether this path, used as a pattern, matches a concrete path.
+    ///
+    /// Paths match element by element. [`PathElement::Wildcard`] matches any
+    /// index, and `*` in a key matches any run of characters, so
+    /// `users[*].*_email` matches `users[3].work_email`.
+    /// [`PathElement::Descendants`] matches any number of elements, so
+    /// `..email` matches `email` and `users[3].email`.
+    pub fn matches(&self, path: &[PathElement]) -> bool {
+        // Positions in the pattern and the path still to try. Descendants
+        // branch into every number of elements they could match.
+        let mut stack = vec![(0, 0)];
+        while let Some((pattern_index, path_index)) = stack.pop() {
+            match self.0.get(pattern_index) {
+                None if path_index == path.len() => return true,
+                None => {}
+                Some(PathElement::Descendants) => {
+                    stack.extend((path_index..=path.len()).map(|i| (pattern_index + 1, i)))
+                }
+                Some(pattern) => {
+                    let matched =
+                        path.get(path_index)
+                            .is_some_and(|element| match (pattern, element) {
+                                (PathElement::Field(pattern), PathElement::Field(key)) => {
+                                    glob_matches(pattern, key)
+                                }
+                                (PathElement::Wildcard, PathElement::Index(_)) => true,
+                                (pattern, element) => pattern == element,
+                            });
+                    if matched {
+                        stack.push((pattern_index + 1, path_index + 1));
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Whether `key` matches `pattern`, where `*` in the pattern matches any run
+/// of characters.
+fn glob_matches(pattern: &str, key: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == key;
+    };
+    let Some(mut key) = key.strip_prefix(prefix) else {
+        return false;
+    };
+    let mut parts = rest.split('*').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // The last part must be a suffix of what is left.
+            return key.len() >= part.len() && key.ends_with(part);
+        }
+        match key.find(part) {
+            Some(start) => key = &key[start + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+impl Display for VariantPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, element) in self.0.iter().enumerate() {
+            match element {
+                PathElement::Field(key) if key.contains(['.', '[', ']', '"']) || key.is_empty() => {
+                    write!(f, "[\"{}\"]", key)?
+                }
+                PathElement::Field(key) if i == 0 || self.0[i - 1] == PathElement::Descendants => {
+                    write!(f, "{}", key)?
+                }
+                PathElement::Field(key) => write!(f, ".{}", key)?,
+                PathElement::Index(index) => write!(f, "[{}]", index)?,
+                PathElement::Wildcard => write!(f, "[*]")?,
+                PathElement::Descendants => write!(f, "..")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [`VariantPath`] with its keys resolved against one metadata dictionary,
+/// for getting the same path from many values written with that metadata.
+///
+/// Keys are looked up with a [`FieldLookup`] each, so objects with the same
+/// fields as a recent one skip the search for the key.
+#[derive(Debug, Clone)]
+pub struct ResolvedPath {
+    /// `None` if a key is not in the metadata, so the path matches nothing.
+    steps: Option<Vec<ResolvedStep>>,
+}
+
+#[derive(Debug, Clone)]
+enum ResolvedStep {
+    Field(FieldLookup),
+    Index(usize),
+    /// Wildcards and descendants match nothing in [`ResolvedPath::get`].
+    Wildcard,
+    Descendants,
+}
+
+impl ResolvedPath {
+    pub fn new(path: &VariantPath, metadata: &MetadataRef) -> Self {
+        Self::with_field_ids(path, metadata, |key| metadata.find_string(key))
+    }
+
+    /// Resolve the path with `field_id` giving the id of each key in
+    /// `metadata`, such as from a cache of earlier lookups.
+    pub fn with_field_ids(
+        path: &VariantPath,
+        metadata: &MetadataRef,
+        mut field_id: impl FnMut(&str) -> Option<usize>,
+    ) -> Self {
+        let steps = path
+            .elements()
+            .iter()
+            .map(|element| match element {
+                PathElement::Field(key) => field_id(key).map(|field_id| {
+                    ResolvedStep::Field(FieldLookup::with_field_id(field_id, metadata))
+                }),
+                PathElement::Index(index) => Some(ResolvedStep::Index(*index)),
+                PathElement::Wildcard => Some(ResolvedStep::Wildcard),
+                PathElement::Descendants => Some(ResolvedStep::Descendants),
+            })
+            .collect();
+        Self { steps }
+    }
+
+    /// Like [`VariantRef::get_path`], for a value written with the metadata
+    /// the path was resolved against.
+    pub fn get<'a>(&mut self, value: &VariantRef<'a>) -> Option<VariantRef<'a>> {
+        let mut current = value.clone();
+        for step in self.steps.as_mut()? {
+            current = match step {
+                ResolvedStep::Field(lookup) if current.basic_type() == BasicType::Object => {
+                    lookup.get(&current.get_object().ok()?)?
+                }
+                ResolvedStep::Index(index) if current.basic_type() == BasicType::Array => {
+                    current.get_array().ok()?.get_element(*index)?
+                }
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Like [`VariantRef::get_path_all`], for a value written with the
+    /// metadata the path was resolved against.
+    ///
+    /// The elements of an array of objects usually share their fields, so
+    /// with `items[*].name` the position of `name` is found once for each
+    /// distinct set of fields rather than once per element.
+    pub fn get_all<'a>(&mut self, value: &VariantRef<'a>) -> Vec<VariantRef<'a>> {
+        let Some(steps) = self.steps.as_mut() else {
+            return Vec::new();
+        };
+        let mut current = vec![value.clone()];
+        for step in steps {
+            let mut next = Vec::with_capacity(current.len());
+            for value in &current {
+                match (&mut *step, value.basic_type()) {
+                    (ResolvedStep::Field(lookup), BasicType::Object) => {
+                        if let Ok(object) = value.get_object() {
+                            next.extend(lookup.get(&object));
+                        }
+                    }
+                    (ResolvedStep::Index(index), BasicType::Array) => {
+                        if let Ok(array) = value.get_array() {
+                            next.extend(array.get_element(*index));
+                        }
+                    }
+                    (ResolvedStep::Wildcard, BasicType::Array) => {
+                        if let Ok(array) = value.get_array() {
+                            next.extend(array.elements());
+                        }
+                    }
+                    (ResolvedStep::Descendants, _) => value.push_descendants(&mut next),
+                    _ => {}
+                }
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+/// Resolves keys to the ids of the keys in a metadata dictionary that are
+/// equal ignoring case.
+///
+/// Building this reads the whole dictionary, so build one per metadata buffer
+/// and reuse it for every value written with that metadata.
+#[derive(Debug, Clone)]
+pub struct CaseInsensitiveKeys {
+    ids: BTreeMap<String, Vec<usize>>,
+    sorted: bool,
+}
+
+impl CaseInsensitiveKeys {
+    pub fn new(metadata: &MetadataRef) -> Self {
+        let mut ids: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for id in 0..metadata.dictionary_len() {
+            if let Some(key) = metadata.get_string(id) {
+                ids.entry(key.to_lowercase()).or_default().push(id);
+            }
+        }
+        Self {
+            ids,
+            sorted: metadata.sorted_strings(),
+        }
+    }
+
+    /// The ids of the keys equal to `key` ignoring case, in increasing order.
+    pub fn ids(&self, key: &str) -> &[usize] {
+        self.ids
+            .get(&key.to_lowercase())
+            .map_or(&[], |ids| ids.as_slice())
+    }
+}
+
+impl<'a> VariantRef<'a> {
+    /// Get the value at a path.
+    ///
+    /// Returns `None` if any step of the path does not exist, including if a
+    /// key is not in the metadata dictionary, or if a step is applied to a
+    /// value of the wrong type (such as an index applied to an object). Paths
+    /// with wildcards or descendants can match many values, so they also
+    /// return `None`; use [`VariantRef::get_path_all`] for those.
+    pub fn get_path(&self, path: &VariantPath, metadata: &MetadataRef) -> Option<VariantRef<'a>> {
+        let mut current = self.clone();
+        for element in path.elements() {
+            current = current.get_step(element, metadata)?;
+        }
+        Some(current)
+    }
+
+    /// Get every value matching a path, expanding wildcards and descendants.
+    ///
+    /// Values are returned in document order. Branches where a step does not
+    /// exist are skipped, so the result can be empty. With descendants, a
+    /// value can be returned more than once: `..a..b` returns `b` in
+    /// `{"a": {"a": {"b": 1}}}` twice, as JSONPath does.
+    pub fn get_path_all(&self, path: &VariantPath, metadata: &MetadataRef) -> Vec<VariantRef<'a>> {
+        let mut current = vec![self.clone()];
+        for element in path.elements() {
+            let mut next = Vec::with_capacity(current.len());
+            for value in &current {
+                match element {
+                    PathElement::Wildcard if value.basic_type() == BasicType::Array => {
+                        if let Ok(array) = value.get_array() {
+                            next.extend(array.elements());
+                        }
+                    }
+                    PathElement::Descendants => value.push_descendants(&mut next),
+                    _ => next.extend(value.get_step(element, metadata)),
+                }
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// Call `f` with every value nested in this one and its path, starting
+    /// with this value at the empty path.
+    ///
+    /// Array elements are visited at the path of the array followed by
+    /// [`PathElement::Wildcard`], so the elements of different arrays share
+    /// paths.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in the metadata.
+    pub fn visit_paths(
+        &self,
+        metadata: &MetadataRef,
+        mut f: impl FnMut(&[PathElement], &VariantRef<'a>),
+    ) -> Result<(), String> {
+        let mut stack = vec![(Vec::new(), self.clone())];
+        while let Some((path, value)) = stack.pop() {
+            f(&path, &value);
+            let child_path = |element| {
+                let mut child_path = path.clone();
+                child_path.push(element);
+                child_path
+            };
+            match value.basic_type() {
+                BasicType::Object => {
+                    for (field_id, field) in value.get_object()?.fields() {
+                        let key = metadata.get_string(field_id).ok_or_else(|| {
+                            format!("Field id {} is not in the metadata", field_id)
+                        })?;
+                        stack.push((child_path(PathElement::Field(key.to_string())), field));
+                    }
+                }
+                BasicType::Array => {
+                    for element in value.get_array()?.elements() {
+                        stack.push((child_path(PathElement::Wildcard), element));
+                    }
+                }
+                BasicType::Primitive | BasicType::ShortString => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Every leaf nested in this value with its path, in document order.
+    ///
+    /// Leaves are primitives, strings, and empty objects and arrays. Unlike
+    /// [`VariantRef::visit_paths`], array elements have their index in the
+    /// path.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in the metadata.
+    pub fn leaves(
+        &self,
+        metadata: &MetadataRef,
+    ) -> Result<Vec<(VariantPath, VariantRef<'a>)>, String> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![(Vec::new(), self.clone())];
+        while let Some((path, value)) = stack.pop() {
+            let start = stack.len();
+            match value.basic_type() {
+                BasicType::Object => {
+                    for (field_id, field) in value.get_object()?.fields() {
+                        let key = metadata.get_string(field_id).ok_or_else(|| {
+                            format!("Field id {} is not in the metadata", field_id)
+                        })?;
+                        let mut child_path = path.clone();
+                        child_path.push(PathElement::Field(key.to_string()));
+                        stack.push((child_path, field));
+                    }
+                }
+                BasicType::Array => {
+                    for (index, element) in value.get_array()?.elements().enumerate() {
+                        let mut child_path = path.clone();
+                        child_path.push(PathElement::Index(index));
+                        stack.push((child_path, element));
+                    }
+                }
+                BasicType::Primitive | BasicType::ShortString => {}
+            }
+            if stack.len() == start {
+                leaves.push((VariantPath::new(path), value));
+            } else {
+                // Pop the first child first, to keep document order.
+                stack[start..].reverse();
+            }
+        }
+        Ok(leaves)
+    }
+
+    /// Like [`VariantRef::get_path`], but keys are matched ignoring case.
+    ///
+    /// `keys` must be built from the metadata of this value. If an object has
+    /// several fields matching a key, the one with the lowest field id is
+    /// used. Wildcards return `None`.
+    pub fn get_path_case_insensitive(
+        &self,
+        path: &VariantPath,
+        keys: &CaseInsensitiveKeys,
+    ) -> Option<VariantRef<'a>> {
+        let mut current = self.clone();
+        for element in path.elements() {
+            current = match element {
+                PathElement::Field(key) => {
+                    if current.basic_type() != BasicType::Object {
+                        return None;
+                    }
+                    let object = current.get_object().ok()?;
+                    keys.ids(key)
+                        .iter()
+                        .find_map(|id| object.get_field_sorted(*id, keys.sorted))?
+                }
+                PathElement::Index(index) => {
+                    if current.basic_type() != BasicType::Array {
+                        return None;
+                    }
+                    current.get_array().ok()?.get_element(*index)?
+                }
+                PathElement::Wildcard | PathElement::Descendants => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Push this value and every value nested in it, in document order.
+    /// Invalid nested values are skipped.
+    fn push_descendants(&self, output: &mut Vec<VariantRef<'a>>) {
+        let mut stack = vec![self.clone()];
+        while let Some(value) = stack.pop() {
+            let start = stack.len();
+            match value.basic_type() {
+                BasicType::Object => {
+                    if let Ok(object) = value.get_object() {
+                        stack.extend(object.fields().map(|(_, field)| field));
+                    }
+                }
+                BasicType::Array => {
+                    if let Ok(array) = value.get_array() {
+                        stack.extend(array.elements());
+                    }
+                }
+                BasicType::Primitive | BasicType::ShortString => {}
+            }
+            // Pop the first child first, to keep document order.
+            stack[start..].reverse();
+            output.push(value);
+        }
+    }
+
+    /// Apply a single key or index step.
+    fn get_step(&self, element: &PathElement, metadata: &MetadataRef) -> Option<VariantRef<'a>> {
+        match element {
+            PathElement::Field(key) => {
+                if self.basic_type() != BasicType::Object {
+                    return None;
+                }
+                self.get_object().ok()?.find_field(key, metadata)
+            }
+            PathElement::Index(index) => {
+                if self.basic_type() != BasicType::Array {
+                    return None;
+                }
+                self.get_array().ok()?.get_element(*index)
+            }
+            PathElement::Wildcard | PathElement::Descendants => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::build_metadata;
+    use crate::values::write::{write_i64, write_string, ArrayBuilder, ObjectBuilder};
+
+    fn field(key: &str) -> PathElement {
+        PathElement::Field(key.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        let cases = [
+            ("", vec![]),
+            ("a", vec![field("a")]),
+            ("a.b", vec![field("a"), field("b")]),
+            (
+                "a[1].b",
+                vec![field("a"), PathElement::Index(1), field("b")],
+            ),
+            ("[0][2]", vec![PathElement::Index(0), PathElement::Index(2)]),
+            (r#"["a.b"].c"#, vec![field("a.b"), field("c")]),
+            (
+                "a[*].b",
+                vec![field("a"), PathElement::Wildcard, field("b")],
+            ),
+            ("..a", vec![PathElement::Descendants, field("a")]),
+            (
+                "a..b[0]",
+                vec![
+                    field("a"),
+                    PathElement::Descendants,
+                    field("b"),
+                    PathElement::Index(0),
+                ],
+            ),
+            (
+                "a..[*]",
+                vec![field("a"), PathElement::Descendants, PathElement::Wildcard],
+            ),
+        ];
+        for (input, expected) in cases {
+            let path = VariantPath::parse(input).unwrap();
+            assert_eq!(path.elements(), expected.as_slice(), "for '{}'", input);
+            // Display round trips
+            assert_eq!(VariantPath::parse(&path.to_string()).unwrap(), path);
+        }
+
+        for input in [
+            ".a", "a.", "a[", "a[x]", "a]", r#"a["b"#, "a[0]b", "a[*", "a..", "a...b", "..",
+        ] {
+            assert!(VariantPath::parse(input).is_err(), "for '{}'", input);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let parse = |path: &str| VariantPath::parse(path).unwrap();
+        let pattern = parse("users[*].*_email");
+        assert!(pattern.matches(parse("users[3].work_email").elements()));
+        assert!(pattern.matches(parse("users[0]._email").elements()));
+        assert!(!pattern.matches(parse("users[3].email").elements()));
+        assert!(!pattern.matches(parse("users.work_email").elements()));
+        assert!(!pattern.matches(parse("users[3].work_email.x").elements()));
+
+        assert!(parse("a[1]").matches(parse("a[1]").elements()));
+        assert!(!parse("a[1]").matches(parse("a[2]").elements()));
+        assert!(VariantPath::default().matches(&[]));
+
+        let pattern = parse("..email");
+        assert!(pattern.matches(parse("email").elements()));
+        assert!(pattern.matches(parse("users[3].email").elements()));
+        assert!(!pattern.matches(parse("users[3].email.x").elements()));
+        assert!(parse("a..[*]").matches(parse("a.b[0]").elements()));
+        assert!(!parse("a..[*]").matches(parse("a").elements()));
+
+        for (pattern, key, expected) in [
+            ("*", "", true),
+            ("*", "abc", true),
+            ("a*c", "abc", true),
+            ("a*c", "ac", true),
+            ("a*c", "abcd", false),
+            ("*b*", "abc", true),
+            ("a*b*a", "aba", true),
+            ("a*a", "a", false),
+            ("**", "x", true),
+        ] {
+            assert_eq!(glob_matches(pattern, key), expected, "{} {}", pattern, key);
+        }
+    }
+
+    #[test]
+    fn test_resolved_path() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let mut values = Vec::new();
+        for (key, value) in [("a", 1), ("b", 2), ("a", 3)] {
+            // [{key: value, "c": 0}]
+            let mut object = Vec::new();
+            let mut builder = ObjectBuilder::with_capacity(&mut object, &metadata, 2);
+            builder.append_i64(key, value).unwrap();
+            builder.append_i64("c", 0).unwrap();
+            builder.finish();
+            let mut array = Vec::new();
+            let mut builder = ArrayBuilder::new(&mut array, 1);
+            builder.append_value(&object);
+            builder.finish();
+            values.push(array);
+        }
+
+        let paths = [
+            "[0].a", "[0].c", "[1].a", "a", "[0].d", "[*].a", "[*].c", "..c", "",
+        ];
+        for path in paths {
+            let parsed = VariantPath::parse(path).unwrap();
+            let mut resolved = ResolvedPath::new(&parsed, &metadata);
+            for value in &values {
+                let value = VariantRef::try_new(value).unwrap();
+                let expected = value.get_path(&parsed, &metadata);
+                let actual = resolved.get(&value);
+                assert_eq!(
+                    actual.map(|value| value.as_bytes()),
+                    expected.map(|value| value.as_bytes()),
+                    "{}",
+                    path
+                );
+
+                let bytes = |values: Vec<VariantRef>| {
+                    values
+                        .iter()
+                        .map(|value| value.as_bytes().to_vec())
+                        .collect::<Vec<_>>()
+                };
+                let expected = bytes(value.get_path_all(&parsed, &metadata));
+                assert_eq!(bytes(resolved.get_all(&value)), expected, "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaves() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+
+        // {"a": [1, "x", []], "b": {}, "c": 2}
+        let mut array = Vec::new();
+        let mut elements = Vec::new();
+        let mut builder = ArrayBuilder::new(&mut array, 3);
+        write_i64(&mut elements, 1);
+        builder.append_value(&elements);
+        elements.clear();
+        write_string(&mut elements, "x");
+        builder.append_value(&elements);
+        elements.clear();
+        ArrayBuilder::new(&mut elements, 0).finish();
+        builder.append_value(&elements);
+        builder.finish();
+        let mut empty = Vec::new();
+        ObjectBuilder::with_capacity(&mut empty, &metadata, 0).finish();
+        let mut buffer = Vec::new();
+        let mut builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 3);
+        builder.append_value("a", &array).unwrap();
+        builder.append_value("b", &empty).unwrap();
+        builder.append_i64("c", 2).unwrap();
+        builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let leaves = variant.leaves(&metadata).unwrap();
+        let paths = leaves
+            .iter()
+            .map(|(path, value)| (path.to_string(), value.type_name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                ("a[0]".to_string(), "int64"),
+                ("a[1]".to_string(), "string"),
+                ("a[2]".to_string(), "array"),
+                ("b".to_string(), "object"),
+                ("c".to_string(), "int64"),
+            ]
+        );
+
+        let leaves = leaves[4].1.leaves(&metadata).unwrap();
+        assert_eq!(leaves.len(), 1);
+        assert!(leaves[0].0.is_empty());
+    }
+
+    #[test]
+    fn test_get_path_case_insensitive() {
+        let metadata = build_metadata(["Name", "USER", "name"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+
+        let mut user = Vec::new();
+        let mut builder = ObjectBuilder::with_capacity(&mut user, &metadata, 1);
+        builder.append_string("name", "lower").unwrap();
+        builder.finish();
+        let mut buffer = Vec::new();
+        let mut builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 1);
+        builder.append_value("USER", &user).unwrap();
+        builder.finish();
+        let variant = VariantRef::try_new(&buffer).unwrap();
+
+        let keys = CaseInsensitiveKeys::new(&metadata);
+        assert_eq!(keys.ids("NAME").len(), 2);
+        let get = |path: &str| {
+            let path = VariantPath::parse(path).unwrap();
+            variant.get_path_case_insensitive(&path, &keys)
+        };
+        assert_eq!(get("user.NAME").unwrap().get_string(), "lower");
+        assert_eq!(get("User.name").unwrap().get_string(), "lower");
+        assert!(get("user.missing").is_none());
+        assert!(get("user[0]").is_none());
+    }
+
+    #[test]
+    fn test_get_path() {
+        // {"a": {"b": [1, "x"]}, "c": 2}
+        let metadata = build_metadata(["a", "b", "c", "d"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+
+        let mut array_buffer = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut array_buffer, 2);
+        let mut tmp_buffer = Vec::new();
+        write_i64(&mut tmp_buffer, 1);
+        array_builder.append_value(&tmp_buffer);
+        tmp_buffer.clear();
+        write_string(&mut tmp_buffer, "x");
+        array_builder.append_value(&tmp_buffer);
+        array_builder.finish();
+
+        let mut inner_buffer = Vec::new();
+        let mut inner = ObjectBuilder::with_capacity(&mut inner_buffer, &metadata, 1);
+        inner.append_value("b", &array_buffer).unwrap();
+        inner.finish();
+
+        let mut buffer = Vec::new();
+        let mut outer = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+        outer.append_value("a", &inner_buffer).unwrap();
+        outer.append_i64("c", 2).unwrap();
+        outer.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let get = |path: &str| variant.get_path(&VariantPath::parse(path).unwrap(), &metadata);
+
+        assert_eq!(get("").unwrap().basic_type(), BasicType::Object);
+        assert_eq!(get("c").unwrap().get_i64(), 2);
+        assert_eq!(get("a.b[0]").unwrap().get_i64(), 1);
+        assert_eq!(get("a.b[1]").unwrap().get_string(), "x");
+        assert!(get("a.b[2]").is_none());
+        // Key in dictionary, but not in object
+        assert!(get("d").is_none());
+        // Key not in dictionary
+        assert!(get("e").is_none());
+        // Wrong container types
+        assert!(get("[0]").is_none());
+        assert!(get("a.b.c").is_none());
+        assert!(get("c.d").is_none());
+        assert!(get("a.b[*]").is_none());
+
+        let get_all = |path: &str| {
+            let path = VariantPath::parse(path).unwrap();
+            let values = variant.get_path_all(&path, &metadata);
+            values
+                .iter()
+                .map(|value| value.type_name())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(get_all("a.b[*]"), vec!["int64", "string"]);
+        assert_eq!(get_all("a.b[0]"), vec!["int64"]);
+        assert_eq!(get_all("c"), vec!["int64"]);
+        assert_eq!(get_all(""), vec!["object"]);
+        assert!(get_all("a.b[*].c").is_empty());
+        assert!(get_all("a[*]").is_empty());
+        assert!(get_all("e[*]").is_empty());
+        assert_eq!(get_all("..c"), vec!["int64"]);
+        assert_eq!(get_all("..b"), vec!["array"]);
+        assert_eq!(get_all("..[*]"), vec!["int64", "string"]);
+        assert_eq!(get_all("a..[1]"), vec!["string"]);
+        assert!(get_all("..d").is_empty());
+        assert!(get("..c").is_none());
+
+        let mut paths = Vec::new();
+        variant
+            .visit_paths(&metadata, |path, value| {
+                let path = VariantPath::new(path.to_vec()).to_string();
+                paths.push((path, value.type_name()));
+            })
+            .unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                ("".to_string(), "object"),
+                ("a".to_string(), "object"),
+                ("a.b".to_string(), "array"),
+                ("a.b[*]".to_string(), "int64"),
+                ("a.b[*]".to_string(), "string"),
+                ("c".to_string(), "int64"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Copy only the parts of a value at some paths.
+//!
+//! Projecting a value keeps the values at the given paths and the objects and
+//! arrays leading to them, and drops everything else. The result gets its
+//! own metadata with only the keys it uses, so it is small enough to carry
+//! through a query in place of the full value.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::metadata::MetadataRef;
+use crate::path::{PathElement, VariantPath};
+use crate::values::write::{write_null, ArrayBuilder, ObjectBuilder};
+use crate::values::{BasicType, VariantRef};
+
+impl<'a> VariantRef<'a> {
+    /// Copy the values at `paths` into a new value and metadata.
+    ///
+    /// Objects keep only the fields on the way to a path, and arrays keep
+    /// their length with variant nulls in place of elements not on a path,
+    /// so indexes still refer to the same elements. Values at the end of a
+    /// path are copied whole. Paths may use wildcards and descendants, and
+    /// keys are matched exactly, as in [`VariantRef::get_path_all`]. If no
+    /// path exists in the value, the result is a variant null.
+    ///
+    /// Returns the metadata and the value. The metadata is sorted and only
+    /// has the keys of the projected value.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in the metadata.
+    pub fn project(
+        &self,
+        paths: &[VariantPath],
+        metadata: &MetadataRef,
+    ) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let suffixes = paths.iter().map(|path| path.elements()).collect();
+        let mut projected = Vec::new();
+        if !project_value(&mut projected, self, suffixes, metadata)? {
+            write_null(&mut projected);
+        }
+        // The projection still uses the ids of the original metadata, so
+        // move it to a dictionary of only the keys it uses.
+        VariantRef::try_new(&projected)?.canonicalize(metadata)
+    }
+}
+
+/// Write the projection of `value` to `buffer`, where `suffixes` are the
+/// rest of the paths that reached it. Returns false, without writing
+/// anything, if none of the paths exist in the value.
+fn project_value<'a, 'p>(
+    buffer: &mut Vec<u8>,
+    value: &VariantRef<'a>,
+    suffixes: Vec<&'p [PathElement]>,
+    metadata: &MetadataRef,
+) -> Result<bool, String> {
+    let mut stack: Vec<ProjectFrame<'a, 'p>> = Vec::new();
+    let mut pending = Some((value.clone(), suffixes));
+    loop {
+        // Whether the last value finished was found, once there is one.
+        let mut found = None;
+        if let Some((value, suffixes)) = pending.take() {
+            match ProjectFrame::visit(&value, suffixes, metadata)? {
+                Visit::Keep => {
+                    let target = stack
+                        .last_mut()
+                        .map_or(&mut *buffer, |frame| &mut frame.buffer);
+                    target.extend_from_slice(value.as_bytes());
+                    found = Some(true);
+                }
+                Visit::Missing => found = Some(false),
+                Visit::Descend(frame) => stack.push(frame),
+            }
+        }
+
+        loop {
+            if let Some(found) = found.take() {
+                match stack.last_mut() {
+                    Some(frame) => {
+                        let end = frame.buffer.len();
+                        frame.ends.push(found.then_some(end));
+                    }
+                    None => return Ok(found),
+                }
+            }
+
+            let frame = stack
+                .last_mut()
+                .expect("stack is empty only after the top-level value");
+            if let Some((child, suffixes)) = frame.children.get_mut(frame.ends.len()) {
+                pending = Some((child.clone(), core::mem::take(suffixes)));
+                break;
+            }
+
+            // All children are projected, so close the container.
+            let frame = stack.pop().unwrap();
+            let target = stack
+                .last_mut()
+                .map_or(&mut *buffer, |parent| &mut parent.buffer);
+            found = Some(frame.finish(target, metadata));
+        }
+    }
+}
+
+/// What to do with a value reached by [`project_value`].
+enum Visit<'a, 'p> {
+    /// A path ends at the value, so copy it whole.
+    Keep,
+    /// None of the paths exist in the value.
+    Missing,
+    /// Paths continue into the children of the value.
+    Descend(ProjectFrame<'a, 'p>),
+}
+
+/// An object or array being projected by [`project_value`].
+struct ProjectFrame<'a, 'p> {
+    /// The field ids of the children of an object, or `None` for an array.
+    field_ids: Option<Vec<usize>>,
+    /// The children to project, with the rest of the paths that reach them.
+    children: Vec<(VariantRef<'a>, Vec<&'p [PathElement]>)>,
+    /// The projected children so far, concatenated.
+    buffer: Vec<u8>,
+    /// The end of each child in `buffer`, or `None` if it is missing.
+    ends: Vec<Option<usize>>,
+}
+
+impl<'a, 'p> ProjectFrame<'a, 'p> {
+    fn visit(
+        value: &VariantRef<'a>,
+        suffixes: Vec<&'p [PathElement]>,
+        metadata: &MetadataRef,
+    ) -> Result<Visit<'a, 'p>, String> {
+        // Descendants also match the value itself, so they apply both here
+        // and, unchanged, to every child.
+        let mut expanded = Vec::with_capacity(suffixes.len());
+        for mut suffix in suffixes {
+            expanded.push(suffix);
+            while let Some((PathElement::Descendants, rest)) = suffix.split_first() {
+                expanded.push(rest);
+                suffix = rest;
+            }
+        }
+        if expanded.iter().any(|suffix| suffix.is_empty()) {
+            return Ok(Visit::Keep);
+        }
+
+        let step = |suffix: &'p [PathElement], matches: &dyn Fn(&PathElement) -> bool| match suffix
+            .split_first()
+        {
+            Some((PathElement::Descendants, _)) => Some(suffix),
+            Some((element, rest)) if matches(element) => Some(rest),
+            _ => None,
+        };
+        let frame = match value.basic_type() {
+            BasicType::Object => {
+                let mut field_ids = Vec::new();
+                let mut children = Vec::new();
+                for (field_id, field) in value.get_object()?.fields() {
+                    let key = metadata
+                        .get_string(field_id)
+                        .ok_or_else(|| format!("Field id {} is not in the metadata", field_id))?;
+                    let matches = |element: &PathElement| matches!(element, PathElement::Field(field_key) if field_key == key);
+                    let child_suffixes = expanded
+                        .iter()
+                        .filter_map(|suffix| step(suffix, &matches))
+                        .collect::<Vec<_>>();
+                    if !child_suffixes.is_empty() {
+                        field_ids.push(field_id);
+                        children.push((field, child_suffixes));
+                    }
+                }
+                ProjectFrame::new(Some(field_ids), children)
+            }
+            BasicType::Array => {
+                // Every element is a child, so that missing ones become nulls.
+                let children = value
+                    .get_array()?
+                    .elements()
+                    .enumerate()
+                    .map(|(index, element)| {
+                        let matches = |element: &PathElement| match element {
+                            PathElement::Index(i) => *i == index,
+                            PathElement::Wildcard => true,
+                            _ => false,
+                        };
+                        let child_suffixes = expanded
+                            .iter()
+                            .filter_map(|suffix| step(suffix, &matches))
+                            .collect::<Vec<_>>();
+                        (element, child_suffixes)
+                    })
+                    .collect::<Vec<_>>();
+                ProjectFrame::new(None, children)
+            }
+            BasicType::Primitive | BasicType::ShortString => return Ok(Visit::Missing),
+        };
+        if frame
+            .children
+            .iter()
+            .all(|(_, suffixes)| suffixes.is_empty())
+        {
+            return Ok(Visit::Missing);
+        }
+        Ok(Visit::Descend(frame))
+    }
+
+    fn new(
+        field_ids: Option<Vec<usize>>,
+        children: Vec<(VariantRef<'a>, Vec<&'p [PathElement]>)>,
+    ) -> Self {
+        Self {
+            field_ids,
+            ends: Vec::with_capacity(children.len()),
+            children,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Write the projected container to `buffer`, unless none of its
+    /// children were found. Returns whether it was written.
+    fn finish(self, buffer: &mut Vec<u8>, metadata: &MetadataRef) -> bool {
+        if self.ends.iter().all(Option::is_none) {
+            return false;
+        }
+        let mut start = 0;
+        let mut values = self.ends.iter().map(|end| {
+            end.map(|end| {
+                let value = &self.buffer[start..end];
+                start = end;
+                value
+            })
+        });
+        match self.field_ids {
+            None => {
+                let mut array_builder = ArrayBuilder::new(buffer, self.ends.len());
+                let mut null = Vec::new();
+                write_null(&mut null);
+                for value in values {
+                    array_builder.append_value(value.unwrap_or(&null));
+                }
+                array_builder.finish();
+            }
+            Some(field_ids) => {
+                let found = field_ids
+                    .iter()
+                    .zip(values.by_ref())
+                    .filter_map(|(field_id, value)| Some((*field_id, value?)))
+                    .collect::<Vec<_>>();
+                let mut object_builder =
+                    ObjectBuilder::with_capacity(buffer, metadata, found.len());
+                for (field_id, value) in found {
+                    object_builder.append_value_with_field_id(field_id, value);
+                }
+                object_builder.finish();
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{to_json, JsonWriteOptions};
+    use crate::metadata::build_metadata;
+    use crate::values::write::{write_i64, write_string};
+
+    /// `{"a": {"b": 1, "x": 2}, "c": [{"d": 3, "e": 4}, 5], "f": "s"}`
+    fn write_value(metadata: &MetadataRef) -> Vec<u8> {
+        let mut a = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut a, metadata, 2);
+        object_builder.append_i64("b", 1).unwrap();
+        object_builder.append_i64("x", 2).unwrap();
+        object_builder.finish();
+
+        let mut element = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut element, metadata, 2);
+        object_builder.append_i64("d", 3).unwrap();
+        object_builder.append_i64("e", 4).unwrap();
+        object_builder.finish();
+        let mut c = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut c, 2);
+        array_builder.append_value(&element);
+        element.clear();
+        write_i64(&mut element, 5);
+        array_builder.append_value(&element);
+        array_builder.finish();
+
+        let mut f = Vec::new();
+        write_string(&mut f, "s");
+
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, metadata, 3);
+        object_builder.append_value("a", &a).unwrap();
+        object_builder.append_value("c", &c).unwrap();
+        object_builder.append_value("f", &f).unwrap();
+        object_builder.finish();
+        buffer
+    }
+
+    #[test]
+    fn test_project() {
+        let metadata = build_metadata(["a", "b", "c", "d", "e", "f", "x"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let value = write_value(&metadata);
+        let value = VariantRef::try_new(&value).unwrap();
+
+        let project = |paths: &[&str]| {
+            let paths = paths
+                .iter()
+                .map(|path| VariantPath::parse(path).unwrap())
+                .collect::<Vec<_>>();
+            let (metadata, value) = value.project(&paths, &metadata).unwrap();
+            let metadata = MetadataRef::new(&metadata);
+            let value = VariantRef::try_new(&value).unwrap();
+            let json = to_json(&value, &metadata, &JsonWriteOptions::default()).unwrap();
+            (json, metadata.dictionary_len())
+        };
+
+        assert_eq!(project(&["a.b"]), (r#"{"a":{"b":1}}"#.into(), 2));
+        assert_eq!(project(&["a"]), (r#"{"a":{"b":1,"x":2}}"#.into(), 3));
+        assert_eq!(project(&["c[*].d"]), (r#"{"c":[{"d":3},null]}"#.into(), 2));
+        assert_eq!(
+            project(&["c[1]", "f", "a.missing"]),
+            (r#"{"c":[null,5],"f":"s"}"#.into(), 2)
+        );
+        assert_eq!(project(&["..d"]), (r#"{"c":[{"d":3},null]}"#.into(), 2));
+        assert_eq!(project(&["a.b", "a"]), (r#"{"a":{"b":1,"x":2}}"#.into(), 3));
+        assert_eq!(project(&[""]).1, 7);
+        assert_eq!(project(&["missing"]), ("null".into(), 0));
+        assert_eq!(project(&["f.g"]), ("null".into(), 0));
+        assert_eq!(project(&[]), ("null".into(), 0));
+    }
+}
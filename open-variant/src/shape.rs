@@ -0,0 +1,346 @@
+//! The structure of variant values, ignoring the leaf values.
+//!
+//! Two values have the same shape if they have the same keys at the same
+//! paths, with leaves of the same kinds of types. This is used to group rows
+//! by the structure of their payload.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::metadata::MetadataRef;
+use crate::values::{BasicType, PrimitiveTypeId, VariantRef};
+
+impl<'a> VariantRef<'a> {
+    /// A stable 64-bit hash of the shape of this value.
+    ///
+    /// Values with the same keys at the same paths, and the same kinds of leaf
+    /// types, have the same fingerprint regardless of their leaf values or
+    /// metadata. Integers of any width are the same kind, as are floats of any
+    /// precision, decimals of any width, and strings however they are stored.
+    /// Elements of an array only contribute their distinct shapes, so arrays
+    /// of different lengths with the same kinds of elements share a
+    /// fingerprint.
+    ///
+    /// The hash is FNV-1a, which is stable across platforms and releases.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in the metadata.
+    pub fn fingerprint(&self, metadata: &MetadataRef) -> Result<u64, String> {
+        Ok(fnv1a(self.shape(metadata)?.as_bytes()))
+    }
+
+    /// A canonical string describing the shape of this value, which
+    /// [`VariantRef::fingerprint`] hashes.
+    ///
+    /// Leaves are written as a letter for the kind of their type: `n` null,
+    /// `b` boolean, `i` integer, `f` float, `d` decimal, `D` date, `t`
+    /// timestamp, `T` timestamp without timezone, `s` string, `x` binary, and
+    /// `u` UUID. Objects list their keys in order, quoted, with the shape of
+    /// each field. Arrays list the distinct shapes of their elements, sorted
+    /// and separated by `|`. For example, `{"a": 1, "b": ["x", 2, "y"]}` has
+    /// the shape `{"a":i,"b":[i|s]}`.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in the metadata.
+    pub fn shape(&self, metadata: &MetadataRef) -> Result<String, String> {
+        // Shapes are built bottom-up.
+        let mut stack: Vec<ShapeFrame> = Vec::new();
+        let mut pending = Some(self.clone());
+        loop {
+            if let Some(value) = pending.take() {
+                match value.basic_type() {
+                    BasicType::Object => {
+                        let (keys, children) = value
+                            .get_object()?
+                            .fields()
+                            .map(|(field_id, field)| {
+                                metadata
+                                    .get_string(field_id)
+                                    .map(|key| (key, field))
+                                    .ok_or_else(|| {
+                                        format!("Field id {} is not in the metadata", field_id)
+                                    })
+                            })
+                            .collect::<Result<(Vec<_>, Vec<_>), _>>()?;
+                        stack.push(ShapeFrame::new(Some(keys), children));
+                    }
+                    BasicType::Array => {
+                        let array = value.get_array()?;
+                        stack.push(ShapeFrame::new(None, array.elements().collect()));
+                    }
+                    BasicType::Primitive | BasicType::ShortString => {
+                        let letter = String::from(leaf_letter(&value));
+                        match stack.last_mut() {
+                            Some(frame) => frame.shapes.push(letter),
+                            None => return Ok(letter),
+                        }
+                    }
+                }
+            }
+
+            let frame = stack
+                .last_mut()
+                .expect("stack is empty only after the top-level value");
+            if let Some(child) = frame.children.get(frame.shapes.len()) {
+                pending = Some(child.clone());
+                continue;
+            }
+
+            let shape = stack.pop().unwrap().finish();
+            match stack.last_mut() {
+                Some(parent) => parent.shapes.push(shape),
+                None => return Ok(shape),
+            }
+        }
+    }
+}
+
+/// Measures of the structure of a value, from [`VariantRef::metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StructureMetrics {
+    /// The nesting depth. Leaves have depth 0, and an object or array has one
+    /// more than its deepest child, so `[]` and `{}` have depth 1.
+    pub depth: usize,
+    /// The number of leaves, which are values that aren't objects or arrays.
+    pub leaf_count: usize,
+    /// The length of the longest array in the value, or 0 if it has none.
+    pub max_array_len: usize,
+}
+
+impl<'a> VariantRef<'a> {
+    /// Measure the structure of this value in a single traversal.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid.
+    pub fn metrics(&self) -> Result<StructureMetrics, String> {
+        let mut metrics = StructureMetrics::default();
+        // Each value with its own depth below this one.
+        let mut stack = Vec::from([(self.clone(), 0)]);
+        while let Some((value, depth)) = stack.pop() {
+            match value.basic_type() {
+                BasicType::Object => {
+                    metrics.depth = metrics.depth.max(depth + 1);
+                    let object = value.get_object()?;
+                    stack.extend(object.fields().map(|(_, field)| (field, depth + 1)));
+                }
+                BasicType::Array => {
+                    metrics.depth = metrics.depth.max(depth + 1);
+                    let array = value.get_array()?;
+                    metrics.max_array_len = metrics.max_array_len.max(array.len());
+                    stack.extend(array.elements().map(|element| (element, depth + 1)));
+                }
+                BasicType::Primitive | BasicType::ShortString => metrics.leaf_count += 1,
+            }
+        }
+        Ok(metrics)
+    }
+}
+
+/// An object or array whose shape is being built by [`VariantRef::shape`].
+struct ShapeFrame<'a, 'm> {
+    /// The keys of an object, or `None` for an array.
+    keys: Option<Vec<&'m str>>,
+    children: Vec<VariantRef<'a>>,
+    /// The shapes of the children so far.
+    shapes: Vec<String>,
+}
+
+impl<'a, 'm> ShapeFrame<'a, 'm> {
+    fn new(keys: Option<Vec<&'m str>>, children: Vec<VariantRef<'a>>) -> Self {
+        Self {
+            keys,
+            shapes: Vec::with_capacity(children.len()),
+            children,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let mut shape = String::new();
+        match self.keys {
+            // Fields are stored in key order, so objects with the same keys
+            // list them in the same order.
+            Some(keys) => {
+                shape.push('{');
+                for (i, (key, field_shape)) in keys.iter().zip(&self.shapes).enumerate() {
+                    if i > 0 {
+                        shape.push(',');
+                    }
+                    push_quoted(&mut shape, key);
+                    shape.push(':');
+                    shape.push_str(field_shape);
+                }
+                shape.push('}');
+            }
+            None => {
+                self.shapes.sort_unstable();
+                self.shapes.dedup();
+                shape.push('[');
+                shape.push_str(&self.shapes.join("|"));
+                shape.push(']');
+            }
+        }
+        shape
+    }
+}
+
+/// Push a key in double quotes, escaping quotes and backslashes.
+fn push_quoted(shape: &mut String, key: &str) {
+    shape.push('"');
+    for c in key.chars() {
+        if matches!(c, '"' | '\\') {
+            shape.push('\\');
+        }
+        shape.push(c);
+    }
+    shape.push('"');
+}
+
+/// The letter for the kind of type of a leaf value.
+fn leaf_letter(value: &VariantRef) -> &'static str {
+    if value.basic_type() == BasicType::ShortString {
+        return "s";
+    }
+    match value.primitive_type_id() {
+        PrimitiveTypeId::Null => "n",
+        PrimitiveTypeId::BoolTrue | PrimitiveTypeId::BoolFalse => "b",
+        PrimitiveTypeId::Int8
+        | PrimitiveTypeId::Int16
+        | PrimitiveTypeId::Int32
+        | PrimitiveTypeId::Int64 => "i",
+        PrimitiveTypeId::Float32 | PrimitiveTypeId::Float64 => "f",
+        PrimitiveTypeId::Decimal4 | PrimitiveTypeId::Decimal8 | PrimitiveTypeId::Decimal16 => "d",
+        PrimitiveTypeId::Date32 => "D",
+        PrimitiveTypeId::TimestampMicro => "t",
+        PrimitiveTypeId::TimestampMicroNTZ | PrimitiveTypeId::TimestampNanoNTZ => "T",
+        PrimitiveTypeId::Binary | PrimitiveTypeId::BinaryFromDictionary => "x",
+        PrimitiveTypeId::String | PrimitiveTypeId::StringFromDictionary => "s",
+        PrimitiveTypeId::Uuid => "u",
+    }
+}
+
+/// The 64-bit FNV-1a hash, which is stable across platforms and releases.
+///
+/// Not part of the public API. It is shared with arrow-open-variant, which
+/// needs the same stable hash.
+#[doc(hidden)]
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::build_metadata;
+    use crate::values::write::{
+        write_f64, write_i64, write_null, write_string, ArrayBuilder, ObjectBuilder,
+    };
+
+    /// `{"a": [<elements>], "b\"": <b>}`
+    fn write_value(metadata: &MetadataRef, elements: &[Vec<u8>], b: &[u8]) -> Vec<u8> {
+        let mut array = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut array, elements.len());
+        for element in elements {
+            array_builder.append_value(element);
+        }
+        array_builder.finish();
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, metadata, 2);
+        object_builder.append_value("b\"", b).unwrap();
+        object_builder.append_value("a", &array).unwrap();
+        object_builder.finish();
+        buffer
+    }
+
+    fn leaf(write: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn test_shape() {
+        let metadata = build_metadata(["a", "b\""].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let int = |value| leaf(|buffer| write_i64(buffer, value));
+        let string = |value| leaf(|buffer| write_string(buffer, value));
+
+        let value = write_value(&metadata, &[int(1), string("x"), int(2)], &int(3));
+        let value = VariantRef::try_new(&value).unwrap();
+        assert_eq!(value.shape(&metadata).unwrap(), r#"{"a":[i|s],"b\"":i}"#);
+
+        // Leaf values and array lengths don't change the fingerprint.
+        let same = write_value(&metadata, &[string("y"), int(5)], &int(4));
+        let same = VariantRef::try_new(&same).unwrap();
+        assert_eq!(
+            same.fingerprint(&metadata).unwrap(),
+            value.fingerprint(&metadata).unwrap()
+        );
+
+        // Leaf types do.
+        let float = write_value(&metadata, &[int(1)], &leaf(|buffer| write_f64(buffer, 1.0)));
+        let float = VariantRef::try_new(&float).unwrap();
+        assert_eq!(float.shape(&metadata).unwrap(), r#"{"a":[i],"b\"":f}"#);
+        assert_ne!(
+            float.fingerprint(&metadata).unwrap(),
+            value.fingerprint(&metadata).unwrap()
+        );
+
+        let empty = write_value(&metadata, &[], &leaf(write_null));
+        let empty = VariantRef::try_new(&empty).unwrap();
+        assert_eq!(empty.shape(&metadata).unwrap(), r#"{"a":[],"b\"":n}"#);
+
+        let scalar = string("a long enough string to not be a short string, at all");
+        let scalar = VariantRef::try_new(&scalar).unwrap();
+        assert_eq!(scalar.shape(&metadata).unwrap(), "s");
+        assert_eq!(fnv1a(b"s"), scalar.fingerprint(&metadata).unwrap());
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metadata = build_metadata(["a", "b\""].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let int = |value| leaf(|buffer| write_i64(buffer, value));
+
+        let nested = write_value(&metadata, &[int(1)], &int(2));
+        let value = write_value(&metadata, &[int(1), nested, int(3)], &leaf(write_null));
+        let value = VariantRef::try_new(&value).unwrap();
+        assert_eq!(
+            value.metrics().unwrap(),
+            StructureMetrics {
+                depth: 4,
+                leaf_count: 5,
+                max_array_len: 3,
+            }
+        );
+
+        let empty = write_value(&metadata, &[], &int(1));
+        let empty = VariantRef::try_new(&empty).unwrap();
+        assert_eq!(
+            empty.metrics().unwrap(),
+            StructureMetrics {
+                depth: 2,
+                leaf_count: 1,
+                max_array_len: 0,
+            }
+        );
+
+        let scalar = int(1);
+        let scalar = VariantRef::try_new(&scalar).unwrap();
+        assert_eq!(
+            scalar.metrics().unwrap(),
+            StructureMetrics {
+                depth: 0,
+                leaf_count: 1,
+                max_array_len: 0,
+            }
+        );
+    }
+}
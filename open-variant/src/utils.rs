@@ -1,23 +1,42 @@
-/// Given a maximum value, determine the appropriate byte width to encode with.
+use crate::values::write::VariantValueWriter;
+
+/// Given a maximum value, determine the smallest byte width that encodes it
+/// as an unsigned integer.
+///
+/// Widths are 1 to 4 bytes. The format has no wider integers, so values
+/// above `u32::MAX` can't be encoded.
 pub fn determine_byte_width(max_value: usize) -> u8 {
-    if max_value <= i8::MAX as usize {
+    if max_value <= u8::MAX as usize {
         1
-    } else if max_value <= i16::MAX as usize {
+    } else if max_value <= u16::MAX as usize {
         2
-    } else if max_value <= i32::MAX as usize {
-        4
+    } else if max_value <= 0xFF_FFFF {
+        3
     } else {
-        8
+        4
     }
 }
 
-/// Write an integer to a buffer with a specific byte width.
-pub fn write_integer(buffer: &mut Vec<u8>, value: usize, byte_width: u8) {
-    match byte_width {
-        1 => buffer.extend_from_slice(&(value as i8).to_le_bytes()),
-        2 => buffer.extend_from_slice(&(value as i16).to_le_bytes()),
-        4 => buffer.extend_from_slice(&(value as i32).to_le_bytes()),
-        8 => buffer.extend_from_slice(&(value as i64).to_le_bytes()),
-        _ => unreachable!(),
-    };
+/// Read an unsigned little-endian integer of `byte_width` bytes, from 1 to 4,
+/// starting at `offset`.
+pub fn read_integer(data: &[u8], offset: usize, byte_width: u8) -> usize {
+    let mut bytes = [0; 4];
+    bytes[..byte_width as usize].copy_from_slice(&data[offset..offset + byte_width as usize]);
+    u32::from_le_bytes(bytes) as usize
+}
+
+/// Write an unsigned little-endian integer to a buffer with a specific byte
+/// width.
+pub fn write_integer(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    value: usize,
+    byte_width: u8,
+) {
+    debug_assert!(
+        byte_width <= 4 && (value as u64) >> (8 * byte_width as u32) == 0,
+        "{} does not fit in {} bytes",
+        value,
+        byte_width
+    );
+    buffer.extend_from_slice(&(value as u64).to_le_bytes()[..byte_width as usize]);
 }
@@ -0,0 +1,682 @@
+//! Check that metadata and values are well formed.
+//!
+//! The readers in [`values`](crate::values) and [`metadata`](crate::metadata)
+//! trust their input, and panic on buffers that are truncated or have
+//! offsets out of bounds. The functions here check every header, offset,
+//! field id and string without panicking, so data from untrusted writers can
+//! be checked before it is read. Errors describe the first problem found and
+//! the path of the value it was found in, such as
+//! `field id 7 is not in the metadata at a.b[2]`.
+//!
+//! [`repair_value`] recovers what it can from a malformed value instead.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::metadata::MetadataRef;
+use crate::path::{PathElement, VariantPath};
+use crate::values::write::{write_null, ArrayBuilder, ObjectBuilder};
+use crate::values::{PrimitiveTypeId, VariantRef};
+
+/// Check that `metadata` and `value` are well formed, and that `value` only
+/// uses keys in `metadata`.
+///
+/// # Errors
+///
+/// Describes the first problem found.
+pub fn validate(metadata: &[u8], value: &[u8]) -> Result<(), String> {
+    validate_metadata(metadata)?;
+    validate_value(value, &MetadataRef::new(metadata))
+}
+
+/// Check that a metadata buffer is well formed.
+///
+/// The buffer may hold more data after the metadata, as for
+/// [`MetadataRef::new`].
+///
+/// # Errors
+///
+/// If the version is not 1, the buffer is too short, the offsets are not
+/// increasing, a key is not valid UTF-8, or the keys of a sorted dictionary
+/// are not sorted and unique.
+pub fn validate_metadata(metadata: &[u8]) -> Result<(), String> {
+    let header = *metadata.first().ok_or("metadata is empty")?;
+    let version = header & 0b0000_1111;
+    if version != 1 {
+        return Err(format!("unsupported metadata version {}", version));
+    }
+    let sorted = header & 0b0001_0000 != 0;
+    let offset_size = ((header & 0b1100_0000) >> 6) + 1;
+
+    let dictionary_len = read_integer(metadata, 1, offset_size, "metadata dictionary size")?;
+    let offsets_start = 1 + offset_size as usize;
+    let offsets_len = dictionary_len
+        .checked_add(1)
+        .and_then(|len| len.checked_mul(offset_size as usize))
+        .ok_or("metadata dictionary size is too large")?;
+    let offsets = slice(metadata, offsets_start, offsets_len, "metadata offsets")?;
+    let data = &metadata[offsets_start + offsets_len..];
+
+    if read_integer(offsets, 0, offset_size, "metadata offsets")? != 0 {
+        return Err("first metadata offset is not 0".to_string());
+    }
+    let mut start = 0;
+    let mut previous: Option<&str> = None;
+    for id in 0..dictionary_len {
+        let end = read_integer(
+            offsets,
+            (id + 1) * offset_size as usize,
+            offset_size,
+            "metadata offsets",
+        )?;
+        if end < start || end > data.len() {
+            return Err(format!("offset of metadata key {} is out of bounds", id));
+        }
+        let key = core::str::from_utf8(&data[start..end])
+            .map_err(|_| format!("metadata key {} is not valid UTF-8", id))?;
+        if sorted && previous.is_some_and(|previous| previous >= key) {
+            return Err(format!(
+                "metadata key {} is out of order in a sorted dictionary",
+                id
+            ));
+        }
+        previous = Some(key);
+        start = end;
+    }
+    Ok(())
+}
+
+/// Check that a value is well formed, and that the field ids of its objects
+/// are in `metadata`, which must already be valid.
+///
+/// The buffer may hold more data after the value, as for
+/// [`VariantRef::try_new`](crate::values::VariantRef::try_new).
+///
+/// # Errors
+///
+/// If a header is invalid, the buffer is too short, an offset is out of
+/// bounds, a string is not valid UTF-8, a field id is not in the metadata,
+/// or the fields of an object are not ordered by key.
+pub fn validate_value(value: &[u8], metadata: &MetadataRef) -> Result<(), String> {
+    // Values are checked depth first. `path` holds the path of the value
+    // being checked, and each entry on the stack holds its depth and the last
+    // element of its path, so `path` is truncated to the parent's path
+    // before the element is pushed.
+    let mut stack = vec![(value, 0, None)];
+    let mut path = Vec::new();
+    while let Some((bytes, depth, element)) = stack.pop() {
+        path.truncate(depth);
+        if let Some(element) = element {
+            path.push(element);
+        }
+        let located = |error: String| {
+            if path.is_empty() {
+                error
+            } else {
+                format!("{} at {}", error, VariantPath::new(path.clone()))
+            }
+        };
+        let children = validate_one(bytes, metadata).map_err(located)?;
+        let depth = path.len();
+        stack.extend(
+            children
+                .into_iter()
+                .rev()
+                .map(|(bytes, element)| (bytes, depth, Some(element))),
+        );
+    }
+    Ok(())
+}
+
+/// Re-encode a value, dropping the parts that can't be read, for recovering
+/// data from buggy writers. `metadata` must be valid.
+///
+/// Returns the repaired value and the number of values dropped. Fields of
+/// objects are dropped if their value is unreadable, their field id is not
+/// in `metadata`, or their key is repeated, in which case the first field
+/// with the key is kept. Unreadable elements of arrays are replaced with
+/// null, so the remaining elements keep their index, and an unreadable value
+/// is replaced with null. Objects and arrays are rebuilt with fields ordered
+/// by key and the narrowest widths, so the result is always valid.
+pub fn repair_value(value: &[u8], metadata: &MetadataRef) -> (Vec<u8>, usize) {
+    // Like `validate_value`, this uses an explicit stack. Each frame holds an
+    // object or array being repaired, and the repaired bytes of the nested
+    // values done so far, or `None` for dropped ones.
+    let mut dropped = 0;
+    let mut stack: Vec<RepairFrame> = Vec::new();
+    let mut next = value;
+    loop {
+        let mut finished = match repair_one(next, metadata) {
+            Repaired::Nested(frame, dropped_fields) => {
+                dropped += dropped_fields;
+                stack.push(frame);
+                None
+            }
+            Repaired::Leaf(bytes) => Some(Some(bytes)),
+            Repaired::Unreadable => {
+                dropped += 1;
+                Some(None)
+            }
+        };
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                let value = finished.flatten().unwrap_or_else(|| {
+                    let mut null = Vec::new();
+                    write_null(&mut null);
+                    null
+                });
+                return (value, dropped);
+            };
+            if let Some(child) = finished.take() {
+                frame.repaired.push(child);
+            }
+            match frame.children.get(frame.repaired.len()) {
+                Some(Some(child)) => {
+                    next = child;
+                    break;
+                }
+                // The offsets of the value are out of bounds.
+                Some(None) => {
+                    dropped += 1;
+                    finished = Some(None);
+                }
+                None => {
+                    let frame = stack.pop().unwrap();
+                    finished = Some(Some(frame.finish(metadata)));
+                }
+            }
+        }
+    }
+}
+
+enum Repaired<'a> {
+    /// A primitive value or short string, copied unchanged.
+    Leaf(Vec<u8>),
+    /// An object or array, with the number of fields dropped up front.
+    Nested(RepairFrame<'a>, usize),
+    Unreadable,
+}
+
+struct RepairFrame<'a> {
+    /// The field ids of an object, ordered by key, or `None` for an array.
+    field_ids: Option<Vec<usize>>,
+    children: Vec<Option<&'a [u8]>>,
+    repaired: Vec<Option<Vec<u8>>>,
+}
+
+impl<'a> RepairFrame<'a> {
+    fn new(field_ids: Option<Vec<usize>>, children: Vec<Option<&'a [u8]>>) -> Self {
+        let repaired = Vec::with_capacity(children.len());
+        Self {
+            field_ids,
+            children,
+            repaired,
+        }
+    }
+
+    fn finish(self, metadata: &MetadataRef) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        match self.field_ids {
+            Some(field_ids) => {
+                let mut builder =
+                    ObjectBuilder::with_capacity(&mut buffer, metadata, field_ids.len());
+                for (field_id, value) in field_ids.into_iter().zip(self.repaired) {
+                    if let Some(value) = value {
+                        builder.append_value_with_field_id(field_id, &value);
+                    }
+                }
+                builder.finish();
+            }
+            None => {
+                let mut null = Vec::new();
+                write_null(&mut null);
+                let mut builder = ArrayBuilder::new(&mut buffer, self.repaired.len());
+                for value in &self.repaired {
+                    builder.append_value(value.as_deref().unwrap_or(&null));
+                }
+                builder.finish();
+            }
+        }
+        buffer
+    }
+}
+
+/// Start repairing one value. Objects drop fields with unknown field ids and
+/// repeated keys here, before their values are repaired.
+fn repair_one<'a>(bytes: &'a [u8], metadata: &MetadataRef) -> Repaired<'a> {
+    match bytes.first().map(|header| header & 0b11) {
+        Some(2) => {
+            let Ok(fields) = object_fields(bytes) else {
+                return Repaired::Unreadable;
+            };
+            let len = fields.len();
+            let mut fields = fields
+                .into_iter()
+                .filter_map(|(field_id, field)| {
+                    let key = metadata.get_string(field_id)?;
+                    Some((key, field_id, field))
+                })
+                .collect::<Vec<_>>();
+            // The sort is stable, so the first field with a key is kept.
+            fields.sort_by_key(|(key, _, _)| *key);
+            fields.dedup_by_key(|(key, _, _)| *key);
+            let dropped = len - fields.len();
+            let (field_ids, children) = fields
+                .into_iter()
+                .map(|(_, field_id, field)| (field_id, field))
+                .unzip();
+            Repaired::Nested(RepairFrame::new(Some(field_ids), children), dropped)
+        }
+        Some(3) => match array_elements(bytes) {
+            Ok(elements) => Repaired::Nested(RepairFrame::new(None, elements), 0),
+            Err(_) => Repaired::Unreadable,
+        },
+        _ => match validate_one(bytes, metadata) {
+            Ok(_) => {
+                let value = VariantRef::try_new(bytes).expect("the value is valid");
+                Repaired::Leaf(value.as_bytes().to_vec())
+            }
+            Err(_) => Repaired::Unreadable,
+        },
+    }
+}
+
+/// Check the encoding of one value, without its nested values, and return
+/// the bytes and path element of each nested value.
+fn validate_one<'a>(
+    bytes: &'a [u8],
+    metadata: &MetadataRef,
+) -> Result<Vec<(&'a [u8], PathElement)>, String> {
+    let header = *bytes.first().ok_or("value is empty")?;
+    match header & 0b11 {
+        0 => {
+            validate_primitive(bytes, metadata)?;
+            Ok(Vec::new())
+        }
+        1 => {
+            let len = (header >> 2) as usize;
+            let string = slice(bytes, 1, len, "short string")?;
+            core::str::from_utf8(string).map_err(|_| "short string is not valid UTF-8")?;
+            Ok(Vec::new())
+        }
+        2 => validate_object(bytes, metadata),
+        _ => validate_array(bytes),
+    }
+}
+
+fn validate_primitive(bytes: &[u8], metadata: &MetadataRef) -> Result<(), String> {
+    let type_id = bytes[0] >> 2;
+    let type_id = PrimitiveTypeId::try_from(type_id)
+        .map_err(|_| format!("invalid primitive type id {}", type_id))?;
+    let payload_len = match type_id {
+        PrimitiveTypeId::Null | PrimitiveTypeId::BoolTrue | PrimitiveTypeId::BoolFalse => 0,
+        PrimitiveTypeId::Int8 => 1,
+        PrimitiveTypeId::Int16 => 2,
+        PrimitiveTypeId::Int32 | PrimitiveTypeId::Float32 | PrimitiveTypeId::Date32 => 4,
+        PrimitiveTypeId::Int64
+        | PrimitiveTypeId::Float64
+        | PrimitiveTypeId::TimestampMicro
+        | PrimitiveTypeId::TimestampMicroNTZ
+        | PrimitiveTypeId::TimestampNanoNTZ => 8,
+        PrimitiveTypeId::Decimal4 => 1 + 4,
+        PrimitiveTypeId::Decimal8 => 1 + 8,
+        PrimitiveTypeId::Decimal16 => 1 + 16,
+        PrimitiveTypeId::Uuid => 16,
+        PrimitiveTypeId::Binary | PrimitiveTypeId::String => {
+            4 + read_integer(bytes, 1, 4, "string or binary length")?
+        }
+        PrimitiveTypeId::BinaryFromDictionary | PrimitiveTypeId::StringFromDictionary => 4,
+    };
+    let payload = slice(bytes, 1, payload_len, "primitive value")?;
+    match type_id {
+        PrimitiveTypeId::String => {
+            core::str::from_utf8(&payload[4..]).map_err(|_| "string is not valid UTF-8")?;
+        }
+        PrimitiveTypeId::BinaryFromDictionary | PrimitiveTypeId::StringFromDictionary => {
+            let id = read_integer(payload, 0, 4, "dictionary id")?;
+            if id >= metadata.dictionary_len() {
+                return Err(format!("dictionary id {} is not in the metadata", id));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn validate_object<'a>(
+    bytes: &'a [u8],
+    metadata: &MetadataRef,
+) -> Result<Vec<(&'a [u8], PathElement)>, String> {
+    let fields = object_fields(bytes)?;
+    let mut children = Vec::with_capacity(fields.len());
+    let mut previous: Option<&str> = None;
+    for (field_id, field) in fields {
+        let key = metadata
+            .get_string(field_id)
+            .ok_or_else(|| format!("field id {} is not in the metadata", field_id))?;
+        if previous.is_some_and(|previous| previous >= key) {
+            return Err(format!("field {:?} is out of order or repeated", key));
+        }
+        previous = Some(key);
+        let field = field.ok_or_else(|| format!("offset of field {:?} is out of bounds", key))?;
+        children.push((field, PathElement::Field(key.to_string())));
+    }
+    Ok(children)
+}
+
+fn validate_array(bytes: &[u8]) -> Result<Vec<(&[u8], PathElement)>, String> {
+    array_elements(bytes)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, element)| {
+            // Elements are checked within their own bytes, so an element
+            // longer than its offsets is reported as truncated.
+            let element =
+                element.ok_or_else(|| format!("offset of element {} is out of bounds", index))?;
+            Ok((element, PathElement::Index(index)))
+        })
+        .collect()
+}
+
+/// The field ids of an object, with the bytes from the start of each field
+/// value to the end of the object, or `None` if the offset of the field is
+/// out of bounds.
+///
+/// Offsets of objects don't have to be increasing, so each field extends to
+/// the end of the object's data, like in `ObjectRef`.
+fn object_fields(bytes: &[u8]) -> Result<Vec<ObjectField<'_>>, String> {
+    let header = bytes[0] >> 2;
+    let offset_width = (header & 0b11) + 1;
+    let field_id_width = ((header >> 2) & 0b11) + 1;
+    let is_large = (header >> 4) & 1 == 1;
+    let (len, mut position) = read_num_elements(bytes, is_large, "object")?;
+
+    let field_ids = slice(bytes, position, len * field_id_width as usize, "field ids")?;
+    position += field_ids.len();
+    let offsets = slice(
+        bytes,
+        position,
+        (len + 1) * offset_width as usize,
+        "offsets",
+    )?;
+    position += offsets.len();
+    let data_len = read_integer(
+        offsets,
+        len * offset_width as usize,
+        offset_width,
+        "offsets",
+    )?;
+    let data = slice(bytes, position, data_len, "object")?;
+
+    (0..len)
+        .map(|index| {
+            let field_id = read_integer(
+                field_ids,
+                index * field_id_width as usize,
+                field_id_width,
+                "field ids",
+            )?;
+            let offset = read_integer(
+                offsets,
+                index * offset_width as usize,
+                offset_width,
+                "offsets",
+            )?;
+            Ok((field_id, (offset < data_len).then(|| &data[offset..])))
+        })
+        .collect()
+}
+
+/// A field id, and the bytes of the field value if its offset is in bounds.
+type ObjectField<'a> = (usize, Option<&'a [u8]>);
+
+/// The bytes of each element of an array, or `None` if the offsets of the
+/// element are out of bounds.
+fn array_elements(bytes: &[u8]) -> Result<Vec<Option<&[u8]>>, String> {
+    let header = bytes[0] >> 2;
+    let offset_width = (header & 0b11) + 1;
+    let is_large = (header >> 2) & 1 == 1;
+    let (len, mut position) = read_num_elements(bytes, is_large, "array")?;
+
+    let offsets = slice(
+        bytes,
+        position,
+        (len + 1) * offset_width as usize,
+        "offsets",
+    )?;
+    position += offsets.len();
+    let data_len = read_integer(
+        offsets,
+        len * offset_width as usize,
+        offset_width,
+        "offsets",
+    )?;
+    let data = slice(bytes, position, data_len, "array")?;
+
+    let mut elements = Vec::with_capacity(len);
+    let mut start = read_integer(offsets, 0, offset_width, "offsets")?;
+    for index in 0..len {
+        let end = read_integer(
+            offsets,
+            (index + 1) * offset_width as usize,
+            offset_width,
+            "offsets",
+        )?;
+        elements.push((start <= end && end <= data_len).then(|| &data[start..end]));
+        start = end;
+    }
+    Ok(elements)
+}
+
+/// Read the element count of an object or array, and return it with the
+/// position after it.
+fn read_num_elements(bytes: &[u8], is_large: bool, what: &str) -> Result<(usize, usize), String> {
+    if is_large {
+        Ok((read_integer(bytes, 1, 4, what)?, 5))
+    } else {
+        Ok((read_integer(bytes, 1, 1, what)?, 2))
+    }
+}
+
+/// Get `len` bytes at `start`, or an error naming `what` if the buffer is too
+/// short.
+fn slice<'a>(bytes: &'a [u8], start: usize, len: usize, what: &str) -> Result<&'a [u8], String> {
+    start
+        .checked_add(len)
+        .and_then(|end| bytes.get(start..end))
+        .ok_or_else(|| format!("{} is truncated", what))
+}
+
+/// Like [`crate::utils::read_integer`], but returns an error naming `what`
+/// if the buffer is too short.
+fn read_integer(bytes: &[u8], start: usize, width: u8, what: &str) -> Result<usize, String> {
+    slice(bytes, start, width as usize, what)?;
+    Ok(crate::utils::read_integer(bytes, start, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::build_metadata;
+    use crate::values::write::{write_i64, write_string};
+
+    /// `{"a": {"b": [1, "x", "a much longer string than a short string"]}, "c": 2}`
+    fn write_value(metadata: &MetadataRef) -> Vec<u8> {
+        let mut elements = Vec::new();
+        for element in [1, 2, 3] {
+            let mut buffer = Vec::new();
+            match element {
+                1 => write_i64(&mut buffer, 1),
+                2 => write_string(&mut buffer, "x"),
+                _ => write_string(&mut buffer, &"long string ".repeat(8)),
+            }
+            elements.push(buffer);
+        }
+        let mut b = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut b, elements.len());
+        for element in &elements {
+            array_builder.append_value(element);
+        }
+        array_builder.finish();
+        let mut a = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut a, metadata, 1);
+        object_builder.append_value("b", &b).unwrap();
+        object_builder.finish();
+
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, metadata, 2);
+        object_builder.append_value("a", &a).unwrap();
+        object_builder.append_i64("c", 2).unwrap();
+        object_builder.finish();
+        buffer
+    }
+
+    #[test]
+    fn test_validate() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let value = write_value(&MetadataRef::new(&metadata));
+        assert_eq!(validate(&metadata, &value), Ok(()));
+        assert_eq!(
+            validate_metadata(&build_metadata(core::iter::empty())),
+            Ok(())
+        );
+
+        // Every truncation and every changed byte is either valid or reported,
+        // without panicking.
+        for len in 0..value.len() {
+            assert!(validate(&metadata, &value[..len]).is_err(), "{}", len);
+        }
+        for len in 0..metadata.len() {
+            assert!(validate_metadata(&metadata[..len]).is_err(), "{}", len);
+        }
+        for index in 0..value.len() {
+            for byte in [0, 1, 0x7f, 0x80, 0xff] {
+                let mut changed = value.clone();
+                changed[index] = byte;
+                let _ = validate(&metadata, &changed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_diagnostics() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let value = write_value(&MetadataRef::new(&metadata));
+
+        // Without "b" in the metadata, the field id of "c" is out of bounds.
+        let small = build_metadata(["a", "c"].into_iter());
+        assert_eq!(
+            validate(&small, &value),
+            Err("field id 2 is not in the metadata".to_string())
+        );
+
+        // Make the string "x" invalid UTF-8.
+        let mut invalid = value.clone();
+        let position = invalid.iter().position(|byte| *byte == b'x').unwrap();
+        invalid[position] = 0xff;
+        assert_eq!(
+            validate(&metadata, &invalid),
+            Err("string is not valid UTF-8 at a.b[1]".to_string())
+        );
+
+        // Swap the field ids of the top-level object.
+        let mut unordered = value.clone();
+        unordered.swap(2, 3);
+        assert_eq!(
+            validate(&metadata, &unordered),
+            Err(r#"field "a" is out of order or repeated"#.to_string())
+        );
+
+        assert_eq!(validate(&metadata, &[]), Err("value is empty".to_string()));
+        assert_eq!(
+            validate(&metadata, &[21 << 2]),
+            Err("invalid primitive type id 21".to_string())
+        );
+
+        let mut version_2 = metadata.clone();
+        version_2[0] = version_2[0] & 0b1111_0000 | 2;
+        assert_eq!(
+            validate_metadata(&version_2),
+            Err("unsupported metadata version 2".to_string())
+        );
+
+        // Claim that an unsorted dictionary is sorted.
+        let mut unsorted = build_metadata(["b", "a"].into_iter());
+        let len = unsorted.len();
+        unsorted[len - 2..].copy_from_slice(b"ba");
+        assert_eq!(
+            validate_metadata(&unsorted),
+            Err("metadata key 1 is out of order in a sorted dictionary".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repair_value() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let value = write_value(&metadata_ref);
+        assert_eq!(repair_value(&value, &metadata_ref), (value.clone(), 0));
+
+        // Without "b" in the metadata, "c" is dropped.
+        let small = build_metadata(["a", "b"].into_iter());
+        let small = MetadataRef::new(&small);
+        let (repaired, dropped) = repair_value(&value, &small);
+        assert_eq!(dropped, 1);
+        let object = VariantRef::try_new(&repaired)
+            .unwrap()
+            .get_object()
+            .unwrap();
+        assert_eq!(object.field_ids().collect::<Vec<_>>(), vec![0]);
+
+        // An unreadable element is replaced with null.
+        let mut invalid = value.clone();
+        let position = invalid.iter().position(|byte| *byte == b'x').unwrap();
+        invalid[position] = 0xff;
+        let (repaired, dropped) = repair_value(&invalid, &metadata_ref);
+        assert_eq!(dropped, 1);
+        let path = VariantPath::parse("a.b").unwrap();
+        let array = VariantRef::try_new(&repaired)
+            .unwrap()
+            .get_path(&path, &metadata_ref)
+            .unwrap();
+        let elements = array.get_array().unwrap();
+        assert_eq!(elements.len(), 3);
+        assert!(elements.get_element(1).unwrap().is_null());
+
+        // Repeated keys keep the first field.
+        let mut one = Vec::new();
+        write_i64(&mut one, 1);
+        let mut two = Vec::new();
+        write_i64(&mut two, 2);
+        let mut repeated = Vec::new();
+        let mut builder = ObjectBuilder::with_capacity(&mut repeated, &metadata_ref, 2);
+        builder.append_value_with_field_id(0, &one);
+        builder.append_value_with_field_id(0, &two);
+        builder.finish();
+        let (repaired, dropped) = repair_value(&repeated, &metadata_ref);
+        assert_eq!(dropped, 1);
+        let repaired = VariantRef::try_new(&repaired).unwrap();
+        assert_eq!(repaired.get_object().unwrap().len(), 1);
+        assert_eq!(
+            repaired.field(0, &metadata_ref).unwrap().unwrap().get_i64(),
+            1
+        );
+
+        // An unreadable value is replaced with null.
+        let (repaired, dropped) = repair_value(&value[..value.len() - 1], &metadata_ref);
+        assert_eq!(dropped, 1);
+        assert!(VariantRef::try_new(&repaired).unwrap().is_null());
+
+        // Whatever is changed, the result is valid.
+        for index in 0..value.len() {
+            for byte in [0, 1, 0x7f, 0x80, 0xff] {
+                let mut changed = value.clone();
+                changed[index] = byte;
+                let (repaired, _) = repair_value(&changed, &metadata_ref);
+                assert_eq!(validate_value(&repaired, &metadata_ref), Ok(()));
+            }
+        }
+    }
+}
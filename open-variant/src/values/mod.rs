@@ -1,15 +1,35 @@
 //! Read and write the values part of the variant format.
 
+mod owned;
 mod read;
+pub mod uuid;
 pub mod write;
 
-pub use read::{ArrayRef, ObjectRef, VariantRef};
+pub use owned::Variant;
+pub use read::{ArrayRef, BoundObjectRef, FieldLookup, ObjectRef, VariantRef};
+
+/// The decoded header of a variant value, from [`VariantRef::header`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueHeader {
+    pub basic_type: BasicType,
+    /// The primitive type, for [`BasicType::Primitive`] values.
+    pub primitive_type_id: Option<PrimitiveTypeId>,
+    /// Width in bytes of the offsets of an object or array.
+    pub offset_width: Option<u8>,
+    /// Width in bytes of the field ids of an object.
+    pub field_id_width: Option<u8>,
+    /// Whether an object or array stores its element count in 4 bytes rather
+    /// than 1.
+    pub is_large: bool,
+    /// The number of fields of an object or elements of an array.
+    pub num_elements: Option<usize>,
+}
 
 /// Basic type of a variant value.
 ///
 /// For [`BasicType::Primitive`], a more specific type is given by [`PrimitiveTypeId`].
 #[repr(u8)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BasicType {
     Primitive = 0,
     ShortString = 1,
@@ -34,7 +54,7 @@ impl TryFrom<u8> for BasicType {
 
 /// Specific type of a primitive variant value.
 #[repr(u8)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum PrimitiveTypeId {
     Null = 0,
@@ -57,6 +77,8 @@ pub enum PrimitiveTypeId {
     String = 16,
     BinaryFromDictionary = 17,
     StringFromDictionary = 18,
+    TimestampNanoNTZ = 19, // (without timezone)
+    Uuid = 20,             // 16 bytes, big-endian
 }
 
 impl TryFrom<u8> for PrimitiveTypeId {
@@ -84,6 +106,8 @@ impl TryFrom<u8> for PrimitiveTypeId {
             16 => Ok(PrimitiveTypeId::String),
             17 => Ok(PrimitiveTypeId::BinaryFromDictionary),
             18 => Ok(PrimitiveTypeId::StringFromDictionary),
+            19 => Ok(PrimitiveTypeId::TimestampNanoNTZ),
+            20 => Ok(PrimitiveTypeId::Uuid),
             _ => Err(()),
         }
     }
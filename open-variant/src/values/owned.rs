@@ -0,0 +1,345 @@
+//! An owned variant value, for building values in code.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::metadata::{build_metadata, MetadataRef};
+
+use super::write::{
+    write_bool, write_decimal, write_f64, write_i64, write_null, write_string,
+    write_timestamp_nanos_ntz, write_uuid, ArrayBuilder, ObjectBuilder, VariantValueWriter,
+};
+
+/// An owned variant value.
+///
+/// This is meant for building values in tests and examples, and for
+/// applications that construct values in code. Values read from a buffer
+/// are better used through [`VariantRef`](super::VariantRef), which doesn't
+/// copy them.
+///
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// use open_variant::json::{to_json, JsonWriteOptions};
+/// use open_variant::metadata::MetadataRef;
+/// use open_variant::values::{Variant, VariantRef};
+///
+/// let value = Variant::Object(BTreeMap::from([
+///     ("id".to_string(), Variant::from(1)),
+///     ("tags".to_string(), Variant::from(vec!["a", "b"])),
+/// ]));
+/// let (metadata, value) = value.encode();
+/// let metadata = MetadataRef::new(&metadata);
+/// let value = VariantRef::try_new(&value).unwrap();
+/// let json = to_json(&value, &metadata, &JsonWriteOptions::default()).unwrap();
+/// assert_eq!(json, r#"{"id":1,"tags":["a","b"]}"#);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variant {
+    Null,
+    Boolean(bool),
+    Int64(i64),
+    Float64(f64),
+    /// An unscaled value and a scale between 0 and 38.
+    Decimal(i128, u8),
+    String(String),
+    /// Nanoseconds since the Unix epoch, without timezone.
+    TimestampNanosNtz(i64),
+    /// A UUID, big-endian.
+    Uuid([u8; 16]),
+    Array(Vec<Variant>),
+    Object(BTreeMap<String, Variant>),
+}
+
+impl Variant {
+    /// Add the object keys used in this value to `keys`.
+    pub fn collect_keys<'a>(&'a self, keys: &mut BTreeSet<&'a str>) {
+        let mut stack = vec![self];
+        while let Some(value) = stack.pop() {
+            match value {
+                Variant::Array(elements) => stack.extend(elements),
+                Variant::Object(fields) => {
+                    for (key, field) in fields {
+                        keys.insert(key);
+                        stack.push(field);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Encode the value, with metadata holding just its keys.
+    ///
+    /// Returns the metadata and the value.
+    pub fn encode(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut keys = BTreeSet::new();
+        self.collect_keys(&mut keys);
+        let metadata = build_metadata(keys.into_iter());
+        let mut value = Vec::new();
+        self.write(&mut value, &MetadataRef::new(&metadata))
+            .expect("every key is in the metadata");
+        (metadata, value)
+    }
+
+    /// Write the value to `buffer`, using the ids of its keys in `metadata`.
+    ///
+    /// # Errors
+    ///
+    /// If a key is not in the metadata.
+    pub fn write(
+        &self,
+        buffer: &mut (impl VariantValueWriter + ?Sized),
+        metadata: &MetadataRef,
+    ) -> Result<(), String> {
+        let mut stack: Vec<WriteFrame> = Vec::new();
+        let mut pending = Some(self);
+        loop {
+            if let Some(value) = pending.take() {
+                match value {
+                    Variant::Array(elements) => {
+                        stack.push(WriteFrame::new(None, elements.iter().collect()))
+                    }
+                    Variant::Object(fields) => {
+                        let (keys, children) = fields
+                            .iter()
+                            .map(|(key, field)| (key.as_str(), field))
+                            .unzip();
+                        stack.push(WriteFrame::new(Some(keys), children));
+                    }
+                    leaf => match stack.last_mut() {
+                        Some(frame) => {
+                            leaf.write_leaf(&mut frame.buffer);
+                            frame.offsets.push(frame.buffer.len());
+                        }
+                        None => {
+                            leaf.write_leaf(buffer);
+                            return Ok(());
+                        }
+                    },
+                }
+            }
+
+            let frame = stack
+                .last_mut()
+                .expect("stack is empty only after the top-level value");
+            if let Some(child) = frame.children.get(frame.offsets.len() - 1) {
+                pending = Some(*child);
+                continue;
+            }
+
+            // All children are written, so close the container.
+            let frame = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some(parent) => {
+                    frame.finish(&mut parent.buffer, metadata)?;
+                    parent.offsets.push(parent.buffer.len());
+                }
+                None => return frame.finish(buffer, metadata),
+            }
+        }
+    }
+
+    /// Write a value that is not an array or object.
+    fn write_leaf(&self, buffer: &mut (impl VariantValueWriter + ?Sized)) {
+        match self {
+            Variant::Null => write_null(buffer),
+            Variant::Boolean(value) => write_bool(buffer, *value),
+            Variant::Int64(value) => write_i64(buffer, *value),
+            Variant::Float64(value) => write_f64(buffer, *value),
+            Variant::Decimal(value, scale) => write_decimal(buffer, *value, *scale),
+            Variant::String(value) => write_string(buffer, value),
+            Variant::TimestampNanosNtz(value) => write_timestamp_nanos_ntz(buffer, *value),
+            Variant::Uuid(value) => write_uuid(buffer, value),
+            Variant::Array(_) | Variant::Object(_) => unreachable!("not a leaf"),
+        }
+    }
+}
+
+/// An object or array being written by [`Variant::write`].
+struct WriteFrame<'a> {
+    /// The keys of an object, or `None` for an array.
+    keys: Option<Vec<&'a str>>,
+    children: Vec<&'a Variant>,
+    /// The written children so far, concatenated.
+    buffer: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl<'a> WriteFrame<'a> {
+    fn new(keys: Option<Vec<&'a str>>, children: Vec<&'a Variant>) -> Self {
+        Self {
+            keys,
+            children,
+            buffer: Vec::new(),
+            offsets: vec![0],
+        }
+    }
+
+    fn finish(
+        self,
+        buffer: &mut (impl VariantValueWriter + ?Sized),
+        metadata: &MetadataRef,
+    ) -> Result<(), String> {
+        let values = self
+            .offsets
+            .windows(2)
+            .map(|window| &self.buffer[window[0]..window[1]]);
+        match self.keys {
+            None => {
+                let mut array_builder = ArrayBuilder::new(buffer, self.children.len());
+                values.for_each(|value| array_builder.append_value(value));
+                array_builder.finish();
+            }
+            Some(keys) => {
+                let mut object_builder = ObjectBuilder::with_capacity(buffer, metadata, keys.len());
+                for (key, value) in keys.into_iter().zip(values) {
+                    object_builder.append_value(key, value)?;
+                }
+                object_builder.finish();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<bool> for Variant {
+    fn from(value: bool) -> Self {
+        Variant::Boolean(value)
+    }
+}
+
+impl From<i8> for Variant {
+    fn from(value: i8) -> Self {
+        Variant::Int64(value.into())
+    }
+}
+
+impl From<i16> for Variant {
+    fn from(value: i16) -> Self {
+        Variant::Int64(value.into())
+    }
+}
+
+impl From<i32> for Variant {
+    fn from(value: i32) -> Self {
+        Variant::Int64(value.into())
+    }
+}
+
+impl From<i64> for Variant {
+    fn from(value: i64) -> Self {
+        Variant::Int64(value)
+    }
+}
+
+impl From<u8> for Variant {
+    fn from(value: u8) -> Self {
+        Variant::Int64(value.into())
+    }
+}
+
+impl From<u16> for Variant {
+    fn from(value: u16) -> Self {
+        Variant::Int64(value.into())
+    }
+}
+
+impl From<u32> for Variant {
+    fn from(value: u32) -> Self {
+        Variant::Int64(value.into())
+    }
+}
+
+impl From<f32> for Variant {
+    fn from(value: f32) -> Self {
+        Variant::Float64(value.into())
+    }
+}
+
+impl From<f64> for Variant {
+    fn from(value: f64) -> Self {
+        Variant::Float64(value)
+    }
+}
+
+impl From<&str> for Variant {
+    fn from(value: &str) -> Self {
+        Variant::String(value.into())
+    }
+}
+
+impl From<String> for Variant {
+    fn from(value: String) -> Self {
+        Variant::String(value)
+    }
+}
+
+impl<T: Into<Variant>> From<Vec<T>> for Variant {
+    fn from(elements: Vec<T>) -> Self {
+        Variant::Array(elements.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<Variant>> FromIterator<(K, V)> for Variant {
+    /// Build an object from its fields. If a key is repeated, the last value
+    /// is kept.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(fields: I) -> Self {
+        Variant::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::json::{to_json, JsonWriteOptions};
+    use crate::values::VariantRef;
+
+    fn json(value: &Variant) -> String {
+        let (metadata, value) = value.encode();
+        let metadata = MetadataRef::new(&metadata);
+        let value = VariantRef::try_new(&value).unwrap();
+        to_json(&value, &metadata, &JsonWriteOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(json(&Variant::Null), "null");
+        assert_eq!(json(&Variant::from(-3)), "-3");
+        assert_eq!(json(&Variant::Decimal(12345, 2)), "123.45");
+        assert_eq!(json(&Variant::from(vec![1.5, 2.0])), "[1.5,2.0]");
+        assert_eq!(json(&Variant::Array(Vec::new())), "[]");
+
+        let value = Variant::from_iter([
+            ("b", Variant::from(vec![Variant::from(true), Variant::Null])),
+            ("a", Variant::from_iter([("c", "x")])),
+            ("empty", Variant::Object(BTreeMap::new())),
+        ]);
+        assert_eq!(
+            json(&value),
+            r#"{"a":{"c":"x"},"b":[true,null],"empty":{}}"#
+        );
+
+        let (metadata, _) = value.encode();
+        let metadata = MetadataRef::new(&metadata);
+        assert_eq!(metadata.dictionary_len(), 4);
+        assert!(metadata.sorted_strings());
+
+        // Writing with metadata missing a key fails.
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let mut buffer = Vec::new();
+        let error = value
+            .write(&mut buffer, &MetadataRef::new(&metadata))
+            .unwrap_err();
+        assert!(error.contains("'c'"), "{}", error.to_string());
+    }
+}
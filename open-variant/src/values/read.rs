@@ -1,9 +1,15 @@
 // TODO: make this codebase not care about whether there is more data after
 // the value.
-// TODO: implement function to shrink to the slice where the value is, if people
-// want that.
 
-use super::{BasicType, PrimitiveTypeId};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::metadata::MetadataRef;
+use crate::utils::read_integer;
+
+use super::{BasicType, PrimitiveTypeId, ValueHeader};
 
 /// A view into a variant data buffer.
 #[derive(Clone)]
@@ -29,6 +35,43 @@ impl<'a> VariantRef<'a> {
         (header >> 2).try_into().expect("Invalid PrimitiveTypeId")
     }
 
+    /// The name of the type of the value, such as `int64` or `object`.
+    ///
+    /// Short strings and strings are both `string`.
+    pub fn type_name(&self) -> &'static str {
+        match self.basic_type() {
+            BasicType::ShortString => "string",
+            BasicType::Object => "object",
+            BasicType::Array => "array",
+            BasicType::Primitive => match self.primitive_type_id() {
+                PrimitiveTypeId::Null => "null",
+                PrimitiveTypeId::BoolTrue | PrimitiveTypeId::BoolFalse => "boolean",
+                PrimitiveTypeId::Int8 => "int8",
+                PrimitiveTypeId::Int16 => "int16",
+                PrimitiveTypeId::Int32 => "int32",
+                PrimitiveTypeId::Int64 => "int64",
+                PrimitiveTypeId::Float32 => "float",
+                PrimitiveTypeId::Float64 => "double",
+                PrimitiveTypeId::Decimal4 => "decimal4",
+                PrimitiveTypeId::Decimal8 => "decimal8",
+                PrimitiveTypeId::Decimal16 => "decimal16",
+                PrimitiveTypeId::Date32 => "date",
+                PrimitiveTypeId::TimestampMicro => "timestamp",
+                PrimitiveTypeId::TimestampMicroNTZ => "timestamp_ntz",
+                PrimitiveTypeId::TimestampNanoNTZ => "timestamp_ntz_nanos",
+                PrimitiveTypeId::Binary | PrimitiveTypeId::BinaryFromDictionary => "binary",
+                PrimitiveTypeId::String | PrimitiveTypeId::StringFromDictionary => "string",
+                PrimitiveTypeId::Uuid => "uuid",
+            },
+        }
+    }
+
+    /// Whether the value is a variant null.
+    pub fn is_null(&self) -> bool {
+        self.basic_type() == BasicType::Primitive
+            && self.primitive_type_id() == PrimitiveTypeId::Null
+    }
+
     pub fn get_bool(&self) -> bool {
         match self.primitive_type_id() {
             PrimitiveTypeId::BoolTrue => true,
@@ -61,14 +104,246 @@ impl<'a> VariantRef<'a> {
         f64::from_le_bytes(self.0[1..9].try_into().unwrap())
     }
 
+    /// Nanoseconds since the Unix epoch of a timestamp without timezone.
+    ///
+    /// Microsecond timestamps are converted exactly, or `None` if they are
+    /// outside the range of nanosecond timestamps, about the years 1677 to
+    /// 2262.
+    pub fn get_timestamp_nanos_ntz(&self) -> Option<i64> {
+        let type_id = self.primitive_type_id();
+        if !matches!(
+            type_id,
+            PrimitiveTypeId::TimestampNanoNTZ | PrimitiveTypeId::TimestampMicroNTZ
+        ) {
+            panic!("Not a timestamp without timezone");
+        }
+        let value = i64::from_le_bytes(self.0[1..9].try_into().unwrap());
+        match type_id {
+            PrimitiveTypeId::TimestampMicroNTZ => value.checked_mul(1000),
+            _ => Some(value),
+        }
+    }
+
+    /// The 16 bytes of a UUID, in big-endian order.
+    ///
+    /// See [`uuid::format_uuid`](super::uuid::format_uuid) for the string form.
+    pub fn get_uuid(&self) -> [u8; 16] {
+        if !matches!(self.primitive_type_id(), PrimitiveTypeId::Uuid) {
+            panic!("Not a UUID");
+        }
+        self.0[1..17].try_into().unwrap()
+    }
+
     pub fn get_string<'b>(&'b self) -> &'a str {
         if !matches!(self.primitive_type_id(), PrimitiveTypeId::String) {
             panic!("Not a string");
         }
-        let size = i32::from_le_bytes(self.0[1..5].try_into().unwrap()) as usize;
+        let size = read_integer(self.0, 1, 4);
         let start = 5;
         let end = start + size;
-        std::str::from_utf8(&self.0[start..end]).unwrap()
+        core::str::from_utf8(&self.0[start..end]).unwrap()
+    }
+
+    pub fn get_short_string<'b>(&'b self) -> &'a str {
+        if self.basic_type() != BasicType::ShortString {
+            panic!("Not a short string");
+        }
+        core::str::from_utf8(&self.0[1..self.encoded_len()]).unwrap()
+    }
+
+    /// The value of a string, whether it is a short or long string.
+    ///
+    /// Returns `None` for other values, including strings stored in the
+    /// metadata dictionary (see [`VariantRef::get_str_with_metadata`]), and
+    /// strings that are not valid UTF-8.
+    pub fn get_str(&self) -> Option<&'a str> {
+        core::str::from_utf8(self.string_bytes()?).ok()
+    }
+
+    /// Like [`VariantRef::get_str`], but also resolves strings stored in the
+    /// metadata dictionary.
+    pub fn get_str_with_metadata<'m>(&self, metadata: &MetadataRef<'m>) -> Option<&'m str>
+    where
+        'a: 'm,
+    {
+        if self.basic_type() == BasicType::Primitive
+            && self.primitive_type_id() == PrimitiveTypeId::StringFromDictionary
+        {
+            let id = u32::from_le_bytes(self.0[1..5].try_into().unwrap());
+            return metadata.get_string(id as usize);
+        }
+        self.get_str()
+    }
+
+    /// The number of bytes the value occupies, including its header.
+    ///
+    /// The buffer passed to [`VariantRef::try_new`] may hold more data after
+    /// the value, so this can be smaller than the length of that buffer.
+    pub fn encoded_len(&self) -> usize {
+        match self.basic_type() {
+            BasicType::Primitive => 1 + self.primitive_payload_len(),
+            BasicType::ShortString => 1 + (self.0[0] >> 2) as usize,
+            BasicType::Object => self.get_object().unwrap().encoded_len(),
+            BasicType::Array => self.get_array().unwrap().encoded_len(),
+        }
+    }
+
+    /// Decode the header of the value.
+    ///
+    /// Describes how the value is encoded, without reading its contents. For
+    /// objects and arrays this also reads the element count.
+    pub fn header(&self) -> ValueHeader {
+        let mut header = ValueHeader {
+            basic_type: self.basic_type(),
+            primitive_type_id: None,
+            offset_width: None,
+            field_id_width: None,
+            is_large: false,
+            num_elements: None,
+        };
+        match header.basic_type {
+            BasicType::Primitive => header.primitive_type_id = Some(self.primitive_type_id()),
+            BasicType::ShortString => {}
+            BasicType::Object => {
+                let object = self.get_object().unwrap();
+                header.offset_width = Some(object.offset_width);
+                header.field_id_width = Some(object.field_id_width);
+                header.is_large = object.is_large;
+                header.num_elements = Some(object.len);
+            }
+            BasicType::Array => {
+                let array = self.get_array().unwrap();
+                header.offset_width = Some(array.offset_width);
+                header.is_large = array.is_large;
+                header.num_elements = Some(array.len);
+            }
+        }
+        header
+    }
+
+    /// The bytes of the value, excluding any data after it in the buffer.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        &self.0[..self.encoded_len()]
+    }
+
+    /// Size of the data following the header of a primitive value.
+    fn primitive_payload_len(&self) -> usize {
+        match self.primitive_type_id() {
+            PrimitiveTypeId::Null | PrimitiveTypeId::BoolTrue | PrimitiveTypeId::BoolFalse => 0,
+            PrimitiveTypeId::Int8 => 1,
+            PrimitiveTypeId::Int16 => 2,
+            PrimitiveTypeId::Int32 | PrimitiveTypeId::Float32 | PrimitiveTypeId::Date32 => 4,
+            PrimitiveTypeId::Int64
+            | PrimitiveTypeId::Float64
+            | PrimitiveTypeId::TimestampMicro
+            | PrimitiveTypeId::TimestampMicroNTZ
+            | PrimitiveTypeId::TimestampNanoNTZ => 8,
+            // 1 byte scale + unscaled value
+            PrimitiveTypeId::Decimal4 => 1 + 4,
+            PrimitiveTypeId::Decimal8 => 1 + 8,
+            PrimitiveTypeId::Decimal16 => 1 + 16,
+            PrimitiveTypeId::Uuid => 16,
+            // 4 byte length + data
+            PrimitiveTypeId::Binary | PrimitiveTypeId::String => 4 + read_integer(self.0, 1, 4),
+            // 4 byte id in the metadata dictionary
+            PrimitiveTypeId::BinaryFromDictionary | PrimitiveTypeId::StringFromDictionary => 4,
+        }
+    }
+
+    /// Compare two primitive values.
+    ///
+    /// Integers, decimals and floats of any width are compared by numeric
+    /// value (see [`coerce`](crate::coerce)), and short strings are equal to
+    /// long strings with the same contents. Other primitives are equal if they
+    /// have the same type and encoding. Nulls, objects and arrays are never
+    /// equal to anything.
+    pub fn scalar_eq(&self, other: &VariantRef) -> bool {
+        if let (Some(left), Some(right)) = (self.string_bytes(), other.string_bytes()) {
+            return left == right;
+        }
+        if self.basic_type() != BasicType::Primitive || other.basic_type() != BasicType::Primitive {
+            return false;
+        }
+        if let Some(equal) = self.numeric_eq(other) {
+            return equal;
+        }
+        match (self.primitive_type_id(), other.primitive_type_id()) {
+            (PrimitiveTypeId::Null, _) | (_, PrimitiveTypeId::Null) => false,
+            (PrimitiveTypeId::BinaryFromDictionary | PrimitiveTypeId::StringFromDictionary, _)
+            | (_, PrimitiveTypeId::BinaryFromDictionary | PrimitiveTypeId::StringFromDictionary) => {
+                false
+            }
+            (left, right) if left == right => self.as_bytes() == other.as_bytes(),
+            _ => false,
+        }
+    }
+
+    /// Compare two values structurally, each read with its own metadata.
+    ///
+    /// Objects are equal if they have the same keys with equal values, and
+    /// arrays if they have equal elements in the same order. Primitives are
+    /// compared with [`VariantRef::scalar_eq`], except that nulls are equal to
+    /// each other. The values can use different dictionaries.
+    ///
+    /// # Errors
+    ///
+    /// If a nested value is invalid, or a field id is not in its metadata.
+    pub fn structural_eq<'b>(
+        &self,
+        metadata: &MetadataRef,
+        other: &VariantRef<'b>,
+        other_metadata: &MetadataRef,
+    ) -> Result<bool, String> {
+        fn key<'m>(metadata: &MetadataRef<'m>, field_id: usize) -> Result<&'m str, String> {
+            metadata
+                .get_string(field_id)
+                .ok_or_else(|| format!("Field id {} is not in the metadata", field_id))
+        }
+        let mut stack = vec![(self.clone(), other.clone())];
+        while let Some((left, right)) = stack.pop() {
+            match (left.basic_type(), right.basic_type()) {
+                (BasicType::Object, BasicType::Object) => {
+                    let (left, right) = (left.get_object()?, right.get_object()?);
+                    if left.len() != right.len() {
+                        return Ok(false);
+                    }
+                    // Fields are ordered by key, so equal objects have their
+                    // keys in the same order.
+                    for ((left_id, left_field), (right_id, right_field)) in
+                        left.fields().zip(right.fields())
+                    {
+                        if key(metadata, left_id)? != key(other_metadata, right_id)? {
+                            return Ok(false);
+                        }
+                        stack.push((left_field, right_field));
+                    }
+                }
+                (BasicType::Array, BasicType::Array) => {
+                    let (left, right) = (left.get_array()?, right.get_array()?);
+                    if left.len() != right.len() {
+                        return Ok(false);
+                    }
+                    stack.extend(left.elements().zip(right.elements()));
+                }
+                (BasicType::Object | BasicType::Array, _)
+                | (_, BasicType::Object | BasicType::Array) => return Ok(false),
+                _ if left.is_null() && right.is_null() => {}
+                _ if !left.scalar_eq(&right) => return Ok(false),
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// The UTF-8 bytes of a string value, short or long.
+    pub(crate) fn string_bytes(&self) -> Option<&'a [u8]> {
+        match self.basic_type() {
+            BasicType::ShortString => Some(&self.0[1..self.encoded_len()]),
+            BasicType::Primitive if self.primitive_type_id() == PrimitiveTypeId::String => {
+                Some(&self.0[5..self.encoded_len()])
+            }
+            _ => None,
+        }
     }
 
     pub fn get_object<'b>(&'b self) -> Result<ObjectRef<'a>, String> {
@@ -81,12 +356,19 @@ impl<'a> VariantRef<'a> {
 
     /// Get a field from an object or an element from an array.
     ///
+    /// `metadata` is the dictionary the variant was written with; see
+    /// [`ObjectRef::get_field`].
+    ///
     /// Returns None if the variant is not an object or an array.
     /// Returns an error if the field_id is out of bounds, or if the variant
     /// data is invalid.
-    pub fn field<'b>(&'b self, field_id: usize) -> Result<Option<VariantRef<'a>>, String> {
+    pub fn field<'b>(
+        &'b self,
+        field_id: usize,
+        metadata: &MetadataRef,
+    ) -> Result<Option<VariantRef<'a>>, String> {
         match self.basic_type() {
-            BasicType::Object => Ok(self.get_object()?.get_field(field_id)),
+            BasicType::Object => Ok(self.get_object()?.get_field(field_id, metadata)),
             BasicType::Array => Ok(self.get_array()?.get_element(field_id)),
             _ => Ok(None),
         }
@@ -98,6 +380,7 @@ impl<'a> VariantRef<'a> {
 /// This has been validated that it is an object.
 pub struct ObjectRef<'a> {
     len: usize,
+    is_large: bool,
     field_id_width: u8,
     offset_width: u8,
     field_ids: &'a [u8],
@@ -124,13 +407,13 @@ impl<'a> ObjectRef<'a> {
         data = &data[1..];
 
         let len = if is_large == 1 {
-            // i32 for number of elements
-            let len = i32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+            // u32 for number of elements
+            let len = read_integer(data, 0, 4);
             data = &data[4..];
             len
         } else {
-            // i8 for number of elements
-            let len = i8::from_le_bytes(data[..1].try_into().unwrap()) as usize;
+            // u8 for number of elements
+            let len = data[0] as usize;
             data = &data[1..];
             len
         };
@@ -145,6 +428,7 @@ impl<'a> ObjectRef<'a> {
 
         Ok(Self {
             len,
+            is_large: is_large == 1,
             field_id_width,
             offset_width,
             field_ids,
@@ -153,23 +437,118 @@ impl<'a> ObjectRef<'a> {
         })
     }
 
-    pub fn get_field<'b>(&'b self, field_id: usize) -> Option<VariantRef<'a>> {
-        // Fields are required to be sorted by field_id, so we can binary search
+    /// The number of bytes the object occupies, including its header.
+    pub fn encoded_len(&self) -> usize {
+        let num_elements_width = if self.is_large { 4 } else { 1 };
+        // The final offset is the total size of the field values.
+        1 + num_elements_width
+            + self.field_ids.len()
+            + self.offsets.len()
+            + self.get_offset(self.len)
+    }
+
+    /// Get a field by field id.
+    ///
+    /// `metadata` is the dictionary the object was written with. With a
+    /// sorted dictionary this binary searches the field ids. Otherwise the ids
+    /// are in insertion order rather than key order, so it scans them.
+    pub fn get_field<'b>(
+        &'b self,
+        field_id: usize,
+        metadata: &MetadataRef,
+    ) -> Option<VariantRef<'a>> {
+        self.get_field_sorted(field_id, metadata.sorted_strings())
+    }
+
+    /// [`ObjectRef::get_field`], with the sorted flag of the metadata.
+    pub(crate) fn get_field_sorted<'b>(
+        &'b self,
+        field_id: usize,
+        sorted: bool,
+    ) -> Option<VariantRef<'a>> {
+        let index = self.field_index(field_id, sorted)?;
+        Some(VariantRef(self.get_value(index)))
+    }
+
+    /// The index of the field with the given id.
+    ///
+    /// With a sorted metadata dictionary, fields are sorted by field id, so
+    /// this binary searches. Otherwise it scans the field ids.
+    pub(crate) fn field_index(&self, field_id: usize, sorted: bool) -> Option<usize> {
         let field_id = field_id as u64;
+        if !sorted {
+            return (0..self.len).find(|idx| self.get_field_id(*idx) == field_id);
+        }
         let mut left = 0;
-        let mut right = self.len as u64;
+        let mut right = self.len;
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_field_id = self.get_field_id(mid as usize);
-            match mid_field_id.cmp(&field_id) {
-                std::cmp::Ordering::Equal => return Some(VariantRef(self.get_value(mid as usize))),
-                std::cmp::Ordering::Less => left = mid + 1,
-                std::cmp::Ordering::Greater => right = mid,
+            match self.get_field_id(mid).cmp(&field_id) {
+                core::cmp::Ordering::Equal => return Some(mid),
+                core::cmp::Ordering::Less => left = mid + 1,
+                core::cmp::Ordering::Greater => right = mid,
             }
         }
         None
     }
 
+    /// The number of fields.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The field ids, in the order the fields are stored. With a sorted
+    /// metadata dictionary they are in increasing order.
+    pub fn field_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).map(|idx| self.get_field_id(idx) as usize)
+    }
+
+    /// Iterate over the fields as pairs of field id and value, in the order
+    /// they are stored (which is the order of the field names).
+    pub fn fields<'b>(&'b self) -> impl Iterator<Item = (usize, VariantRef<'a>)> + 'b {
+        (0..self.len).map(|idx| {
+            let field_id = self.get_field_id(idx) as usize;
+            let value = VariantRef(self.get_value(idx));
+            (field_id, VariantRef(value.as_bytes()))
+        })
+    }
+
+    /// Get a field by name.
+    ///
+    /// With a sorted metadata dictionary this resolves the field id and then
+    /// uses [`ObjectRef::get_field`]. Otherwise, fields are ordered by name
+    /// rather than by id, so this binary searches comparing the names.
+    pub fn find_field(&self, key: &str, metadata: &MetadataRef) -> Option<VariantRef<'a>> {
+        if metadata.sorted_strings() {
+            return self.get_field(metadata.find_string(key)?, metadata);
+        }
+        let mut left = 0;
+        let mut right = self.len;
+        while left < right {
+            let mid = left + (right - left) / 2;
+            let mid_key = metadata.get_string(self.get_field_id(mid) as usize)?;
+            match mid_key.cmp(key) {
+                core::cmp::Ordering::Equal => return Some(VariantRef(self.get_value(mid))),
+                core::cmp::Ordering::Less => left = mid + 1,
+                core::cmp::Ordering::Greater => right = mid,
+            }
+        }
+        None
+    }
+
+    /// Bind the metadata the object was written with, to access fields by
+    /// name.
+    pub fn with_metadata<'m>(self, metadata: &'m MetadataRef<'m>) -> BoundObjectRef<'a, 'm> {
+        BoundObjectRef {
+            object: self,
+            metadata,
+        }
+    }
+
     fn get_value<'b>(&'b self, idx: usize) -> &'a [u8] {
         let start = self.get_offset(idx);
 
@@ -187,34 +566,171 @@ impl<'a> ObjectRef<'a> {
 
     fn get_field_id(&'a self, idx: usize) -> u64 {
         let start = idx * self.field_id_width as usize;
-        let end = start + self.field_id_width as usize;
-        match self.field_id_width {
-            1 => u8::from_le_bytes(self.field_ids[start..end].try_into().unwrap()) as u64,
-            2 => u16::from_le_bytes(self.field_ids[start..end].try_into().unwrap()) as u64,
-            4 => u32::from_le_bytes(self.field_ids[start..end].try_into().unwrap()) as u64,
-            8 => u64::from_le_bytes(self.field_ids[start..end].try_into().unwrap()),
-            _ => unreachable!(),
-        }
+        read_integer(self.field_ids, start, self.field_id_width) as u64
     }
 
     fn get_offset(&'a self, idx: usize) -> usize {
         let start = idx * self.offset_width as usize;
-        let end = start + self.offset_width as usize;
-        match self.offset_width {
-            1 => u8::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            2 => u16::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            4 => u32::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            8 => u64::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            _ => unreachable!(),
+        read_integer(self.offsets, start, self.offset_width)
+    }
+}
+
+/// Objects whose field ids take more bytes than this are not cached by
+/// [`FieldLookup`], as comparing them would cost about as much as a search.
+const MAX_SHAPE_BYTES: usize = 256;
+
+/// The number of object shapes a [`FieldLookup`] remembers.
+const SHAPE_CACHE_SIZE: usize = 8;
+
+/// Gets one field from many objects that share a metadata dictionary.
+///
+/// Objects in a batch often have the same fields, in which case the field is
+/// at the same index in each of them. This remembers the index of the field
+/// for the last few distinct lists of field ids ("shapes") it has seen, so
+/// objects with a repeated shape skip the search for the field entirely.
+/// Shapes are compared byte for byte, so a cached index is always correct.
+///
+/// ```rust
+/// use open_variant::metadata::{build_metadata, MetadataRef};
+/// use open_variant::values::write::ObjectBuilder;
+/// use open_variant::values::{FieldLookup, VariantRef};
+///
+/// let metadata = build_metadata(["id", "name"].into_iter());
+/// let metadata = MetadataRef::new(&metadata);
+/// let mut buffer = Vec::new();
+/// let mut builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+/// builder.append_i64("id", 1).unwrap();
+/// builder.append_string("name", "a").unwrap();
+/// builder.finish();
+///
+/// let mut lookup = FieldLookup::new("name", &metadata).unwrap();
+/// let object = VariantRef::try_new(&buffer).unwrap().get_object().unwrap();
+/// assert_eq!(lookup.get(&object).unwrap().get_string(), "a");
+/// ```
+#[derive(Debug, Clone)]
+pub struct FieldLookup {
+    field_id: usize,
+    sorted: bool,
+    shapes: Vec<Shape>,
+    /// The next slot of `shapes` to replace once it is full.
+    next: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Shape {
+    field_id_width: u8,
+    field_ids: Vec<u8>,
+    index: Option<usize>,
+}
+
+impl FieldLookup {
+    /// A lookup of `key` in objects written with `metadata`, or `None` if the
+    /// key is not in the dictionary.
+    pub fn new(key: &str, metadata: &MetadataRef) -> Option<Self> {
+        Some(Self::with_field_id(metadata.find_string(key)?, metadata))
+    }
+
+    /// A lookup of the key with id `field_id` in `metadata`, for keys already
+    /// found in the dictionary.
+    pub fn with_field_id(field_id: usize, metadata: &MetadataRef) -> Self {
+        Self {
+            field_id,
+            sorted: metadata.sorted_strings(),
+            shapes: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn field_id(&self) -> usize {
+        self.field_id
+    }
+
+    /// Get the field from an object written with the metadata of this lookup.
+    pub fn get<'a>(&mut self, object: &ObjectRef<'a>) -> Option<VariantRef<'a>> {
+        let cached = self.shapes.iter().find(|shape| {
+            shape.field_id_width == object.field_id_width && shape.field_ids == object.field_ids
+        });
+        let index = match cached {
+            Some(shape) => shape.index,
+            None => {
+                let index = object.field_index(self.field_id, self.sorted);
+                if object.field_ids.len() <= MAX_SHAPE_BYTES {
+                    self.insert(Shape {
+                        field_id_width: object.field_id_width,
+                        field_ids: object.field_ids.to_vec(),
+                        index,
+                    });
+                }
+                index
+            }
+        };
+        Some(VariantRef(object.get_value(index?)))
+    }
+
+    fn insert(&mut self, shape: Shape) {
+        if self.shapes.len() < SHAPE_CACHE_SIZE {
+            self.shapes.push(shape);
+        } else {
+            self.shapes[self.next] = shape;
+            self.next = (self.next + 1) % SHAPE_CACHE_SIZE;
         }
     }
 }
 
+/// An [`ObjectRef`] bound to its metadata, so fields can be accessed by name.
+///
+/// Created with [`ObjectRef::with_metadata`].
+pub struct BoundObjectRef<'a, 'm> {
+    object: ObjectRef<'a>,
+    metadata: &'m MetadataRef<'m>,
+}
+
+impl<'a, 'm> BoundObjectRef<'a, 'm> {
+    /// Get a field by name. See [`ObjectRef::find_field`].
+    pub fn get(&self, key: &str) -> Option<VariantRef<'a>> {
+        self.object.find_field(key, self.metadata)
+    }
+
+    /// The field names, in the order they are stored.
+    pub fn keys<'b>(&'b self) -> impl Iterator<Item = &'m str> + 'b {
+        self.entries().map(|(key, _)| key)
+    }
+
+    /// Iterate over the fields as pairs of name and value, in the order they
+    /// are stored (which is the order of the names).
+    ///
+    /// # Panics
+    ///
+    /// If a field id is not in the metadata dictionary.
+    pub fn entries<'b>(&'b self) -> impl Iterator<Item = (&'m str, VariantRef<'a>)> + 'b {
+        self.object.fields().map(|(field_id, value)| {
+            let key = self
+                .metadata
+                .get_string(field_id)
+                .expect("field id is in the metadata dictionary");
+            (key, value)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.object.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.object.is_empty()
+    }
+
+    pub fn object(&self) -> &ObjectRef<'a> {
+        &self.object
+    }
+}
+
 /// A view into an array variant data buffer.
 ///
 /// This has been validated that it is an array.
 pub struct ArrayRef<'a> {
     len: usize,
+    is_large: bool,
     offset_width: u8,
     offsets: &'a [u8],
     values: &'a [u8],
@@ -234,13 +750,13 @@ impl<'a> ArrayRef<'a> {
         data = &data[1..];
 
         let len = if is_large {
-            // i32 for number of elements
-            let len = i32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+            // u32 for number of elements
+            let len = read_integer(data, 0, 4);
             data = &data[4..];
             len
         } else {
-            // i8 for number of elements
-            let len = i8::from_le_bytes(data[..1].try_into().unwrap()) as usize;
+            // u8 for number of elements
+            let len = data[0] as usize;
             data = &data[1..];
             len
         };
@@ -251,12 +767,50 @@ impl<'a> ArrayRef<'a> {
 
         Ok(Self {
             len,
+            is_large,
             offset_width,
             offsets,
             values,
         })
     }
 
+    /// The number of bytes the array occupies, including its header.
+    pub fn encoded_len(&self) -> usize {
+        let num_elements_width = if self.is_large { 4 } else { 1 };
+        // The final offset is the total size of the element values.
+        1 + num_elements_width + self.offsets.len() + self.get_offset(self.len)
+    }
+
+    /// The number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the elements.
+    pub fn elements<'b>(&'b self) -> impl Iterator<Item = VariantRef<'a>> + 'b {
+        (0..self.len).map(|index| self.get_element(index).unwrap())
+    }
+
+    /// Whether any element is equal to `needle`.
+    ///
+    /// Only primitive values are compared, using [`VariantRef::scalar_eq`].
+    /// Strings are compared by their contents, with elements stored in the
+    /// dictionary resolved through `metadata`. Elements are checked by their
+    /// header before their data is read, so elements of a different type are
+    /// skipped cheaply.
+    pub fn contains(&self, needle: &VariantRef, metadata: &MetadataRef) -> bool {
+        match needle.get_str() {
+            Some(needle) => self
+                .elements()
+                .any(|element| element.get_str_with_metadata(metadata) == Some(needle)),
+            None => self.elements().any(|element| element.scalar_eq(needle)),
+        }
+    }
+
     pub fn get_element<'b>(&'b self, index: usize) -> Option<VariantRef<'a>> {
         if index >= self.len {
             return None;
@@ -268,13 +822,6 @@ impl<'a> ArrayRef<'a> {
 
     fn get_offset(&self, idx: usize) -> usize {
         let start = idx * self.offset_width as usize;
-        let end = start + self.offset_width as usize;
-        match self.offset_width {
-            1 => u8::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            2 => u16::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            4 => u32::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            8 => u64::from_le_bytes(self.offsets[start..end].try_into().unwrap()) as usize,
-            _ => unreachable!(),
-        }
+        read_integer(self.offsets, start, self.offset_width)
     }
 }
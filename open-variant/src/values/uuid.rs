@@ -0,0 +1,83 @@
+//! Conversion between UUID values and their canonical string form,
+//! `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+
+use alloc::string::String;
+
+/// Byte offsets in the canonical form where a `-` is expected.
+const HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
+/// Parse a UUID in canonical form into its 16 big-endian bytes.
+///
+/// Hex digits may be upper or lower case. Returns `None` for any other
+/// string, including the braced and unhyphenated forms.
+pub fn parse_uuid(value: &str) -> Option<[u8; 16]> {
+    let bytes = value.as_bytes();
+    if bytes.len() != 36 || HYPHENS.iter().any(|i| bytes[*i] != b'-') {
+        return None;
+    }
+    let mut digits = bytes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !HYPHENS.contains(i))
+        .map(|(_, digit)| (*digit as char).to_digit(16));
+    let mut uuid = [0; 16];
+    for byte in &mut uuid {
+        let high = digits.next()??;
+        let low = digits.next()??;
+        *byte = (high << 4 | low) as u8;
+    }
+    Some(uuid)
+}
+
+/// Format a UUID in lower case canonical form.
+pub fn format_uuid(value: &[u8; 16]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut output = String::with_capacity(36);
+    for (i, byte) in value.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            output.push('-');
+        }
+        output.push(DIGITS[(byte >> 4) as usize] as char);
+        output.push(DIGITS[(byte & 0xf) as usize] as char);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::values::write::write_uuid;
+    use crate::values::{PrimitiveTypeId, VariantRef};
+
+    #[test]
+    fn test_uuid_round_trip() {
+        let value = "f81d4fae-7dec-11d0-a765-00a0c91e6bf6";
+        let bytes = parse_uuid(value).unwrap();
+        assert_eq!(bytes[0], 0xf8);
+        assert_eq!(bytes[15], 0xf6);
+        assert_eq!(format_uuid(&bytes), value);
+        assert_eq!(parse_uuid(&value.to_uppercase()), Some(bytes));
+
+        let mut buffer = Vec::new();
+        write_uuid(&mut buffer, &bytes);
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Uuid);
+        assert_eq!(variant.type_name(), "uuid");
+        assert_eq!(variant.encoded_len(), 17);
+        assert_eq!(variant.get_uuid(), bytes);
+    }
+
+    #[test]
+    fn test_parse_invalid_uuid() {
+        for value in [
+            "",
+            "f81d4fae7dec11d0a76500a0c91e6bf6",
+            "{f81d4fae-7dec-11d0-a765-00a0c91e6bf6}",
+            "f81d4fae-7dec-11d0-a765-00a0c91e6bfg",
+            "f81d4fae-7dec-11d0-a765+00a0c91e6bf6",
+            "f81d4fae-7dec-11d0-a765-00a0c91e6bf6 ",
+        ] {
+            assert_eq!(parse_uuid(value), None, "{}", value);
+        }
+    }
+}
@@ -1,6 +1,48 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use crate::path::PathElement;
 use crate::{metadata::MetadataRef, utils::write_integer};
 
-use super::{BasicType, PrimitiveTypeId};
+use super::{ArrayRef, BasicType, PrimitiveTypeId, VariantRef};
+
+/// A sink that encoded values are written to.
+///
+/// The functions and builders in this module write to any
+/// `VariantValueWriter`, so values can be written straight to where they are
+/// stored, such as a memory-mapped file, a fixed arena or an Arrow buffer,
+/// rather than to a `Vec` and copied. Writing can't fail, so a sink with a
+/// fixed capacity should panic when it is full.
+pub trait VariantValueWriter {
+    /// Append bytes.
+    fn extend_from_slice(&mut self, bytes: &[u8]);
+
+    /// Append one byte.
+    fn push(&mut self, byte: u8) {
+        self.extend_from_slice(&[byte]);
+    }
+
+    /// Reserve space for at least `additional` more bytes. This is only a
+    /// hint, and does nothing by default.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+}
+
+impl VariantValueWriter for Vec<u8> {
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        Vec::extend_from_slice(self, bytes);
+    }
+
+    fn push(&mut self, byte: u8) {
+        Vec::push(self, byte);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+}
 
 fn primitive_header(primitive_type_id: PrimitiveTypeId) -> u8 {
     // 7                                  2 1          0
@@ -11,12 +53,12 @@ fn primitive_header(primitive_type_id: PrimitiveTypeId) -> u8 {
     basic_type | (primitive_type_id as u8) << 2
 }
 
-pub fn write_null(buffer: &mut Vec<u8>) {
+pub fn write_null(buffer: &mut (impl VariantValueWriter + ?Sized)) {
     let header = primitive_header(PrimitiveTypeId::Null);
     buffer.push(header);
 }
 
-pub fn write_bool(buffer: &mut Vec<u8>, value: bool) {
+pub fn write_bool(buffer: &mut (impl VariantValueWriter + ?Sized), value: bool) {
     // Booleans are just headers
     let header = match value {
         true => primitive_header(PrimitiveTypeId::BoolTrue),
@@ -25,31 +67,54 @@ pub fn write_bool(buffer: &mut Vec<u8>, value: bool) {
     buffer.push(header);
 }
 
-// TODO: Make generic and support others.
-pub fn write_i64(buffer: &mut Vec<u8>, value: i64) {
+pub fn write_i8(buffer: &mut (impl VariantValueWriter + ?Sized), value: i8) {
+    let header = primitive_header(PrimitiveTypeId::Int8);
+    buffer.push(header);
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_i16(buffer: &mut (impl VariantValueWriter + ?Sized), value: i16) {
+    let header = primitive_header(PrimitiveTypeId::Int16);
+    buffer.push(header);
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_i32(buffer: &mut (impl VariantValueWriter + ?Sized), value: i32) {
+    let header = primitive_header(PrimitiveTypeId::Int32);
+    buffer.push(header);
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_i64(buffer: &mut (impl VariantValueWriter + ?Sized), value: i64) {
     let header = primitive_header(PrimitiveTypeId::Int64);
     buffer.push(header);
     buffer.extend_from_slice(&value.to_le_bytes());
 }
 
-pub fn write_f64(buffer: &mut Vec<u8>, value: f64) {
+pub fn write_f32(buffer: &mut (impl VariantValueWriter + ?Sized), value: f32) {
+    let header = primitive_header(PrimitiveTypeId::Float32);
+    buffer.push(header);
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_f64(buffer: &mut (impl VariantValueWriter + ?Sized), value: f64) {
     let header = primitive_header(PrimitiveTypeId::Float64);
     buffer.push(header);
     buffer.extend_from_slice(&value.to_le_bytes());
 }
 
-pub fn write_decimal(buffer: &mut Vec<u8>, value: i128, scale: u8) {
+pub fn write_decimal(buffer: &mut (impl VariantValueWriter + ?Sized), value: i128, scale: u8) {
     if scale > 38 {
         panic!("Decimal scale must be between 0 and 38.");
     }
-    if value < i32::MAX as i128 {
+    if let Ok(value) = i32::try_from(value) {
         buffer.push(primitive_header(PrimitiveTypeId::Decimal4));
         buffer.push(scale.to_le());
-        buffer.extend_from_slice(&(value as i32).to_le_bytes());
-    } else if value < i64::MAX as i128 {
+        buffer.extend_from_slice(&value.to_le_bytes());
+    } else if let Ok(value) = i64::try_from(value) {
         buffer.push(primitive_header(PrimitiveTypeId::Decimal8));
         buffer.push(scale.to_le());
-        buffer.extend_from_slice(&(value as i64).to_le_bytes());
+        buffer.extend_from_slice(&value.to_le_bytes());
     } else {
         buffer.push(primitive_header(PrimitiveTypeId::Decimal16));
         buffer.push(scale.to_le());
@@ -57,78 +122,121 @@ pub fn write_decimal(buffer: &mut Vec<u8>, value: i128, scale: u8) {
     };
 }
 
-pub fn write_string(buffer: &mut Vec<u8>, value: &str) {
+pub fn write_string(buffer: &mut (impl VariantValueWriter + ?Sized), value: &str) {
     let header = primitive_header(PrimitiveTypeId::String);
     buffer.push(header);
     buffer.extend_from_slice(&(value.len() as i32).to_le_bytes());
     buffer.extend_from_slice(value.as_bytes());
 }
 
+pub fn write_binary(buffer: &mut (impl VariantValueWriter + ?Sized), value: &[u8]) {
+    let header = primitive_header(PrimitiveTypeId::Binary);
+    buffer.push(header);
+    buffer.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    buffer.extend_from_slice(value);
+}
+
+/// Write a date, in days since the Unix epoch.
+pub fn write_date32(buffer: &mut (impl VariantValueWriter + ?Sized), value: i32) {
+    buffer.push(primitive_header(PrimitiveTypeId::Date32));
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Write a timestamp with timezone, in microseconds since the Unix epoch in
+/// UTC.
+pub fn write_timestamp_micros(buffer: &mut (impl VariantValueWriter + ?Sized), value: i64) {
+    buffer.push(primitive_header(PrimitiveTypeId::TimestampMicro));
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Write a timestamp without timezone, in microseconds since the Unix epoch.
+pub fn write_timestamp_micros_ntz(buffer: &mut (impl VariantValueWriter + ?Sized), value: i64) {
+    buffer.push(primitive_header(PrimitiveTypeId::TimestampMicroNTZ));
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Write a timestamp without timezone, in nanoseconds since the Unix epoch.
+pub fn write_timestamp_nanos_ntz(buffer: &mut (impl VariantValueWriter + ?Sized), value: i64) {
+    buffer.push(primitive_header(PrimitiveTypeId::TimestampNanoNTZ));
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Write a UUID, given as 16 big-endian bytes.
+///
+/// See [`uuid::parse_uuid`](super::uuid::parse_uuid) to get the bytes of a
+/// UUID string.
+pub fn write_uuid(buffer: &mut (impl VariantValueWriter + ?Sized), value: &[u8; 16]) {
+    buffer.push(primitive_header(PrimitiveTypeId::Uuid));
+    buffer.extend_from_slice(value);
+}
+
+/// Check a count or size fits in the 4-byte unsigned integers of the format.
+fn check_limit(value: usize, what: &str) -> Result<(), String> {
+    if value > u32::MAX as usize {
+        return Err(format!(
+            "A variant value can have at most {} {}, got {}",
+            u32::MAX,
+            what,
+            value
+        ));
+    }
+    Ok(())
+}
+
 // See: https://github.com/apache/spark/tree/master/common/variant#value-data-for-array-basic_type3
-pub struct ArrayBuilder<'a> {
-    buffer: &'a mut Vec<u8>,
-    field_offset_width: u8,
+pub struct ArrayBuilder<'a, W: VariantValueWriter + ?Sized = Vec<u8>> {
+    buffer: &'a mut W,
+    // The end offset of each element. (The first offset is always 0.)
+    offsets: Vec<usize>,
     // This is used to hold the value data as we collect. Once finished, it will
     // be appended to the buffer.
     tmp_buffer: Vec<u8>,
 }
 
 // See: https://github.com/apache/spark/tree/master/common/variant#value-data-for-object-basic_type2
-impl<'a> ArrayBuilder<'a> {
-    pub fn new(buffer: &'a mut Vec<u8>, num_elements: usize) -> Self {
-        let field_offset_width = crate::utils::determine_byte_width(num_elements);
-        let is_large = if num_elements > i8::MAX as usize {
-            1
-        } else {
-            0
-        };
-        let num_elements_width = if is_large == 1 { 4 } else { 1 };
-
-        let mut capacity_needed = 1 + num_elements_width; // header plus num_elements
-        capacity_needed += field_offset_width as usize * (num_elements + 1); // offsets
-        capacity_needed += num_elements; // for value headers
-        buffer.reserve(capacity_needed);
-
-        // Array header layout
-        //  5         3  2  1     0
-        // +-----------+---+-------+
-        // |           |   |       |
-        // +-----------+---+-------+
-        //               ^     ^
-        //               |     +-- field_offset_size_minus_one
-        //               +-- is_large
-        let header = is_large << 2 | (field_offset_width - 1);
-        let header = header << 2 | BasicType::Array as u8;
-        buffer.push(header);
-
-        write_integer(buffer, num_elements, num_elements_width as u8);
-        // Offsets always start at 0.
-        write_integer(buffer, 0, field_offset_width);
+impl<'a, W: VariantValueWriter + ?Sized> ArrayBuilder<'a, W> {
+    /// Start an array, with space for `num_elements` elements.
+    ///
+    /// The array is written when it is finished, with the number of elements
+    /// actually appended.
+    pub fn new(buffer: &'a mut W, num_elements: usize) -> Self {
         Self {
             buffer,
-            field_offset_width,
+            offsets: Vec::with_capacity(num_elements),
             tmp_buffer: Vec::new(),
         }
     }
 
     pub fn append_value(&mut self, value: &[u8]) {
         self.tmp_buffer.extend_from_slice(value);
-        let size = self.tmp_buffer.len();
-        write_integer(self.buffer, size, self.field_offset_width);
+        self.offsets.push(self.tmp_buffer.len());
     }
 
+    /// Write the array.
+    ///
+    /// # Panics
+    ///
+    /// If the array exceeds the limits of the format, see
+    /// [`ArrayBuilder::try_finish`].
     pub fn finish(self) {
+        if let Err(error) = self.try_finish() {
+            panic!("{}", error);
+        }
+    }
+
+    /// Write the array, or return an error if it has more than `u32::MAX`
+    /// elements or bytes of elements. On error, nothing is written.
+    pub fn try_finish(self) -> Result<(), String> {
+        write_array_header(self.buffer, &self.offsets)?;
         // Append the collected data.
         self.buffer.extend_from_slice(&self.tmp_buffer);
+        Ok(())
     }
 }
 
 /// TODO: how can we make the builders re-useable?
-pub struct ObjectBuilder<'a> {
-    buffer: &'a mut Vec<u8>,
-    // Offset into buffer where the header is. This is used to update the width
-    // of the field offset values.
-    header_offset: usize,
+pub struct ObjectBuilder<'a, W: VariantValueWriter + ?Sized = Vec<u8>> {
+    buffer: &'a mut W,
     // Pairs of field id and offset. (The final offset is managed separately.)
     field_id_and_offsets: Vec<(usize, usize)>,
     // This is used to hold the value data as we collect. Once finished, it will
@@ -137,56 +245,22 @@ pub struct ObjectBuilder<'a> {
     metadata: &'a MetadataRef<'a>,
 }
 
-// We should pass down the object size
-// Then we can pre-allocate for the field ids, offsets and value headers.
-//
 // The field ids and field offsets must be in lexicographical order of the
 // corresponding field names in the metadata dictionary. We can assume the field
 // ids themselves have already been sorted, and thus we just need to sort the
 // field ids in numeric order.
-impl<'a> ObjectBuilder<'a> {
+impl<'a, W: VariantValueWriter + ?Sized> ObjectBuilder<'a, W> {
+    /// Start an object, with space for `num_elements` fields.
+    ///
+    /// The object is written when it is finished, with the number of fields
+    /// actually appended.
     pub fn with_capacity(
-        buffer: &'a mut Vec<u8>,
+        buffer: &'a mut W,
         metadata: &'a MetadataRef<'a>,
-        num_elements: usize, // TODO: make this function like capacity, and make not required.
+        num_elements: usize,
     ) -> Self {
-        // Object Header
-        //   5   4  3     2 1     0
-        // +---+---+-------+-------+
-        // |   |   |       |       |
-        // +---+---+-------+-------+
-        //       ^     ^       ^
-        //       |     |       +-- field_offset_size_minus_one
-        //       |     +-- field_id_size_minus_one
-        //       +-- is_large
-        let is_large = if num_elements > i8::MAX as usize {
-            1 // Use 64-bit size
-        } else {
-            0 // Use 8-bit size
-        };
-        let num_elements_width = if is_large > 0 { 4 } else { 1 };
-        let field_id_size = crate::utils::determine_byte_width(num_elements);
-        // We skip field offset until the end.
-        let header = is_large << 4 | (field_id_size - 1) << 2;
-        let header = header << 2 | BasicType::Object as u8;
-
-        // TODO: this is all deferred so we might as well do a reservation in finish()
-        // Reserve lower bound of space needed for object.
-        let mut needed_capacity = 1 + num_elements_width; // for header and size
-        needed_capacity += num_elements * field_id_size as usize; // for field ids
-        needed_capacity += 1 + num_elements; // for field offsets (We don't know width, so we assume 1 byte for now.)
-        needed_capacity += num_elements; // for value headers
-        buffer.reserve(needed_capacity);
-
-        let header_offset = buffer.len();
-        buffer.push(header);
-
-        // Append num elements
-        write_integer(buffer, num_elements, num_elements_width as u8);
-
         Self {
             buffer,
-            header_offset,
             field_id_and_offsets: Vec::with_capacity(num_elements),
             tmp_buffer: Vec::new(),
             metadata,
@@ -214,6 +288,16 @@ impl<'a> ObjectBuilder<'a> {
         self.append(field_name, |buffer| buffer.extend_from_slice(value))
     }
 
+    /// Append a value using an already resolved field id.
+    ///
+    /// The caller is responsible for the id being valid in the metadata
+    /// dictionary.
+    pub fn append_value_with_field_id(&mut self, field_id: usize, value: &[u8]) {
+        let offset = self.tmp_buffer.len();
+        self.field_id_and_offsets.push((field_id, offset));
+        self.tmp_buffer.extend_from_slice(value);
+    }
+
     pub fn append_string(&mut self, field_name: &str, value: &str) -> Result<(), String> {
         self.append(field_name, |buffer| write_string(buffer, value))
     }
@@ -235,50 +319,480 @@ impl<'a> ObjectBuilder<'a> {
         self.append(field_name, |buffer| write_decimal(buffer, value, scale))
     }
 
-    pub fn finish(mut self) {
-        let final_offset = self.tmp_buffer.len();
-        let offset_width = crate::utils::determine_byte_width(final_offset);
-        let max_field_id = self
-            .field_id_and_offsets
-            .iter()
-            .map(|(field_id, _offset)| *field_id)
-            .max()
-            .unwrap_or_default();
-        let field_id_width = crate::utils::determine_byte_width(max_field_id);
+    /// Write the object.
+    ///
+    /// # Panics
+    ///
+    /// If the object exceeds the limits of the format, see
+    /// [`ObjectBuilder::try_finish`].
+    pub fn finish(self) {
+        if let Err(error) = self.try_finish() {
+            panic!("{}", error);
+        }
+    }
+
+    /// Write the object, or return an error if it has more than `u32::MAX`
+    /// fields or bytes of field values. On error, nothing is written.
+    pub fn try_finish(mut self) -> Result<(), String> {
+        write_object_header(
+            self.buffer,
+            self.metadata,
+            &mut self.field_id_and_offsets,
+            self.tmp_buffer.len(),
+        )?;
+        self.buffer.extend_from_slice(&self.tmp_buffer);
+        Ok(())
+    }
+}
+
+/// Write the header of an array whose elements end at `offsets`, relative
+/// to the start of their data. The caller appends the data, the elements
+/// concatenated, after the header.
+///
+/// This lets writers that already have the elements in one buffer skip
+/// [`ArrayBuilder`] and its copy of the elements. Space for the data is
+/// reserved too.
+///
+/// # Errors
+///
+/// If the array has more than `u32::MAX` elements or bytes of elements. On
+/// error, nothing is written.
+pub fn write_array_header(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    offsets: &[usize],
+) -> Result<(), String> {
+    let num_elements = offsets.len();
+    check_limit(num_elements, "elements")?;
+    // The largest offset is the size of all the elements.
+    let data_len = offsets.last().copied().unwrap_or(0);
+    check_limit(data_len, "bytes of elements")?;
+
+    // The number of elements is an unsigned byte unless it needs 4 bytes.
+    let is_large = u8::from(num_elements > u8::MAX as usize);
+    let num_elements_width = if is_large == 1 { 4 } else { 1 };
+    let offset_width = crate::utils::determine_byte_width(data_len);
+
+    let mut capacity_needed = 1 + num_elements_width; // header plus num_elements
+    capacity_needed += offset_width as usize * (num_elements + 1); // offsets
+    capacity_needed += data_len; // values
+    buffer.reserve(capacity_needed);
+
+    // Array header layout
+    //  5         3  2  1     0
+    // +-----------+---+-------+
+    // |           |   |       |
+    // +-----------+---+-------+
+    //               ^     ^
+    //               |     +-- field_offset_size_minus_one
+    //               +-- is_large
+    let header = is_large << 2 | (offset_width - 1);
+    let header = header << 2 | BasicType::Array as u8;
+    buffer.push(header);
+
+    write_integer(buffer, num_elements, num_elements_width as u8);
+    // Offsets always start at 0.
+    write_integer(buffer, 0, offset_width);
+    for offset in offsets {
+        write_integer(buffer, *offset, offset_width);
+    }
+    Ok(())
+}
+
+/// Write the header of an object whose fields are the pairs of field id and
+/// offset in `fields`, with `data_len` bytes of field values. The caller
+/// appends the field values after the header.
+///
+/// Offsets are relative to the start of the field values, and don't have
+/// to be increasing. `fields` is sorted by key in place, as the format
+/// requires. Like [`write_array_header`], this lets writers skip
+/// [`ObjectBuilder`] and its copy of the values.
+///
+/// # Errors
+///
+/// If the object has more than `u32::MAX` fields or bytes of field values.
+/// On error, nothing is written.
+pub fn write_object_header(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    metadata: &MetadataRef,
+    fields: &mut [(usize, usize)],
+    data_len: usize,
+) -> Result<(), String> {
+    let num_elements = fields.len();
+    check_limit(num_elements, "fields")?;
+    check_limit(data_len, "bytes of field values")?;
+
+    let is_large = if num_elements > u8::MAX as usize {
+        1 // Use a 4-byte size
+    } else {
+        0 // Use a 1-byte size
+    };
+    let num_elements_width = if is_large > 0 { 4 } else { 1 };
+    let offset_width = crate::utils::determine_byte_width(data_len);
+    let max_field_id = fields
+        .iter()
+        .map(|(field_id, _offset)| *field_id)
+        .max()
+        .unwrap_or_default();
+    let field_id_width = crate::utils::determine_byte_width(max_field_id);
+
+    let mut needed_capacity = 1 + num_elements_width; // for header and size
+    needed_capacity += field_id_width as usize * num_elements; // for field ids
+    needed_capacity += offset_width as usize * (num_elements + 1); // for field offsets
+    needed_capacity += data_len; // for field values
+    buffer.reserve(needed_capacity);
+
+    // Object Header
+    //   5   4  3     2 1     0
+    // +---+---+-------+-------+
+    // |   |   |       |       |
+    // +---+---+-------+-------+
+    //       ^     ^       ^
+    //       |     |       +-- field_offset_size_minus_one
+    //       |     +-- field_id_size_minus_one
+    //       +-- is_large
+    let header = is_large << 4 | (field_id_width - 1) << 2 | (offset_width - 1);
+    let header = header << 2 | BasicType::Object as u8;
+    buffer.push(header);
+    write_integer(buffer, num_elements, num_elements_width as u8);
+
+    // Fields must be ordered by name. For a sorted dictionary, that is the
+    // same as ordering by field id.
+    if metadata.sorted_strings() {
+        fields.sort_unstable_by_key(|(field_id, _offset)| *field_id);
+    } else {
+        fields.sort_unstable_by_key(|(field_id, _offset)| metadata.get_string(*field_id));
+    }
+
+    for (field_id, _offset) in fields.iter() {
+        write_integer(buffer, *field_id, field_id_width);
+    }
+    for (_field_id, offset) in fields.iter() {
+        write_integer(buffer, *offset, offset_width);
+    }
+    write_integer(buffer, data_len, offset_width);
+    Ok(())
+}
 
-        // Since it was unknown as the time, we did not set the offset width
-        // in the header, so we do that now.
-        let current_header = self.buffer[self.header_offset];
-        self.buffer[self.header_offset] = current_header | (offset_width - 1) << 2;
+/// Write the elements `start..end` of an array as a new array.
+///
+/// The range is clamped to the length of the array, so an out of bounds or
+/// empty range writes an empty array. The elements are copied without being
+/// decoded.
+pub fn write_array_slice(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    array: &ArrayRef,
+    start: usize,
+    end: usize,
+) {
+    let end = end.min(array.len());
+    let start = start.min(end);
+    let mut array_builder = ArrayBuilder::new(buffer, end - start);
+    for index in start..end {
+        array_builder.append_value(array.get_element(index).unwrap().as_bytes());
+    }
+    array_builder.finish();
+}
 
-        let mut needed_capacity = field_id_width as usize * self.field_id_and_offsets.len();
-        needed_capacity += offset_width as usize * self.field_id_and_offsets.len();
-        needed_capacity += self.buffer.len();
-        self.buffer.reserve(needed_capacity);
+/// Copy a value, replacing every field id `i` with `mapping[i]`.
+///
+/// `metadata` is the dictionary the new ids refer to. This is used to move
+/// values between dictionaries, such as when converting from a
+/// [`StreamingMetadataBuilder`](crate::metadata::StreamingMetadataBuilder)
+/// dictionary to the sorted form. The values of objects are written in the
+/// order of their field names, as [`ObjectBuilder`] writes them when fields
+/// are appended in that order.
+///
+/// # Errors
+///
+/// If a field id is not covered by the mapping, or if the value is invalid.
+pub fn remap_field_ids(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    value: &VariantRef,
+    mapping: &[usize],
+    metadata: &MetadataRef,
+) -> Result<(), String> {
+    remap(buffer, value, mapping, metadata, false)
+}
 
-        // Sort by field id.
-        self.field_id_and_offsets
-            .sort_unstable_by_key(|(field_id, _offset)| *field_id);
+/// Copy a value like [`remap_field_ids`], but keep the values of objects in
+/// the order they are stored. If the mapping keeps the order of the keys,
+/// the copy has the same layout as the value, apart from the width of the
+/// field ids.
+///
+/// # Errors
+///
+/// If a field id is not covered by the mapping, or if the value is invalid.
+pub fn remap_field_ids_in_value_order(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    value: &VariantRef,
+    mapping: &[usize],
+    metadata: &MetadataRef,
+) -> Result<(), String> {
+    remap(buffer, value, mapping, metadata, true)
+}
 
-        for (field_id, _offset) in &self.field_id_and_offsets {
-            write_integer(self.buffer, *field_id, field_id_width);
+fn remap(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    value: &VariantRef,
+    mapping: &[usize],
+    metadata: &MetadataRef,
+    in_value_order: bool,
+) -> Result<(), String> {
+    let mut stack: Vec<RemapFrame> = Vec::new();
+    let mut pending = Some(value.clone());
+    loop {
+        if let Some(value) = pending.take() {
+            match value.basic_type() {
+                BasicType::Object => {
+                    let object = value.get_object()?;
+                    let mut fields = object.fields().collect::<Vec<_>>();
+                    if in_value_order {
+                        fields.sort_by_key(|(_, field)| field.as_bytes().as_ptr());
+                    }
+                    let (field_ids, children) = fields
+                        .into_iter()
+                        .map(|(field_id, field)| {
+                            mapping
+                                .get(field_id)
+                                .map(|new_field_id| (*new_field_id, field))
+                                .ok_or_else(|| {
+                                    format!("Field id {} is not in the mapping", field_id)
+                                })
+                        })
+                        .collect::<Result<(Vec<_>, Vec<_>), _>>()?;
+                    stack.push(RemapFrame::new(Some(field_ids), children));
+                }
+                BasicType::Array => {
+                    let array = value.get_array()?;
+                    stack.push(RemapFrame::new(None, array.elements().collect()));
+                }
+                BasicType::Primitive | BasicType::ShortString => match stack.last_mut() {
+                    Some(frame) => {
+                        frame.buffer.extend_from_slice(value.as_bytes());
+                        frame.offsets.push(frame.buffer.len());
+                    }
+                    None => {
+                        buffer.extend_from_slice(value.as_bytes());
+                        return Ok(());
+                    }
+                },
+            }
         }
 
-        for (_field_id, offset) in self.field_id_and_offsets {
-            write_integer(self.buffer, offset, offset_width);
+        let frame = stack
+            .last_mut()
+            .expect("stack is empty only after the top-level value");
+        if let Some(child) = frame.children.get(frame.offsets.len() - 1) {
+            pending = Some(child.clone());
+            continue;
         }
-        write_integer(self.buffer, final_offset, offset_width);
 
-        self.buffer.extend_from_slice(&self.tmp_buffer);
+        // All children are copied, so close the container.
+        let frame = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some(parent) => {
+                frame.finish(&mut parent.buffer, metadata);
+                parent.offsets.push(parent.buffer.len());
+            }
+            None => {
+                frame.finish(buffer, metadata);
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Copy a value, replacing some of the values nested in it.
+///
+/// `replace` is called with the path and value of each nested value, starting
+/// with the value itself at the empty path. It returns the encoded
+/// replacement, or `None` to keep the value and go on to its children. Field
+/// ids are kept, so the copy uses the same metadata as the value, and any
+/// replacement must be valid with that metadata.
+///
+/// # Errors
+///
+/// If the value is invalid, or a field id is not in the metadata.
+pub fn replace_values(
+    buffer: &mut (impl VariantValueWriter + ?Sized),
+    value: &VariantRef,
+    metadata: &MetadataRef,
+    mut replace: impl FnMut(&[PathElement], &VariantRef) -> Option<Vec<u8>>,
+) -> Result<(), String> {
+    // Like `remap_field_ids`, this uses an explicit stack. `path` holds the
+    // path of the value being copied.
+    let mut stack: Vec<RemapFrame> = Vec::new();
+    let mut path = Vec::new();
+    let mut pending = Some(value.clone());
+    loop {
+        if let Some(value) = pending.take() {
+            let replacement = replace(&path, &value);
+            match (replacement, value.basic_type()) {
+                (None, BasicType::Object) => {
+                    let object = value.get_object()?;
+                    let (field_ids, children) = object.fields().unzip();
+                    stack.push(RemapFrame::new(Some(field_ids), children));
+                }
+                (None, BasicType::Array) => {
+                    let array = value.get_array()?;
+                    stack.push(RemapFrame::new(None, array.elements().collect()));
+                }
+                (replacement, _) => {
+                    let bytes = replacement.as_deref().unwrap_or(value.as_bytes());
+                    match stack.last_mut() {
+                        Some(frame) => {
+                            frame.buffer.extend_from_slice(bytes);
+                            frame.offsets.push(frame.buffer.len());
+                            path.pop();
+                        }
+                        None => {
+                            buffer.extend_from_slice(bytes);
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+
+        let frame = stack
+            .last_mut()
+            .expect("stack is empty only after the top-level value");
+        let index = frame.offsets.len() - 1;
+        if let Some(child) = frame.children.get(index) {
+            let element = match &frame.field_ids {
+                Some(field_ids) => {
+                    let key = metadata.get_string(field_ids[index]).ok_or_else(|| {
+                        format!("Field id {} is not in the metadata", field_ids[index])
+                    })?;
+                    PathElement::Field(key.into())
+                }
+                None => PathElement::Index(index),
+            };
+            path.push(element);
+            pending = Some(child.clone());
+            continue;
+        }
+
+        let frame = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some(parent) => {
+                frame.finish(&mut parent.buffer, metadata);
+                parent.offsets.push(parent.buffer.len());
+                path.pop();
+            }
+            None => {
+                frame.finish(buffer, metadata);
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// An object or array being copied by [`remap_field_ids`] or
+/// [`replace_values`].
+struct RemapFrame<'a> {
+    /// The new field ids of an object, or `None` for an array.
+    field_ids: Option<Vec<usize>>,
+    children: Vec<VariantRef<'a>>,
+    /// The copied children so far, concatenated.
+    buffer: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl<'a> RemapFrame<'a> {
+    fn new(field_ids: Option<Vec<usize>>, children: Vec<VariantRef<'a>>) -> Self {
+        Self {
+            field_ids,
+            children,
+            buffer: Vec::new(),
+            offsets: vec![0],
+        }
+    }
+
+    fn finish(self, buffer: &mut (impl VariantValueWriter + ?Sized), metadata: &MetadataRef) {
+        let values = self
+            .offsets
+            .windows(2)
+            .map(|window| &self.buffer[window[0]..window[1]]);
+        match self.field_ids {
+            None => {
+                let mut array_builder = ArrayBuilder::new(buffer, self.children.len());
+                values.for_each(|value| array_builder.append_value(value));
+                array_builder.finish();
+            }
+            Some(field_ids) => {
+                let mut object_builder =
+                    ObjectBuilder::with_capacity(buffer, metadata, field_ids.len());
+                for (field_id, value) in field_ids.into_iter().zip(values) {
+                    object_builder.append_value_with_field_id(field_id, value);
+                }
+                object_builder.finish();
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{metadata::build_metadata, values::VariantRef};
+    use crate::metadata::build_metadata;
+    #[cfg(feature = "std")]
+    use crate::metadata::StreamingMetadataBuilder;
+    use crate::values::FieldLookup;
 
     use super::*;
 
+    /// A sink with a fixed capacity, like an arena.
+    struct FixedWriter {
+        bytes: [u8; 64],
+        len: usize,
+    }
+
+    impl VariantValueWriter for FixedWriter {
+        fn extend_from_slice(&mut self, bytes: &[u8]) {
+            self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+        }
+    }
+
+    #[test]
+    fn test_value_writer() {
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        let write = |buffer: &mut dyn VariantValueWriter| {
+            write_string(buffer, "x");
+            let mut object_builder = ObjectBuilder::with_capacity(buffer, &metadata, 2);
+            object_builder.append_i64("b", 1).unwrap();
+            object_builder.append_string("a", "y").unwrap();
+            object_builder.finish();
+            let mut array_builder = ArrayBuilder::new(buffer, 1);
+            array_builder.append_value(&[0]);
+            array_builder.finish();
+        };
+
+        let mut expected = Vec::new();
+        write(&mut expected);
+        let mut fixed = FixedWriter {
+            bytes: [0; 64],
+            len: 0,
+        };
+        write(&mut fixed);
+        assert_eq!(&fixed.bytes[..fixed.len], expected.as_slice());
+        assert_eq!(VariantRef::try_new(&expected).unwrap().get_str(), Some("x"));
+    }
+
+    #[test]
+    fn test_write_null() {
+        let mut buffer = Vec::new();
+        write_null(&mut buffer);
+
+        assert_eq!(buffer, [0]);
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(variant.basic_type(), BasicType::Primitive);
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Null);
+        assert!(variant.is_null());
+    }
+
     #[test]
     fn test_write_bool() {
         let mut buffer = Vec::new();
@@ -289,6 +803,8 @@ mod tests {
         let variant = VariantRef::try_new(&buffer).unwrap();
         assert_eq!(variant.basic_type(), BasicType::Primitive);
         assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::BoolTrue);
+        assert_eq!(variant.type_name(), "boolean");
+        assert!(!variant.is_null());
 
         buffer.clear();
         write_bool(&mut buffer, false);
@@ -310,6 +826,7 @@ mod tests {
             let variant = VariantRef::try_new(&buffer).unwrap();
             assert_eq!(variant.basic_type(), BasicType::Primitive);
             assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Int64);
+            assert_eq!(variant.type_name(), "int64");
 
             let roundtripped = variant.get_i64();
             assert_eq!(value, roundtripped);
@@ -318,6 +835,151 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_write_narrow_numbers() {
+        let mut buffer = Vec::new();
+        write_i8(&mut buffer, -5);
+        write_i16(&mut buffer, -300);
+        write_i32(&mut buffer, 70_000);
+        write_f32(&mut buffer, 1.5);
+        assert_eq!(buffer.len(), 2 + 3 + 5 + 5);
+
+        let variant = VariantRef::try_new(&buffer[..2]).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Int8);
+        assert_eq!(buffer[1] as i8, -5);
+        let variant = VariantRef::try_new(&buffer[2..5]).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Int16);
+        assert_eq!(i16::from_le_bytes([buffer[3], buffer[4]]), -300);
+        let variant = VariantRef::try_new(&buffer[5..10]).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Int32);
+        assert_eq!(
+            i32::from_le_bytes(buffer[6..10].try_into().unwrap()),
+            70_000
+        );
+        let variant = VariantRef::try_new(&buffer[10..]).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Float32);
+        assert_eq!(f32::from_le_bytes(buffer[11..].try_into().unwrap()), 1.5);
+    }
+
+    #[test]
+    fn test_write_date_and_timestamps() {
+        let mut buffer = Vec::new();
+        write_date32(&mut buffer, 19_000);
+        write_timestamp_micros(&mut buffer, -1);
+        write_timestamp_micros_ntz(&mut buffer, i64::MAX);
+        assert_eq!(buffer.len(), 5 + 9 + 9);
+
+        let variant = VariantRef::try_new(&buffer[..5]).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Date32);
+        assert_eq!(variant.encoded_len(), 5);
+        assert_eq!(i32::from_le_bytes(buffer[1..5].try_into().unwrap()), 19_000);
+        let variant = VariantRef::try_new(&buffer[5..14]).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::TimestampMicro);
+        assert_eq!(i64::from_le_bytes(buffer[6..14].try_into().unwrap()), -1);
+        let variant = VariantRef::try_new(&buffer[14..]).unwrap();
+        assert_eq!(
+            variant.primitive_type_id(),
+            PrimitiveTypeId::TimestampMicroNTZ
+        );
+        assert_eq!(variant.get_timestamp_nanos_ntz(), None);
+    }
+
+    #[test]
+    fn test_write_binary() {
+        let mut buffer = Vec::new();
+        write_binary(&mut buffer, b"\x00\xff");
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(variant.primitive_type_id(), PrimitiveTypeId::Binary);
+        assert_eq!(variant.encoded_len(), buffer.len());
+        assert_eq!(&buffer[5..], b"\x00\xff");
+    }
+
+    #[test]
+    fn test_write_decimal_widths() {
+        let cases = [
+            (0, PrimitiveTypeId::Decimal4),
+            (i32::MAX as i128, PrimitiveTypeId::Decimal4),
+            (i32::MIN as i128, PrimitiveTypeId::Decimal4),
+            (i32::MAX as i128 + 1, PrimitiveTypeId::Decimal8),
+            (i32::MIN as i128 - 1, PrimitiveTypeId::Decimal8),
+            (i64::MIN as i128, PrimitiveTypeId::Decimal8),
+            (i64::MIN as i128 - 1, PrimitiveTypeId::Decimal16),
+            (i128::MAX, PrimitiveTypeId::Decimal16),
+        ];
+        let mut buffer = Vec::new();
+        for (value, type_id) in cases {
+            write_decimal(&mut buffer, value, 2);
+            let variant = VariantRef::try_new(&buffer).unwrap();
+            assert_eq!(variant.primitive_type_id(), type_id, "{}", value);
+            let unscaled = match type_id {
+                PrimitiveTypeId::Decimal4 => {
+                    i32::from_le_bytes(buffer[2..6].try_into().unwrap()) as i128
+                }
+                PrimitiveTypeId::Decimal8 => {
+                    i64::from_le_bytes(buffer[2..10].try_into().unwrap()) as i128
+                }
+                _ => variant.get_i128(),
+            };
+            assert_eq!(unscaled, value);
+            buffer.clear();
+        }
+    }
+
+    #[test]
+    fn test_write_timestamp_nanos_ntz() {
+        let mut buffer = Vec::new();
+        write_timestamp_nanos_ntz(&mut buffer, 1_700_000_000_123_456_789);
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(
+            variant.primitive_type_id(),
+            PrimitiveTypeId::TimestampNanoNTZ
+        );
+        assert_eq!(variant.encoded_len(), 9);
+        assert_eq!(
+            variant.get_timestamp_nanos_ntz(),
+            Some(1_700_000_000_123_456_789)
+        );
+
+        // Microsecond timestamps are read as nanoseconds.
+        let mut micros = Vec::new();
+        write_timestamp_micros_ntz(&mut micros, 1_700_000_000_123_456);
+        let variant = VariantRef::try_new(&micros).unwrap();
+        assert_eq!(
+            variant.primitive_type_id(),
+            PrimitiveTypeId::TimestampMicroNTZ
+        );
+        assert_eq!(
+            variant.get_timestamp_nanos_ntz(),
+            Some(1_700_000_000_123_456_000)
+        );
+
+        // Past about the year 2262, they don't fit in nanoseconds.
+        for (micros, nanos) in [
+            (i64::MAX / 1000, Some(i64::MAX / 1000 * 1000)),
+            (i64::MAX / 1000 + 1, None),
+            (i64::MIN / 1000 - 1, None),
+        ] {
+            let buffer = [primitive_header(PrimitiveTypeId::TimestampMicroNTZ)]
+                .into_iter()
+                .chain(micros.to_le_bytes())
+                .collect::<Vec<_>>();
+            let variant = VariantRef::try_new(&buffer).unwrap();
+            assert_eq!(variant.get_timestamp_nanos_ntz(), nanos);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Not a timestamp without timezone")]
+    fn test_timestamp_nanos_ntz_of_other_type() {
+        // A boolean is a single byte, shorter than a timestamp.
+        let mut buffer = Vec::new();
+        write_bool(&mut buffer, true);
+        VariantRef::try_new(&buffer)
+            .unwrap()
+            .get_timestamp_nanos_ntz();
+    }
+
     #[test]
     fn test_write_object() {
         let mut buffer = Vec::new();
@@ -353,18 +1015,66 @@ mod tests {
         let variant = VariantRef::try_new(&buffer).unwrap();
 
         let field_id = metadata_ref.find_string("user_id").unwrap();
-        let user_id = variant.get_object().unwrap().get_field(field_id).unwrap();
+        let user_id = variant
+            .get_object()
+            .unwrap()
+            .get_field(field_id, &metadata_ref)
+            .unwrap();
         assert_eq!(user_id.get_i64(), 42);
 
         let field_id = metadata_ref.find_string("date").unwrap();
-        let date = variant.get_object().unwrap().get_field(field_id).unwrap();
+        let date = variant
+            .get_object()
+            .unwrap()
+            .get_field(field_id, &metadata_ref)
+            .unwrap();
         assert_eq!(date.get_string(), "2024-01-01");
 
         let field_id = metadata_ref.find_string("score").unwrap();
-        let score = variant.get_object().unwrap().get_field(field_id).unwrap();
+        let score = variant
+            .get_object()
+            .unwrap()
+            .get_field(field_id, &metadata_ref)
+            .unwrap();
         assert_eq!(score.get_f64(), 23.0);
 
-        assert!(variant.get_object().unwrap().get_field(42).is_none());
+        assert!(variant
+            .get_object()
+            .unwrap()
+            .get_field(42, &metadata_ref)
+            .is_none());
+    }
+
+    #[test]
+    fn test_bound_object() {
+        let metadata = build_metadata(["a", "b", "c"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 2);
+        let mut value = Vec::new();
+        write_i64(&mut value, 1);
+        object_builder.append_value("c", &value).unwrap();
+        value.clear();
+        write_string(&mut value, "x");
+        object_builder.append_value("a", &value).unwrap();
+        object_builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let object = variant.get_object().unwrap().with_metadata(&metadata_ref);
+        assert_eq!(object.len(), 2);
+        assert!(!object.is_empty());
+        assert_eq!(object.get("a").unwrap().get_string(), "x");
+        assert_eq!(object.get("c").unwrap().get_i64(), 1);
+        assert!(object.get("b").is_none());
+        assert!(object.get("d").is_none());
+        assert_eq!(object.keys().collect::<Vec<_>>(), vec!["a", "c"]);
+        let mut entries = object.entries();
+        let (key, value) = entries.next().unwrap();
+        assert_eq!((key, value.get_string()), ("a", "x"));
+        let (key, value) = entries.next().unwrap();
+        assert_eq!((key, value.get_i64()), ("c", 1));
+        assert!(entries.next().is_none());
+        assert_eq!(object.object().len(), 2);
     }
 
     #[test]
@@ -404,4 +1114,670 @@ mod tests {
 
         assert!(array_ref.get_element(3).is_none());
     }
+
+    #[test]
+    fn test_get_str() {
+        let metadata = build_metadata(["a", "key"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        // Short string: length in the upper 6 bits of the header.
+        let short = [(2 << 2) | BasicType::ShortString as u8, b'h', b'i'];
+        let short = VariantRef::try_new(&short).unwrap();
+        assert_eq!(short.get_short_string(), "hi");
+        assert_eq!(short.get_str(), Some("hi"));
+
+        let mut long = Vec::new();
+        write_string(&mut long, "hello");
+        let long = VariantRef::try_new(&long).unwrap();
+        assert_eq!(long.get_str(), Some("hello"));
+        assert_eq!(long.get_str_with_metadata(&metadata_ref), Some("hello"));
+
+        let mut from_dictionary = vec![primitive_header(PrimitiveTypeId::StringFromDictionary)];
+        from_dictionary.extend_from_slice(&1_u32.to_le_bytes());
+        let from_dictionary = VariantRef::try_new(&from_dictionary).unwrap();
+        assert_eq!(from_dictionary.encoded_len(), 5);
+        assert_eq!(from_dictionary.get_str(), None);
+        assert_eq!(
+            from_dictionary.get_str_with_metadata(&metadata_ref),
+            Some("key")
+        );
+
+        let mut number = Vec::new();
+        write_i64(&mut number, 1);
+        assert_eq!(VariantRef::try_new(&number).unwrap().get_str(), None);
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        let mut buffer = Vec::new();
+        let mut expected_lens = Vec::new();
+        let mut push = |buffer: &mut Vec<u8>, write: &dyn Fn(&mut Vec<u8>)| {
+            let start = buffer.len();
+            write(buffer);
+            expected_lens.push((start, buffer.len() - start));
+        };
+
+        push(&mut buffer, &write_null);
+        push(&mut buffer, &|buf| write_bool(buf, true));
+        push(&mut buffer, &|buf| write_i64(buf, -7));
+        push(&mut buffer, &|buf| write_f64(buf, 1.5));
+        push(&mut buffer, &|buf| write_decimal(buf, 12345, 2));
+        push(&mut buffer, &|buf| write_decimal(buf, i128::MAX, 0));
+        push(&mut buffer, &|buf| write_string(buf, "hello world"));
+        push(&mut buffer, &|buf| {
+            let mut builder = ArrayBuilder::new(buf, 2);
+            let mut tmp_buf = Vec::new();
+            write_string(&mut tmp_buf, "x");
+            builder.append_value(&tmp_buf);
+            tmp_buf.clear();
+            write_i64(&mut tmp_buf, 1);
+            builder.append_value(&tmp_buf);
+            builder.finish();
+        });
+        push(&mut buffer, &|buf| {
+            let mut builder = ObjectBuilder::with_capacity(buf, &metadata_ref, 2);
+            builder.append_string("b", "value").unwrap();
+            builder.append_i64("a", 2).unwrap();
+            builder.finish();
+        });
+
+        // Each value is followed by the others in the buffer, so this also
+        // validates the length doesn't depend on the slice length.
+        for (start, len) in expected_lens {
+            let variant = VariantRef::try_new(&buffer[start..]).unwrap();
+            assert_eq!(variant.encoded_len(), len);
+            assert_eq!(variant.as_bytes(), &buffer[start..start + len]);
+        }
+    }
+
+    #[test]
+    fn test_field_lookup() {
+        let keys = (0..12).map(|i| format!("k{:02}", i)).collect::<Vec<_>>();
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata_ref = MetadataRef::new(&metadata);
+        // Objects with the first n keys, for more shapes than are cached,
+        // then again in reverse so some shapes have been evicted.
+        let objects = (1..=keys.len())
+            .chain((1..=keys.len()).rev())
+            .map(|n| {
+                let mut buffer = Vec::new();
+                let mut object_builder =
+                    ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, n);
+                for (i, key) in keys[..n].iter().enumerate() {
+                    object_builder.append_i64(key, i as i64).unwrap();
+                }
+                object_builder.finish();
+                buffer
+            })
+            .collect::<Vec<_>>();
+
+        let mut lookup = FieldLookup::new("k05", &metadata_ref).unwrap();
+        assert_eq!(lookup.field_id(), 5);
+        for _ in 0..2 {
+            for buffer in &objects {
+                let object = VariantRef::try_new(buffer).unwrap().get_object().unwrap();
+                let expected = object
+                    .get_field(5, &metadata_ref)
+                    .map(|value| value.get_i64());
+                assert_eq!(lookup.get(&object).map(|value| value.get_i64()), expected);
+            }
+        }
+        assert!(FieldLookup::new("missing", &metadata_ref).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_write_object_unsorted_metadata() {
+        let mut metadata_builder = StreamingMetadataBuilder::new();
+        for key in ["zebra", "apple", "mango"] {
+            metadata_builder.get_or_insert(key);
+        }
+        let metadata = metadata_builder.build();
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 3);
+        object_builder.append_i64("zebra", 1).unwrap();
+        object_builder.append_i64("apple", 2).unwrap();
+        object_builder.append_i64("mango", 3).unwrap();
+        object_builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let object = variant.get_object().unwrap();
+        // Fields are stored in name order, not id order.
+        let field_ids = object.fields().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(field_ids, vec![1, 2, 0]);
+        for (key, expected) in [("zebra", 1), ("apple", 2), ("mango", 3)] {
+            let field = object.find_field(key, &metadata_ref).unwrap();
+            assert_eq!(field.get_i64(), expected);
+        }
+        assert!(object.find_field("kiwi", &metadata_ref).is_none());
+        // The ids aren't increasing, so they can't be binary searched.
+        for (field_id, expected) in [(0, 1), (1, 2), (2, 3)] {
+            let field = object.get_field(field_id, &metadata_ref).unwrap();
+            assert_eq!(field.get_i64(), expected);
+            let field = variant.field(field_id, &metadata_ref).unwrap().unwrap();
+            assert_eq!(field.get_i64(), expected);
+        }
+        assert!(object.get_field(3, &metadata_ref).is_none());
+        let mut lookup = FieldLookup::new("zebra", &metadata_ref).unwrap();
+        assert_eq!(lookup.get(&object).unwrap().get_i64(), 1);
+        assert_eq!(lookup.get(&object).unwrap().get_i64(), 1);
+
+        // Convert to the sorted dictionary.
+        let (sorted_metadata, mapping) = metadata_builder.build_sorted();
+        let sorted_metadata_ref = MetadataRef::new(&sorted_metadata);
+        let mut remapped = Vec::new();
+        remap_field_ids(&mut remapped, &variant, &mapping, &sorted_metadata_ref).unwrap();
+
+        let object = VariantRef::try_new(&remapped).unwrap();
+        let object = object.get_object().unwrap();
+        let field_ids = object.fields().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(field_ids, vec![0, 1, 2]);
+        for (key, expected) in [("zebra", 1), ("apple", 2), ("mango", 3)] {
+            let field_id = sorted_metadata_ref.find_string(key).unwrap();
+            assert_eq!(
+                object
+                    .get_field(field_id, &sorted_metadata_ref)
+                    .unwrap()
+                    .get_i64(),
+                expected
+            );
+        }
+
+        let res = remap_field_ids(&mut Vec::new(), &variant, &[0], &sorted_metadata_ref);
+        assert!(matches!(res, Err(err) if err.contains("not in the mapping")));
+    }
+
+    #[test]
+    fn test_write_object_large_field_ids() {
+        // Field ids above what fits in one byte, with few fields in the object.
+        let keys = (0..300).map(|i| format!("key{:03}", i)).collect::<Vec<_>>();
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 2);
+        object_builder.append_i64("key299", 299).unwrap();
+        object_builder.append_i64("key001", 1).unwrap();
+        object_builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(variant.encoded_len(), buffer.len());
+        let object = variant.get_object().unwrap();
+        assert_eq!(object.len(), 2);
+        assert_eq!(object.get_field(299, &metadata_ref).unwrap().get_i64(), 299);
+        assert_eq!(object.get_field(1, &metadata_ref).unwrap().get_i64(), 1);
+        assert!(object.get_field(2, &metadata_ref).is_none());
+    }
+
+    #[test]
+    fn test_header() {
+        let mut buffer = Vec::new();
+        write_i64(&mut buffer, 1);
+        let header = VariantRef::try_new(&buffer).unwrap().header();
+        assert_eq!(header.basic_type, BasicType::Primitive);
+        assert_eq!(header.primitive_type_id, Some(PrimitiveTypeId::Int64));
+        assert_eq!(header.num_elements, None);
+
+        let short = [(2 << 2) | BasicType::ShortString as u8, b'h', b'i'];
+        let header = VariantRef::try_new(&short).unwrap().header();
+        assert_eq!(header.basic_type, BasicType::ShortString);
+        assert_eq!(header.primitive_type_id, None);
+
+        let keys = (0..300).map(|i| format!("key{:03}", i)).collect::<Vec<_>>();
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata_ref = MetadataRef::new(&metadata);
+        buffer.clear();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 2);
+        object_builder.append_i64("key299", 299).unwrap();
+        object_builder.append_i64("key001", 1).unwrap();
+        object_builder.finish();
+        let header = VariantRef::try_new(&buffer).unwrap().header();
+        assert_eq!(header.basic_type, BasicType::Object);
+        assert_eq!(header.field_id_width, Some(2));
+        assert_eq!(header.offset_width, Some(1));
+        assert!(!header.is_large);
+        assert_eq!(header.num_elements, Some(2));
+
+        let elements = 300;
+        buffer.clear();
+        let mut array_builder = ArrayBuilder::new(&mut buffer, elements);
+        let mut element = Vec::new();
+        write_i64(&mut element, 1);
+        for _ in 0..elements {
+            array_builder.append_value(&element);
+        }
+        array_builder.finish();
+        let header = VariantRef::try_new(&buffer).unwrap().header();
+        assert_eq!(header.basic_type, BasicType::Array);
+        assert_eq!(header.field_id_width, None);
+        assert_eq!(header.offset_width, Some(2));
+        assert!(header.is_large);
+        assert_eq!(header.num_elements, Some(elements));
+    }
+
+    #[test]
+    fn test_builder_size_boundaries() {
+        // The number of elements is a 1-byte unsigned integer up to 255, and
+        // offsets use the narrowest unsigned width for the size of the values.
+        for elements in [127, 128, 255, 256, 65535, 65536] {
+            let keys = (0..elements)
+                .map(|i| format!("k{:05}", i))
+                .collect::<Vec<_>>();
+            let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+            let metadata = MetadataRef::new(&metadata);
+            let mut buffer = Vec::new();
+            let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, elements);
+            for (i, key) in keys.iter().enumerate() {
+                object_builder.append_i64(key, i as i64).unwrap();
+            }
+            object_builder.finish();
+            let object = VariantRef::try_new(&buffer).unwrap();
+            let header = object.header();
+            assert_eq!(header.is_large, elements > 255, "{}", elements);
+            assert_eq!(header.num_elements, Some(elements));
+            let field_id_width = if elements <= 256 { 1 } else { 2 };
+            assert_eq!(header.field_id_width, Some(field_id_width), "{}", elements);
+            let object = object.get_object().unwrap();
+            assert_eq!(object.len(), elements);
+            let (last_id, last) = object.fields().last().unwrap();
+            assert_eq!(last_id, elements - 1);
+            assert_eq!(last.get_i64(), elements as i64 - 1);
+
+            buffer.clear();
+            let mut array_builder = ArrayBuilder::new(&mut buffer, elements);
+            for i in 0..elements {
+                let mut element = Vec::new();
+                write_i64(&mut element, i as i64);
+                array_builder.append_value(&element);
+            }
+            array_builder.finish();
+            let array = VariantRef::try_new(&buffer).unwrap();
+            let header = array.header();
+            assert_eq!(header.is_large, elements > 255, "{}", elements);
+            assert_eq!(header.num_elements, Some(elements));
+            let array = array.get_array().unwrap();
+            let values_len = array.elements().map(|e| e.as_bytes().len()).sum::<usize>();
+            let offset_width = crate::utils::determine_byte_width(values_len);
+            assert_eq!(header.offset_width, Some(offset_width), "{}", elements);
+            let last = array.get_element(elements - 1).unwrap();
+            assert_eq!(last.get_i64(), elements as i64 - 1);
+        }
+    }
+
+    #[test]
+    fn test_builder_capacity_is_a_hint() {
+        // The header is written from the number of fields appended, so a
+        // wide object can be built without knowing its size up front.
+        let keys = (0..300).map(|i| format!("k{:03}", i)).collect::<Vec<_>>();
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 0);
+        for key in &keys {
+            object_builder.append_i64(key, 1).unwrap();
+        }
+        object_builder.try_finish().unwrap();
+        let object = VariantRef::try_new(&buffer).unwrap();
+        assert!(object.header().is_large);
+        assert_eq!(object.get_object().unwrap().len(), 300);
+        assert_eq!(object.get_object().unwrap().encoded_len(), buffer.len());
+
+        buffer.clear();
+        let mut array_builder = ArrayBuilder::new(&mut buffer, 1000);
+        for i in 0..3 {
+            let mut element = Vec::new();
+            write_i64(&mut element, i);
+            array_builder.append_value(&element);
+        }
+        array_builder.try_finish().unwrap();
+        let array = VariantRef::try_new(&buffer).unwrap();
+        assert!(!array.header().is_large);
+        assert_eq!(array.header().num_elements, Some(3));
+        assert_eq!(array.get_array().unwrap().encoded_len(), buffer.len());
+    }
+
+    #[test]
+    fn test_check_limit() {
+        assert!(check_limit(u32::MAX as usize, "elements").is_ok());
+        let error = check_limit(u32::MAX as usize + 1, "elements").unwrap_err();
+        assert!(error.contains("at most 4294967295 elements"), "{}", error);
+    }
+
+    #[test]
+    fn test_array_offsets_sized_by_values() {
+        // Two elements holding more than 255 bytes need 2-byte offsets.
+        let long = "x".repeat(200);
+        let mut element = Vec::new();
+        write_string(&mut element, &long);
+        let mut buffer = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut buffer, 2);
+        array_builder.append_value(&element);
+        array_builder.append_value(&element);
+        array_builder.finish();
+
+        let array = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(array.header().offset_width, Some(2));
+        let array = array.get_array().unwrap();
+        for element in array.elements() {
+            assert_eq!(element.get_str(), Some(long.as_str()));
+        }
+        assert_eq!(array.encoded_len(), buffer.len());
+    }
+
+    #[test]
+    fn test_three_byte_widths() {
+        // Field ids above 65535 and offsets above 65535 bytes use 3 bytes.
+        let keys = (0..70_000)
+            .map(|i| format!("k{:05}", i))
+            .collect::<Vec<_>>();
+        let metadata = build_metadata(keys.iter().map(|key| key.as_str()));
+        let metadata = MetadataRef::new(&metadata);
+        let long = "x".repeat(70_000);
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+        object_builder.append_string("k69999", &long).unwrap();
+        object_builder.append_i64("k00000", 1).unwrap();
+        object_builder.finish();
+
+        let object = VariantRef::try_new(&buffer).unwrap();
+        let header = object.header();
+        assert_eq!(header.field_id_width, Some(3));
+        assert_eq!(header.offset_width, Some(3));
+        let object = object.get_object().unwrap();
+        assert_eq!(object.encoded_len(), buffer.len());
+        let fields = object.fields().collect::<Vec<_>>();
+        assert_eq!(fields[0].0, 0);
+        assert_eq!(fields[0].1.get_i64(), 1);
+        assert_eq!(fields[1].0, 69_999);
+        assert_eq!(fields[1].1.get_str(), Some(long.as_str()));
+        let found = object.find_field("k69999", &metadata).unwrap();
+        assert_eq!(found.get_str(), Some(long.as_str()));
+    }
+
+    #[test]
+    fn test_array_contains() {
+        let metadata = build_metadata(["a", "c"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+
+        // [1, 2.5, "a", true, null, [3], "c"], with "c" stored in the metadata
+        let mut nested = Vec::new();
+        let mut nested_builder = ArrayBuilder::new(&mut nested, 1);
+        let mut tmp_buffer = Vec::new();
+        write_i64(&mut tmp_buffer, 3);
+        nested_builder.append_value(&tmp_buffer);
+        nested_builder.finish();
+
+        let mut elements = vec![Vec::new(); 5];
+        write_i64(&mut elements[0], 1);
+        write_f64(&mut elements[1], 2.5);
+        write_string(&mut elements[2], "a");
+        write_bool(&mut elements[3], true);
+        write_null(&mut elements[4]);
+        elements.push(nested.clone());
+        let mut from_dictionary = vec![primitive_header(PrimitiveTypeId::StringFromDictionary)];
+        from_dictionary.extend_from_slice(&1_u32.to_le_bytes());
+        elements.push(from_dictionary);
+
+        let mut buffer = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut buffer, elements.len());
+        for element in &elements {
+            array_builder.append_value(element);
+        }
+        array_builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        assert_eq!(variant.type_name(), "array");
+        let array = variant.get_array().unwrap();
+        let type_names = array.elements().map(|element| element.type_name());
+        assert_eq!(
+            type_names.collect::<Vec<_>>(),
+            vec!["int64", "double", "string", "boolean", "null", "array", "string"]
+        );
+        let contains = |write: &dyn Fn(&mut Vec<u8>)| {
+            let mut needle = Vec::new();
+            write(&mut needle);
+            array.contains(&VariantRef::try_new(&needle).unwrap(), &metadata_ref)
+        };
+
+        assert!(contains(&|buffer| write_i64(buffer, 1)));
+        // Numeric coercion
+        assert!(contains(&|buffer| write_f64(buffer, 1.0)));
+        assert!(!contains(&|buffer| write_i64(buffer, 2)));
+        assert!(contains(&|buffer| write_f64(buffer, 2.5)));
+        assert!(contains(&|buffer| write_string(buffer, "a")));
+        // Short strings compare equal to long strings
+        assert!(contains(
+            &|buffer| buffer.extend_from_slice(&[1 << 2 | 1, b'a'])
+        ));
+        assert!(!contains(&|buffer| write_string(buffer, "b")));
+        assert!(contains(&|buffer| write_bool(buffer, true)));
+        assert!(!contains(&|buffer| write_bool(buffer, false)));
+        // Nulls and containers never match
+        assert!(!contains(&|buffer| write_null(buffer)));
+        assert!(!contains(&|buffer| buffer.extend_from_slice(&nested)));
+        assert!(!contains(&|buffer| write_i64(buffer, 3)));
+        // Strings stored in the metadata are resolved
+        assert!(contains(&|buffer| write_string(buffer, "c")));
+        assert!(contains(
+            &|buffer| buffer.extend_from_slice(&[1 << 2 | 1, b'c'])
+        ));
+    }
+
+    #[test]
+    fn test_write_array_slice() {
+        let mut buffer = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut buffer, 4);
+        let mut tmp_buffer = Vec::new();
+        for i in 0..4 {
+            tmp_buffer.clear();
+            write_i64(&mut tmp_buffer, i);
+            array_builder.append_value(&tmp_buffer);
+        }
+        array_builder.finish();
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let array = variant.get_array().unwrap();
+
+        let slice = |start, end| {
+            let mut buffer = Vec::new();
+            write_array_slice(&mut buffer, &array, start, end);
+            let variant = VariantRef::try_new(&buffer).unwrap();
+            assert_eq!(variant.encoded_len(), buffer.len());
+            let array = variant.get_array().unwrap();
+            array
+                .elements()
+                .map(|element| element.get_i64())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(slice(1, 3), vec![1, 2]);
+        assert_eq!(slice(0, 4), vec![0, 1, 2, 3]);
+        assert_eq!(slice(2, 10), vec![2, 3]);
+        assert_eq!(slice(3, 1), Vec::<i64>::new());
+        assert_eq!(slice(5, 8), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn test_replace_values() {
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata = MetadataRef::new(&metadata);
+        // {"a": [1, 2], "b": {"a": 3}}
+        let mut array = Vec::new();
+        let mut array_builder = ArrayBuilder::new(&mut array, 2);
+        for value in [1, 2] {
+            let mut element = Vec::new();
+            write_i64(&mut element, value);
+            array_builder.append_value(&element);
+        }
+        array_builder.finish();
+        let mut inner = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut inner, &metadata, 1);
+        object_builder.append_i64("a", 3).unwrap();
+        object_builder.finish();
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata, 2);
+        object_builder.append_value("a", &array).unwrap();
+        object_builder.append_value("b", &inner).unwrap();
+        object_builder.finish();
+        let variant = VariantRef::try_new(&buffer).unwrap();
+
+        let mut paths = Vec::new();
+        let mut replaced = Vec::new();
+        replace_values(&mut replaced, &variant, &metadata, |path, _| {
+            paths.push(path.to_vec());
+            (path.last() == Some(&PathElement::Index(1))).then(|| {
+                let mut null = Vec::new();
+                write_null(&mut null);
+                null
+            })
+        })
+        .unwrap();
+        let field = |key: &str| PathElement::Field(key.into());
+        assert_eq!(
+            paths,
+            vec![
+                vec![],
+                vec![field("a")],
+                vec![field("a"), PathElement::Index(0)],
+                vec![field("a"), PathElement::Index(1)],
+                vec![field("b")],
+                vec![field("b"), field("a")],
+            ]
+        );
+
+        let object = VariantRef::try_new(&replaced)
+            .unwrap()
+            .get_object()
+            .unwrap();
+        let array = object.get_field(0, &metadata).unwrap().get_array().unwrap();
+        assert_eq!(array.get_element(0).unwrap().get_i64(), 1);
+        assert!(array.get_element(1).unwrap().is_null());
+        let inner = object
+            .get_field(1, &metadata)
+            .unwrap()
+            .get_object()
+            .unwrap();
+        assert_eq!(inner.get_field(0, &metadata).unwrap().get_i64(), 3);
+
+        // Replacing the value itself.
+        let mut replaced = Vec::new();
+        replace_values(&mut replaced, &variant, &metadata, |_, _| Some(vec![0])).unwrap();
+        assert_eq!(replaced, [0]);
+    }
+
+    #[test]
+    fn test_remap_deeply_nested() {
+        // {"a": {"a": ... {"a": 1} ... }}, deeper than the call stack would
+        // allow if traversal were recursive.
+        let depth = 20_000;
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        write_i64(&mut buffer, 1);
+        for _ in 0..depth {
+            let mut object_buffer = Vec::new();
+            let mut object_builder =
+                ObjectBuilder::with_capacity(&mut object_buffer, &metadata_ref, 1);
+            object_builder.append_value("a", &buffer).unwrap();
+            object_builder.finish();
+            buffer = object_buffer;
+        }
+
+        let mut remapped = Vec::new();
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        remap_field_ids(&mut remapped, &variant, &[1, 0], &metadata_ref).unwrap();
+        assert_eq!(remapped.len(), buffer.len());
+
+        let mut variant = VariantRef::try_new(&remapped).unwrap();
+        for _ in 0..depth {
+            variant = variant
+                .get_object()
+                .unwrap()
+                .get_field(1, &metadata_ref)
+                .unwrap();
+        }
+        assert_eq!(variant.get_i64(), 1);
+    }
+
+    #[test]
+    fn test_remap_keeps_value_order() {
+        // {"b": 1, "a": "x"}, with the value of "b" stored first.
+        let metadata = build_metadata(["a", "b"].into_iter());
+        let metadata_ref = MetadataRef::new(&metadata);
+        let mut buffer = Vec::new();
+        let mut object_builder = ObjectBuilder::with_capacity(&mut buffer, &metadata_ref, 2);
+        let mut value = Vec::new();
+        write_i64(&mut value, 1);
+        object_builder.append_value("b", &value).unwrap();
+        value.clear();
+        write_string(&mut value, "x");
+        object_builder.append_value("a", &value).unwrap();
+        object_builder.finish();
+
+        let variant = VariantRef::try_new(&buffer).unwrap();
+        let mut remapped = Vec::new();
+        remap_field_ids_in_value_order(&mut remapped, &variant, &[0, 1], &metadata_ref).unwrap();
+        assert_eq!(remapped, buffer);
+
+        // remap_field_ids stores the value of "a" first.
+        remapped.clear();
+        remap_field_ids(&mut remapped, &variant, &[0, 1], &metadata_ref).unwrap();
+        assert_eq!(remapped.len(), buffer.len());
+        assert_ne!(remapped, buffer);
+    }
+
+    #[test]
+    fn test_structural_eq() {
+        use crate::values::Variant;
+
+        let value = Variant::from_iter([
+            ("a", Variant::from(vec![Variant::from(1), Variant::Null])),
+            ("b", Variant::from_iter([("c", "x")])),
+        ]);
+        let (metadata, left) = value.encode();
+        let metadata = MetadataRef::new(&metadata);
+        let left = VariantRef::try_new(&left).unwrap();
+
+        // The same value written with a larger dictionary has other field ids.
+        let other_metadata = build_metadata(["0", "a", "aa", "b", "c"].into_iter());
+        let other_metadata = MetadataRef::new(&other_metadata);
+        let eq = |other: &Variant| {
+            let mut buffer = Vec::new();
+            other.write(&mut buffer, &other_metadata).unwrap();
+            let other = VariantRef::try_new(&buffer).unwrap();
+            left.structural_eq(&metadata, &other, &other_metadata)
+                .unwrap()
+        };
+        assert!(eq(&value));
+
+        // Numbers compare by value.
+        let mut other = value.clone();
+        if let Variant::Object(fields) = &mut other {
+            fields.insert(
+                "a".into(),
+                Variant::from(vec![Variant::from(1.0), Variant::Null]),
+            );
+        }
+        assert!(eq(&other));
+
+        for other in [
+            Variant::from_iter([("a", Variant::from(vec![1])), ("b", Variant::Null)]),
+            Variant::from_iter([("a", Variant::Null)]),
+            Variant::from_iter([
+                ("aa", Variant::from(vec![Variant::from(1), Variant::Null])),
+                ("b", Variant::from_iter([("c", "x")])),
+            ]),
+            Variant::from_iter([
+                ("a", Variant::from(vec![Variant::from(1), Variant::Null])),
+                ("b", Variant::from_iter([("c", "y")])),
+            ]),
+            Variant::Null,
+        ] {
+            assert!(!eq(&other), "{:?}", other);
+        }
+    }
 }